@@ -5,58 +5,252 @@ pub struct NotFoundError {
 	pub message: String,
 }
 
-impl Into<ParsingError> for String {
-	fn into(self) -> ParsingError {
-		ParsingError { message: self }
+impl From<String> for ParsingError {
+	fn from(message: String) -> ParsingError {
+		ParsingError::new(message)
 	}
 }
-impl Into<ParsingError> for &str {
-	fn into(self) -> ParsingError {
-		ParsingError {
-			message: self.to_string(),
-		}
+impl From<&str> for ParsingError {
+	fn from(message: &str) -> ParsingError {
+		ParsingError::new(message.to_string())
 	}
 }
 
+#[derive(Debug)]
+pub struct EventNotFound {
+	pub event_uuid: Uuid,
+}
+
 pub struct EventTemplateNotFound {
 	pub event_template_uuid: Uuid,
 }
+#[derive(Debug)]
 pub struct TraceTemplateNotFound {
 	pub trace_template_uuid: Uuid,
 }
+#[derive(Debug)]
+pub struct ProjectNotFound {
+	pub project_uuid: Uuid,
+}
+#[derive(Debug)]
+pub struct TraceNotFound {
+	pub trace_uuid: Uuid,
+}
+
+// Storage errors are those of writing the journal, whatever was to change is left as it was
+
+#[derive(Debug)]
+pub enum ChangeTraceError {
+	TraceNotFound(TraceNotFound),
+	Storage(std::io::Error),
+}
+
+#[derive(Debug)]
+pub enum ChangeTraceTemplateError {
+	TraceNotFound(TraceNotFound),
+	TraceTemplateNotFound(TraceTemplateNotFound),
+	Storage(std::io::Error),
+}
+
+#[derive(Debug)]
+pub enum SaveTraceError {
+	OriginTraceNotFound(TraceNotFound),
+	// The trace template gives the trace its project
+	TraceTemplateNotFound(TraceTemplateNotFound),
+	Storage(std::io::Error),
+}
+
+#[derive(Debug)]
+pub enum ChangeEventError {
+	EventNotFound(EventNotFound),
+	TraceNotFound(TraceNotFound),
+	Storage(std::io::Error),
+}
+
+#[derive(Debug)]
+pub enum SaveEventError {
+	TraceNotFound(TraceNotFound),
+	Storage(std::io::Error),
+}
 
 #[derive(Debug)]
+pub enum ChangeProjectError {
+	ProjectNotFound(ProjectNotFound),
+	Storage(std::io::Error),
+}
+
+#[derive(Debug)]
+pub enum DeleteProjectError {
+	ProjectNotFound(ProjectNotFound),
+	// The confirmation did not repeat the project's name
+	NotConfirmed,
+	Storage(std::io::Error),
+}
+
+#[derive(Debug, Clone)]
 pub struct ParsingError {
 	pub message: String,
+	// Where in the definition the error is, e.g. event-templates[3].fields[1].default-value
+	pub path: Option<String>,
+	// 1-based, when the position could be recovered from the source file
+	pub line: Option<usize>,
+	pub column: Option<usize>,
+	pub template_name: Option<String>,
+}
+
+impl ParsingError {
+	pub fn new(message: String) -> ParsingError {
+		ParsingError {
+			message,
+			path: None,
+			line: None,
+			column: None,
+			template_name: None,
+		}
+	}
+
+	// serde reports syntax errors as "<message> at line L column C"; keep the position in its
+	// own fields so it is not printed twice.
+	fn located(message: String, line: usize, column: usize) -> ParsingError {
+		let suffix = format!(" at line {} column {}", line, column);
+		ParsingError {
+			line: Some(line),
+			column: Some(column),
+			..ParsingError::new(message.strip_suffix(&suffix).unwrap_or(&message).to_string())
+		}
+	}
+}
+
+impl std::fmt::Display for ParsingError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		if let Some(line) = self.line {
+			write!(f, "line {}", line)?;
+			if let Some(column) = self.column {
+				write!(f, ", column {}", column)?;
+			}
+			write!(f, ": ")?;
+		}
+		if let Some(path) = &self.path {
+			write!(f, "{}: ", path)?;
+		}
+		if let Some(template_name) = &self.template_name {
+			write!(f, "(in '{}') ", template_name)?;
+		}
+		write!(f, "{}", self.message)
+	}
+}
+
+// Every problem found while reading a definition, rather than just the first one
+#[derive(Debug, Clone, Default)]
+pub struct DefinitionErrors {
+	pub errors: Vec<ParsingError>,
+	pub warnings: Vec<ParsingError>,
+}
+
+impl std::fmt::Display for DefinitionErrors {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		for error in self.errors.iter() {
+			writeln!(f, "error: {}", error)?;
+		}
+		for warning in self.warnings.iter() {
+			writeln!(f, "warning: {}", warning)?;
+		}
+		Ok(())
+	}
 }
 
-impl Into<EventTemplateNotFound> for Uuid {
-	fn into(self) -> EventTemplateNotFound {
+impl From<ParsingError> for DefinitionErrors {
+	fn from(error: ParsingError) -> DefinitionErrors {
+		DefinitionErrors {
+			errors: vec![error],
+			warnings: vec![],
+		}
+	}
+}
+
+impl From<Uuid> for EventNotFound {
+	fn from(uuid: Uuid) -> EventNotFound {
+		EventNotFound {
+			event_uuid: uuid,
+		}
+	}
+}
+
+impl From<Uuid> for EventTemplateNotFound {
+	fn from(uuid: Uuid) -> EventTemplateNotFound {
 		EventTemplateNotFound {
-			event_template_uuid: self,
+			event_template_uuid: uuid,
 		}
 	}
 }
 
-impl Into<TraceTemplateNotFound> for Uuid {
-	fn into(self) -> TraceTemplateNotFound {
+impl From<Uuid> for TraceTemplateNotFound {
+	fn from(uuid: Uuid) -> TraceTemplateNotFound {
 		TraceTemplateNotFound {
-			trace_template_uuid: self,
+			trace_template_uuid: uuid,
 		}
 	}
 }
 
-impl Into<ParsingError> for std::io::Error {
-	fn into(self) -> ParsingError {
-		ParsingError {
-			message: self.to_string(),
+impl From<Uuid> for ProjectNotFound {
+	fn from(uuid: Uuid) -> ProjectNotFound {
+		ProjectNotFound { project_uuid: uuid }
+	}
+}
+
+impl From<Uuid> for TraceNotFound {
+	fn from(uuid: Uuid) -> TraceNotFound {
+		TraceNotFound { trace_uuid: uuid }
+	}
+}
+
+impl From<TraceNotFound> for ChangeTraceError {
+	fn from(error: TraceNotFound) -> ChangeTraceError {
+		ChangeTraceError::TraceNotFound(error)
+	}
+}
+
+impl From<ChangeTraceError> for ChangeTraceTemplateError {
+	fn from(error: ChangeTraceError) -> ChangeTraceTemplateError {
+		match error {
+			ChangeTraceError::TraceNotFound(error) => ChangeTraceTemplateError::TraceNotFound(error),
+			ChangeTraceError::Storage(error) => ChangeTraceTemplateError::Storage(error),
 		}
 	}
 }
-impl Into<ParsingError> for serde_json::Error {
-	fn into(self) -> ParsingError {
-		ParsingError {
-			message: self.to_string(),
+
+impl From<EventNotFound> for ChangeEventError {
+	fn from(error: EventNotFound) -> ChangeEventError {
+		ChangeEventError::EventNotFound(error)
+	}
+}
+
+impl From<ProjectNotFound> for ChangeProjectError {
+	fn from(error: ProjectNotFound) -> ChangeProjectError {
+		ChangeProjectError::ProjectNotFound(error)
+	}
+}
+
+impl From<std::io::Error> for ParsingError {
+	fn from(error: std::io::Error) -> ParsingError {
+		ParsingError::new(error.to_string())
+	}
+}
+impl From<serde_json::Error> for ParsingError {
+	fn from(error: serde_json::Error) -> ParsingError {
+		if error.line() == 0 {
+			return ParsingError::new(error.to_string());
+		}
+		ParsingError::located(error.to_string(), error.line(), error.column())
+	}
+}
+impl From<serde_yaml::Error> for ParsingError {
+	fn from(error: serde_yaml::Error) -> ParsingError {
+		match error.location() {
+			Some(location) => {
+				ParsingError::located(error.to_string(), location.line(), location.column())
+			}
+			None => ParsingError::new(error.to_string()),
 		}
 	}
 }
@@ -1,5 +1,7 @@
 
 use chrono::DateTime;
+use chrono::NaiveDate;
+use chrono::NaiveTime;
 use chrono::Utc;
 use std::collections::HashSet;
 use uuid::Uuid;
@@ -36,12 +38,62 @@ pub struct EnumeratedSuggestion {
 	pub options: Vec<views::EnumerationOption>,
 }
 
+#[derive(Clone, serde::Deserialize, serde::Serialize)]
+pub struct ArraySuggestion {
+	pub items: Vec<FieldValueSuggestion>,
+	// Each earlier event's rows
+	pub last_values: Vec<Vec<FieldValueSuggestion>>,
+	// What an added row starts out as
+	pub new_item: Box<FieldValueSuggestion>,
+}
+
+#[derive(Clone, serde::Deserialize, serde::Serialize)]
+pub struct DateTimeSuggestion {
+	pub value: Option<DateTime<Utc>>,
+	pub last_values: Vec<DateTime<Utc>>,
+	pub default_value: Option<DateTime<Utc>>,
+}
+
+#[derive(Clone, serde::Deserialize, serde::Serialize)]
+pub struct DateSuggestion {
+	pub value: Option<NaiveDate>,
+	pub last_values: Vec<NaiveDate>,
+	pub default_value: Option<NaiveDate>,
+}
+
+#[derive(Clone, serde::Deserialize, serde::Serialize)]
+pub struct TimeOfDaySuggestion {
+	pub value: Option<NaiveTime>,
+	pub last_values: Vec<NaiveTime>,
+	pub default_value: Option<NaiveTime>,
+}
+
+// In whole seconds
+#[derive(Clone, serde::Deserialize, serde::Serialize)]
+pub struct DurationSuggestion {
+	pub value: Option<i64>,
+	pub last_values: Vec<i64>,
+	pub default_value: Option<i64>,
+}
+
+// A section of fields, each with its own last values
+#[derive(Clone, serde::Deserialize, serde::Serialize)]
+pub struct GroupSuggestion {
+	pub fields: Vec<FieldSuggestion>,
+}
+
 #[derive(Clone, serde::Deserialize, serde::Serialize)]
 pub enum FieldValueSuggestion {
 	Number(NumberSuggestion),
 	Text(TextSuggestion),
 	Bool(BoolSuggestion),
 	Enumerated(EnumeratedSuggestion),
+	Array(ArraySuggestion),
+	Group(GroupSuggestion),
+	DateTime(DateTimeSuggestion),
+	Date(DateSuggestion),
+	TimeOfDay(TimeOfDaySuggestion),
+	Duration(DurationSuggestion),
 }
 
 #[derive(Clone, serde::Deserialize, serde::Serialize)]
@@ -49,6 +101,206 @@ pub struct FieldSuggestion {
 	pub name: String,
 	pub label: String,
 	pub value: FieldValueSuggestion,
+	// The policy that proposed the value the field started with, None when nothing did. Taking
+	// one of the last values clears it.
+	pub source: Option<ValuePolicy>,
+}
+
+// How create_event proposes a field's value. A policy that finds nothing to go on falls back
+// to the template's default value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Deserialize, serde::Serialize)]
+pub enum ValuePolicy {
+	#[default]
+	TemplateDefault,
+	LastInTrace,
+	// The last value of the event template in any trace
+	LastInTemplate,
+	// Of the template's recent values, for number and duration fields
+	RollingMedian,
+	// Of the template's recent values, for enumerated fields
+	MostFrequentOption,
+}
+
+impl ValuePolicy {
+	pub const ALL: [ValuePolicy; 5] = [
+		ValuePolicy::TemplateDefault,
+		ValuePolicy::LastInTrace,
+		ValuePolicy::LastInTemplate,
+		ValuePolicy::RollingMedian,
+		ValuePolicy::MostFrequentOption,
+	];
+
+	// As written in definitions
+	pub fn name(&self) -> &'static str {
+		match self {
+			ValuePolicy::TemplateDefault => "template-default",
+			ValuePolicy::LastInTrace => "last-in-trace",
+			ValuePolicy::LastInTemplate => "last-in-template",
+			ValuePolicy::RollingMedian => "rolling-median",
+			ValuePolicy::MostFrequentOption => "most-frequent-option",
+		}
+	}
+
+	pub fn from_name(name: &str) -> Option<ValuePolicy> {
+		ValuePolicy::ALL.into_iter().find(|policy| policy.name() == name)
+	}
+
+	pub fn label(&self) -> &'static str {
+		match self {
+			ValuePolicy::TemplateDefault => "Template default",
+			ValuePolicy::LastInTrace => "Last value in this trace",
+			ValuePolicy::LastInTemplate => "Last value",
+			ValuePolicy::RollingMedian => "Median of recent values",
+			ValuePolicy::MostFrequentOption => "Most frequent recent option",
+		}
+	}
+}
+
+impl ArraySuggestion {
+	pub fn add_item(&mut self) {
+		self.items.push(self.new_item.as_ref().clone());
+	}
+
+	pub fn remove_item(&mut self, index: usize) {
+		if index < self.items.len() {
+			self.items.remove(index);
+		}
+	}
+
+	// The rows in between shift by one
+	pub fn move_item(&mut self, from: usize, to: usize) {
+		if from < self.items.len() && to < self.items.len() {
+			let item = self.items.remove(from);
+			self.items.insert(to, item);
+		}
+	}
+}
+
+impl FieldValueSuggestion {
+	// Takes one of the last values, newest first, as the value. Returns whether there was one.
+	// The fields of a group each take theirs.
+	pub fn use_last_value(&mut self, index: usize) -> bool {
+		match self {
+			FieldValueSuggestion::Number(suggestion) => {
+				suggestion.last_values.get(index).map(|value| suggestion.value = Some(*value)).is_some()
+			}
+			FieldValueSuggestion::Text(suggestion) => suggestion
+				.last_values
+				.get(index)
+				.map(|value| suggestion.value = Some(value.clone()))
+				.is_some(),
+			FieldValueSuggestion::Bool(suggestion) => {
+				suggestion.last_values.get(index).map(|value| suggestion.value = Some(*value)).is_some()
+			}
+			FieldValueSuggestion::Enumerated(suggestion) => suggestion
+				.last_values
+				.get(index)
+				.map(|value| suggestion.selected = Some(value.clone()))
+				.is_some(),
+			FieldValueSuggestion::Array(suggestion) => suggestion
+				.last_values
+				.get(index)
+				.map(|items| suggestion.items = items.clone())
+				.is_some(),
+			FieldValueSuggestion::Group(suggestion) => {
+				suggestion.fields.iter_mut().fold(false, |used, field| {
+					if field.value.use_last_value(index) {
+						field.source = None;
+						return true;
+					}
+					used
+				})
+			}
+			FieldValueSuggestion::DateTime(suggestion) => {
+				suggestion.last_values.get(index).map(|value| suggestion.value = Some(*value)).is_some()
+			}
+			FieldValueSuggestion::Date(suggestion) => {
+				suggestion.last_values.get(index).map(|value| suggestion.value = Some(*value)).is_some()
+			}
+			FieldValueSuggestion::TimeOfDay(suggestion) => {
+				suggestion.last_values.get(index).map(|value| suggestion.value = Some(*value)).is_some()
+			}
+			FieldValueSuggestion::Duration(suggestion) => {
+				suggestion.last_values.get(index).map(|value| suggestion.value = Some(*value)).is_some()
+			}
+		}
+	}
+
+	pub fn has_last_values(&self) -> bool {
+		match self {
+			FieldValueSuggestion::Group(suggestion) => {
+				suggestion.fields.iter().any(|field| field.value.has_last_values())
+			}
+			_ => !self.last_value_labels().is_empty(),
+		}
+	}
+
+	// The value as it would be shown, None when there is none
+	pub fn value_label(&self) -> Option<String> {
+		match self {
+			FieldValueSuggestion::Number(suggestion) => suggestion.value.map(|value| value.to_string()),
+			FieldValueSuggestion::Text(suggestion) => suggestion.value.clone(),
+			FieldValueSuggestion::Bool(suggestion) => suggestion.value.map(|value| value.to_string()),
+			FieldValueSuggestion::Enumerated(suggestion) => {
+				suggestion.selected.as_ref().map(|value| value.label.clone())
+			}
+			FieldValueSuggestion::Array(suggestion) => Some(array_label(&suggestion.items)),
+			FieldValueSuggestion::Group(suggestion) => {
+				let labels: Vec<String> = suggestion
+					.fields
+					.iter()
+					.flat_map(|field| field.value.value_label().map(|label| format!("{}: {}", field.label, label)))
+					.collect();
+				Some(format!("{{{}}}", labels.join(", ")))
+			}
+			FieldValueSuggestion::DateTime(suggestion) => suggestion.value.as_ref().map(date_time_label),
+			FieldValueSuggestion::Date(suggestion) => suggestion.value.map(|value| value.to_string()),
+			FieldValueSuggestion::TimeOfDay(suggestion) => suggestion.value.as_ref().map(views::format_time_of_day),
+			FieldValueSuggestion::Duration(suggestion) => suggestion.value.map(views::format_duration),
+		}
+	}
+
+	pub fn last_value_labels(&self) -> Vec<String> {
+		match self {
+			FieldValueSuggestion::Number(suggestion) => {
+				suggestion.last_values.iter().map(|value| value.to_string()).collect()
+			}
+			FieldValueSuggestion::Text(suggestion) => suggestion.last_values.clone(),
+			FieldValueSuggestion::Bool(suggestion) => {
+				suggestion.last_values.iter().map(|value| value.to_string()).collect()
+			}
+			FieldValueSuggestion::Enumerated(suggestion) => {
+				suggestion.last_values.iter().map(|value| value.label.clone()).collect()
+			}
+			FieldValueSuggestion::Array(suggestion) => {
+				suggestion.last_values.iter().map(|items| array_label(items)).collect()
+			}
+			// Offered field by field
+			FieldValueSuggestion::Group(_) => Vec::new(),
+			FieldValueSuggestion::DateTime(suggestion) => {
+				suggestion.last_values.iter().map(date_time_label).collect()
+			}
+			FieldValueSuggestion::Date(suggestion) => {
+				suggestion.last_values.iter().map(|value| value.to_string()).collect()
+			}
+			FieldValueSuggestion::TimeOfDay(suggestion) => {
+				suggestion.last_values.iter().map(views::format_time_of_day).collect()
+			}
+			FieldValueSuggestion::Duration(suggestion) => {
+				suggestion.last_values.iter().map(|value| views::format_duration(*value)).collect()
+			}
+		}
+	}
+}
+
+// In local time, as it is entered
+fn date_time_label(value: &DateTime<Utc>) -> String {
+	value.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M").to_string()
+}
+
+fn array_label(items: &[FieldValueSuggestion]) -> String {
+	let labels: Vec<String> = items.iter().flat_map(|item| item.value_label()).collect();
+	format!("[{}]", labels.join(", "))
 }
 
 #[derive(Debug, PartialEq, Eq, Hash)]
@@ -66,6 +318,83 @@ impl TraceSelection {
 	}
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DefinitionFormat {
+	Json,
+	Yaml,
+}
+
+impl DefinitionFormat {
+	pub fn from_path(path: &std::path::Path) -> DefinitionFormat {
+		match path
+			.extension()
+			.and_then(|extension| extension.to_str())
+			.map(|extension| extension.to_lowercase())
+			.as_deref()
+		{
+			Some("yaml") | Some("yml") => DefinitionFormat::Yaml,
+			_ => DefinitionFormat::Json,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadMode {
+	// What is in memory is dropped, a failed load leaves it untouched
+	Replace,
+	// Projects on disk are added to what is in memory. A project that is in both keeps its
+	// definition from memory and only gains the events and traces it does not have, since
+	// whatever is in memory was saved later.
+	Merge,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TraceFilter {
+	// Neither completed nor archived
+	#[default]
+	Active,
+	// Completed but not archived
+	Completed,
+	Archived,
+	All,
+}
+
+impl TraceFilter {
+	pub fn matches(&self, completed: bool, archived: bool) -> bool {
+		match self {
+			TraceFilter::Active => !completed && !archived,
+			TraceFilter::Completed => completed && !archived,
+			TraceFilter::Archived => archived,
+			TraceFilter::All => true,
+		}
+	}
+
+	pub fn label(&self) -> &'static str {
+		match self {
+			TraceFilter::Active => "Active",
+			TraceFilter::Completed => "Completed",
+			TraceFilter::Archived => "Archived",
+			TraceFilter::All => "All",
+		}
+	}
+}
+
+// Which earlier events the last values of a new event's fields come from, always those of
+// the same event template
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Deserialize, serde::Serialize)]
+pub enum HistoryScope {
+	#[default]
+	AnyTrace,
+	SameTrace,
+}
+
+pub enum LineageDirection {
+	Ancestors,
+	Descendants,
+	// Everything connected through origins in either direction
+	Family,
+}
+
 pub struct RenameMe_EventTemplate {
 	pub event_template_uuid: Uuid,
 	pub name: String,
@@ -80,6 +409,8 @@ pub struct EventBuilder {
 	pub traces: Vec<Uuid>,
 	pub began_at: DateTime<Utc>,
 	pub selected_trace: TraceSelection,
+	// Set by edit_event, the builder is saved with update_event
+	pub editing: bool,
 }
 
 pub struct RenameMe_TraceTemplate {
@@ -104,10 +435,13 @@ pub trait EventsService {
 		project_uuid: Option<Uuid>,
 	) -> Box<dyn Iterator<Item = views::EventTemplateItemView>>;
 
+	// The fields list the last values they had in the scope and start out with the value their
+	// template's policy proposes
 	fn create_event(
 		&self,
 		trace: &views::TraceView,
 		template_uuid: Uuid,
+		history: HistoryScope,
 	) -> Result<EventBuilder, errors::EventTemplateNotFound>;
 	
 	fn create_trace(
@@ -115,11 +449,41 @@ pub trait EventsService {
 		trace_template_uuid: Uuid,
 	) -> Result<TraceBuilder, errors::TraceTemplateNotFound>;
 
-	fn complete_trace(&mut self, trace_uuid: Uuid);
+	fn complete_trace(&mut self, trace_uuid: Uuid) -> Result<(), errors::ChangeTraceError>;
+
+	// Takes back complete_trace
+	fn reopen_trace(&mut self, trace_uuid: Uuid) -> Result<(), errors::ChangeTraceError>;
+
+	fn rename_trace(&mut self, trace_uuid: Uuid, name: String) -> Result<(), errors::ChangeTraceError>;
+
+	// For a trace started from the wrong template. Its events keep their own templates.
+	fn change_trace_template(
+		&mut self,
+		trace_uuid: Uuid,
+		trace_template_uuid: Uuid,
+	) -> Result<(), errors::ChangeTraceTemplateError>;
+
+	// Archived traces are kept out of the way whether or not they were completed
+	fn archive_trace(&mut self, trace_uuid: Uuid) -> Result<(), errors::ChangeTraceError>;
 
-	fn save_event(&mut self, event_builder: &EventBuilder);
+	fn unarchive_trace(&mut self, trace_uuid: Uuid) -> Result<(), errors::ChangeTraceError>;
 
-	fn save_trace(&mut self, trace_builder: &TraceBuilder);
+	fn save_event(&mut self, event_builder: &EventBuilder) -> Result<(), errors::SaveEventError>;
+
+	// A builder holding what the event holds now
+	fn edit_event(&self, event_uuid: Uuid) -> Result<EventBuilder, errors::EventNotFound>;
+
+	// Replaces the fields, tags, start and trace of the event the builder came from. What it
+	// held before is kept as a revision.
+	fn update_event(&mut self, event_builder: &EventBuilder) -> Result<(), errors::ChangeEventError>;
+
+	// Leaves a tombstone, deleted events are no longer listed or suggested from
+	fn delete_event(&mut self, event_uuid: Uuid) -> Result<(), errors::ChangeEventError>;
+
+	// Takes back the deletion or else the last update, false when there was nothing to undo
+	fn undo_event_change(&mut self, event_uuid: Uuid) -> Result<bool, errors::ChangeEventError>;
+
+	fn save_trace(&mut self, trace_builder: &TraceBuilder) -> Result<(), errors::SaveTraceError>;
 
 	fn view_event(&self, event_uuid: Uuid) -> Option<views::EventView>;
 
@@ -130,9 +494,18 @@ pub trait EventsService {
 		project_uuid: Option<Uuid>,
 	) -> Box<dyn Iterator<Item = views::EventItemView> + '_>;
 
+	// Total and mean of a duration field over the template's events. Deleted events and events
+	// that left the field empty are not counted.
+	fn summarize_duration(
+		&self,
+		event_template_uuid: Uuid,
+		field_path: &str,
+	) -> Result<views::DurationSummaryView, errors::EventTemplateNotFound>;
+
 	fn list_traces(
 		&self,
 		project_uuid: Option<Uuid>,
+		filter: TraceFilter,
 	) -> Box<dyn Iterator<Item = views::TraceItemView> + '_>;
 
 	fn list_trace_templates(
@@ -140,24 +513,73 @@ pub trait EventsService {
 		project_uuid: Option<Uuid>,
 	) -> Box<dyn Iterator<Item = views::TraceTemplateItemView>>;
 
+	fn view_lineage(
+		&self,
+		trace_uuid: Uuid,
+		direction: LineageDirection,
+	) -> Result<views::LineageView, errors::TraceNotFound>;
+
+	// Shortest chain of origin links between two traces, None when they are not related
+	fn find_lineage_path(
+		&self,
+		from_trace_uuid: Uuid,
+		to_trace_uuid: Uuid,
+	) -> Result<Option<Vec<views::LineagePathStepView>>, errors::TraceNotFound>;
 
-	fn load_from_disk(&mut self) -> Result<(), std::io::Error>;
+	// Projects that cannot be read are skipped and listed in the report
+	fn load_from_disk(&mut self, mode: LoadMode) -> Result<views::LoadReport, std::io::Error>;
 	fn save_to_disk(&self) -> Result<(), std::io::Error>;
+	// Importing a definition of an existing project replaces its templates and runs the
+	// definition's migrations over the project's events. The definition goes to the project it
+	// names, or into an existing project given as into_project.
 	fn import_definition(
 		&mut self,
 		project_name: String,
-		definition_path: &std::path::PathBuf
-	) -> Result<(), errors::ParsingError>;
+		definition_path: &std::path::Path,
+		into_project: Option<Uuid>,
+	) -> Result<views::ImportReport, errors::DefinitionErrors>;
+	// Reports what import_definition would do, without changing anything
+	fn preview_definition_import(
+		&self,
+		definition_path: &std::path::Path,
+		into_project: Option<Uuid>,
+	) -> Result<views::ImportReport, errors::DefinitionErrors>;
+	// Writes the project's definition in the same format import_definition reads
+	fn export_definition(
+		&self,
+		project_uuid: Uuid,
+		format: DefinitionFormat,
+	) -> Result<String, errors::ProjectNotFound>;
+
+	// Oldest first
+	fn list_projects(&self) -> Box<dyn Iterator<Item = views::ProjectItemView> + '_>;
+	fn view_project(&self, project_uuid: Uuid) -> Option<views::ProjectView>;
+	// An empty project, templates come from importing a definition into it
+	fn create_project(&mut self, name: String, description: String) -> Result<views::ProjectView, std::io::Error>;
+	fn rename_project(&mut self, project_uuid: Uuid, name: String) -> Result<(), errors::ChangeProjectError>;
+	fn describe_project(
+		&mut self,
+		project_uuid: Uuid,
+		description: String,
+	) -> Result<(), errors::ChangeProjectError>;
+	// Deletes the project with its templates, traces and events, also from disk. The
+	// confirmation has to be the project's name.
+	fn delete_project(
+		&mut self,
+		project_uuid: Uuid,
+		confirmation: &str,
+	) -> Result<(), errors::DeleteProjectError>;
+	// A new project with a copy of the definition under new uuids, without traces or events
+	fn duplicate_project(
+		&mut self,
+		project_uuid: Uuid,
+		name: String,
+	) -> Result<views::ProjectView, errors::ChangeProjectError>;
 
 	/*
 	fn import_all_projects(&self, json: String);
 	fn export_all_projects(&self) -> String;
 
-	fn list_projects(&self) -> Box<dyn Iterator<Item = views::ProjectItemView>>;
-	fn view_project(&self) -> Box<dyn Iterator<Item = views::ProjectView>>;
-
-	// fn create_project(&self, name: String) -> views::ProjectView;
-
 	fn list_event_templates(
 		&self,
 		project_uuid: Option<Uuid>,
@@ -202,6 +624,17 @@ pub trait EventsService {
 }
 
 
+impl EventBuilder {
+	// Sets every field to its most recent value, fields without one keep theirs
+	pub fn fill_from_last_values(&mut self) {
+		for field in self.fields.iter_mut() {
+			if field.value.use_last_value(0) {
+				field.source = None;
+			}
+		}
+	}
+}
+
 impl TraceBuilder {
 	pub fn label(&self) -> String {
 		self.name.clone()
@@ -1,8 +1,13 @@
 use chrono::DateTime;
+use chrono::NaiveDate;
+use chrono::NaiveTime;
+use chrono::Timelike;
 use chrono::Utc;
 use uuid::Uuid;
 use chrono::serde::ts_milliseconds;
 
+use crate::errors;
+
 
 #[derive(Clone, serde::Deserialize, serde::Serialize)]
 pub struct TraceCompletion {
@@ -30,12 +35,92 @@ pub struct EnumerationOptionView {
 	pub label: String,
 }
 
+#[derive(Clone, serde::Deserialize, serde::Serialize)]
+pub struct DateTimeValueView {
+	pub value: Option<DateTime<Utc>>,
+}
+
+#[derive(Clone, serde::Deserialize, serde::Serialize)]
+pub struct DateValueView {
+	pub value: Option<NaiveDate>,
+}
+
+#[derive(Clone, serde::Deserialize, serde::Serialize)]
+pub struct TimeOfDayValueView {
+	pub value: Option<NaiveTime>,
+}
+
+#[derive(Clone, serde::Deserialize, serde::Serialize)]
+pub struct DurationValueView {
+	// Whole seconds
+	pub value: Option<i64>,
+}
+
+// Like 4h30m, the way definitions write durations
+pub fn format_duration(seconds: i64) -> String {
+	if seconds == 0 {
+		return "0s".to_string();
+	}
+	let parts = [(seconds / 3600, "h"), (seconds / 60 % 60, "m"), (seconds % 60, "s")];
+	let parts: Vec<String> = parts
+		.iter()
+		.filter(|(amount, _)| *amount != 0)
+		.map(|(amount, unit)| format!("{}{}", amount, unit))
+		.collect();
+	parts.join("")
+}
+
+// A duration field over the events of a template that have it filled in
+#[derive(Clone, Default)]
+pub struct DurationSummaryView {
+	pub count: usize,
+	// Whole seconds
+	pub total: i64,
+}
+
+impl DurationSummaryView {
+	pub fn add(&mut self, seconds: i64) {
+		self.count += 1;
+		self.total = self.total.saturating_add(seconds);
+	}
+
+	// Rounded to whole seconds, None when no event had the field filled in
+	pub fn mean(&self) -> Option<i64> {
+		(self.count > 0).then(|| (self.total as f64 / self.count as f64).round() as i64)
+	}
+}
+
+// Seconds are only shown when there are any
+pub fn format_time_of_day(time: &NaiveTime) -> String {
+	if time.second() == 0 {
+		time.format("%H:%M").to_string()
+	} else {
+		time.format("%H:%M:%S").to_string()
+	}
+}
+
+#[derive(Clone, serde::Deserialize, serde::Serialize)]
+pub struct ArrayValueView {
+	pub items: Vec<FieldValueView>,
+}
+
+#[derive(Clone, serde::Deserialize, serde::Serialize)]
+pub struct GroupValueView {
+	pub fields: Vec<FieldView>,
+}
+
 #[derive(Clone, serde::Deserialize, serde::Serialize)]
 pub enum FieldValueView {
 	Number(NumberValueView),
 	Text(TextValueView),
 	Bool(BoolValueView),
 	Enumerated(EnumerationOptionView),
+	Array(ArrayValueView),
+	Group(GroupValueView),
+	DateTime(DateTimeValueView),
+	Date(DateValueView),
+	TimeOfDay(TimeOfDayValueView),
+	Duration(DurationValueView),
 }
 
 // This could change in the future, to hold recent values, etc. 
@@ -50,10 +135,238 @@ pub struct FieldView {
 
 
 #[derive(Clone)]
-pub struct ProjectItemView {}
+pub struct ImportReport {
+	pub project_uuid: Uuid,
+	pub warnings: Vec<errors::ParsingError>,
+	pub changes: DefinitionChangesView,
+}
+
+#[derive(Clone, Debug)]
+pub struct RenameView {
+	pub from: String,
+	pub to: String,
+}
+
+// Templates are named by their name, renames are templates that kept their uuid
+#[derive(Clone, Debug, Default)]
+pub struct TemplateChangesView {
+	pub added: Vec<String>,
+	pub removed: Vec<String>,
+	pub renamed: Vec<RenameView>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct OptionChangesView {
+	pub field: String,
+	pub added: Vec<String>,
+	pub removed: Vec<String>,
+	pub renamed: Vec<RenameView>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct FieldChangesView {
+	pub event_template: String,
+	pub added: Vec<String>,
+	pub removed: Vec<String>,
+	pub renamed: Vec<RenameView>,
+	// Same name, different kind of value
+	pub retyped: Vec<String>,
+	pub options: Vec<OptionChangesView>,
+}
+
+// Transitions are written as "From -> To"
+#[derive(Clone, Debug, Default)]
+pub struct FlowChangesView {
+	pub trace_template: String,
+	pub added: Vec<String>,
+	pub removed: Vec<String>,
+}
+
+// What importing a definition does to the project it belongs to
+#[derive(Clone, Debug, Default)]
+pub struct DefinitionChangesView {
+	// None when the project does not exist yet
+	pub from_version: Option<u32>,
+	pub to_version: u32,
+	pub trace_templates: TemplateChangesView,
+	pub event_templates: TemplateChangesView,
+	pub fields: Vec<FieldChangesView>,
+	pub flows: Vec<FlowChangesView>,
+	pub applied_migrations: usize,
+	pub migrated_events: usize,
+	// Left pointing at templates the definition no longer has
+	pub orphaned_events: usize,
+	pub orphaned_traces: usize,
+}
+
+// The path names a group's field after the group's name and a dot, like proof.temperature
+pub fn find_field<'a>(fields: &'a [FieldView], path: &str) -> Option<&'a FieldView> {
+	match path.split_once('.') {
+		None => fields.iter().find(|field| field.name == path),
+		Some((group, rest)) => match fields.iter().find(|field| field.name == group)?.value.as_ref()? {
+			FieldValueView::Group(group) => find_field(&group.fields, rest),
+			_ => None,
+		},
+	}
+}
+
+impl TemplateChangesView {
+	pub fn is_empty(&self) -> bool {
+		self.added.is_empty() && self.removed.is_empty() && self.renamed.is_empty()
+	}
+}
+
+impl DefinitionChangesView {
+	// Whether the templates differ, regardless of the version and what happens to events
+	pub fn changes_templates(&self) -> bool {
+		!self.trace_templates.is_empty()
+			|| !self.event_templates.is_empty()
+			|| !self.fields.is_empty()
+			|| !self.flows.is_empty()
+	}
+}
+
+fn write_list(f: &mut std::fmt::Formatter<'_>, what: &str, items: &[String]) -> std::fmt::Result {
+	if !items.is_empty() {
+		writeln!(f, "  {}: {}", what, items.join(", "))?;
+	}
+	Ok(())
+}
+
+fn write_renames(f: &mut std::fmt::Formatter<'_>, what: &str, renames: &[RenameView]) -> std::fmt::Result {
+	let renames: Vec<String> = renames
+		.iter()
+		.map(|rename| format!("{} -> {}", rename.from, rename.to))
+		.collect();
+	write_list(f, what, &renames)
+}
+
+impl std::fmt::Display for DefinitionChangesView {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self.from_version {
+			Some(from_version) => writeln!(f, "Version {} -> {}", from_version, self.to_version)?,
+			None => writeln!(f, "New project at version {}", self.to_version)?,
+		}
+		write_list(f, "Added trace templates", &self.trace_templates.added)?;
+		write_list(f, "Removed trace templates", &self.trace_templates.removed)?;
+		write_renames(f, "Renamed trace templates", &self.trace_templates.renamed)?;
+		write_list(f, "Added event templates", &self.event_templates.added)?;
+		write_list(f, "Removed event templates", &self.event_templates.removed)?;
+		write_renames(f, "Renamed event templates", &self.event_templates.renamed)?;
+		for fields in self.fields.iter() {
+			writeln!(f, "  Fields of {}:", fields.event_template)?;
+			write_list(f, "  Added", &fields.added)?;
+			write_list(f, "  Removed", &fields.removed)?;
+			write_renames(f, "  Renamed", &fields.renamed)?;
+			write_list(f, "  Changed type", &fields.retyped)?;
+			for options in fields.options.iter() {
+				write_list(f, &format!("  Added options of {}", options.field), &options.added)?;
+				write_list(f, &format!("  Removed options of {}", options.field), &options.removed)?;
+				write_renames(f, &format!("  Renamed options of {}", options.field), &options.renamed)?;
+			}
+		}
+		for flow in self.flows.iter() {
+			writeln!(f, "  Transitions of {}:", flow.trace_template)?;
+			write_list(f, "  Added", &flow.added)?;
+			write_list(f, "  Removed", &flow.removed)?;
+		}
+		if self.applied_migrations > 0 {
+			writeln!(
+				f,
+				"  {} migrations rewrite {} events",
+				self.applied_migrations, self.migrated_events
+			)?;
+		}
+		if self.orphaned_events > 0 || self.orphaned_traces > 0 {
+			writeln!(
+				f,
+				"  {} events and {} traces are left without a template",
+				self.orphaned_events, self.orphaned_traces
+			)?;
+		}
+		Ok(())
+	}
+}
+
+#[derive(Clone, Debug)]
+pub struct SkippedProjectView {
+	pub directory: String,
+	pub reason: String,
+}
+
+// A project found both in memory and on disk while merging, the copy in memory was kept
+#[derive(Clone, Debug)]
+pub struct LoadConflictView {
+	pub project_uuid: Uuid,
+	pub definition_differs: bool,
+	pub events: usize,
+	pub traces: usize,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct LoadReport {
+	pub loaded_projects: Vec<Uuid>,
+	// Interrupted saves that were completed or rolled back before loading
+	pub recovered_projects: Vec<Uuid>,
+	pub skipped_projects: Vec<SkippedProjectView>,
+	pub conflicts: Vec<LoadConflictView>,
+}
+
+impl LoadReport {
+	// Whether there is anything to tell the user about
+	pub fn has_problems(&self) -> bool {
+		!self.skipped_projects.is_empty() || !self.conflicts.is_empty()
+	}
+}
+
+impl std::fmt::Display for LoadReport {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		writeln!(f, "Loaded {} projects", self.loaded_projects.len())?;
+		for project_uuid in self.recovered_projects.iter() {
+			writeln!(f, "  Recovered an interrupted save of {}", project_uuid)?;
+		}
+		for skipped in self.skipped_projects.iter() {
+			writeln!(f, "  Skipped {}: {}", skipped.directory, skipped.reason)?;
+		}
+		for conflict in self.conflicts.iter() {
+			write!(f, "  Kept the copy in memory of project {}:", conflict.project_uuid)?;
+			if conflict.definition_differs {
+				write!(f, " its definition,")?;
+			}
+			writeln!(f, " {} events and {} traces", conflict.events, conflict.traces)?;
+		}
+		Ok(())
+	}
+}
+
+#[derive(Clone)]
+pub struct ProjectItemView {
+	pub project_uuid: Uuid,
+	pub name: String,
+	pub description: String,
+	pub created_at: DateTime<Utc>,
+	pub event_template_count: usize,
+	pub trace_template_count: usize,
+	pub trace_count: usize,
+	// Deleted events are not counted
+	pub event_count: usize,
+	// When the newest trace or event was created
+	pub last_activity: Option<DateTime<Utc>>,
+}
 
 #[derive(Clone)]
-pub struct ProjectView {}
+pub struct ProjectView {
+	pub project_uuid: Uuid,
+	pub name: String,
+	pub description: String,
+	pub created_at: DateTime<Utc>,
+	pub definition_version: u32,
+	pub event_templates: Vec<EventTemplateItemView>,
+	pub trace_templates: Vec<TraceTemplateItemView>,
+	pub trace_count: usize,
+	pub event_count: usize,
+	pub last_activity: Option<DateTime<Utc>>,
+}
 
 #[derive(Clone)]
 pub struct EventTemplateItemView {
@@ -105,6 +418,9 @@ pub struct TraceView {
 	pub trace_template: Option<TraceTemplateItemView>,
 	pub tags: Vec<String>,
 	pub completion: Option<TraceCompletion>,
+	pub archived_at: Option<DateTime<Utc>>,
+	pub origin_traces: Vec<TraceItemView>,
+	pub child_traces: Vec<TraceItemView>,
 
 	pub last_event: Option<EventItemView>,
 	pub suggested_event_templates: Vec<EventTemplateItemView>,
@@ -124,6 +440,37 @@ impl Into<TraceItemView> for &TraceView {
 	}
 }
 
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LineageLink {
+	Start,
+	// The step is an origin of the trace before it
+	Origin,
+	// The step was started from the trace before it
+	Child,
+}
+
+#[derive(Clone)]
+pub struct LineageEntryView {
+	pub trace: TraceItemView,
+	// Number of origin links between this trace and the one the lineage was requested for
+	pub distance: usize,
+	pub origin_trace_uuids: Vec<Uuid>,
+}
+
+#[derive(Clone)]
+pub struct LineageView {
+	pub trace: TraceItemView,
+	pub traces: Vec<LineageEntryView>,
+	// Each cycle lists the traces involved, in origin order
+	pub cycles: Vec<Vec<TraceItemView>>,
+}
+
+#[derive(Clone)]
+pub struct LineagePathStepView {
+	pub trace: TraceItemView,
+	pub link: LineageLink,
+}
+
 #[derive(Clone)]
 pub struct EventItemView {
 	pub event_uuid: Uuid,
@@ -146,11 +493,29 @@ pub struct EventView {
 
 	pub began_at: DateTime<Utc>,
 	pub created_at: DateTime<Utc>,
+	pub deleted_at: Option<DateTime<Utc>>,
+	// Oldest first
+	pub revisions: Vec<EventRevisionView>,
 	// TODO
 	// trace template
 	// project
 }
 
+impl EventView {
+	pub fn field(&self, path: &str) -> Option<&FieldView> {
+		find_field(&self.fields, path)
+	}
+}
+
+#[derive(Clone)]
+pub struct EventRevisionView {
+	pub trace_uuid: Uuid,
+	pub fields: Vec<FieldView>,
+	pub tags: Vec<String>,
+	pub began_at: DateTime<Utc>,
+	pub replaced_at: DateTime<Utc>,
+}
+
 /*
 
 fn list_event_templates(&self, project_uuid: Option<Uuid>) -> Vec<views::EventTemplateItemView>;
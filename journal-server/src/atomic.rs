@@ -0,0 +1,92 @@
+use std::io::Write;
+use std::path::Path;
+
+// Saving a project replaces several files that have to agree with each other. Each one is
+// first written next to its destination and synced, then a marker listing them is renamed
+// into place. That rename is the commit point: once the marker exists the new files are
+// complete and recovery finishes moving them, before it they are thrown away.
+
+const MARKER: &str = "save.pending";
+const TEMP_EXTENSION: &str = "tmp";
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Recovery {
+	Clean,
+	// An unfinished save was committed and has been completed
+	RolledForward,
+	// An unfinished save was not committed and its files have been removed
+	RolledBack,
+}
+
+fn temp_path(dir: &Path, name: &str) -> std::path::PathBuf {
+	dir.join(format!("{}.{}", name, TEMP_EXTENSION))
+}
+
+fn write_synced(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+	let mut file = std::fs::File::create(path)?;
+	file.write_all(contents)?;
+	file.sync_all()
+}
+
+// Renames and removals only survive a crash once the directory itself is synced
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> std::io::Result<()> {
+	std::fs::File::open(dir)?.sync_all()
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) -> std::io::Result<()> {
+	Ok(())
+}
+
+fn finish(dir: &Path, names: &[String]) -> std::io::Result<()> {
+	for name in names.iter() {
+		let temp = temp_path(dir, name);
+		// Already moved by an earlier attempt
+		if temp.exists() {
+			std::fs::rename(&temp, dir.join(name))?;
+		}
+	}
+	sync_dir(dir)?;
+	std::fs::remove_file(dir.join(MARKER))?;
+	sync_dir(dir)
+}
+
+// Replaces the named files in the directory all at once
+pub fn write_files(dir: &Path, files: &[(&str, Vec<u8>)]) -> std::io::Result<()> {
+	std::fs::create_dir_all(dir)?;
+	for (name, contents) in files.iter() {
+		write_synced(&temp_path(dir, name), contents)?;
+	}
+
+	let names: Vec<String> = files.iter().map(|(name, _)| name.to_string()).collect();
+	write_synced(&temp_path(dir, MARKER), &serde_json::to_vec(&names)?)?;
+	std::fs::rename(temp_path(dir, MARKER), dir.join(MARKER))?;
+	sync_dir(dir)?;
+
+	finish(dir, &names)
+}
+
+// Brings a directory back to a consistent state after a save was interrupted. Has to run
+// before any of its files are read.
+pub fn recover(dir: &Path) -> std::io::Result<Recovery> {
+	let marker = dir.join(MARKER);
+	if marker.exists() {
+		let names: Vec<String> = serde_json::from_slice(&std::fs::read(&marker)?)?;
+		finish(dir, &names)?;
+		return Ok(Recovery::RolledForward);
+	}
+
+	let mut recovery = Recovery::Clean;
+	for entry in std::fs::read_dir(dir)? {
+		let path = entry?.path();
+		if path.is_file() && path.extension().is_some_and(|extension| extension == TEMP_EXTENSION) {
+			std::fs::remove_file(&path)?;
+			recovery = Recovery::RolledBack;
+		}
+	}
+	if recovery == Recovery::RolledBack {
+		sync_dir(dir)?;
+	}
+	Ok(recovery)
+}
@@ -1,21 +1,31 @@
 
 
+use crate::definition;
 use crate::events;
 
 use chrono::Utc;
 use uuid::Uuid;
 use journal_lib::service;
+use journal_lib::views;
 
 
 
-pub fn build_trace(trace_builder: &service::TraceBuilder) -> events::Trace {
+pub fn build_trace(trace_builder: &service::TraceBuilder, project_uuid: Uuid) -> events::Trace {
+		let mut origin_trace_uuids: Vec<Uuid> = trace_builder
+			.origin_traces
+			.iter()
+			.map(|trace| trace.trace_uuid)
+			.collect();
+		origin_trace_uuids.sort();
 		events::Trace {
 			trace_uuid: Uuid::new_v4(),
 			trace_template_uuid: trace_builder.trace_template.trace_template_uuid,
+			project_uuid,
 			created_at: Utc::now(),
 			name: trace_builder.name.clone(),
 			completion: None,
-			origin_trace_uuids: Vec::new(), // TODO
+			archived_at: None,
+			origin_trace_uuids,
 	}
 }
 
@@ -28,13 +38,459 @@ pub fn build_trace(trace_builder: &service::TraceBuilder) -> events::Trace {
 				service::TraceSelection::None => panic!("Event must have a trace"),
 				service::TraceSelection::Selected(trace) => trace.trace_uuid,
 			},
-			fields: event_builder.fields.iter().flat_map(|f| build_field_suggestion(f)).collect(),
+			fields: build_fields(event_builder),
 			tags: event_builder.tags.clone(),
 			began_at: event_builder.began_at,
 			created_at: Utc::now(),
+			revisions: Vec::new(),
+			deleted_at: None,
 		}
 	}
 
+// How many of the newest events the last values of a new event's fields come from
+pub const LAST_VALUES_EVENTS: usize = 5;
+
+// How many of the newest events value policies look at
+pub const HISTORY_EVENTS: usize = 20;
+
+// Fills the last values of the fields from the events of the history scope and proposes a
+// value for each field as its template's policy says. The events are the template's newest,
+// newest first, up to HISTORY_EVENTS in the trace and anywhere.
+pub fn suggest_values(
+	fields: &mut [service::FieldSuggestion],
+	event_template: &definition::EventTemplate,
+	history: service::HistoryScope,
+	in_trace: &[&events::Event],
+	in_template: &[&events::Event],
+) {
+	let history_events = match history {
+		service::HistoryScope::AnyTrace => in_template,
+		service::HistoryScope::SameTrace => in_trace,
+	};
+	let last_value_events: Vec<&events::Event> = history_events.iter().take(LAST_VALUES_EVENTS).copied().collect();
+	suggest_field_values("", fields, &event_template.fields, &last_value_events, in_trace, in_template);
+}
+
+// The fields of groups are looked up in the events by their dotted paths
+fn suggest_field_values(
+	prefix: &str,
+	fields: &mut [service::FieldSuggestion],
+	templates: &[definition::FieldTemplate],
+	last_value_events: &[&events::Event],
+	in_trace: &[&events::Event],
+	in_template: &[&events::Event],
+) {
+	for field in fields.iter_mut() {
+		let Some(field_template) = templates.iter().find(|template| template.name == field.name) else {
+			continue;
+		};
+		let path = definition::field_path(prefix, &field.name);
+		if let (service::FieldValueSuggestion::Group(group), definition::FieldValueTemplate::Group(group_template)) =
+			(&mut field.value, &field_template.value)
+		{
+			suggest_field_values(&path, &mut group.fields, &group_template.fields, last_value_events, in_trace, in_template);
+			continue;
+		}
+		// A value is listed once, at its most recent use
+		for value in field_values(&path, &field.value, last_value_events) {
+			add_last_value(&mut field.value, value);
+		}
+		let policy = field_template.value_policy;
+		let proposed = match policy {
+			service::ValuePolicy::TemplateDefault => None,
+			service::ValuePolicy::LastInTrace => field_values(&path, &field.value, in_trace).first().copied().cloned(),
+			service::ValuePolicy::LastInTemplate => {
+				field_values(&path, &field.value, in_template).first().copied().cloned()
+			}
+			service::ValuePolicy::RollingMedian => {
+				let values = field_values(&path, &field.value, in_template);
+				match field.value {
+					service::FieldValueSuggestion::Duration(_) => median(
+						values
+							.into_iter()
+							.filter_map(|value| match value {
+								events::FieldValue::Duration(value) => Some(*value as f64),
+								_ => None,
+							})
+							.collect(),
+					)
+					.map(|value| events::FieldValue::Duration(value.round() as i64)),
+					_ => median(
+						values
+							.into_iter()
+							.filter_map(|value| match value {
+								events::FieldValue::Number(value) => Some(*value),
+								_ => None,
+							})
+							.collect(),
+					)
+					.map(events::FieldValue::Number),
+				}
+			}
+			service::ValuePolicy::MostFrequentOption => {
+				most_frequent(&field_values(&path, &field.value, in_template)).cloned()
+			}
+		};
+		// Otherwise the field keeps its default value
+		if let Some(value) = proposed {
+			set_field_value(&mut field.value, Some(&value));
+			field.source = Some(policy);
+		}
+	}
+}
+
+// The values the field at the path had in the events, in their order, that it could still take
+fn field_values<'a>(
+	path: &str,
+	suggestion: &service::FieldValueSuggestion,
+	events: &[&'a events::Event],
+) -> Vec<&'a events::FieldValue> {
+	events
+		.iter()
+		.filter_map(|event| event.field(path).and_then(|event_field| event_field.value.as_ref()))
+		.filter(|value| accepts(suggestion, value))
+		.collect()
+}
+
+fn accepts(suggestion: &service::FieldValueSuggestion, value: &events::FieldValue) -> bool {
+	match (suggestion, value) {
+		(service::FieldValueSuggestion::Number(_), events::FieldValue::Number(_))
+		| (service::FieldValueSuggestion::Text(_), events::FieldValue::Text(_))
+		| (service::FieldValueSuggestion::Bool(_), events::FieldValue::Bool(_))
+		| (service::FieldValueSuggestion::DateTime(_), events::FieldValue::DateTime(_))
+		| (service::FieldValueSuggestion::Date(_), events::FieldValue::Date(_))
+		| (service::FieldValueSuggestion::TimeOfDay(_), events::FieldValue::TimeOfDay(_))
+		| (service::FieldValueSuggestion::Duration(_), events::FieldValue::Duration(_)) => true,
+		// Options the template no longer has are not offered again
+		(service::FieldValueSuggestion::Enumerated(suggestion), events::FieldValue::Enumerated(value)) => {
+			suggestion.options.contains(value)
+		}
+		(service::FieldValueSuggestion::Array(suggestion), events::FieldValue::Array(values)) => {
+			values.iter().all(|value| accepts(&suggestion.new_item, value))
+		}
+		// Fields the group no longer has are left out
+		(service::FieldValueSuggestion::Group(suggestion), events::FieldValue::Group(fields)) => {
+			fields.iter().all(|event_field| {
+				match (
+					suggestion.fields.iter().find(|field| field.name == event_field.name),
+					&event_field.value,
+				) {
+					(Some(field), Some(value)) => accepts(&field.value, value),
+					_ => true,
+				}
+			})
+		}
+		// The field had another type then
+		_ => false,
+	}
+}
+
+// Between the two middle values when there is an even number of them
+fn median(mut values: Vec<f64>) -> Option<f64> {
+	if values.is_empty() {
+		return None;
+	}
+	values.sort_by(|a, b| a.total_cmp(b));
+	let middle = values.len() / 2;
+	if values.len().is_multiple_of(2) {
+		Some((values[middle - 1] + values[middle]) / 2.0)
+	} else {
+		Some(values[middle])
+	}
+}
+
+// Of enumerated values given newest first, a tie goes to the option used most recently
+fn most_frequent<'a>(values: &[&'a events::FieldValue]) -> Option<&'a events::FieldValue> {
+	let mut counts: Vec<(&views::EnumerationOption, &events::FieldValue, usize)> = Vec::new();
+	for value in values {
+		let events::FieldValue::Enumerated(option) = value else {
+			continue;
+		};
+		match counts.iter_mut().find(|(counted, _, _)| *counted == option) {
+			Some((_, _, count)) => *count += 1,
+			None => counts.push((option, value, 1)),
+		}
+	}
+	let mut best: Option<(&events::FieldValue, usize)> = None;
+	for (_, value, count) in counts {
+		if best.is_none_or(|(_, best_count)| count > best_count) {
+			best = Some((value, count));
+		}
+	}
+	best.map(|(value, _)| value)
+}
+
+fn push_distinct<T: PartialEq>(values: &mut Vec<T>, value: T) {
+	if !values.contains(&value) {
+		values.push(value);
+	}
+}
+
+fn add_last_value(suggestion: &mut service::FieldValueSuggestion, value: &events::FieldValue) {
+	match (suggestion, value) {
+		(service::FieldValueSuggestion::Number(suggestion), events::FieldValue::Number(value)) => {
+			push_distinct(&mut suggestion.last_values, *value)
+		}
+		(service::FieldValueSuggestion::Text(suggestion), events::FieldValue::Text(value)) => {
+			push_distinct(&mut suggestion.last_values, value.clone())
+		}
+		(service::FieldValueSuggestion::Bool(suggestion), events::FieldValue::Bool(value)) => {
+			push_distinct(&mut suggestion.last_values, *value)
+		}
+		(service::FieldValueSuggestion::Enumerated(suggestion), events::FieldValue::Enumerated(value)) => {
+			push_distinct(&mut suggestion.last_values, value.clone())
+		}
+		(service::FieldValueSuggestion::DateTime(suggestion), events::FieldValue::DateTime(value)) => {
+			push_distinct(&mut suggestion.last_values, *value)
+		}
+		(service::FieldValueSuggestion::Date(suggestion), events::FieldValue::Date(value)) => {
+			push_distinct(&mut suggestion.last_values, *value)
+		}
+		(service::FieldValueSuggestion::TimeOfDay(suggestion), events::FieldValue::TimeOfDay(value)) => {
+			push_distinct(&mut suggestion.last_values, *value)
+		}
+		(service::FieldValueSuggestion::Duration(suggestion), events::FieldValue::Duration(value)) => {
+			push_distinct(&mut suggestion.last_values, *value)
+		}
+		(service::FieldValueSuggestion::Array(suggestion), events::FieldValue::Array(values))
+			if !suggestion.last_values.iter().any(|items| &build_items(items) == values) =>
+		{
+			suggestion.last_values.push(array_items(&suggestion.new_item, values))
+		}
+		_ => {}
+	}
+}
+
+// The fields of an event builder as they would be saved
+pub fn build_fields(event_builder: &service::EventBuilder) -> Vec<events::Field> {
+	event_builder.fields.iter().flat_map(build_field_suggestion).collect()
+}
+
+fn set_field_value(suggestion: &mut service::FieldValueSuggestion, value: Option<&events::FieldValue>) {
+	match suggestion {
+		service::FieldValueSuggestion::Number(suggestion) => {
+			suggestion.value = match value {
+				Some(events::FieldValue::Number(value)) => Some(*value),
+				_ => None,
+			}
+		}
+		service::FieldValueSuggestion::Text(suggestion) => {
+			suggestion.value = match value {
+				Some(events::FieldValue::Text(value)) => Some(value.clone()),
+				_ => None,
+			}
+		}
+		service::FieldValueSuggestion::Bool(suggestion) => {
+			suggestion.value = match value {
+				Some(events::FieldValue::Bool(value)) => Some(*value),
+				_ => None,
+			}
+		}
+		service::FieldValueSuggestion::Enumerated(suggestion) => {
+			suggestion.selected = match value {
+				Some(events::FieldValue::Enumerated(value)) => Some(value.clone()),
+				_ => None,
+			}
+		}
+		service::FieldValueSuggestion::Array(suggestion) => {
+			suggestion.items = match value {
+				Some(events::FieldValue::Array(values)) => array_items(&suggestion.new_item, values),
+				_ => Vec::new(),
+			}
+		}
+		service::FieldValueSuggestion::Group(suggestion) => {
+			let values = match value {
+				Some(events::FieldValue::Group(values)) => values.as_slice(),
+				_ => &[],
+			};
+			for field in suggestion.fields.iter_mut() {
+				let value = values
+					.iter()
+					.find(|event_field| event_field.name == field.name)
+					.and_then(|event_field| event_field.value.as_ref());
+				set_field_value(&mut field.value, value);
+				field.source = None;
+			}
+		}
+		service::FieldValueSuggestion::DateTime(suggestion) => {
+			suggestion.value = match value {
+				Some(events::FieldValue::DateTime(value)) => Some(*value),
+				_ => None,
+			}
+		}
+		service::FieldValueSuggestion::Date(suggestion) => {
+			suggestion.value = match value {
+				Some(events::FieldValue::Date(value)) => Some(*value),
+				_ => None,
+			}
+		}
+		service::FieldValueSuggestion::TimeOfDay(suggestion) => {
+			suggestion.value = match value {
+				Some(events::FieldValue::TimeOfDay(value)) => Some(*value),
+				_ => None,
+			}
+		}
+		service::FieldValueSuggestion::Duration(suggestion) => {
+			suggestion.value = match value {
+				Some(events::FieldValue::Duration(value)) => Some(*value),
+				_ => None,
+			}
+		}
+	}
+}
+
+// A row for each value, laid out like a new row
+fn array_items(
+	new_item: &service::FieldValueSuggestion,
+	values: &[events::FieldValue],
+) -> Vec<service::FieldValueSuggestion> {
+	values
+		.iter()
+		.map(|value| {
+			let mut item = new_item.clone();
+			set_field_value(&mut item, Some(value));
+			item
+		})
+		.collect()
+}
+
+// The rows as they would be saved, those without a value are dropped
+fn build_items(items: &[service::FieldValueSuggestion]) -> Vec<events::FieldValue> {
+	items.iter().flat_map(build_field_value_suggestion).collect()
+}
+
+// For a field the template no longer has, so editing the event does not lose it
+fn suggest_field_value(value: &events::FieldValue) -> service::FieldValueSuggestion {
+	match value {
+		events::FieldValue::Number(value) => service::FieldValueSuggestion::Number(service::NumberSuggestion {
+			value: Some(*value),
+			last_values: Vec::new(),
+			default_value: None,
+		}),
+		events::FieldValue::Text(value) => service::FieldValueSuggestion::Text(service::TextSuggestion {
+			value: Some(value.clone()),
+			last_values: Vec::new(),
+			default_value: None,
+		}),
+		events::FieldValue::Bool(value) => service::FieldValueSuggestion::Bool(service::BoolSuggestion {
+			value: Some(*value),
+			last_values: Vec::new(),
+			default_value: None,
+		}),
+		events::FieldValue::Enumerated(value) => {
+			service::FieldValueSuggestion::Enumerated(service::EnumeratedSuggestion {
+				selected: Some(value.clone()),
+				last_values: Vec::new(),
+				options: vec![value.clone()],
+			})
+		}
+		events::FieldValue::Array(values) => {
+			// Rows added while editing are like the first one, arrays are only saved with rows
+			let mut new_item = values.first().map_or(
+				service::FieldValueSuggestion::Text(service::TextSuggestion {
+					value: None,
+					last_values: Vec::new(),
+					default_value: None,
+				}),
+				suggest_field_value,
+			);
+			set_field_value(&mut new_item, None);
+			service::FieldValueSuggestion::Array(service::ArraySuggestion {
+				items: values.iter().map(suggest_field_value).collect(),
+				last_values: Vec::new(),
+				new_item: Box::new(new_item),
+			})
+		}
+		events::FieldValue::Group(fields) => {
+			let mut suggestions = Vec::new();
+			set_fields(&mut suggestions, fields);
+			service::FieldValueSuggestion::Group(service::GroupSuggestion { fields: suggestions })
+		}
+		events::FieldValue::DateTime(value) => {
+			service::FieldValueSuggestion::DateTime(service::DateTimeSuggestion {
+				value: Some(*value),
+				last_values: Vec::new(),
+				default_value: None,
+			})
+		}
+		events::FieldValue::Date(value) => service::FieldValueSuggestion::Date(service::DateSuggestion {
+			value: Some(*value),
+			last_values: Vec::new(),
+			default_value: None,
+		}),
+		events::FieldValue::TimeOfDay(value) => {
+			service::FieldValueSuggestion::TimeOfDay(service::TimeOfDaySuggestion {
+				value: Some(*value),
+				last_values: Vec::new(),
+				default_value: None,
+			})
+		}
+		events::FieldValue::Duration(value) => {
+			service::FieldValueSuggestion::Duration(service::DurationSuggestion {
+				value: Some(*value),
+				last_values: Vec::new(),
+				default_value: None,
+			})
+		}
+	}
+}
+
+// Sets the fields to the values, also in groups. Fields the template no longer has are
+// added, so editing the event does not lose them.
+fn set_fields(fields: &mut Vec<service::FieldSuggestion>, values: &[events::Field]) {
+	for field in fields.iter_mut() {
+		let value = values
+			.iter()
+			.find(|event_field| event_field.name == field.name)
+			.and_then(|event_field| event_field.value.as_ref());
+		match (&mut field.value, value) {
+			(service::FieldValueSuggestion::Group(group), Some(events::FieldValue::Group(group_values))) => {
+				set_fields(&mut group.fields, group_values)
+			}
+			(suggestion, value) => set_field_value(suggestion, value),
+		}
+		field.source = None;
+	}
+	for event_field in values.iter() {
+		if fields.iter().any(|field| field.name == event_field.name) {
+			continue;
+		}
+		if let Some(value) = &event_field.value {
+			fields.push(service::FieldSuggestion {
+				name: event_field.name.clone(),
+				label: event_field.label.clone(),
+				value: suggest_field_value(value),
+				source: None,
+			});
+		}
+	}
+}
+
+// A builder holding what the event holds now, the fields laid out as its template has them
+pub fn edit_event_builder(
+	event: &events::Event,
+	event_template: Option<&definition::EventTemplate>,
+	trace: Option<views::TraceItemView>,
+) -> service::EventBuilder {
+	let mut fields = event_template.map_or(Vec::new(), |event_template| event_template.get_initial_fields());
+	set_fields(&mut fields, &event.fields);
+	service::EventBuilder {
+		uuid: event.event_uuid,
+		event_template: service::RenameMe_EventTemplate {
+			event_template_uuid: event.event_template_uuid,
+			name: event_template.map_or("Missing event template".to_string(), |event_template| {
+				event_template.name.clone()
+			}),
+		},
+		fields,
+		tags: event.tags.clone(),
+		traces: Vec::new(),
+		began_at: event.began_at,
+		selected_trace: trace.map_or(service::TraceSelection::None, service::TraceSelection::Selected),
+		editing: true,
+	}
+}
+
 pub fn build_field_value_suggestion(field_value_suggestion: &service::FieldValueSuggestion) -> Option<events::FieldValue> {
 	match field_value_suggestion {
 		service::FieldValueSuggestion::Number(suggestion) => suggestion
@@ -52,6 +508,20 @@ pub fn build_field_value_suggestion(field_value_suggestion: &service::FieldValue
 			.selected
 			.clone()
 			.map(|value| events::FieldValue::Enumerated(value)),
+		// No rows is no value
+		service::FieldValueSuggestion::Array(suggestion) => {
+			let items = build_items(&suggestion.items);
+			(!items.is_empty()).then_some(events::FieldValue::Array(items))
+		}
+		// Neither is a group without values
+		service::FieldValueSuggestion::Group(suggestion) => {
+			let fields: Vec<events::Field> = suggestion.fields.iter().flat_map(build_field_suggestion).collect();
+			(!fields.is_empty()).then_some(events::FieldValue::Group(fields))
+		}
+		service::FieldValueSuggestion::DateTime(suggestion) => suggestion.value.map(events::FieldValue::DateTime),
+		service::FieldValueSuggestion::Date(suggestion) => suggestion.value.map(events::FieldValue::Date),
+		service::FieldValueSuggestion::TimeOfDay(suggestion) => suggestion.value.map(events::FieldValue::TimeOfDay),
+		service::FieldValueSuggestion::Duration(suggestion) => suggestion.value.map(events::FieldValue::Duration),
 	}
 }
 
@@ -0,0 +1,214 @@
+use directories_next::ProjectDirs;
+use std::path::PathBuf;
+
+// Where the journal keeps its data. A directory given on the command line wins over the
+// environment, which wins over the platform's data directory. Profiles are separate journals
+// kept under the data directory, e.g. one to try things out in next to the real one.
+// The backend says whether the journal is kept in JSON files or in one SQLite database.
+
+pub const DATA_DIR_VARIABLE: &str = "JOURNALER_DATA_DIR";
+pub const PROFILE_VARIABLE: &str = "JOURNALER_PROFILE";
+
+const DATA_DIR_FLAG: &str = "--data-dir";
+const PROFILE_FLAG: &str = "--profile";
+const BACKEND_FLAG: &str = "--backend";
+
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backend {
+	#[default]
+	Files,
+	Sqlite,
+}
+
+impl std::str::FromStr for Backend {
+	type Err = std::io::Error;
+
+	fn from_str(value: &str) -> Result<Self, Self::Err> {
+		match value {
+			"files" => Ok(Backend::Files),
+			"sqlite" => Ok(Backend::Sqlite),
+			_ => Err(invalid_input(format!("Unknown backend {:?}, use files or sqlite", value))),
+		}
+	}
+}
+
+#[derive(Default, Clone, Debug, PartialEq, Eq)]
+pub struct DataLocation {
+	pub data_directory: Option<PathBuf>,
+	pub profile: Option<String>,
+	pub backend: Backend,
+}
+
+fn invalid_input(message: String) -> std::io::Error {
+	std::io::Error::new(std::io::ErrorKind::InvalidInput, message)
+}
+
+fn non_empty(value: Option<String>) -> Option<String> {
+	value.filter(|value| !value.is_empty())
+}
+
+pub fn default_data_directory() -> PathBuf {
+	ProjectDirs::from("org", "Hallock", "Journaler")
+		.unwrap()
+		.data_dir()
+		.to_path_buf()
+}
+
+impl DataLocation {
+	pub fn from_env() -> Self {
+		DataLocation {
+			data_directory: non_empty(std::env::var(DATA_DIR_VARIABLE).ok()).map(PathBuf::from),
+			profile: non_empty(std::env::var(PROFILE_VARIABLE).ok()),
+			backend: Backend::Files,
+		}
+	}
+
+	// Takes `--data-dir <path>`, `--profile <name>` and `--backend <files|sqlite>`, also
+	// written as `--flag=value`, on top of the environment. Other arguments are left for whoever else reads them.
+	pub fn from_args(args: impl Iterator<Item = String>) -> Result<Self, std::io::Error> {
+		let mut location = Self::from_env();
+		let mut args = args;
+		while let Some(arg) = args.next() {
+			let (flag, inline_value) = match arg.split_once('=') {
+				Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+				None => (arg, None),
+			};
+			if flag != DATA_DIR_FLAG && flag != PROFILE_FLAG && flag != BACKEND_FLAG {
+				continue;
+			}
+			let value = non_empty(inline_value.or_else(|| args.next()))
+				.ok_or_else(|| invalid_input(format!("{} needs a value", flag)))?;
+			if flag == DATA_DIR_FLAG {
+				location.data_directory = Some(PathBuf::from(value));
+			} else if flag == PROFILE_FLAG {
+				location.profile = Some(value);
+			} else {
+				location.backend = value.parse()?;
+			}
+		}
+		Ok(location)
+	}
+
+	// The profile's own directory, it is created when something is first saved
+	pub fn root_directory(&self) -> Result<PathBuf, std::io::Error> {
+		let data_directory = self.data_directory.clone().unwrap_or_else(default_data_directory);
+		match &self.profile {
+			None => Ok(data_directory),
+			Some(profile) => {
+				// A profile names a directory of its own, never a path leading elsewhere
+				if profile == "." || profile == ".." || profile.contains(['/', '\\']) {
+					return Err(invalid_input(format!("Invalid profile name: {:?}", profile)));
+				}
+				Ok(data_directory.join("profiles").join(profile))
+			}
+		}
+	}
+
+	pub fn projects_directory(&self) -> Result<PathBuf, std::io::Error> {
+		Ok(self.root_directory()?.join("projects"))
+	}
+
+	pub fn database_path(&self) -> Result<PathBuf, std::io::Error> {
+		Ok(self.root_directory()?.join("journal.sqlite"))
+	}
+
+	// The profiles that already have a directory
+	pub fn list_profiles(&self) -> Result<Vec<String>, std::io::Error> {
+		let profiles_directory = self
+			.data_directory
+			.clone()
+			.unwrap_or_else(default_data_directory)
+			.join("profiles");
+		if !profiles_directory.exists() {
+			return Ok(vec![]);
+		}
+		let mut profiles = vec![];
+		for entry in std::fs::read_dir(profiles_directory)? {
+			let entry = entry?;
+			if entry.path().is_dir() {
+				profiles.push(entry.file_name().to_string_lossy().to_string());
+			}
+		}
+		profiles.sort();
+		Ok(profiles)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::sync::Mutex;
+
+	// The environment is shared by every test in the process
+	static ENVIRONMENT: Mutex<()> = Mutex::new(());
+
+	fn args(args: &[&str]) -> impl Iterator<Item = String> {
+		args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>().into_iter()
+	}
+
+	fn with_environment(data_directory: Option<&str>, profile: Option<&str>, test: impl FnOnce()) {
+		let _guard = ENVIRONMENT.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+		for (variable, value) in [(DATA_DIR_VARIABLE, data_directory), (PROFILE_VARIABLE, profile)] {
+			match value {
+				Some(value) => std::env::set_var(variable, value),
+				None => std::env::remove_var(variable),
+			}
+		}
+		test();
+		std::env::remove_var(DATA_DIR_VARIABLE);
+		std::env::remove_var(PROFILE_VARIABLE);
+	}
+
+	#[test]
+	fn flags_override_the_environment() {
+		with_environment(Some("/from/environment"), Some("environment"), || {
+			let location = DataLocation::from_args(args(&[])).unwrap();
+			assert_eq!(location.data_directory, Some(PathBuf::from("/from/environment")));
+			assert_eq!(location.profile.as_deref(), Some("environment"));
+
+			let location =
+				DataLocation::from_args(args(&["--data-dir", "/from/flag", "--profile=flag", "--other"])).unwrap();
+			assert_eq!(location.data_directory, Some(PathBuf::from("/from/flag")));
+			assert_eq!(location.profile.as_deref(), Some("flag"));
+			assert_eq!(location.root_directory().unwrap(), PathBuf::from("/from/flag/profiles/flag"));
+		});
+	}
+
+	#[test]
+	fn without_flags_or_environment_the_platform_directory_is_used() {
+		with_environment(None, None, || {
+			let location = DataLocation::from_args(args(&["--backend", "sqlite"])).unwrap();
+			assert_eq!(location.data_directory, None);
+			assert_eq!(location.profile, None);
+			assert_eq!(location.root_directory().unwrap(), default_data_directory());
+			assert_eq!(location.database_path().unwrap(), default_data_directory().join("journal.sqlite"));
+		});
+		// An empty variable counts as unset
+		with_environment(Some(""), Some(""), || {
+			assert_eq!(DataLocation::from_env(), DataLocation::default());
+		});
+	}
+
+	#[test]
+	fn a_flag_without_a_value_is_an_error() {
+		with_environment(None, None, || {
+			assert!(DataLocation::from_args(args(&["--profile"])).is_err());
+			assert!(DataLocation::from_args(args(&["--data-dir="])).is_err());
+			assert!(DataLocation::from_args(args(&["--backend", "postgres"])).is_err());
+		});
+	}
+
+	#[test]
+	fn a_profile_cannot_lead_out_of_the_profiles_directory() {
+		for profile in ["../x", "..", ".", "a/b", "a\\b"] {
+			let location = DataLocation {
+				data_directory: Some(PathBuf::from("/data")),
+				profile: Some(profile.to_string()),
+				..Default::default()
+			};
+			let err = location.root_directory().err().unwrap();
+			assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput, "{}", profile);
+			assert!(location.projects_directory().is_err());
+		}
+	}
+}
@@ -1,5 +1,7 @@
 
 use chrono::DateTime;
+use chrono::NaiveDate;
+use chrono::NaiveTime;
 use chrono::Utc;
 use std::collections::HashSet;
 use uuid::Uuid;
@@ -40,12 +42,57 @@ pub struct EnumeratedTemplate {
 	pub options: Vec<views::EnumerationOption>,
 }
 
+#[derive(Clone, Default, serde::Deserialize, serde::Serialize)]
+pub struct DateTimeTemplate {
+	// The field starts out at the time the event is created
+	pub default_now: bool,
+	// A fixed default, when it is not now
+	#[serde(default)]
+	pub default_value: Option<DateTime<Utc>>,
+}
+
+#[derive(Clone, Default, serde::Deserialize, serde::Serialize)]
+pub struct DateTemplate {
+	pub default_today: bool,
+	#[serde(default)]
+	pub default_value: Option<NaiveDate>,
+}
+
+#[derive(Clone, serde::Deserialize, serde::Serialize)]
+pub struct TimeOfDayTemplate {
+	pub default_value: Option<NaiveTime>,
+}
+
+#[derive(Clone, serde::Deserialize, serde::Serialize)]
+pub struct DurationTemplate {
+	// Whole seconds
+	pub default_value: Option<i64>,
+}
+
+// A field holding any number of rows of the same kind. Rows start out empty.
+#[derive(Clone, serde::Deserialize, serde::Serialize)]
+pub struct ArrayTemplate {
+	pub item: Box<FieldValueTemplate>,
+}
+
+// A section of fields. Its fields are addressed as the group's name, a dot and their name.
+#[derive(Clone, serde::Deserialize, serde::Serialize)]
+pub struct GroupTemplate {
+	pub fields: Vec<FieldTemplate>,
+}
+
 #[derive(Clone, serde::Deserialize, serde::Serialize)]
 pub enum FieldValueTemplate {
 	Number(NumberTemplate),
 	Text(TextTemplate),
 	Bool(BoolTemplate),
 	Enumerated(EnumeratedTemplate),
+	Array(ArrayTemplate),
+	Group(GroupTemplate),
+	DateTime(DateTimeTemplate),
+	Date(DateTemplate),
+	TimeOfDay(TimeOfDayTemplate),
+	Duration(DurationTemplate),
 }
 
 #[derive(Clone, serde::Deserialize, serde::Serialize)]
@@ -53,6 +100,8 @@ pub struct FieldTemplate {
 	pub name: String,
 	pub label: String,
 	pub value: FieldValueTemplate,
+	#[serde(default)]
+	pub value_policy: service::ValuePolicy,
 }
 
 #[derive(Clone, serde::Deserialize, serde::Serialize)]
@@ -61,6 +110,9 @@ pub struct EventTemplate {
 	pub event_template_uuid: Uuid,
 	pub trace_template_uuid: Uuid,
 	pub name: String,
+	// Stable name other templates can refer to this one by, also the seed of a generated uuid
+	#[serde(default)]
+	pub slug: String,
 	pub fields: Vec<FieldTemplate>,
 	pub default_tags: Vec<String>,
 	pub created_at: DateTime<Utc>,
@@ -77,42 +129,163 @@ pub struct TraceTemplate {
 	pub trace_template_uuid: Uuid,
 	pub project_uuid: Uuid,
 	pub name: String,
+	#[serde(default)]
+	pub slug: String,
 	pub created_at: DateTime<Utc>,
 	pub flow: Vec<TraceFlowEntry>,
 }
 
+// Rewrites events recorded against an older version of a definition
+#[derive(Clone, serde::Deserialize, serde::Serialize)]
+pub enum MigrationStep {
+	RenameField {
+		event_template_uuid: Uuid,
+		from: String,
+		to: String,
+	},
+	RemoveField {
+		event_template_uuid: Uuid,
+		field: String,
+	},
+	RenameOption {
+		event_template_uuid: Uuid,
+		field: String,
+		from: String,
+		to: String,
+	},
+	MoveEvents {
+		from: Uuid,
+		to: Uuid,
+	},
+}
+
+#[derive(Clone, serde::Deserialize, serde::Serialize)]
+pub struct Migration {
+	// The definition version the step belongs to, it runs when a project moves past it
+	pub version: u32,
+	pub step: MigrationStep,
+}
+
 impl FieldValueTemplate {
+	fn has_default(&self) -> bool {
+		match self {
+			FieldValueTemplate::Number(template) => template.default_value.is_some(),
+			FieldValueTemplate::Text(template) => template.default_value.is_some(),
+			FieldValueTemplate::Bool(template) => template.default_value.is_some(),
+			FieldValueTemplate::Enumerated(template) => template.default_value.is_some(),
+			FieldValueTemplate::Array(_) | FieldValueTemplate::Group(_) => false,
+			FieldValueTemplate::DateTime(template) => template.default_now || template.default_value.is_some(),
+			FieldValueTemplate::Date(template) => template.default_today || template.default_value.is_some(),
+			FieldValueTemplate::TimeOfDay(template) => template.default_value.is_some(),
+			FieldValueTemplate::Duration(template) => template.default_value.is_some(),
+		}
+	}
+
+	// The fields of a group, or of an array of groups
+	pub fn fields(&self) -> Option<&Vec<FieldTemplate>> {
+		match self {
+			FieldValueTemplate::Group(template) => Some(&template.fields),
+			FieldValueTemplate::Array(template) => template.item.fields(),
+			_ => None,
+		}
+	}
+
+	// The options of an enumerated field, or of an array of them
+	pub fn enumerated(&self) -> Option<&EnumeratedTemplate> {
+		match self {
+			FieldValueTemplate::Enumerated(template) => Some(template),
+			FieldValueTemplate::Array(template) => template.item.enumerated(),
+			_ => None,
+		}
+	}
+
+	// Whether events recorded against one fit the other, whatever their defaults and options
+	pub fn same_type(&self, other: &FieldValueTemplate) -> bool {
+		match (self, other) {
+			(FieldValueTemplate::Array(template), FieldValueTemplate::Array(other)) => {
+				template.item.same_type(&other.item)
+			}
+			_ => std::mem::discriminant(self) == std::mem::discriminant(other),
+		}
+	}
+
 	fn get_initial(&self) -> service::FieldValueSuggestion {
 		match self {
 			// TODO move these into their own classes
 			FieldValueTemplate::Number(template) => {
 				service::FieldValueSuggestion::Number(service::NumberSuggestion {
 					value: template.default_value,
-					last_values: Vec::new(), // TODO
+					last_values: Vec::new(),
 					default_value: template.default_value,
 				})
 			}
 			FieldValueTemplate::Text(template) => {
 				service::FieldValueSuggestion::Text(service::TextSuggestion {
 					value: template.default_value.clone(),
-					last_values: Vec::new(), // TODO
+					last_values: Vec::new(),
 					default_value: template.default_value.clone(),
 				})
 			}
 			FieldValueTemplate::Bool(template) => {
 				service::FieldValueSuggestion::Bool(service::BoolSuggestion {
 					value: template.default_value,
-					last_values: Vec::new(), // TODO
+					last_values: Vec::new(),
 					default_value: template.default_value,
 				})
 			}
 			FieldValueTemplate::Enumerated(template) => {
 				service::FieldValueSuggestion::Enumerated(service::EnumeratedSuggestion {
 					selected: template.default_value.clone(),
-					last_values: Vec::new(), // TODO
+					last_values: Vec::new(),
 					options: template.options.clone(),
 				})
 			}
+			FieldValueTemplate::Array(template) => {
+				service::FieldValueSuggestion::Array(service::ArraySuggestion {
+					items: Vec::new(),
+					last_values: Vec::new(),
+					new_item: Box::new(template.item.get_initial()),
+				})
+			}
+			FieldValueTemplate::Group(template) => {
+				service::FieldValueSuggestion::Group(service::GroupSuggestion {
+					fields: template.fields.iter().map(|field| field.get_initial()).collect(),
+				})
+			}
+			FieldValueTemplate::DateTime(template) => {
+				let default_value = if template.default_now { Some(Utc::now()) } else { template.default_value };
+				service::FieldValueSuggestion::DateTime(service::DateTimeSuggestion {
+					value: default_value,
+					last_values: Vec::new(),
+					default_value,
+				})
+			}
+			FieldValueTemplate::Date(template) => {
+				let default_value = if template.default_today {
+					Some(chrono::Local::now().date_naive())
+				} else {
+					template.default_value
+				};
+				service::FieldValueSuggestion::Date(service::DateSuggestion {
+					value: default_value,
+					last_values: Vec::new(),
+					default_value,
+				})
+			}
+			FieldValueTemplate::TimeOfDay(template) => {
+				service::FieldValueSuggestion::TimeOfDay(service::TimeOfDaySuggestion {
+					value: template.default_value,
+					last_values: Vec::new(),
+					default_value: template.default_value,
+				})
+			}
+			FieldValueTemplate::Duration(template) => {
+				service::FieldValueSuggestion::Duration(service::DurationSuggestion {
+					value: template.default_value,
+					last_values: Vec::new(),
+					default_value: template.default_value,
+				})
+			}
 		}
 	}
 }
@@ -123,12 +296,50 @@ impl FieldTemplate {
 			name: self.name.clone(),
 			label: self.label.clone(),
 			value: self.value.get_initial(),
+			source: self.value.has_default().then_some(service::ValuePolicy::TemplateDefault),
 		}
 	}
 }
 
+// Also into arrays of groups, where the field is in every row
+pub fn find_field_template<'a>(fields: &'a [FieldTemplate], path: &str) -> Option<&'a FieldTemplate> {
+	match path.split_once('.') {
+		None => fields.iter().find(|field| field.name == path),
+		Some((group, rest)) => {
+			find_field_template(fields.iter().find(|field| field.name == group)?.value.fields()?, rest)
+		}
+	}
+}
+
+// Every field by its dotted path, groups before their fields
+pub fn field_paths<'a>(prefix: &str, fields: &'a [FieldTemplate]) -> Vec<(String, &'a FieldTemplate)> {
+	let mut paths = Vec::new();
+	for field in fields {
+		let path = field_path(prefix, &field.name);
+		if let Some(group_fields) = field.value.fields() {
+			paths.push((path.clone(), field));
+			paths.extend(field_paths(&path, group_fields));
+		} else {
+			paths.push((path, field));
+		}
+	}
+	paths
+}
+
+pub fn field_path(prefix: &str, name: &str) -> String {
+	if prefix.is_empty() {
+		name.to_string()
+	} else {
+		format!("{}.{}", prefix, name)
+	}
+}
+
 impl EventTemplate {
-	fn get_initial_fields(&self) -> Vec<service::FieldSuggestion> {
+	pub fn field(&self, path: &str) -> Option<&FieldTemplate> {
+		find_field_template(&self.fields, path)
+	}
+
+	pub fn get_initial_fields(&self) -> Vec<service::FieldSuggestion> {
 		self.fields
 			.iter()
 			.map(|field| field.get_initial())
@@ -151,6 +362,7 @@ impl EventTemplate {
 			traces: Vec::new(),
 			began_at: Utc::now(),
 			selected_trace: service::TraceSelection::Selected(trace.into()),
+			editing: false,
 		}
 	}
 
@@ -0,0 +1,1142 @@
+use crate::definition;
+use crate::fs_service;
+use crate::source_map;
+
+use chrono::Utc;
+use journal_lib::errors;
+use journal_lib::service;
+use journal_lib::views;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use source_map::child_index;
+use source_map::child_key;
+
+pub fn map_name(name: &str) -> String {
+	name.to_string().replace(" ", "-").to_lowercase()
+}
+
+// Hours and minutes, optionally seconds
+pub fn parse_time_of_day(text: &str) -> Option<chrono::NaiveTime> {
+	chrono::NaiveTime::parse_from_str(text.trim(), "%H:%M")
+		.or_else(|_| chrono::NaiveTime::parse_from_str(text.trim(), "%H:%M:%S"))
+		.ok()
+}
+
+// In whole seconds. Written with units like 4h30m, 90m or 1d 2h, or as hours and minutes like
+// 4:30, optionally with seconds.
+pub fn parse_duration(text: &str) -> Option<i64> {
+	let text = text.trim();
+	if text.contains(':') {
+		let parts: Vec<i64> = text
+			.split(':')
+			.map(|part| part.parse::<u32>().ok().map(i64::from))
+			.collect::<Option<_>>()?;
+		return match parts[..] {
+			[hours, minutes] if minutes < 60 => hours.checked_mul(3600)?.checked_add(minutes * 60),
+			[hours, minutes, seconds] if minutes < 60 && seconds < 60 => {
+				hours.checked_mul(3600)?.checked_add(minutes * 60 + seconds)
+			}
+			_ => None,
+		};
+	}
+	let mut seconds: i64 = 0;
+	let mut amount = String::new();
+	for c in text.chars() {
+		if c.is_ascii_digit() {
+			amount.push(c);
+			continue;
+		}
+		if c.is_whitespace() {
+			continue;
+		}
+		let unit = match c {
+			'd' => 86400,
+			'h' => 3600,
+			'm' => 60,
+			's' => 1,
+			_ => return None,
+		};
+		seconds = amount.parse::<i64>().ok()?.checked_mul(unit).and_then(|part| seconds.checked_add(part))?;
+		amount.clear();
+	}
+	// A number needs its unit
+	(amount.is_empty() && !text.is_empty()).then_some(seconds)
+}
+
+// "now", or a time with its offset like 2024-03-01T08:00:00Z
+fn parse_date_time_default(text: &str) -> Option<definition::DateTimeTemplate> {
+	let text = text.trim();
+	if text == "now" {
+		return Some(definition::DateTimeTemplate { default_now: true, default_value: None });
+	}
+	let value = chrono::DateTime::parse_from_rfc3339(text).ok()?;
+	Some(definition::DateTimeTemplate { default_now: false, default_value: Some(value.with_timezone(&Utc)) })
+}
+
+// "today", or a date like 2024-03-01
+fn parse_date_default(text: &str) -> Option<definition::DateTemplate> {
+	let text = text.trim();
+	if text == "today" {
+		return Some(definition::DateTemplate { default_today: true, default_value: None });
+	}
+	let value = chrono::NaiveDate::parse_from_str(text, "%Y-%m-%d").ok()?;
+	Some(definition::DateTemplate { default_today: false, default_value: Some(value) })
+}
+
+const UNNAMED_EVENT_TEMPLATE: &str = "Unnamed Event Template";
+const UNNAMED_TRACE_TEMPLATE: &str = "Unnamed Trace Template";
+
+// Templates without an explicit uuid get one derived from the project and their slug,
+// so importing the same file again gives the same uuids.
+pub fn derive_event_template_uuid(project_uuid: &Uuid, slug: &str) -> Uuid {
+	Uuid::new_v5(project_uuid, format!("event-template/{}", slug).as_bytes())
+}
+
+pub fn derive_trace_template_uuid(project_uuid: &Uuid, slug: &str) -> Uuid {
+	Uuid::new_v5(project_uuid, format!("trace-template/{}", slug).as_bytes())
+}
+
+pub struct ParsedDefinition {
+	pub project_definition: fs_service::ProjectDefinition,
+	// Kept so that later checks can point into the file as well
+	pub source_map: source_map::SourceMap,
+}
+
+// Parses the authoring format of a definition (kebab-case keys, in JSON or YAML).
+// Errors do not stop the parse: everything wrong with the file is reported at once.
+// A project uuid given here replaces the one in the file, templates without a uuid of their
+// own then get theirs from it.
+pub fn parse_definition(
+	contents: &str,
+	format: service::DefinitionFormat,
+	project_uuid: Option<Uuid>,
+) -> Result<ParsedDefinition, errors::DefinitionErrors> {
+	let definition_js: serde_json::Value = match format {
+		service::DefinitionFormat::Json => serde_json::from_str(contents).map_err(errors::ParsingError::from),
+		service::DefinitionFormat::Yaml => serde_yaml::from_str(contents).map_err(errors::ParsingError::from),
+	}?;
+
+	let mut parser = DefinitionParser {
+		source_map: source_map::SourceMap::new(contents),
+		project_uuid,
+		template_name: None,
+		event_template_slugs: HashMap::new(),
+		trace_template_slugs: HashMap::new(),
+		errors: vec![],
+	};
+	let project_definition = parser.parse_project_definition(&definition_js);
+	match project_definition {
+		Some(project_definition) if parser.errors.is_empty() => Ok(ParsedDefinition {
+			project_definition,
+			source_map: parser.source_map,
+		}),
+		_ => Err(errors::DefinitionErrors {
+			errors: parser.errors,
+			warnings: vec![],
+		}),
+	}
+}
+
+// Reads a definition file, the format follows from its extension
+pub fn read_definition(
+	definition_path: &std::path::Path,
+	project_uuid: Option<Uuid>,
+) -> Result<ParsedDefinition, errors::DefinitionErrors> {
+	let contents = std::fs::read_to_string(definition_path).map_err(errors::ParsingError::from)?;
+	parse_definition(&contents, service::DefinitionFormat::from_path(definition_path), project_uuid)
+}
+
+pub struct DefinitionParser {
+	source_map: source_map::SourceMap,
+	// Replaces the project uuid of the file
+	project_uuid: Option<Uuid>,
+	// Name of the template being parsed, attached to every error found inside it
+	template_name: Option<String>,
+	// Slug -> uuid of every template in the file, so references can be resolved before the
+	// template they point at has been parsed
+	event_template_slugs: HashMap<String, Uuid>,
+	trace_template_slugs: HashMap<String, Uuid>,
+	errors: Vec<errors::ParsingError>,
+}
+
+impl DefinitionParser {
+	fn error(&self, path: &str, message: String) -> errors::ParsingError {
+		let position = self.source_map.locate(path);
+		errors::ParsingError {
+			message,
+			path: Some(path.to_string()).filter(|path| !path.is_empty()),
+			line: position.map(|(line, _)| line),
+			column: position.map(|(_, column)| column),
+			template_name: self.template_name.clone(),
+		}
+	}
+
+	fn record<T>(&mut self, result: Result<T, errors::ParsingError>) -> Option<T> {
+		match result {
+			Ok(value) => Some(value),
+			Err(error) => {
+				self.errors.push(error);
+				None
+			}
+		}
+	}
+
+	fn expect_object<'v>(
+		&self,
+		val: &'v serde_json::Value,
+		path: &str,
+	) -> Result<&'v serde_json::Map<String, serde_json::Value>, errors::ParsingError> {
+		val.as_object()
+			.ok_or_else(|| self.error(path, "Expected object".into()))
+	}
+
+	fn expect_array<'v>(
+		&self,
+		val: &'v serde_json::Value,
+		path: &str,
+	) -> Result<&'v Vec<serde_json::Value>, errors::ParsingError> {
+		val.as_array()
+			.ok_or_else(|| self.error(path, "Expected array".into()))
+	}
+
+	fn parse_uuid(&self, val: &serde_json::Value, path: &str) -> Result<Uuid, errors::ParsingError> {
+		Uuid::parse_str(
+			val.as_str()
+				.ok_or_else(|| self.error(path, "Expected a UUID string".into()))?,
+		)
+		.map_err(|e| self.error(path, format!("Failed to parse UUID: {}", e)))
+	}
+
+	fn parse_required_uuid(
+		&self,
+		val: &serde_json::Value,
+		path: &str,
+		field: &str,
+	) -> Result<Uuid, errors::ParsingError> {
+		let field_path = child_key(path, field);
+		self.parse_uuid(
+			self.expect_object(val, path)?
+				.get(field)
+				.ok_or_else(|| self.error(&field_path, format!("Field {} is required", field)))?,
+			&field_path,
+		)
+	}
+
+	fn parse_optional_uuid(
+		&self,
+		val: &serde_json::Value,
+		path: &str,
+		field: &str,
+		default_uuid: Uuid,
+	) -> Result<Uuid, errors::ParsingError> {
+		self.expect_object(val, path)?
+			.get(field)
+			.map_or(Ok(default_uuid), |uuid| {
+				self.parse_uuid(uuid, &child_key(path, field))
+			})
+	}
+
+	fn parse_slug(
+		&self,
+		val: &serde_json::Value,
+		path: &str,
+		unnamed: &str,
+	) -> Result<String, errors::ParsingError> {
+		Ok(match self.parse_str(val, path, "slug")? {
+			Some(slug) => slug,
+			None => map_name(&self.parse_str(val, path, "name")?.unwrap_or(unnamed.to_string())),
+		})
+	}
+
+	// A reference is either a uuid or the slug of a template defined in the same file.
+	fn resolve_reference(
+		&self,
+		val: &serde_json::Value,
+		path: &str,
+		slugs: &HashMap<String, Uuid>,
+		kind: &str,
+	) -> Result<Uuid, errors::ParsingError> {
+		let reference = val
+			.as_str()
+			.ok_or_else(|| self.error(path, format!("Expected a {} slug or uuid", kind)))?;
+		match slugs.get(reference) {
+			Some(uuid) => Ok(*uuid),
+			None => Uuid::parse_str(reference)
+				.map_err(|_| self.error(path, format!("Unknown {} '{}'", kind, reference))),
+		}
+	}
+
+	// Looks for "<field>-uuid" first, then "<field>" holding a slug or uuid.
+	fn parse_template_reference(
+		&self,
+		val: &serde_json::Value,
+		path: &str,
+		field: &str,
+		slugs: &HashMap<String, Uuid>,
+		kind: &str,
+	) -> Result<Uuid, errors::ParsingError> {
+		let obj = self.expect_object(val, path)?;
+		let uuid_field = format!("{}-uuid", field);
+		if let Some(uuid) = obj.get(&uuid_field) {
+			return self.parse_uuid(uuid, &child_key(path, &uuid_field));
+		}
+		let reference = obj.get(field).ok_or_else(|| {
+			self.error(
+				&child_key(path, &uuid_field),
+				format!("Field {} or {} is required", uuid_field, field),
+			)
+		})?;
+		self.resolve_reference(reference, &child_key(path, field), slugs, kind)
+	}
+
+	fn collect_slugs(
+		&mut self,
+		val: &serde_json::Value,
+		path: &str,
+		unnamed: &str,
+		derive_uuid: impl Fn(&str) -> Uuid,
+		uuid_field: &str,
+	) -> HashMap<String, Uuid> {
+		let mut slugs = HashMap::new();
+		// Shape errors are reported when the templates themselves are parsed
+		for (index, template) in val.as_array().into_iter().flatten().enumerate() {
+			let template_path = child_index(path, index);
+			let Ok(slug) = self.parse_slug(template, &template_path, unnamed) else {
+				continue;
+			};
+			let Ok(uuid) = self.parse_optional_uuid(template, &template_path, uuid_field, derive_uuid(&slug)) else {
+				continue;
+			};
+			if slugs.insert(slug.clone(), uuid).is_some() {
+				let error = self.error(
+					&template_path,
+					format!("Another template already uses the slug '{}', give one of them a 'slug'", slug),
+				);
+				self.errors.push(error);
+			}
+		}
+		slugs
+	}
+
+	fn parse_str(
+		&self,
+		val: &serde_json::Value,
+		path: &str,
+		field: &str,
+	) -> Result<Option<String>, errors::ParsingError> {
+		self.expect_object(val, path)?
+			.get(field)
+			.map(|val| {
+				val.as_str().map(|val| val.to_string()).ok_or_else(|| {
+					self.error(
+						&child_key(path, field),
+						format!("Expected '{}' to be a string", field),
+					)
+				})
+			})
+			.transpose()
+	}
+
+	fn parse_required_str(
+		&self,
+		val: &serde_json::Value,
+		path: &str,
+		field: &str,
+	) -> Result<String, errors::ParsingError> {
+		self.parse_str(val, path, field)?.ok_or_else(|| {
+			self.error(&child_key(path, field), format!("Field {} is required", field))
+		})
+	}
+
+	fn parse_enumeration_option(
+		&self,
+		val: &serde_json::Value,
+		path: &str,
+	) -> Result<views::EnumerationOption, errors::ParsingError> {
+		let label = self.parse_required_str(val, path, "label")?;
+		let name = self
+			.parse_str(val, path, "name")?
+			.unwrap_or(map_name(&label));
+		Ok(views::EnumerationOption { name, label })
+	}
+
+	fn parse_enumerated_template(
+		&mut self,
+		val: &serde_json::Value,
+		path: &str,
+	) -> Option<definition::EnumeratedTemplate> {
+		let obj = self.record(self.expect_object(val, path))?;
+		let options_path = child_key(path, "options");
+		let options_val = self.record(
+			obj.get("options")
+				.ok_or_else(|| self.error(&options_path, "Expected 'options' field".into())),
+		)?;
+		let options = self
+			.record(self.expect_array(options_val, &options_path))?
+			.iter()
+			.enumerate()
+			.map(|(index, option)| {
+				self.parse_enumeration_option(option, &child_index(&options_path, index))
+			})
+			.collect::<Vec<_>>()
+			.into_iter()
+			.flat_map(|option| self.record(option))
+			.collect::<Vec<views::EnumerationOption>>();
+
+		let default_path = child_key(path, "default-value");
+		let default_value = obj
+			.get("default-value")
+			.map(|val| {
+				let name = val
+					.as_str()
+					.ok_or_else(|| {
+						self.error(&default_path, "Expected 'default-value' to be a string".into())
+					})?
+					.to_string();
+
+				options
+					.iter()
+					.find(|option| option.name == name || option.label == name)
+					.cloned()
+					.ok_or_else(|| {
+						self.error(
+							&default_path,
+							format!("Default value {} not found in options", name),
+						)
+					})
+			})
+			.transpose();
+		let default_value = self.record(default_value)?;
+
+		Some(definition::EnumeratedTemplate {
+			default_value,
+			options,
+		})
+	}
+
+	// The item is written like a field without a label
+	fn parse_array_template(
+		&mut self,
+		val: &serde_json::Value,
+		path: &str,
+	) -> Option<definition::ArrayTemplate> {
+		let obj = self.record(self.expect_object(val, path))?;
+		let item_path = child_key(path, "item");
+		let item = self.record(
+			obj.get("item")
+				.ok_or_else(|| self.error(&item_path, "Expected 'item' field".into())),
+		)?;
+		self.record(self.expect_object(item, &item_path))?;
+		let item_type = self.record(self.parse_required_str(item, &item_path, "type"))?;
+		let item = self.parse_field_value_template(item, &item_path, &item_type)?;
+		Some(definition::ArrayTemplate { item: Box::new(item) })
+	}
+
+	fn parse_group_template(
+		&mut self,
+		val: &serde_json::Value,
+		path: &str,
+	) -> Option<definition::GroupTemplate> {
+		let obj = self.record(self.expect_object(val, path))?;
+		let fields_path = child_key(path, "fields");
+		let fields = self.record(
+			obj.get("fields")
+				.ok_or_else(|| self.error(&fields_path, "Expected 'fields' field".into())),
+		)?;
+		let fields = self.parse_field_templates(fields, &fields_path)?;
+		Some(definition::GroupTemplate { fields })
+	}
+
+	fn parse_default_value<T>(
+		&self,
+		val: &serde_json::Value,
+		path: &str,
+		convert: impl Fn(&serde_json::Value) -> Option<T>,
+		expected: &str,
+	) -> Result<Option<T>, errors::ParsingError> {
+		val.get("default-value")
+			.map(|default_value| {
+				convert(default_value).ok_or_else(|| {
+					self.error(
+						&child_key(path, "default-value"),
+						format!("Expected {}, found: {}", expected, default_value),
+					)
+				})
+			})
+			.transpose()
+	}
+
+	fn parse_field_value_template(
+		&mut self,
+		val: &serde_json::Value,
+		path: &str,
+		field_type: &str,
+	) -> Option<definition::FieldValueTemplate> {
+		match field_type {
+			"Number" => self
+				.record(self.parse_default_value(val, path, |val| val.as_f64(), "a number"))
+				.map(|default_value| {
+					definition::FieldValueTemplate::Number(definition::NumberTemplate {
+						default_value,
+					})
+				}),
+			"Text" => self
+				.record(self.parse_default_value(
+					val,
+					path,
+					|val| val.as_str().map(|val| val.to_string()),
+					"a string",
+				))
+				.map(|default_value| {
+					definition::FieldValueTemplate::Text(definition::TextTemplate { default_value })
+				}),
+			"Boolean" => self
+				.record(self.parse_default_value(val, path, |val| val.as_bool(), "a boolean"))
+				.map(|default_value| {
+					definition::FieldValueTemplate::Bool(definition::BoolTemplate { default_value })
+				}),
+			"Enumerated" => self
+				.parse_enumerated_template(val, path)
+				.map(definition::FieldValueTemplate::Enumerated),
+			"Array" => self
+				.parse_array_template(val, path)
+				.map(definition::FieldValueTemplate::Array),
+			"Group" => self
+				.parse_group_template(val, path)
+				.map(definition::FieldValueTemplate::Group),
+			"DateTime" => self
+				.record(self.parse_default_value(
+					val,
+					path,
+					|val| val.as_str().and_then(parse_date_time_default),
+					"\"now\" or a time like 2024-03-01T08:00:00Z",
+				))
+				.map(|template| definition::FieldValueTemplate::DateTime(template.unwrap_or_default())),
+			"Date" => self
+				.record(self.parse_default_value(
+					val,
+					path,
+					|val| val.as_str().and_then(parse_date_default),
+					"\"today\" or a date like 2024-03-01",
+				))
+				.map(|template| definition::FieldValueTemplate::Date(template.unwrap_or_default())),
+			"TimeOfDay" => self
+				.record(self.parse_default_value(
+					val,
+					path,
+					|val| val.as_str().and_then(parse_time_of_day),
+					"a time of day like 07:30",
+				))
+				.map(|default_value| {
+					definition::FieldValueTemplate::TimeOfDay(definition::TimeOfDayTemplate { default_value })
+				}),
+			"Duration" => self
+				.record(self.parse_default_value(
+					val,
+					path,
+					|val| val.as_str().and_then(parse_duration),
+					"a duration like 4h30m or 4:30",
+				))
+				.map(|default_value| {
+					definition::FieldValueTemplate::Duration(definition::DurationTemplate { default_value })
+				}),
+			_ => {
+				let error = self.error(
+					&child_key(path, "type"),
+					format!("Unknown field type: {}", field_type),
+				);
+				self.record(Err(error))
+			}
+		}
+	}
+
+	fn parse_value_policy(
+		&self,
+		val: &serde_json::Value,
+		path: &str,
+	) -> Result<service::ValuePolicy, errors::ParsingError> {
+		let Some(name) = self.parse_str(val, path, "value-policy")? else {
+			return Ok(service::ValuePolicy::default());
+		};
+		service::ValuePolicy::from_name(&name).ok_or_else(|| {
+			let names: Vec<&str> = service::ValuePolicy::ALL.iter().map(|policy| policy.name()).collect();
+			self.error(
+				&child_key(path, "value-policy"),
+				format!("Unknown value policy: {}, expected one of {}", name, names.join(", ")),
+			)
+		})
+	}
+
+	fn parse_field_template(
+		&mut self,
+		val: &serde_json::Value,
+		path: &str,
+	) -> Option<definition::FieldTemplate> {
+		self.record(self.expect_object(val, path))?;
+		let field_type = self.record(self.parse_required_str(val, path, "type"));
+		let label = self.record(self.parse_required_str(val, path, "label"));
+		let name = self.record(self.parse_str(val, path, "name"));
+		let value_policy = self.record(self.parse_value_policy(val, path));
+		let value = field_type.and_then(|field_type| {
+			self.parse_field_value_template(val, path, &field_type)
+		});
+
+		let label = label?;
+		Some(definition::FieldTemplate {
+			name: name?.unwrap_or(map_name(&label)),
+			label,
+			value: value?,
+			value_policy: value_policy?,
+		})
+	}
+
+	fn parse_field_templates(
+		&mut self,
+		val: &serde_json::Value,
+		path: &str,
+	) -> Option<Vec<definition::FieldTemplate>> {
+		let fields = self.record(self.expect_array(val, path))?;
+		Some(
+			fields
+				.iter()
+				.enumerate()
+				.flat_map(|(index, field)| {
+					self.parse_field_template(field, &child_index(path, index))
+				})
+				.collect(),
+		)
+	}
+
+	fn parse_event_template(
+		&mut self,
+		val: &serde_json::Value,
+		path: &str,
+		project_uuid: Uuid,
+	) -> Option<definition::EventTemplate> {
+		let obj = self.record(self.expect_object(val, path))?;
+		let name = self.record(self.parse_str(val, path, "name")).flatten();
+		self.template_name = name.clone();
+
+		let slug = self.record(self.parse_slug(val, path, UNNAMED_EVENT_TEMPLATE));
+		let event_template_uuid = slug.as_ref().and_then(|slug| {
+			self.record(self.parse_optional_uuid(
+				val,
+				path,
+				"event-template-uuid",
+				derive_event_template_uuid(&project_uuid, slug),
+			))
+		});
+		let trace_template_uuid = self.record(self.parse_template_reference(
+			val,
+			path,
+			"trace-template",
+			&self.trace_template_slugs,
+			"trace template",
+		));
+		let fields = obj.get("fields").map_or(Some(vec![]), |fields| {
+			self.parse_field_templates(fields, &child_key(path, "fields"))
+		});
+		self.template_name = None;
+
+		Some(definition::EventTemplate {
+			event_template_uuid: event_template_uuid?,
+			trace_template_uuid: trace_template_uuid?,
+			name: name.unwrap_or(UNNAMED_EVENT_TEMPLATE.to_string()),
+			slug: slug?,
+			created_at: Utc::now(), // TODO
+			fields: fields?,
+			default_tags: vec![], // TODO
+		})
+	}
+
+	fn parse_event_templates(
+		&mut self,
+		val: &serde_json::Value,
+		path: &str,
+		project_uuid: Uuid,
+	) -> Option<Vec<definition::EventTemplate>> {
+		let event_templates = self.record(self.expect_array(val, path))?;
+		Some(
+			event_templates
+				.iter()
+				.enumerate()
+				.flat_map(|(index, event_template)| {
+					self.parse_event_template(event_template, &child_index(path, index), project_uuid)
+				})
+				.collect(),
+		)
+	}
+
+	fn parse_trace_flow_entry(
+		&mut self,
+		val: &serde_json::Value,
+		path: &str,
+	) -> Option<definition::TraceFlowEntry> {
+		let obj = self.record(self.expect_object(val, path))?;
+		let from_path = child_key(path, "from");
+		let from = self
+			.record(
+				obj.get("from")
+					.ok_or_else(|| self.error(&from_path, "Field from is required".into())),
+			)
+			.and_then(|from| {
+				self.record(self.resolve_reference(
+					from,
+					&from_path,
+					&self.event_template_slugs,
+					"event template",
+				))
+			});
+		let to_path = child_key(path, "to");
+		let to = self
+			.record(
+				obj.get("to")
+					.ok_or_else(|| self.error(&to_path, "Expected flow to have a 'to' field".into())),
+			)
+			.and_then(|to| self.record(self.expect_array(to, &to_path)))
+			.map(|to| {
+				to.iter()
+					.enumerate()
+					.map(|(index, reference)| {
+						self.resolve_reference(
+							reference,
+							&child_index(&to_path, index),
+							&self.event_template_slugs,
+							"event template",
+						)
+					})
+					.collect::<Vec<_>>()
+			})
+			.and_then(|to| {
+				// Record every bad entry before giving up on the list
+				let to: Vec<Option<Uuid>> = to.into_iter().map(|uuid| self.record(uuid)).collect();
+				to.into_iter().collect::<Option<Vec<Uuid>>>()
+			});
+
+		Some(definition::TraceFlowEntry {
+			from: from?,
+			to: to?,
+		})
+	}
+
+	fn parse_trace_flow_entries(
+		&mut self,
+		val: &serde_json::Value,
+		path: &str,
+	) -> Option<Vec<definition::TraceFlowEntry>> {
+		let flow_entries = self.record(self.expect_array(val, path))?;
+		Some(
+			flow_entries
+				.iter()
+				.enumerate()
+				.flat_map(|(index, flow_entry)| {
+					self.parse_trace_flow_entry(flow_entry, &child_index(path, index))
+				})
+				.collect(),
+		)
+	}
+
+	fn parse_trace_template(
+		&mut self,
+		val: &serde_json::Value,
+		path: &str,
+		project_uuid: Uuid,
+	) -> Option<definition::TraceTemplate> {
+		let obj = self.record(self.expect_object(val, path))?;
+		let name = self.record(self.parse_str(val, path, "name")).flatten();
+		self.template_name = name.clone();
+
+		let slug = self.record(self.parse_slug(val, path, UNNAMED_TRACE_TEMPLATE));
+		let trace_template_uuid = slug.as_ref().and_then(|slug| {
+			self.record(self.parse_optional_uuid(
+				val,
+				path,
+				"trace-template-uuid",
+				derive_trace_template_uuid(&project_uuid, slug),
+			))
+		});
+		let flow = obj.get("transitions").map_or(Some(vec![]), |transitions| {
+			self.parse_trace_flow_entries(transitions, &child_key(path, "transitions"))
+		});
+		self.template_name = None;
+
+		Some(definition::TraceTemplate {
+			trace_template_uuid: trace_template_uuid?,
+			project_uuid,
+			name: name.unwrap_or(UNNAMED_TRACE_TEMPLATE.to_string()),
+			slug: slug?,
+			created_at: Utc::now(), // TODO
+			flow: flow?,
+		})
+	}
+
+	fn parse_trace_templates(
+		&mut self,
+		val: &serde_json::Value,
+		path: &str,
+		project_uuid: Uuid,
+	) -> Option<Vec<definition::TraceTemplate>> {
+		let trace_templates = self.record(self.expect_array(val, path))?;
+		Some(
+			trace_templates
+				.iter()
+				.enumerate()
+				.flat_map(|(index, trace_template)| {
+					self.parse_trace_template(trace_template, &child_index(path, index), project_uuid)
+				})
+				.collect(),
+		)
+	}
+
+	fn parse_version(&self, val: &serde_json::Value, path: &str) -> Result<u32, errors::ParsingError> {
+		val.as_u64()
+			.and_then(|version| u32::try_from(version).ok())
+			.ok_or_else(|| self.error(path, "Expected the version to be a whole number".into()))
+	}
+
+	// Migrations can name event templates the file no longer has. Without a uuid those are
+	// found by the uuid their slug generated.
+	fn resolve_migration_reference(
+		&self,
+		val: &serde_json::Value,
+		path: &str,
+		project_uuid: Uuid,
+	) -> Result<Uuid, errors::ParsingError> {
+		let reference = val
+			.as_str()
+			.ok_or_else(|| self.error(path, "Expected an event template slug or uuid".into()))?;
+		Ok(match self.event_template_slugs.get(reference) {
+			Some(uuid) => *uuid,
+			None => Uuid::parse_str(reference)
+				.unwrap_or(derive_event_template_uuid(&project_uuid, reference)),
+		})
+	}
+
+	fn parse_migration_step(
+		&self,
+		val: &serde_json::Value,
+		path: &str,
+		project_uuid: Uuid,
+	) -> Result<definition::MigrationStep, errors::ParsingError> {
+		let obj = self.expect_object(val, path)?;
+		let kinds: Vec<&str> = ["rename-field", "remove-field", "rename-option", "move-events"]
+			.into_iter()
+			.filter(|kind| obj.contains_key(*kind))
+			.collect();
+		let [kind] = kinds[..] else {
+			return Err(self.error(
+				path,
+				"Expected exactly one of rename-field, remove-field, rename-option or move-events".into(),
+			));
+		};
+		let step_path = child_key(path, kind);
+		let step = &obj[kind];
+
+		if kind == "move-events" {
+			let from = self.parse_migration_reference(step, &step_path, "from", project_uuid)?;
+			let to = self.parse_template_reference(
+				step,
+				&step_path,
+				"to",
+				&self.event_template_slugs,
+				"event template",
+			)?;
+			return Ok(definition::MigrationStep::MoveEvents { from, to });
+		}
+
+		let event_template_uuid = self.parse_migration_reference(val, path, "event-template", project_uuid)?;
+		Ok(match kind {
+			"rename-field" => definition::MigrationStep::RenameField {
+				event_template_uuid,
+				from: self.parse_required_str(step, &step_path, "from")?,
+				to: self.parse_required_str(step, &step_path, "to")?,
+			},
+			"remove-field" => definition::MigrationStep::RemoveField {
+				event_template_uuid,
+				field: step
+					.as_str()
+					.ok_or_else(|| self.error(&step_path, "Expected the name of a field".into()))?
+					.to_string(),
+			},
+			_ => definition::MigrationStep::RenameOption {
+				event_template_uuid,
+				field: self.parse_required_str(step, &step_path, "field")?,
+				from: self.parse_required_str(step, &step_path, "from")?,
+				to: self.parse_required_str(step, &step_path, "to")?,
+			},
+		})
+	}
+
+	fn parse_migration_reference(
+		&self,
+		val: &serde_json::Value,
+		path: &str,
+		field: &str,
+		project_uuid: Uuid,
+	) -> Result<Uuid, errors::ParsingError> {
+		let field_path = child_key(path, field);
+		let reference = self
+			.expect_object(val, path)?
+			.get(field)
+			.ok_or_else(|| self.error(&field_path, format!("Field {} is required", field)))?;
+		self.resolve_migration_reference(reference, &field_path, project_uuid)
+	}
+
+	fn parse_migration(
+		&mut self,
+		val: &serde_json::Value,
+		path: &str,
+		project_uuid: Uuid,
+	) -> Option<definition::Migration> {
+		let obj = self.record(self.expect_object(val, path))?;
+		let version_path = child_key(path, "version");
+		let version = self.record(
+			obj.get("version")
+				.ok_or_else(|| self.error(&version_path, "Field version is required".into()))
+				.and_then(|version| self.parse_version(version, &version_path)),
+		);
+		let step = self.record(self.parse_migration_step(val, path, project_uuid));
+		Some(definition::Migration {
+			version: version?,
+			step: step?,
+		})
+	}
+
+	fn parse_migrations(
+		&mut self,
+		val: &serde_json::Value,
+		path: &str,
+		project_uuid: Uuid,
+	) -> Option<Vec<definition::Migration>> {
+		let migrations = self.record(self.expect_array(val, path))?;
+		Some(
+			migrations
+				.iter()
+				.enumerate()
+				.flat_map(|(index, migration)| {
+					self.parse_migration(migration, &child_index(path, index), project_uuid)
+				})
+				.collect(),
+		)
+	}
+
+	fn parse_project_definition(
+		&mut self,
+		val: &serde_json::Value,
+	) -> Option<fs_service::ProjectDefinition> {
+		let obj = self.record(self.expect_object(val, ""))?;
+		let project_uuid = match self.project_uuid {
+			Some(project_uuid) => Some(project_uuid),
+			None => self.record(self.parse_required_uuid(val, "", "project-uuid")),
+		};
+		// Definitions without a version are the first one
+		let version = obj.get("version").map_or(Some(1), |version| {
+			self.record(self.parse_version(version, "version"))
+		});
+
+		// Keep going without a project uuid so the templates still get checked
+		let templates_project_uuid = project_uuid.unwrap_or(Uuid::nil());
+		if let Some(trace_templates) = obj.get("trace-templates") {
+			self.trace_template_slugs = self.collect_slugs(
+				trace_templates,
+				"trace-templates",
+				UNNAMED_TRACE_TEMPLATE,
+				|slug| derive_trace_template_uuid(&templates_project_uuid, slug),
+				"trace-template-uuid",
+			);
+		}
+		if let Some(event_templates) = obj.get("event-templates") {
+			self.event_template_slugs = self.collect_slugs(
+				event_templates,
+				"event-templates",
+				UNNAMED_EVENT_TEMPLATE,
+				|slug| derive_event_template_uuid(&templates_project_uuid, slug),
+				"event-template-uuid",
+			);
+		}
+		let event_templates = obj.get("event-templates").map_or(Some(vec![]), |val| {
+			self.parse_event_templates(val, "event-templates", templates_project_uuid)
+		});
+		let trace_templates = obj.get("trace-templates").map_or(Some(vec![]), |val| {
+			self.parse_trace_templates(val, "trace-templates", templates_project_uuid)
+		});
+
+		let migrations = obj.get("migrations").map_or(Some(vec![]), |val| {
+			self.parse_migrations(val, "migrations", templates_project_uuid)
+		});
+
+		Some(fs_service::ProjectDefinition {
+			project_uuid: project_uuid?,
+			version: version?,
+			migrations: migrations?,
+			event_templates: event_templates?,
+			trace_templates: trace_templates?,
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const YAML: &str = "\
+project-uuid: 6b3a2c36-3b5e-4f7e-9a51-1c1d6f0f9e01
+trace-templates:
+  - name: Loaf
+event-templates:
+  - name: Mix
+    trace-template: loaf
+  - name: Proof
+    trace-template: loaf
+  - name: Shape
+    trace-template: loaf
+  - name: Bake
+    trace-template: loaf
+    fields:
+      - label: Oven
+        type: Text
+      - label: Weight
+        type: Number
+        default-value: heavy
+";
+
+	const JSON: &str = r#"{
+	"project-uuid": "6b3a2c36-3b5e-4f7e-9a51-1c1d6f0f9e01",
+	"trace-templates": [{"name": "Loaf"}],
+	"event-templates": [
+		{"name": "Mix", "trace-template": "loaf"},
+		{"name": "Proof", "trace-template": "loaf"},
+		{"name": "Shape", "trace-template": "loaf"},
+		{
+			"name": "Bake",
+			"trace-template": "loaf",
+			"fields": [
+				{"label": "Oven", "type": "Text"},
+				{"label": "Weight", "type": "Number",
+					"default-value": "heavy"}
+			]
+		}
+	]
+}"#;
+
+	fn errors_of(contents: &str, format: service::DefinitionFormat) -> Vec<errors::ParsingError> {
+		match parse_definition(contents, format, None) {
+			Ok(_) => panic!("the definition should not parse"),
+			Err(errors) => errors.errors,
+		}
+	}
+
+	fn located(error: &errors::ParsingError) -> (Option<&str>, Option<usize>, Option<usize>, Option<&str>) {
+		(error.path.as_deref(), error.line, error.column, error.template_name.as_deref())
+	}
+
+	#[test]
+	fn yaml_errors_point_into_the_file() {
+		let errors = errors_of(YAML, service::DefinitionFormat::Yaml);
+		assert_eq!(errors.len(), 1);
+		assert_eq!(
+			located(&errors[0]),
+			(Some("event-templates[3].fields[1].default-value"), Some(18), Some(24), Some("Bake"))
+		);
+		assert_eq!(errors[0].message, "Expected a number, found: \"heavy\"");
+	}
+
+	#[test]
+	fn json_errors_point_into_the_file() {
+		let errors = errors_of(JSON, service::DefinitionFormat::Json);
+		assert_eq!(errors.len(), 1);
+		assert_eq!(
+			located(&errors[0]),
+			(Some("event-templates[3].fields[1].default-value"), Some(14), Some(23), Some("Bake"))
+		);
+	}
+
+	#[test]
+	fn every_error_is_collected_in_one_pass() {
+		let yaml = "\
+trace-templates:
+  - name: Loaf
+event-templates:
+  - name: Mix
+    trace-template: bread
+    fields:
+      - label: Flour
+        type: Weight
+      - type: Number
+  - name: Bake
+    trace-template: loaf
+    fields:
+      - label: Oven
+        type: Enumerated
+        options:
+          - label: Hot
+        default-value: Cold
+";
+		let errors = errors_of(yaml, service::DefinitionFormat::Yaml);
+		let mut found: Vec<(Option<&str>, Option<usize>, Option<&str>)> = errors
+			.iter()
+			.map(|error| (error.path.as_deref(), error.line, error.template_name.as_deref()))
+			.collect();
+		found.sort();
+		assert_eq!(
+			found,
+			vec![
+				(Some("event-templates[0].fields[0].type"), Some(8), Some("Mix")),
+				(Some("event-templates[0].fields[1].label"), Some(9), Some("Mix")),
+				(Some("event-templates[0].trace-template"), Some(5), Some("Mix")),
+				(Some("event-templates[1].fields[0].default-value"), Some(17), Some("Bake")),
+				(Some("project-uuid"), Some(1), None),
+			]
+		);
+	}
+
+	#[test]
+	fn syntax_errors_keep_their_position() {
+		let errors = errors_of("{\n\t\"project-uuid\": \n}", service::DefinitionFormat::Json);
+		assert_eq!(errors.len(), 1);
+		assert_eq!(located(&errors[0]), (None, Some(3), Some(1), None));
+
+		let errors = errors_of("event-templates:\n  - name: [Bake\n", service::DefinitionFormat::Yaml);
+		assert_eq!(errors.len(), 1);
+		assert!(errors[0].line.is_some());
+	}
+
+	#[test]
+	fn both_formats_give_the_same_definition() {
+		let yaml = YAML.replace("default-value: heavy", "default-value: 900");
+		let json = JSON.replace("\"heavy\"", "900");
+		let from_yaml = parse_definition(&yaml, service::DefinitionFormat::Yaml, None).ok().unwrap();
+		let from_json = parse_definition(&json, service::DefinitionFormat::Json, None).ok().unwrap();
+		let uuids = |parsed: &ParsedDefinition| {
+			let mut uuids: Vec<Uuid> = parsed
+				.project_definition
+				.event_templates
+				.iter()
+				.map(|event_template| event_template.event_template_uuid)
+				.collect();
+			uuids.sort();
+			uuids
+		};
+		assert_eq!(uuids(&from_yaml), uuids(&from_json));
+		assert_eq!(from_yaml.project_definition.trace_templates.len(), 1);
+	}
+
+	#[test]
+	fn date_defaults_ignore_surrounding_whitespace() {
+		// A quoted YAML or JSON string keeps its spaces
+		for text in ["now", " now", "now\n", "\tnow "] {
+			assert!(parse_date_time_default(text).unwrap().default_now, "{:?}", text);
+		}
+		for text in ["today", " today ", "today\n"] {
+			assert!(parse_date_default(text).unwrap().default_today, "{:?}", text);
+		}
+		let date_time = parse_date_time_default(" 2024-03-01T08:00:00Z ").unwrap();
+		assert!(!date_time.default_now);
+		assert_eq!(date_time.default_value, Some("2024-03-01T08:00:00Z".parse().unwrap()));
+		let date = parse_date_default(" 2024-03-01").unwrap();
+		assert_eq!(date.default_value, chrono::NaiveDate::from_ymd_opt(2024, 3, 1));
+		assert!(parse_date_time_default("no w").is_none());
+		assert!(parse_date_default("to day").is_none());
+	}
+}
@@ -0,0 +1,612 @@
+use crate::definition;
+use crate::definition_parser;
+use crate::fs_service;
+
+use journal_lib::service;
+use journal_lib::views;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+// The authoring format read by definition_parser. Template uuids are always written out
+// so a re-import keeps them, references use slugs where they are unambiguous.
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct DefinitionDocument {
+	project_uuid: Uuid,
+	version: u32,
+	trace_templates: Vec<TraceTemplateDocument>,
+	event_templates: Vec<EventTemplateDocument>,
+	#[serde(skip_serializing_if = "Vec::is_empty")]
+	migrations: Vec<MigrationDocument>,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct TraceTemplateDocument {
+	trace_template_uuid: Uuid,
+	name: String,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	slug: Option<String>,
+	#[serde(skip_serializing_if = "Vec::is_empty")]
+	transitions: Vec<TransitionDocument>,
+}
+
+#[derive(serde::Serialize)]
+struct TransitionDocument {
+	from: String,
+	to: Vec<String>,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct EventTemplateDocument {
+	event_template_uuid: Uuid,
+	name: String,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	slug: Option<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	trace_template: Option<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	trace_template_uuid: Option<Uuid>,
+	#[serde(skip_serializing_if = "Vec::is_empty")]
+	fields: Vec<FieldDocument>,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct FieldDocument {
+	#[serde(rename = "type")]
+	field_type: &'static str,
+	label: String,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	name: Option<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	default_value: Option<serde_json::Value>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	options: Option<Vec<OptionDocument>>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	item: Option<Box<ItemDocument>>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	fields: Option<Vec<FieldDocument>>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	value_policy: Option<&'static str>,
+}
+
+// What each row of an array field holds
+#[derive(serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct ItemDocument {
+	#[serde(rename = "type")]
+	field_type: &'static str,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	default_value: Option<serde_json::Value>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	options: Option<Vec<OptionDocument>>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	item: Option<Box<ItemDocument>>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	fields: Option<Vec<FieldDocument>>,
+}
+
+#[derive(serde::Serialize)]
+struct OptionDocument {
+	label: String,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	name: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct MigrationDocument {
+	version: u32,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	event_template: Option<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	rename_field: Option<RenameDocument>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	remove_field: Option<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	rename_option: Option<RenameOptionDocument>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	move_events: Option<RenameDocument>,
+}
+
+#[derive(serde::Serialize)]
+struct RenameDocument {
+	from: String,
+	to: String,
+}
+
+#[derive(serde::Serialize)]
+struct RenameOptionDocument {
+	field: String,
+	from: String,
+	to: String,
+}
+
+// Only written when the parser would not come up with the same value by itself
+fn unless_derived(value: &str, derived_from: &str) -> Option<String> {
+	Some(value.to_string())
+		.filter(|value| !value.is_empty() && value != &definition_parser::map_name(derived_from))
+}
+
+// Templates saved before slugs existed have an empty one, the parser would derive it from the name
+fn effective_slug(slug: &str, name: &str) -> String {
+	if slug.is_empty() {
+		definition_parser::map_name(name)
+	} else {
+		slug.to_string()
+	}
+}
+
+// Slugs that identify exactly one template, so they can be written instead of uuids
+fn unique_slugs(templates: impl Iterator<Item = (String, Uuid)>) -> HashMap<Uuid, String> {
+	let mut by_slug: HashMap<String, Vec<Uuid>> = HashMap::new();
+	for (slug, uuid) in templates {
+		by_slug.entry(slug).or_default().push(uuid);
+	}
+	by_slug
+		.into_iter()
+		.filter(|(_, uuids)| uuids.len() == 1)
+		.map(|(slug, uuids)| (uuids[0], slug))
+		.collect()
+}
+
+// The parser rejects two templates with the same slug, so shared ones get made unique
+fn written_slug(slugs: &HashMap<Uuid, String>, uuid: &Uuid, slug: &str, name: &str) -> Option<String> {
+	match slugs.get(uuid) {
+		Some(slug) => unless_derived(slug, name),
+		None => Some(format!(
+			"{}-{}",
+			effective_slug(slug, name),
+			&uuid.simple().to_string()[..8]
+		)),
+	}
+}
+
+fn reference(slugs: &HashMap<Uuid, String>, uuid: &Uuid) -> String {
+	slugs.get(uuid).cloned().unwrap_or(uuid.to_string())
+}
+
+fn write_field(field: &definition::FieldTemplate) -> FieldDocument {
+	let value = write_value(&field.value);
+	FieldDocument {
+		field_type: value.field_type,
+		label: field.label.clone(),
+		name: unless_derived(&field.name, &field.label),
+		default_value: value.default_value,
+		options: value.options,
+		item: value.item,
+		fields: value.fields,
+		value_policy: (field.value_policy != service::ValuePolicy::default()).then(|| field.value_policy.name()),
+	}
+}
+
+fn write_value(value: &definition::FieldValueTemplate) -> ItemDocument {
+	let document = ItemDocument {
+		field_type: "",
+		default_value: None,
+		options: None,
+		item: None,
+		fields: None,
+	};
+	match value {
+		definition::FieldValueTemplate::Number(template) => ItemDocument {
+			field_type: "Number",
+			default_value: template.default_value.map(|value| serde_json::json!(value)),
+			..document
+		},
+		definition::FieldValueTemplate::Text(template) => ItemDocument {
+			field_type: "Text",
+			default_value: template.default_value.as_ref().map(|value| serde_json::json!(value)),
+			..document
+		},
+		definition::FieldValueTemplate::Bool(template) => ItemDocument {
+			field_type: "Boolean",
+			default_value: template.default_value.map(|value| serde_json::json!(value)),
+			..document
+		},
+		definition::FieldValueTemplate::Enumerated(template) => ItemDocument {
+			field_type: "Enumerated",
+			default_value: template
+				.default_value
+				.as_ref()
+				.map(|option| serde_json::json!(option.name)),
+			options: Some(
+				template
+					.options
+					.iter()
+					.map(|option| OptionDocument {
+						label: option.label.clone(),
+						name: unless_derived(&option.name, &option.label),
+					})
+					.collect(),
+			),
+			..document
+		},
+		definition::FieldValueTemplate::Array(template) => ItemDocument {
+			field_type: "Array",
+			item: Some(Box::new(write_value(&template.item))),
+			..document
+		},
+		definition::FieldValueTemplate::Group(template) => ItemDocument {
+			field_type: "Group",
+			fields: Some(template.fields.iter().map(write_field).collect()),
+			..document
+		},
+		definition::FieldValueTemplate::DateTime(template) => ItemDocument {
+			field_type: "DateTime",
+			default_value: if template.default_now {
+				Some(serde_json::json!("now"))
+			} else {
+				template
+					.default_value
+					.map(|value| serde_json::json!(value.to_rfc3339_opts(chrono::SecondsFormat::AutoSi, true)))
+			},
+			..document
+		},
+		definition::FieldValueTemplate::Date(template) => ItemDocument {
+			field_type: "Date",
+			default_value: if template.default_today {
+				Some(serde_json::json!("today"))
+			} else {
+				template.default_value.map(|value| serde_json::json!(value.to_string()))
+			},
+			..document
+		},
+		definition::FieldValueTemplate::TimeOfDay(template) => ItemDocument {
+			field_type: "TimeOfDay",
+			default_value: template
+				.default_value
+				.as_ref()
+				.map(|value| serde_json::json!(views::format_time_of_day(value))),
+			..document
+		},
+		definition::FieldValueTemplate::Duration(template) => ItemDocument {
+			field_type: "Duration",
+			default_value: template
+				.default_value
+				.map(|value| serde_json::json!(views::format_duration(value))),
+			..document
+		},
+	}
+}
+
+fn write_migration(slugs: &HashMap<Uuid, String>, migration: &definition::Migration) -> MigrationDocument {
+	let document = MigrationDocument {
+		version: migration.version,
+		event_template: None,
+		rename_field: None,
+		remove_field: None,
+		rename_option: None,
+		move_events: None,
+	};
+	match &migration.step {
+		definition::MigrationStep::RenameField {
+			event_template_uuid,
+			from,
+			to,
+		} => MigrationDocument {
+			event_template: Some(reference(slugs, event_template_uuid)),
+			rename_field: Some(RenameDocument {
+				from: from.clone(),
+				to: to.clone(),
+			}),
+			..document
+		},
+		definition::MigrationStep::RemoveField {
+			event_template_uuid,
+			field,
+		} => MigrationDocument {
+			event_template: Some(reference(slugs, event_template_uuid)),
+			remove_field: Some(field.clone()),
+			..document
+		},
+		definition::MigrationStep::RenameOption {
+			event_template_uuid,
+			field,
+			from,
+			to,
+		} => MigrationDocument {
+			event_template: Some(reference(slugs, event_template_uuid)),
+			rename_option: Some(RenameOptionDocument {
+				field: field.clone(),
+				from: from.clone(),
+				to: to.clone(),
+			}),
+			..document
+		},
+		definition::MigrationStep::MoveEvents { from, to } => MigrationDocument {
+			move_events: Some(RenameDocument {
+				from: reference(slugs, from),
+				to: reference(slugs, to),
+			}),
+			..document
+		},
+	}
+}
+
+fn to_document(project_definition: &fs_service::ProjectDefinition) -> DefinitionDocument {
+	let trace_template_slugs = unique_slugs(
+		project_definition
+			.trace_templates
+			.iter()
+			.map(|template| {
+				(
+					effective_slug(&template.slug, &template.name),
+					template.trace_template_uuid,
+				)
+			}),
+	);
+	let event_template_slugs = unique_slugs(
+		project_definition
+			.event_templates
+			.iter()
+			.map(|template| {
+				(
+					effective_slug(&template.slug, &template.name),
+					template.event_template_uuid,
+				)
+			}),
+	);
+
+	// Sorted so exporting an unchanged project gives an identical file
+	let mut trace_templates: Vec<&definition::TraceTemplate> =
+		project_definition.trace_templates.iter().collect();
+	trace_templates.sort_by(|a, b| {
+		a.name
+			.cmp(&b.name)
+			.then(a.trace_template_uuid.cmp(&b.trace_template_uuid))
+	});
+	let mut event_templates: Vec<&definition::EventTemplate> =
+		project_definition.event_templates.iter().collect();
+	event_templates.sort_by(|a, b| {
+		a.name
+			.cmp(&b.name)
+			.then(a.event_template_uuid.cmp(&b.event_template_uuid))
+	});
+
+	DefinitionDocument {
+		project_uuid: project_definition.project_uuid,
+		version: project_definition.version,
+		trace_templates: trace_templates
+			.into_iter()
+			.map(|template| TraceTemplateDocument {
+				trace_template_uuid: template.trace_template_uuid,
+				name: template.name.clone(),
+				slug: written_slug(
+					&trace_template_slugs,
+					&template.trace_template_uuid,
+					&template.slug,
+					&template.name,
+				),
+				transitions: template
+					.flow
+					.iter()
+					.map(|entry| TransitionDocument {
+						from: reference(&event_template_slugs, &entry.from),
+						to: entry
+							.to
+							.iter()
+							.map(|to| reference(&event_template_slugs, to))
+							.collect(),
+					})
+					.collect(),
+			})
+			.collect(),
+		event_templates: event_templates
+			.into_iter()
+			.map(|template| {
+				let trace_template = trace_template_slugs.get(&template.trace_template_uuid).cloned();
+				EventTemplateDocument {
+					event_template_uuid: template.event_template_uuid,
+					name: template.name.clone(),
+					slug: written_slug(
+						&event_template_slugs,
+						&template.event_template_uuid,
+						&template.slug,
+						&template.name,
+					),
+					trace_template_uuid: match trace_template {
+						Some(_) => None,
+						None => Some(template.trace_template_uuid),
+					},
+					trace_template,
+					fields: template.fields.iter().map(write_field).collect(),
+				}
+			})
+			.collect(),
+		migrations: project_definition
+			.migrations
+			.iter()
+			.map(|migration| write_migration(&event_template_slugs, migration))
+			.collect(),
+	}
+}
+
+pub fn write_definition(
+	project_definition: &fs_service::ProjectDefinition,
+	format: service::DefinitionFormat,
+) -> String {
+	let document = to_document(project_definition);
+	// Serializing these plain structs cannot fail
+	match format {
+		service::DefinitionFormat::Json => serde_json::to_string_pretty(&document).unwrap(),
+		service::DefinitionFormat::Yaml => serde_yaml::to_string(&document).unwrap(),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const PROJECT_UUID: Uuid = Uuid::from_u128(0x6b3a2c36_3b5e_4f7e_9a51_1c1d6f0f9e01);
+	// Of a template a later version dropped
+	const SHAPE_UUID: Uuid = Uuid::from_u128(0x1d0c2b8e_55aa_4f0e_8d4c_7f6a5b4c3d2e);
+
+	const DEFINITION: &str = "
+version: 3
+trace-templates:
+  - name: Loaf
+    trace-template-uuid: 2f1e0d9c-8b7a-4c6d-9e5f-4a3b2c1d0e9f
+    transitions:
+      - from: mix
+        to: [oven]
+  - name: Starter
+    slug: levain
+event-templates:
+  - name: Mix
+    trace-template: loaf
+    fields:
+      - label: Flour
+        type: Group
+        fields:
+          - label: Rye
+            type: Number
+            default-value: 100
+            value-policy: rolling-median
+          - label: Kind
+            type: Enumerated
+            options:
+              - label: Whole grain
+                name: whole
+              - label: Sifted
+            default-value: whole
+      - label: Additions
+        type: Array
+        item:
+          type: Text
+      - label: Folds
+        type: Array
+        item:
+          type: Group
+          fields:
+            - label: At
+              type: TimeOfDay
+            - label: Rest
+              type: Duration
+  - name: Bake
+    slug: oven
+    trace-template: loaf
+    fields:
+      - label: Started
+        type: DateTime
+        default-value: now
+      - label: Day
+        type: Date
+        default-value: 2024-03-01
+      - label: Length
+        type: Duration
+        default-value: 45m
+      - label: Notes
+        type: Text
+        value-policy: last-in-trace
+  - name: Feed
+    trace-template: levain
+migrations:
+  - version: 2
+    event-template: mix
+    rename-field:
+      from: flour.wheat
+      to: flour.rye
+  - version: 2
+    event-template: mix
+    rename-option:
+      field: flour.kind
+      from: white
+      to: sifted
+  - version: 3
+    move-events:
+      from: 1d0c2b8e-55aa-4f0e-8d4c-7f6a5b4c3d2e
+      to: oven
+  - version: 3
+    event-template: mix
+    remove-field: salt
+";
+
+	fn parse(contents: &str, format: service::DefinitionFormat) -> fs_service::ProjectDefinition {
+		match definition_parser::parse_definition(contents, format, None) {
+			Ok(parsed) => parsed.project_definition,
+			Err(errors) => panic!("{}\n{}", errors, contents),
+		}
+	}
+
+	// Everything but the creation times, which an import sets, with the templates in a fixed order
+	fn normalized(project_definition: &fs_service::ProjectDefinition) -> serde_json::Value {
+		let mut value = serde_json::to_value(project_definition).unwrap();
+		for templates in ["event_templates", "trace_templates"] {
+			let templates = value[templates].as_array_mut().unwrap();
+			for template in templates.iter_mut() {
+				template.as_object_mut().unwrap().remove("created_at");
+			}
+			templates.sort_by_key(|template| template.to_string());
+		}
+		value
+	}
+
+	fn round_trip(format: service::DefinitionFormat) {
+		let original = parse(&format!("project-uuid: {}\n{}", PROJECT_UUID, DEFINITION), service::DefinitionFormat::Yaml);
+		let written = write_definition(&original, format);
+		let reimported = parse(&written, format);
+		assert_eq!(normalized(&reimported), normalized(&original), "{}", written);
+
+		// What the comparison covers
+		assert_eq!(reimported.project_uuid, PROJECT_UUID);
+		assert_eq!(reimported.version, 3);
+		assert_eq!(reimported.migrations.len(), 4);
+		assert!(matches!(
+			reimported.migrations[2].step,
+			definition::MigrationStep::MoveEvents { from: SHAPE_UUID, .. }
+		));
+		let slugs: Vec<&str> = reimported.trace_templates.iter().map(|template| template.slug.as_str()).collect();
+		assert!(slugs.contains(&"loaf") && slugs.contains(&"levain"));
+		let mix = reimported.event_templates.iter().find(|template| template.slug == "mix").unwrap();
+		assert!(matches!(mix.fields[0].value, definition::FieldValueTemplate::Group(_)));
+		assert!(matches!(
+			&mix.field("folds").unwrap().value,
+			definition::FieldValueTemplate::Array(array) if matches!(*array.item, definition::FieldValueTemplate::Group(_))
+		));
+		let bake = reimported.event_templates.iter().find(|template| template.slug == "oven").unwrap();
+		assert!(matches!(
+			bake.field("started").unwrap().value,
+			definition::FieldValueTemplate::DateTime(definition::DateTimeTemplate { default_now: true, .. })
+		));
+		assert!(matches!(
+			bake.field("length").unwrap().value,
+			definition::FieldValueTemplate::Duration(definition::DurationTemplate { default_value: Some(2700) })
+		));
+	}
+
+	#[test]
+	fn json_export_imports_as_the_same_definition() {
+		round_trip(service::DefinitionFormat::Json);
+	}
+
+	#[test]
+	fn yaml_export_imports_as_the_same_definition() {
+		round_trip(service::DefinitionFormat::Yaml);
+	}
+
+	#[test]
+	fn exported_definitions_keep_their_uuids_under_another_project() {
+		let original = parse(&format!("project-uuid: {}\n{}", PROJECT_UUID, DEFINITION), service::DefinitionFormat::Yaml);
+		let written = write_definition(&original, service::DefinitionFormat::Json);
+		let other_project = Uuid::new_v4();
+		let reimported = definition_parser::parse_definition(&written, service::DefinitionFormat::Json, Some(other_project))
+			.ok()
+			.unwrap()
+			.project_definition;
+		let uuids = |project_definition: &fs_service::ProjectDefinition| {
+			let mut uuids: Vec<Uuid> = project_definition
+				.event_templates
+				.iter()
+				.map(|template| template.event_template_uuid)
+				.collect();
+			uuids.sort();
+			uuids
+		};
+		assert_eq!(reimported.project_uuid, other_project);
+		assert_eq!(uuids(&reimported), uuids(&original));
+	}
+}
@@ -0,0 +1,66 @@
+use crate::events;
+use crate::schema;
+
+use std::io::Write;
+use std::path::Path;
+
+// Per-project log of everything saved since the last snapshot, one JSON record per line.
+// Records are upserts, so replaying a log that was already folded into the snapshot is
+// harmless.
+
+pub const LOG_FILE: &str = "log.jsonl";
+
+#[derive(Clone, serde::Deserialize, serde::Serialize)]
+pub enum LogRecord {
+	Event(events::Event),
+	Trace(events::Trace),
+}
+
+// Returns once the record is on disk
+pub fn append(dir: &Path, record: &LogRecord) -> std::io::Result<()> {
+	let mut line = schema::to_vec(record)?;
+	line.push(b'\n');
+	let mut file = std::fs::OpenOptions::new()
+		.create(true)
+		.append(true)
+		.open(dir.join(LOG_FILE))?;
+	file.write_all(&line)?;
+	file.sync_data()
+}
+
+// A crash while appending can leave a last line without its newline. That record was never
+// acknowledged, so it is cut off rather than treated as corruption, which also keeps the
+// next append from being glued onto it.
+pub fn replay(dir: &Path) -> std::io::Result<Vec<LogRecord>> {
+	let path = dir.join(LOG_FILE);
+	if !path.exists() {
+		return Ok(vec![]);
+	}
+	let contents = std::fs::read(&path)?;
+	let mut records = vec![];
+	let mut offset = 0;
+	while let Some(length) = contents[offset..].iter().position(|byte| *byte == b'\n') {
+		let line = &contents[offset..offset + length];
+		if !line.is_empty() {
+			records.push(
+				schema::from_slice(schema::FileKind::LogRecord, line)
+					.map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?,
+			);
+		}
+		offset += length + 1;
+	}
+	if offset < contents.len() {
+		log::warn!("Dropping a partially written record from {:?}", path);
+		let file = std::fs::OpenOptions::new().write(true).open(&path)?;
+		file.set_len(offset as u64)?;
+		file.sync_all()?;
+	}
+	Ok(records)
+}
+
+pub fn remove(dir: &Path) -> std::io::Result<()> {
+	match std::fs::remove_file(dir.join(LOG_FILE)) {
+		Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err),
+		_ => Ok(()),
+	}
+}
@@ -1,7 +1,10 @@
 
 use journal_lib::views;
 use chrono::serde::ts_milliseconds;
+use chrono::serde::ts_milliseconds_option;
 use chrono::DateTime;
+use chrono::NaiveDate;
+use chrono::NaiveTime;
 use chrono::Utc;
 use uuid::Uuid;
 
@@ -31,15 +34,23 @@ pub struct EnumeratedField {
 }
 
 
-#[derive(Clone, serde::Deserialize, serde::Serialize)]
+#[derive(Clone, PartialEq, serde::Deserialize, serde::Serialize)]
 pub enum FieldValue {
 	Number(f64),
 	Text(String),
 	Bool(bool),
 	Enumerated(views::EnumerationOption),
+	// The rows of an array field, each of the template's item type
+	Array(Vec<FieldValue>),
+	Group(Vec<Field>),
+	DateTime(#[serde(with = "ts_milliseconds")] DateTime<Utc>),
+	Date(NaiveDate),
+	TimeOfDay(NaiveTime),
+	// Whole seconds
+	Duration(i64),
 }
 
-#[derive(Clone, serde::Deserialize, serde::Serialize)]
+#[derive(Clone, PartialEq, serde::Deserialize, serde::Serialize)]
 pub struct Field {
 	pub name: String,
 	pub label: String,
@@ -50,11 +61,29 @@ pub struct Field {
 pub struct Trace {
 	pub trace_uuid: Uuid,
 	pub trace_template_uuid: Uuid,
+	// A trace stays in its project when a re-import drops its template. Older saves do not
+	// have it, the project directory they are read from says which project they are in.
+	#[serde(default)]
+	pub project_uuid: Uuid,
 	pub origin_trace_uuids: Vec<Uuid>,
 	#[serde(with = "ts_milliseconds")]
 	pub created_at: DateTime<Utc>,
 	pub name: String,
 	pub completion: Option<views::TraceCompletion>,
+	#[serde(with = "ts_milliseconds_option")]
+	pub archived_at: Option<DateTime<Utc>>,
+}
+
+// What an event held before an update
+#[derive(Clone, serde::Deserialize, serde::Serialize)]
+pub struct EventRevision {
+	pub trace_uuid: Uuid,
+	pub fields: Vec<Field>,
+	pub tags: Vec<String>,
+	#[serde(with = "ts_milliseconds")]
+	pub began_at: DateTime<Utc>,
+	#[serde(with = "ts_milliseconds")]
+	pub replaced_at: DateTime<Utc>,
 }
 
 #[derive(Clone, serde::Deserialize, serde::Serialize)]
@@ -69,6 +98,73 @@ pub struct Event {
 	pub began_at: DateTime<Utc>,
 	#[serde(with = "ts_milliseconds")]
 	pub created_at: DateTime<Utc>,
+
+	// Oldest first
+	pub revisions: Vec<EventRevision>,
+	// A deleted event is kept as a tombstone so the deletion can be undone
+	#[serde(with = "ts_milliseconds_option")]
+	pub deleted_at: Option<DateTime<Utc>>,
+}
+
+impl Event {
+	pub fn is_deleted(&self) -> bool {
+		self.deleted_at.is_some()
+	}
+
+	// Looks a field up by its dotted path, like proof.temperature
+	pub fn field(&self, path: &str) -> Option<&Field> {
+		find_field(&self.fields, path)
+	}
+
+	pub fn revise(
+		&mut self,
+		trace_uuid: Uuid,
+		fields: Vec<Field>,
+		tags: Vec<String>,
+		began_at: DateTime<Utc>,
+	) {
+		let previous = EventRevision {
+			trace_uuid: std::mem::replace(&mut self.trace_uuid, trace_uuid),
+			fields: std::mem::replace(&mut self.fields, fields),
+			tags: std::mem::replace(&mut self.tags, tags),
+			began_at: std::mem::replace(&mut self.began_at, began_at),
+			replaced_at: Utc::now(),
+		};
+		self.revisions.push(previous);
+	}
+
+	pub fn delete(&mut self) {
+		if self.deleted_at.is_none() {
+			self.deleted_at = Some(Utc::now());
+		}
+	}
+
+	// Takes back the deletion, or else the last update. False when there is nothing to undo.
+	pub fn undo(&mut self) -> bool {
+		if self.deleted_at.take().is_some() {
+			return true;
+		}
+		let Some(previous) = self.revisions.pop() else {
+			return false;
+		};
+		self.trace_uuid = previous.trace_uuid;
+		self.fields = previous.fields;
+		self.tags = previous.tags;
+		self.began_at = previous.began_at;
+		true
+	}
+}
+
+impl EventRevision {
+	pub fn to_view(&self) -> views::EventRevisionView {
+		views::EventRevisionView {
+			trace_uuid: self.trace_uuid,
+			fields: self.fields.iter().map(|field| field.to_view()).collect(),
+			tags: self.tags.clone(),
+			began_at: self.began_at,
+			replaced_at: self.replaced_at,
+		}
+	}
 }
 
 impl Trace {
@@ -105,17 +201,88 @@ impl Field {
 		views::FieldView {
 			name: self.name.clone(),
 			label: self.label.clone(),
-			value: self.value.as_ref().map(
-				|value| match value {
-					FieldValue::Number(n) => views::FieldValueView::Number(views::NumberValueView { value: Some(*n) }),
-					FieldValue::Text(s) => views::FieldValueView::Text(views::TextValueView { value: Some(s.to_string()) }),
-					FieldValue::Bool(b) => views::FieldValueView::Bool(views::BoolValueView { value: Some(*b) }),
-					FieldValue::Enumerated(e) => views::FieldValueView::Enumerated(
-						views::EnumerationOptionView { label: e.label.clone() },
-					),
-				},
+			value: self.value.as_ref().map(|value| value.to_view()),
+		}
+	}
+}
+
+impl FieldValue {
+	pub fn to_view(&self) -> views::FieldValueView {
+		match self {
+			FieldValue::Number(n) => views::FieldValueView::Number(views::NumberValueView { value: Some(*n) }),
+			FieldValue::Text(s) => views::FieldValueView::Text(views::TextValueView { value: Some(s.to_string()) }),
+			FieldValue::Bool(b) => views::FieldValueView::Bool(views::BoolValueView { value: Some(*b) }),
+			FieldValue::Enumerated(e) => views::FieldValueView::Enumerated(
+				views::EnumerationOptionView { label: e.label.clone() },
 			),
+			FieldValue::Array(items) => views::FieldValueView::Array(views::ArrayValueView {
+				items: items.iter().map(|item| item.to_view()).collect(),
+			}),
+			FieldValue::Group(fields) => views::FieldValueView::Group(views::GroupValueView {
+				fields: fields.iter().map(|field| field.to_view()).collect(),
+			}),
+			FieldValue::DateTime(value) => views::FieldValueView::DateTime(views::DateTimeValueView { value: Some(*value) }),
+			FieldValue::Date(value) => views::FieldValueView::Date(views::DateValueView { value: Some(*value) }),
+			FieldValue::TimeOfDay(value) => {
+				views::FieldValueView::TimeOfDay(views::TimeOfDayValueView { value: Some(*value) })
+			}
+			FieldValue::Duration(value) => views::FieldValueView::Duration(views::DurationValueView { value: Some(*value) }),
 		}
 	}
+}
+
+// The path names a group's field after the group's name and a dot
+pub fn summarize_duration<'a>(
+	events: impl Iterator<Item = &'a Event>,
+	path: &str,
+) -> views::DurationSummaryView {
+	let mut summary = views::DurationSummaryView::default();
+	for event in events.filter(|event| !event.is_deleted()) {
+		if let Some(FieldValue::Duration(seconds)) = event.field(path).and_then(|field| field.value.as_ref()) {
+			summary.add(*seconds);
+		}
+	}
+	summary
+}
+
+pub fn find_field<'a>(fields: &'a [Field], path: &str) -> Option<&'a Field> {
+	match path.split_once('.') {
+		None => fields.iter().find(|field| field.name == path),
+		Some((group, rest)) => match fields.iter().find(|field| field.name == group)?.value.as_ref()? {
+			FieldValue::Group(fields) => find_field(fields, rest),
+			_ => None,
+		},
+	}
+}
+
+// Calls visit with each list of fields the last name of the path is in, one for every row
+// of an array of groups on the way. Returns whether any of the calls changed something.
+pub fn visit_parent_fields(
+	fields: &mut Vec<Field>,
+	path: &str,
+	visit: &mut dyn FnMut(&mut Vec<Field>, &str) -> bool,
+) -> bool {
+	let Some((name, rest)) = path.split_once('.') else {
+		return visit(fields, path);
+	};
+	fields
+		.iter_mut()
+		.filter(|field| field.name == name)
+		.filter_map(|field| field.value.as_mut())
+		.fold(false, |changed, value| visit_value_parents(value, rest, visit) | changed)
+}
+
+fn visit_value_parents(
+	value: &mut FieldValue,
+	path: &str,
+	visit: &mut dyn FnMut(&mut Vec<Field>, &str) -> bool,
+) -> bool {
+	match value {
+		FieldValue::Group(fields) => visit_parent_fields(fields, path, visit),
+		FieldValue::Array(items) => items
+			.iter_mut()
+			.fold(false, |changed, item| visit_value_parents(item, path, visit) | changed),
+		_ => false,
+	}
 
 }
\ No newline at end of file
@@ -12,29 +12,81 @@ use std::collections::HashMap;
 use std::io::Write;
 use uuid::Uuid;
 
-use directories_next::ProjectDirs;
+use crate::atomic;
 use crate::builders;
-
-fn get_project_dirs() -> ProjectDirs {
-	ProjectDirs::from("org", "Hallock",  "Journaler").unwrap()
-}
-
-fn get_data_directory() -> std::path::PathBuf {
-	get_project_dirs().data_dir().to_path_buf()
-}
-
-fn get_projects_directory() -> std::path::PathBuf {
-	get_data_directory().join("projects")
-}
+use crate::config;
+use crate::definition_parser;
+use crate::definition_writer;
+use crate::event_log;
+use crate::lineage;
+use crate::reimport;
+use crate::schema;
 
 
 #[derive(Default, Clone, serde::Deserialize, serde::Serialize)]
 pub struct ProjectDefinition {
 	pub project_uuid: Uuid,
+	#[serde(default)]
+	pub version: u32,
+	#[serde(default)]
+	pub migrations: Vec<definition::Migration>,
 	pub event_templates: Vec<definition::EventTemplate>,
 	pub trace_templates: Vec<definition::TraceTemplate>,
 }
 
+impl ProjectDefinition {
+	// The same templates under new uuids for another project. The migrations stay behind, the
+	// new project has no events from older versions of the definition.
+	pub fn duplicate(&self, project_uuid: Uuid) -> ProjectDefinition {
+		let new_uuids: HashMap<Uuid, Uuid> = self
+			.trace_templates
+			.iter()
+			.map(|trace_template| trace_template.trace_template_uuid)
+			.chain(
+				self.event_templates
+					.iter()
+					.map(|event_template| event_template.event_template_uuid),
+			)
+			.map(|uuid| (uuid, Uuid::new_v4()))
+			.collect();
+		let new_uuid = |uuid: &Uuid| new_uuids.get(uuid).copied().unwrap_or(*uuid);
+		let created_at = Utc::now();
+		ProjectDefinition {
+			project_uuid,
+			version: self.version,
+			migrations: vec![],
+			event_templates: self
+				.event_templates
+				.iter()
+				.map(|event_template| definition::EventTemplate {
+					event_template_uuid: new_uuid(&event_template.event_template_uuid),
+					trace_template_uuid: new_uuid(&event_template.trace_template_uuid),
+					created_at,
+					..event_template.clone()
+				})
+				.collect(),
+			trace_templates: self
+				.trace_templates
+				.iter()
+				.map(|trace_template| definition::TraceTemplate {
+					trace_template_uuid: new_uuid(&trace_template.trace_template_uuid),
+					project_uuid,
+					created_at,
+					flow: trace_template
+						.flow
+						.iter()
+						.map(|entry| definition::TraceFlowEntry {
+							from: new_uuid(&entry.from),
+							to: entry.to.iter().map(new_uuid).collect(),
+						})
+						.collect(),
+					..trace_template.clone()
+				})
+				.collect(),
+		}
+	}
+}
+
 #[derive(Default, Clone, serde::Deserialize, serde::Serialize)]
 pub struct ProjectData {
 	pub project_uuid: Uuid,
@@ -42,14 +94,33 @@ pub struct ProjectData {
 	pub traces: Vec<events::Trace>,
 }
 
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug, serde::Deserialize, serde::Serialize)]
+pub enum StorageMode {
+	// Nothing is written until save_to_disk, which rewrites every project
+	#[default]
+	Snapshot,
+	// Events and traces are appended to their project's log as they are saved,
+	// save_to_disk folds the logs into the snapshot
+	Log,
+}
+
+// Records a project's log may hold before it is folded into the snapshot
+const COMPACTION_THRESHOLD: usize = 1000;
+
 #[derive(Default, Clone, serde::Deserialize, serde::Serialize)]
 pub struct FileSystemEventsService {
+	// Left empty, the directory comes from the environment, see config::DataLocation
 	pub projects_directory: std::path::PathBuf,
 	pub event_templates: HashMap<Uuid, definition::EventTemplate>,
 	pub trace_templates: HashMap<Uuid, definition::TraceTemplate>,
 	pub events: HashMap<Uuid, events::Event>,
 	pub traces: HashMap<Uuid, events::Trace>,
 	pub projects: HashMap<Uuid, project::Project>,
+	#[serde(default)]
+	pub storage_mode: StorageMode,
+	// Records in each project's log since its last snapshot
+	#[serde(skip)]
+	log_lengths: HashMap<Uuid, usize>,
 }
 
 impl service::EventsService for FileSystemEventsService {
@@ -57,22 +128,23 @@ impl service::EventsService for FileSystemEventsService {
 		&self,
 		project_uuid: Option<Uuid>,
 	) -> Box<dyn Iterator<Item = views::EventTemplateItemView>> {
-		let last_used: HashMap<Uuid, DateTime<Utc>> = self
+		// When each template last had an event
+		let mut last_used: HashMap<Uuid, DateTime<Utc>> = HashMap::new();
+		for event in self.events.values().filter(|event| !event.is_deleted()) {
+			let used = last_used.entry(event.event_template_uuid).or_insert(event.created_at);
+			*used = (*used).max(event.created_at);
+		}
+		let mut ret: Vec<views::EventTemplateItemView> = self
 			.event_templates
-			.iter()
-			.filter(|(_, trace)| {
-				project_uuid.as_ref().map_or(true, |uuid| {
-					self.trace_templates
-						.get(&trace.trace_template_uuid)
-						.map_or(false, |trace_template| &trace_template.project_uuid == uuid)
+			.values()
+			.filter(|event_template| {
+				project_uuid.as_ref().is_none_or(|project_uuid| {
+					self.project_contains_event_template(project_uuid, &event_template.event_template_uuid)
 				})
 			})
-			.map(|(uuid, trace)| (trace.event_template_uuid, trace.created_at))
-			.collect();
-		let mut ret: Vec<views::EventTemplateItemView> = self
-			.event_templates
-			.iter()
-			.map(|(uuid, template)| template.to_item(last_used.get(&uuid).cloned()))
+			.map(|event_template| {
+				event_template.to_item(last_used.get(&event_template.event_template_uuid).cloned())
+			})
 			.collect();
 		ret.sort_by(|a, b| a.last_used.cmp(&b.last_used));
 		Box::new(ret.into_iter())
@@ -82,12 +154,18 @@ impl service::EventsService for FileSystemEventsService {
 		&self,
 		trace: &views::TraceView,
 		template_uuid: Uuid,
+		history: service::HistoryScope,
 	) -> Result<service::EventBuilder, errors::EventTemplateNotFound> {
-		Ok(self
-			.event_templates
-			.get(&template_uuid)
-			.ok_or(template_uuid.into())?
-			.create_builder(trace))
+		let event_template = self.event_templates.get(&template_uuid).ok_or(template_uuid)?;
+		let mut event_builder = event_template.create_builder(trace);
+		builders::suggest_values(
+			&mut event_builder.fields,
+			event_template,
+			history,
+			&self.recent_events(template_uuid, trace.trace_uuid, service::HistoryScope::SameTrace),
+			&self.recent_events(template_uuid, trace.trace_uuid, service::HistoryScope::AnyTrace),
+		);
+		Ok(event_builder)
 	}
 	
 	fn create_trace(
@@ -97,26 +175,137 @@ impl service::EventsService for FileSystemEventsService {
 		Ok(self
 			.trace_templates
 			.get(&trace_template_uuid)
-			.ok_or(trace_template_uuid.into())?
+			.ok_or(trace_template_uuid)?
 			.create_builder())
 	}
 
-	fn complete_trace(&mut self, trace_uuid: Uuid) {
-		if let Some(trace) = self.traces.get_mut(&trace_uuid) {
+	fn complete_trace(&mut self, trace_uuid: Uuid) -> Result<(), errors::ChangeTraceError> {
+		self.change_trace(trace_uuid, |trace| {
 			trace.completion = Some(views::TraceCompletion {
 				completed_at: Utc::now(),
-			});
+			})
+		})
+	}
+
+	fn reopen_trace(&mut self, trace_uuid: Uuid) -> Result<(), errors::ChangeTraceError> {
+		self.change_trace(trace_uuid, |trace| trace.completion = None)
+	}
+
+	fn rename_trace(&mut self, trace_uuid: Uuid, name: String) -> Result<(), errors::ChangeTraceError> {
+		self.change_trace(trace_uuid, |trace| trace.name = name)
+	}
+
+	fn change_trace_template(
+		&mut self,
+		trace_uuid: Uuid,
+		trace_template_uuid: Uuid,
+	) -> Result<(), errors::ChangeTraceTemplateError> {
+		// Projects are saved separately, so the trace has to stay within its project
+		let project_uuid = self.project_of_trace(&trace_uuid);
+		if self
+			.trace_templates
+			.get(&trace_template_uuid)
+			.is_none_or(|trace_template| {
+				project_uuid.is_some_and(|project_uuid| trace_template.project_uuid != project_uuid)
+			}) {
+			return Err(errors::ChangeTraceTemplateError::TraceTemplateNotFound(
+				trace_template_uuid.into(),
+			));
 		}
+		Ok(self.change_trace(trace_uuid, |trace| trace.trace_template_uuid = trace_template_uuid)?)
+	}
+
+	fn archive_trace(&mut self, trace_uuid: Uuid) -> Result<(), errors::ChangeTraceError> {
+		self.change_trace(trace_uuid, |trace| {
+			trace.archived_at.get_or_insert(Utc::now());
+		})
 	}
 
-	fn save_event(&mut self, event_builder: &service::EventBuilder) {
+	fn unarchive_trace(&mut self, trace_uuid: Uuid) -> Result<(), errors::ChangeTraceError> {
+		self.change_trace(trace_uuid, |trace| trace.archived_at = None)
+	}
+
+	fn save_event(&mut self, event_builder: &service::EventBuilder) -> Result<(), errors::SaveEventError> {
 		let event = builders::build_event(event_builder);
-		self.events.insert(event.event_uuid, event);
+		if !self.traces.contains_key(&event.trace_uuid) {
+			return Err(errors::SaveEventError::TraceNotFound(event.trace_uuid.into()));
+		}
+		let record = event_log::LogRecord::Event(event.clone());
+		let trace_uuid = event.trace_uuid;
+		let event_uuid = event.event_uuid;
+		self.events.insert(event_uuid, event);
+		self.log(&trace_uuid, record).map_err(|err| {
+			self.events.remove(&event_uuid);
+			errors::SaveEventError::Storage(err)
+		})
+	}
+
+	fn edit_event(&self, event_uuid: Uuid) -> Result<service::EventBuilder, errors::EventNotFound> {
+		let event = self
+			.events
+			.get(&event_uuid)
+			.filter(|event| !event.is_deleted())
+			.ok_or(event_uuid)?;
+		Ok(builders::edit_event_builder(
+			event,
+			self.event_templates.get(&event.event_template_uuid),
+			self.traces.get(&event.trace_uuid).map(|trace| self.view_trace_item(trace)),
+		))
+	}
+
+	fn update_event(&mut self, event_builder: &service::EventBuilder) -> Result<(), errors::ChangeEventError> {
+		let event_uuid = event_builder.uuid;
+		if self.events.get(&event_uuid).is_none_or(|event| event.is_deleted()) {
+			return Err(errors::ChangeEventError::EventNotFound(event_uuid.into()));
+		}
+		// An event of a trace that is not there would not be saved with any project
+		if let service::TraceSelection::Selected(trace) = &event_builder.selected_trace {
+			if !self.traces.contains_key(&trace.trace_uuid) {
+				return Err(errors::ChangeEventError::TraceNotFound(trace.trace_uuid.into()));
+			}
+		}
+		let fields = builders::build_fields(event_builder);
+		self.change_event(event_uuid, |event| {
+			let trace_uuid = match &event_builder.selected_trace {
+				service::TraceSelection::Selected(trace) => trace.trace_uuid,
+				service::TraceSelection::None => event.trace_uuid,
+			};
+			event.revise(trace_uuid, fields, event_builder.tags.clone(), event_builder.began_at);
+		})
+	}
+
+	fn delete_event(&mut self, event_uuid: Uuid) -> Result<(), errors::ChangeEventError> {
+		self.change_event(event_uuid, |event| event.delete())
+	}
+
+	fn undo_event_change(&mut self, event_uuid: Uuid) -> Result<bool, errors::ChangeEventError> {
+		let mut undone = false;
+		self.change_event(event_uuid, |event| undone = event.undo())?;
+		Ok(undone)
 	}
 
-	fn save_trace(&mut self, trace_builder: &service::TraceBuilder) {
-		let trace = builders::build_trace(trace_builder);
-		self.traces.insert(trace.trace_uuid, trace);
+	fn save_trace(&mut self, trace_builder: &service::TraceBuilder) -> Result<(), errors::SaveTraceError> {
+		let trace_template_uuid = trace_builder.trace_template.trace_template_uuid;
+		let project_uuid = self
+			.trace_templates
+			.get(&trace_template_uuid)
+			.map(|trace_template| trace_template.project_uuid)
+			.ok_or(errors::SaveTraceError::TraceTemplateNotFound(trace_template_uuid.into()))?;
+		let trace = builders::build_trace(trace_builder, project_uuid);
+		if let Some(missing) = trace
+			.origin_trace_uuids
+			.iter()
+			.find(|origin_uuid| !self.traces.contains_key(origin_uuid))
+		{
+			return Err(errors::SaveTraceError::OriginTraceNotFound((*missing).into()));
+		}
+		let record = event_log::LogRecord::Trace(trace.clone());
+		let trace_uuid = trace.trace_uuid;
+		self.traces.insert(trace_uuid, trace);
+		self.log(&trace_uuid, record).map_err(|err| {
+			self.traces.remove(&trace_uuid);
+			errors::SaveTraceError::Storage(err)
+		})
 	}
 
 	fn view_event(&self, event_uuid: Uuid) -> Option<views::EventView> {
@@ -139,6 +328,7 @@ impl service::EventsService for FileSystemEventsService {
 			Box::new(
 				self.events
 					.values()
+					.filter(|trace| !trace.is_deleted())
 					.filter(move |trace| {
 						self.project_contains_event(&project_uuid, &trace.event_uuid)
 					})
@@ -148,26 +338,34 @@ impl service::EventsService for FileSystemEventsService {
 			Box::new(
 				self.events
 					.values()
+					.filter(|trace| !trace.is_deleted())
 					.map(|trace| self.view_found_event_item(trace)),
 			)
 		}
 	}
 
+	fn summarize_duration(
+		&self,
+		event_template_uuid: Uuid,
+		field_path: &str,
+	) -> Result<views::DurationSummaryView, errors::EventTemplateNotFound> {
+		self.event_templates.get(&event_template_uuid).ok_or(event_template_uuid)?;
+		Ok(events::summarize_duration(
+			self.events.values().filter(|event| event.event_template_uuid == event_template_uuid),
+			field_path,
+		))
+	}
+
 	fn list_traces(
 		&self,
 		project_uuid: Option<Uuid>,
+		filter: service::TraceFilter,
 	) -> Box<dyn Iterator<Item = views::TraceItemView> + '_> {
 		Box::new(
 			self.traces
 				.values()
-				.filter(|&trace| trace.completion.is_none())
-				.filter(move |trace| {
-					project_uuid.as_ref().map_or(true, |uuid| {
-						self.trace_templates
-							.get(&trace.trace_template_uuid)
-							.map_or(false, |trace_template| &trace_template.project_uuid == uuid)
-					})
-				})
+				.filter(move |&trace| filter.matches(trace.completion.is_some(), trace.archived_at.is_some()))
+				.filter(move |trace| project_uuid.as_ref().is_none_or(|uuid| &trace.project_uuid == uuid))
 				.map(|trace| (trace, self.trace_templates.get(&trace.trace_template_uuid)))
 				// .flat_map(
 				// 	|(trace, trace_template)|
@@ -211,136 +409,463 @@ impl service::EventsService for FileSystemEventsService {
 		Box::new(ret.into_iter())
 	}
 
+	fn view_lineage(
+		&self,
+		trace_uuid: Uuid,
+		direction: service::LineageDirection,
+	) -> Result<views::LineageView, errors::TraceNotFound> {
+		lineage::view_lineage(&self.traces, trace_uuid, &direction, |trace| {
+			self.view_trace_item(trace)
+		})
+	}
+
+	fn find_lineage_path(
+		&self,
+		from_trace_uuid: Uuid,
+		to_trace_uuid: Uuid,
+	) -> Result<Option<Vec<views::LineagePathStepView>>, errors::TraceNotFound> {
+		lineage::find_lineage_path(&self.traces, from_trace_uuid, to_trace_uuid, |trace| {
+			self.view_trace_item(trace)
+		})
+	}
+
 	fn import_definition(
 		&mut self,
 		project_name: String,
-		definition_path: &std::path::PathBuf,
-	) -> Result<(), errors::ParsingError> {
-		let definition_file = std::fs::File::open(definition_path).map_err(|e| e.into())?;
-		let definition_js: serde_json::Value =
-			serde_json::from_reader(definition_file).map_err(|e| e.into())?;
-
-		let project_uuid = Self::parse_required_uuid(&definition_js, "project-uuid")?;
-		let project_definition = ProjectDefinition {
+		definition_path: &std::path::Path,
+		into_project: Option<Uuid>,
+	) -> Result<views::ImportReport, errors::DefinitionErrors> {
+		let planned = self.plan_definition_import(definition_path, into_project)?;
+		let project_definition = planned.project_definition;
+		let project_uuid = project_definition.project_uuid;
+
+		let project = self.projects.entry(project_uuid).or_insert(project::Project {
 			project_uuid,
-			event_templates: definition_js
-				.get("event-templates")
-				.map_or(Ok(vec![]), |val| {
-					FileSystemEventsService::parse_event_templates(val, project_uuid)
-				})?,
-			trace_templates: definition_js
-				.get("trace-templates")
-				.map_or(Ok(vec![]), |val| {
-					FileSystemEventsService::parse_trace_templates(val, project_uuid)
-				})?,
-		};
+			name: project_name.clone(),
+			description: String::new(),
+			created_at: Utc::now(),
+			definition_version: 0,
+			migrations: vec![],
+		});
+		project.name = project_name;
+		project.definition_version = project_definition.version;
+		project.migrations = project_definition.migrations.clone();
+		self.events.extend(
+			planned
+				.reimport
+				.migrated_events
+				.into_iter()
+				.map(|event| (event.event_uuid, event)),
+		);
+		self.replace_project_definition(project_definition);
+		// Logged records have to match the definition they are replayed against
+		// The import is applied either way, a failed snapshot is written again by the next one
+		if self.storage_mode == StorageMode::Log {
+			if let Err(err) = self.compact(&project_uuid) {
+				log::error!("Failed to write a snapshot of project {}: {}", project_uuid, err);
+			}
+		}
+		Ok(views::ImportReport {
+			project_uuid,
+			warnings: planned.warnings,
+			changes: planned.reimport.changes,
+		})
+	}
+
+	fn preview_definition_import(
+		&self,
+		definition_path: &std::path::Path,
+		into_project: Option<Uuid>,
+	) -> Result<views::ImportReport, errors::DefinitionErrors> {
+		let planned = self.plan_definition_import(definition_path, into_project)?;
+		Ok(views::ImportReport {
+			project_uuid: planned.project_definition.project_uuid,
+			warnings: planned.warnings,
+			changes: planned.reimport.changes,
+		})
+	}
+
+	fn export_definition(
+		&self,
+		project_uuid: Uuid,
+		format: service::DefinitionFormat,
+	) -> Result<String, errors::ProjectNotFound> {
+		if !self.projects.contains_key(&project_uuid) {
+			return Err(project_uuid.into());
+		}
+		Ok(definition_writer::write_definition(
+			&self.collect_project_definition(&project_uuid),
+			format,
+		))
+	}
+
+	fn list_projects(&self) -> Box<dyn Iterator<Item = views::ProjectItemView> + '_> {
+		let mut projects: Vec<views::ProjectItemView> = self
+			.projects
+			.values()
+			.map(|project| self.view_project_item(project))
+			.collect();
+		projects.sort_by_key(|project| project.created_at);
+		Box::new(projects.into_iter())
+	}
+
+	fn view_project(&self, project_uuid: Uuid) -> Option<views::ProjectView> {
+		let project = self.projects.get(&project_uuid)?;
+		let mut event_templates: Vec<views::EventTemplateItemView> = self
+			.event_templates
+			.values()
+			.filter(|event_template| {
+				self.project_contains_event_template(&project_uuid, &event_template.event_template_uuid)
+			})
+			.map(|event_template| event_template.to_item(None))
+			.collect();
+		event_templates.sort_by(|a, b| a.name.cmp(&b.name));
+		let mut trace_templates: Vec<views::TraceTemplateItemView> = self
+			.trace_templates
+			.values()
+			.filter(|trace_template| trace_template.project_uuid == project_uuid)
+			.map(|trace_template| trace_template.to_item(None))
+			.collect();
+		trace_templates.sort_by(|a, b| a.name.cmp(&b.name));
+		Some(project.to_view(self.view_project_item(project), event_templates, trace_templates))
+	}
+
+	fn create_project(&mut self, name: String, description: String) -> Result<views::ProjectView, std::io::Error> {
+		let project_uuid = Uuid::new_v4();
 		self.projects.insert(
-			project_definition.project_uuid,
+			project_uuid,
 			project::Project {
-				project_uuid: project_definition.project_uuid,
-				name: project_name,
+				project_uuid,
+				name,
+				description,
 				created_at: Utc::now(),
+				definition_version: 0,
+				migrations: vec![],
 			},
 		);
-		self.import_project_definition(project_definition);
-		Ok(())
+		if let Err(err) = self.write_changed_project(&project_uuid) {
+			self.projects.remove(&project_uuid);
+			return Err(err);
+		}
+		self.view_project(project_uuid).ok_or_else(|| {
+			std::io::Error::new(std::io::ErrorKind::NotFound, format!("project {} is not there", project_uuid))
+		})
 	}
 
-	fn save_to_disk(&self) -> Result<(), std::io::Error> {
-		let projects_directory = get_projects_directory();
-		println!("Writing projects to disk: {:?}", projects_directory);
-		for (uuid, project) in self.projects.iter() {
-			let project_path = projects_directory.join(uuid.to_string());
-			std::fs::create_dir_all(&project_path)?;
-
-			{
-				let project_file = project_path.join("project.json");
-				let file = std::fs::File::create(project_file)?;
-				let writer = std::io::BufWriter::new(&file);
-				serde_json::to_writer_pretty(writer, project)?;
-			}
+	fn rename_project(&mut self, project_uuid: Uuid, name: String) -> Result<(), errors::ChangeProjectError> {
+		self.change_project(project_uuid, |project| project.name = name)
+	}
 
-			{
-				let project_data = self.collect_project_data(uuid);
-				let project_data_file = project_path.join("data.json");
-				let file = std::fs::File::create(project_data_file)?;
-				let writer = std::io::BufWriter::new(&file);
-				serde_json::to_writer_pretty(writer, &project_data)?;
-			}
+	fn describe_project(
+		&mut self,
+		project_uuid: Uuid,
+		description: String,
+	) -> Result<(), errors::ChangeProjectError> {
+		self.change_project(project_uuid, |project| project.description = description)
+	}
 
-			{
-				let project_definition = self.collect_project_definition(uuid);
-				println!(
-					"Writing project definition to disk: {:}",
-					&self.event_templates.len()
-				);
-				println!(
-					"Writing project definition to disk: {:?}",
-					&project_definition.event_templates.len()
-				);
-				let project_definition_file = project_path.join("definition.json");
-
-				let file = std::fs::File::create(project_definition_file)?;
-				let writer = std::io::BufWriter::new(&file);
-				serde_json::to_writer_pretty(writer, &project_definition)?;
-			}
+	fn delete_project(
+		&mut self,
+		project_uuid: Uuid,
+		confirmation: &str,
+	) -> Result<(), errors::DeleteProjectError> {
+		let Some(project) = self.projects.get(&project_uuid) else {
+			return Err(errors::DeleteProjectError::ProjectNotFound(project_uuid.into()));
+		};
+		if project.name != confirmation {
+			return Err(errors::DeleteProjectError::NotConfirmed);
+		}
+		// Off the disk first, otherwise the next load would bring the project back
+		let project_path = self
+			.projects_directory()
+			.map_err(errors::DeleteProjectError::Storage)?
+			.join(project_uuid.to_string());
+		if project_path.exists() {
+			std::fs::remove_dir_all(&project_path).map_err(errors::DeleteProjectError::Storage)?;
 		}
 
+		let project_data = self.collect_project_data(&project_uuid);
+		for event in project_data.events {
+			self.events.remove(&event.event_uuid);
+		}
+		for trace in project_data.traces {
+			self.traces.remove(&trace.trace_uuid);
+		}
+		self.remove_project_definition(&project_uuid);
+		self.projects.remove(&project_uuid);
+		self.log_lengths.remove(&project_uuid);
 		Ok(())
 	}
 
-	fn load_from_disk(&mut self) -> Result<(), std::io::Error> {
-		let projects_directory = &get_projects_directory();
+	fn duplicate_project(
+		&mut self,
+		project_uuid: Uuid,
+		name: String,
+	) -> Result<views::ProjectView, errors::ChangeProjectError> {
+		let project = self.projects.get(&project_uuid).ok_or(errors::ProjectNotFound::from(project_uuid))?;
+		let duplicate_uuid = Uuid::new_v4();
+		let project_definition = self.collect_project_definition(&project_uuid).duplicate(duplicate_uuid);
+		let duplicate = project::Project {
+			project_uuid: duplicate_uuid,
+			name,
+			description: project.description.clone(),
+			created_at: Utc::now(),
+			definition_version: project_definition.version,
+			migrations: vec![],
+		};
+		self.projects.insert(duplicate_uuid, duplicate);
+		self.import_project_definition(project_definition);
+		if let Err(err) = self.write_changed_project(&duplicate_uuid) {
+			self.remove_project_definition(&duplicate_uuid);
+			self.projects.remove(&duplicate_uuid);
+			return Err(errors::ChangeProjectError::Storage(err));
+		}
+		Ok(self.view_project(duplicate_uuid).ok_or(errors::ProjectNotFound::from(duplicate_uuid))?)
+	}
+
+	fn save_to_disk(&self) -> Result<(), std::io::Error> {
+		self.save(&self.projects_directory()?)
+	}
+
+	fn load_from_disk(&mut self, mode: service::LoadMode) -> Result<views::LoadReport, std::io::Error> {
+		let projects_directory = &self.projects_directory()?;
+		// Nothing has been saved yet on first run
+		std::fs::create_dir_all(projects_directory)?;
+
+		// Everything is read before anything changes, so a failure leaves memory as it was
+		let mut report = views::LoadReport::default();
+		let mut stored_projects = vec![];
 		for entry in std::fs::read_dir(projects_directory)? {
-			let entry = entry?;
-			let path = entry.path();
+			let path = entry?.path();
 			if !path.is_dir() {
 				continue;
 			}
-
-			{
-				let project_path = path.join("project.json");
-				if !project_path.exists() {
-					continue;
+			match read_stored_project(&path) {
+				Ok((recovery, stored_project)) => {
+					if recovery != atomic::Recovery::Clean {
+						report.recovered_projects.push(stored_project.project.project_uuid);
+					}
+					stored_projects.push(stored_project);
+				}
+				Err(reason) => {
+					report.skipped_projects.push(views::SkippedProjectView {
+						directory: path.display().to_string(),
+						reason,
+					});
 				}
-				let file: std::fs::File = std::fs::File::open(&project_path)?;
-				// let file_string = std::fs::read_to_string(&project_path)?;
-				// println!("About to read the project: {}", file_string);
-				// let project: project::Project = serde_json::from_str(&file_string)?;
-				let project: project::Project = serde_json::from_reader(file)?;
-				// println!("Read the project");
-				self.projects.insert(project.project_uuid, project);
 			}
+		}
 
-			{
-				let definition_path = path.join("definition.json");
-				if !definition_path.exists() {
-					eprint!("Definition path does not exist: {:?}", definition_path);
-					continue;
-				}
-				let file = std::fs::File::open(&definition_path)?;
-				let project_definition: ProjectDefinition = serde_json::from_reader(file)?;
-				self.import_project_definition(project_definition);
+		if mode == service::LoadMode::Replace {
+			*self = FileSystemEventsService {
+				projects_directory: self.projects_directory.clone(),
+				storage_mode: self.storage_mode,
+				..Default::default()
+			};
+		}
+		for stored_project in stored_projects {
+			report.loaded_projects.push(stored_project.project.project_uuid);
+			if let Some(conflict) = self.merge_stored_project(stored_project) {
+				report.conflicts.push(conflict);
 			}
+		}
+		report.loaded_projects.sort();
+		report
+			.skipped_projects
+			.sort_by(|a, b| a.directory.cmp(&b.directory));
+		Ok(report)
+	}
 
-			{
-				let data_path = path.join("data.json");
-				if !data_path.exists() {
-					eprint!("Data path does not exist: {:?}", data_path);
-					continue;
-				}
-				let file = std::fs::File::open(&data_path)?;
-				let project_data: ProjectData = serde_json::from_reader(file)?;
-				self.import_project_data(project_data);
+}
+
+// A project as it was last saved, with its log replayed
+struct StoredProject {
+	project: project::Project,
+	definition: ProjectDefinition,
+	events: HashMap<Uuid, events::Event>,
+	traces: HashMap<Uuid, events::Trace>,
+	log_length: usize,
+}
+
+fn read_project_file<T: serde::de::DeserializeOwned>(
+	path: &std::path::Path,
+	name: &str,
+	kind: schema::FileKind,
+) -> Result<T, String> {
+	let contents = std::fs::read(path.join(name)).map_err(|err| match err.kind() {
+		std::io::ErrorKind::NotFound => format!("{} is missing", name),
+		_ => format!("{} could not be opened: {}", name, err),
+	})?;
+	schema::from_slice(kind, &contents).map_err(|err| format!("{} could not be read: {}", name, err))
+}
+
+// Reads one project directory. Whatever makes it unusable is returned as the reason to skip it.
+fn read_stored_project(path: &std::path::Path) -> Result<(atomic::Recovery, StoredProject), String> {
+	let project_uuid = path
+		.file_name()
+		.and_then(|name| name.to_str())
+		.and_then(|name| Uuid::parse_str(name).ok())
+		.ok_or("the directory is not named after a project uuid".to_string())?;
+	let recovery = atomic::recover(path)
+		.map_err(|err| format!("an interrupted save could not be recovered: {}", err))?;
+
+	let project: project::Project = read_project_file(path, "project.json", schema::FileKind::Project)?;
+	let definition: ProjectDefinition =
+		read_project_file(path, "definition.json", schema::FileKind::Definition)?;
+	let data: ProjectData = read_project_file(path, "data.json", schema::FileKind::Data)?;
+	for (name, uuid) in [
+		("project.json", project.project_uuid),
+		("definition.json", definition.project_uuid),
+		("data.json", data.project_uuid),
+	] {
+		if uuid != project_uuid {
+			return Err(format!("{} belongs to project {}", name, uuid));
+		}
+	}
+
+	let mut stored_project = StoredProject {
+		project,
+		definition,
+		events: data
+			.events
+			.into_iter()
+			.map(|event| (event.event_uuid, event))
+			.collect(),
+		traces: data
+			.traces
+			.into_iter()
+			.map(|trace| (trace.trace_uuid, trace))
+			.collect(),
+		log_length: 0,
+	};
+	// Saved after the snapshot, so replayed on top of it
+	let records = event_log::replay(path)
+		.map_err(|err| format!("{} could not be read: {}", event_log::LOG_FILE, err))?;
+	stored_project.log_length = records.len();
+	for record in records {
+		match record {
+			event_log::LogRecord::Event(event) => {
+				stored_project.events.insert(event.event_uuid, event);
+			}
+			event_log::LogRecord::Trace(trace) => {
+				stored_project.traces.insert(trace.trace_uuid, trace);
 			}
 		}
+	}
+	// The directory is the project, whatever older saves left out
+	for trace in stored_project.traces.values_mut() {
+		trace.project_uuid = project_uuid;
+	}
+	Ok((recovery, stored_project))
+}
+
+fn same_json(a: &impl serde::Serialize, b: &impl serde::Serialize) -> bool {
+	serde_json::to_value(a).ok() == serde_json::to_value(b).ok()
+}
+
+impl FileSystemEventsService {
+	pub fn new(projects_directory: std::path::PathBuf) -> Self {
+		FileSystemEventsService {
+			projects_directory,
+			..Default::default()
+		}
+	}
+
+	pub fn projects_directory(&self) -> Result<std::path::PathBuf, std::io::Error> {
+		if self.projects_directory.as_os_str().is_empty() {
+			return config::DataLocation::from_env().projects_directory();
+		}
+		Ok(self.projects_directory.clone())
+	}
+
+	// Writes the project in full. Whatever its log held is part of the snapshot afterwards,
+	// so the log is dropped; replaying it again after a crash in between changes nothing.
+	fn write_snapshot(
+		&self,
+		projects_directory: &std::path::Path,
+		project_uuid: &Uuid,
+	) -> Result<(), std::io::Error> {
+		let Some(project) = self.projects.get(project_uuid) else {
+			return Ok(());
+		};
+		let project_path = projects_directory.join(project_uuid.to_string());
+		let project_definition = self.collect_project_definition(project_uuid);
+		// Serialized up front so a failure leaves the files on disk untouched
+		atomic::write_files(
+			&project_path,
+			&[
+				("project.json", schema::to_vec_pretty(project)?),
+				("data.json", schema::to_vec_pretty(&self.collect_project_data(project_uuid))?),
+				("definition.json", schema::to_vec_pretty(&project_definition)?),
+			],
+		)?;
+		event_log::remove(&project_path)
+	}
+
+	fn project_of_trace(&self, trace_uuid: &Uuid) -> Option<Uuid> {
+		self.traces.get(trace_uuid).map(|trace| trace.project_uuid)
+	}
 
+	// Folds the project's log into a new snapshot
+	fn compact(&mut self, project_uuid: &Uuid) -> std::io::Result<()> {
+		self.write_snapshot(&self.projects_directory()?, project_uuid)?;
+		self.log_lengths.insert(*project_uuid, 0);
 		Ok(())
 	}
 
+	// In log mode, makes a saved event or trace durable right away. The caller undoes the
+	// change in memory when the record cannot be written.
+	fn log(&mut self, trace_uuid: &Uuid, record: event_log::LogRecord) -> std::io::Result<()> {
+		if self.storage_mode != StorageMode::Log {
+			return Ok(());
+		}
+		let Some(project_uuid) = self.project_of_trace(trace_uuid) else {
+			log::warn!("Not logging a record of trace {}, it is not saved", trace_uuid);
+			return Ok(());
+		};
+		let project_path = self.projects_directory()?.join(project_uuid.to_string());
+		match self.log_lengths.get(&project_uuid).copied() {
+			// A log is only read back on top of a snapshot, without one the snapshot is written instead
+			Some(length) if length < COMPACTION_THRESHOLD && project_path.join("project.json").exists() => {
+				event_log::append(&project_path, &record)?;
+				self.log_lengths.insert(project_uuid, length + 1);
+				Ok(())
+			}
+			_ => self.compact(&project_uuid),
+		}
+	}
 }
 
 impl FileSystemEventsService {
+	// Everything an import would do, worked out without changing anything
+	fn plan_definition_import(
+		&self,
+		definition_path: &std::path::Path,
+		into_project: Option<Uuid>,
+	) -> Result<reimport::PlannedImport, errors::DefinitionErrors> {
+		if let Some(project_uuid) = into_project {
+			if !self.projects.contains_key(&project_uuid) {
+				return Err(errors::ParsingError::new(format!("Project {} does not exist", project_uuid)).into());
+			}
+		}
+		let parsed = definition_parser::read_definition(definition_path, into_project)?;
+		let project_uuid = parsed.project_definition.project_uuid;
+		let current = self
+			.projects
+			.get(&project_uuid)
+			.map(|_| self.collect_project_definition(&project_uuid));
+		// Only the project's own data is migrated, including whatever an earlier import orphaned
+		let data = self.collect_project_data(&project_uuid);
+		reimport::plan_definition_import(
+			parsed,
+			current.as_ref(),
+			&self.event_templates,
+			&self.trace_templates,
+			data.events.iter(),
+			data.traces.iter(),
+		)
+	}
+
 	fn view_event_template_item(
 		&self,
 		event_template_uuid: Uuid,
@@ -350,23 +875,45 @@ impl FileSystemEventsService {
 			.map(|event_template| event_template.to_item(None))
 	}
 
+	// Opens the journal whose projects are kept in the directory, creating it when needed.
+	// The service keeps saving there.
 	pub fn load(path: &std::path::Path) -> std::io::Result<Self> {
-		Ok(Self::default())
-
-		// EventsInterface {
-		// 	templates: HashMap::new(),
-		// 	events: HashMap::new(),
-		// 	traces: HashMap::new(),
-		// }
+		let mut service = Self::new(path.to_path_buf());
+		service::EventsService::load_from_disk(&mut service, service::LoadMode::Replace)?;
+		Ok(service)
 	}
+
+	// Writes every project into the directory, which need not be the one the service uses
 	pub fn save(&self, path: &std::path::Path) -> std::io::Result<()> {
+		std::fs::create_dir_all(path)?;
+		for uuid in self.projects.keys() {
+			self.write_snapshot(path, uuid)?;
+		}
 		Ok(())
 	}
 
+	// The newest events of the template, newest first
+	fn recent_events(
+		&self,
+		event_template_uuid: Uuid,
+		trace_uuid: Uuid,
+		history: service::HistoryScope,
+	) -> Vec<&events::Event> {
+		let mut events: Vec<&events::Event> = self
+			.events
+			.values()
+			.filter(|event| event.event_template_uuid == event_template_uuid && !event.is_deleted())
+			.filter(|event| history == service::HistoryScope::AnyTrace || event.trace_uuid == trace_uuid)
+			.collect();
+		events.sort_by_key(|event| std::cmp::Reverse(event.created_at));
+		events.truncate(builders::HISTORY_EVENTS);
+		events
+	}
+
 	fn get_last_event_for_trace(&self, trace_uuid: Uuid) -> Option<views::EventItemView> {
 		self.events
 			.values()
-			.filter(|trace| trace.trace_uuid == trace_uuid)
+			.filter(|trace| trace.trace_uuid == trace_uuid && !trace.is_deleted())
 			.max_by_key(|trace| trace.created_at)
 			.map(|trace| self.view_found_event_item(trace))
 	}
@@ -395,8 +942,6 @@ impl FileSystemEventsService {
 					other_event_templates.push(event_template.to_item(last_used));
 				}
 			}
-		} else {
-			eprintln!("Trace template not found: {:?}", trace.trace_template_uuid);
 		}
 		views::TraceView {
 			trace_uuid: trace.trace_uuid,
@@ -407,20 +952,38 @@ impl FileSystemEventsService {
 				.map(|template| template.to_item(last_used)),
 			tags: vec![], // TODO
 			completion: trace.completion.clone(),
+			archived_at: trace.archived_at,
+			origin_traces: trace
+				.origin_trace_uuids
+				.iter()
+				.flat_map(|origin_uuid| self.traces.get(origin_uuid))
+				.map(|origin| self.view_trace_item(origin))
+				.collect(),
+			child_traces: self
+				.traces
+				.values()
+				.filter(|child| child.origin_trace_uuids.contains(&trace.trace_uuid))
+				.map(|child| self.view_trace_item(child))
+				.collect(),
 			last_event,
 			suggested_event_templates,
 			other_event_templates,
 		}
 	}
 
-	fn project_contains_trace(&self, project_uuid: &Uuid, trace_uuid: &Uuid) -> bool {
-		self.traces.get(trace_uuid).map_or(false, |trace| {
+	fn view_trace_item(&self, trace: &events::Trace) -> views::TraceItemView {
+		trace.to_item(
 			self.trace_templates
 				.get(&trace.trace_template_uuid)
-				.map_or(false, |trace_template| {
-					&trace_template.project_uuid == project_uuid
-				})
-		})
+				.map(|trace_template| trace_template.name.clone()),
+		)
+	}
+
+	// Traces and their events stay in their project even when a re-import removed their template
+	fn project_contains_trace(&self, project_uuid: &Uuid, trace_uuid: &Uuid) -> bool {
+		self.traces
+			.get(trace_uuid)
+			.is_some_and(|trace| &trace.project_uuid == project_uuid)
 	}
 
 	fn project_contains_trace_template(
@@ -453,7 +1016,6 @@ impl FileSystemEventsService {
 	fn project_contains_event(&self, project_uuid: &Uuid, event_uuid: &Uuid) -> bool {
 		self.events.get(&event_uuid).map_or(false, |trace| {
 			self.project_contains_trace(project_uuid, &trace.trace_uuid)
-				&& self.project_contains_event_template(project_uuid, &trace.event_template_uuid)
 		})
 	}
 
@@ -491,395 +1053,130 @@ impl FileSystemEventsService {
 			event_template: self.view_event_template_item(event.event_template_uuid),
 			fields: event.fields.iter().map(|x| x.to_view()).collect(),
 			tags: event.tags.clone(),
-			trace: self.traces.get(&event.trace_uuid).map(|trace| {
-				trace.to_item(
-					self.trace_templates
-						.get(&trace.trace_template_uuid)
-						.map(|trace_template| trace_template.name.clone()),
-				)
-			}),
+			trace: self
+				.traces
+				.get(&event.trace_uuid)
+				.map(|trace| self.view_trace_item(trace)),
 			began_at: event.began_at,
 			created_at: event.created_at,
+			deleted_at: event.deleted_at,
+			revisions: event.revisions.iter().map(|revision| revision.to_view()).collect(),
 		}
 	}
 
-	pub fn save_project(
-		project_path: &std::path::PathBuf,
-		project: &project::Project,
-	) -> Result<(), std::io::Error> {
-		let mut project_file = std::fs::File::create(project_path)?;
-		let project_str = serde_json::to_string(&project)?;
-		project_file.write_all(project_str.as_bytes())?;
-		Ok(())
-	}
-
-	fn parse_required_uuid(
-		val: &serde_json::Value,
-		field: &str,
-	) -> Result<Uuid, errors::ParsingError> {
-		Uuid::parse_str(
-			val.as_object()
-				.ok_or(errors::ParsingError {
-					message: "Expected object".into(),
-				})?
-				.get(field)
-				.ok_or_else(|| errors::ParsingError {
-					message: format!("Field {} is required", field),
-				})?
-				.as_str()
-				.ok_or_else(|| errors::ParsingError {
-					message: format!("Field {} is supposed to be a string", field),
-				})?,
-		)
-		.map_err(|e| errors::ParsingError {
-			message: format!("Failed to parse UUID: {}", e),
+	// Applies a change to a saved event and logs the result
+	fn change_event(
+		&mut self,
+		event_uuid: Uuid,
+		change: impl FnOnce(&mut events::Event),
+	) -> Result<(), errors::ChangeEventError> {
+		let event = self.events.get_mut(&event_uuid).ok_or(errors::EventNotFound::from(event_uuid))?;
+		let previous = event.clone();
+		change(event);
+		let record = event_log::LogRecord::Event(event.clone());
+		let trace_uuid = event.trace_uuid;
+		self.log(&trace_uuid, record).map_err(|err| {
+			self.events.insert(event_uuid, previous);
+			errors::ChangeEventError::Storage(err)
 		})
 	}
-	fn parse_optional_uuid(
-		val: &serde_json::Value,
-		field: &str,
-	) -> Result<Uuid, errors::ParsingError> {
-		val.as_object()
-			.ok_or(errors::ParsingError {
-				message: "Expected object".into(),
-			})?
-			.get(field)
-			.map(|val| val.as_str())
-			.flatten()
-			.map(|uuid| Uuid::parse_str(uuid))
-			.unwrap_or(Ok(Uuid::new_v4()))
-			.map_err(|e| errors::ParsingError {
-				message: e.to_string(),
-			})
-	}
-	fn parse_str(
-		val: &serde_json::Value,
-		field: &str,
-	) -> Result<Option<String>, errors::ParsingError> {
-		Ok(val
-			.as_object()
-			.ok_or(errors::ParsingError {
-				message: "Expected object".into(),
-			})?
-			.get(field)
-			.map(|val| val.as_str())
-			.flatten()
-			.map(|val| val.to_string()))
-	}
-
-	fn map_name(name: &str) -> String {
-		name.to_string().replace(" ", "-").to_lowercase()
-	}
-
-	fn parse_enumerated_template(
-		val: &serde_json::Value,
-	) -> Result<definition::EnumeratedTemplate, errors::ParsingError> {
-		let obj = val.as_object().ok_or(errors::ParsingError {
-			message: "Expected object".into(),
-		})?;
-		let options = obj
-			.get("options")
-			.ok_or(errors::ParsingError {
-				message: "Expected 'options' field".into(),
-			})?
-			.as_array()
-			.ok_or(errors::ParsingError {
-				message: "Expected 'options' to be an array".into(),
-			})?
-			.iter()
-			.map(|val| {
-				let option = val
-					.as_object()
-					.ok_or("Expected the option to be an object".into())?;
-				let label = option
-					.get("label")
-					.ok_or("Expected 'label' field".into())?
-					.as_str()
-					.ok_or("Expected 'label' to be a string".into())?;
-				let name = option
-					.get("name")
-					.map(|val| val.as_str())
-					.flatten()
-					.map(|val| val.to_string())
-					.unwrap_or(Self::map_name(label));
-				Ok(views::EnumerationOption {
-					name: name.to_string(),
-					label: label.to_string(),
-				})
-			})
-			.collect::<Result<Vec<views::EnumerationOption>, errors::ParsingError>>()?;
-
-		let default_value = obj
-			.get("default-value")
-			.map(|val| {
-				let name = val
-					.as_str()
-					.ok_or("Expected 'default-value' to be a string".into())
-					.map(|val| val.to_string())?;
-
-				let default_value = options
-					.iter()
-					.find(|option| option.name == name || option.label == name)
-					.ok_or(format!("Default value {} not found in options", name).into())?;
 
-				Ok(default_value.clone())
-			})
-			.transpose()?;
-		Ok(definition::EnumeratedTemplate {
-			default_value,
-			options,
+	fn change_project(
+		&mut self,
+		project_uuid: Uuid,
+		change: impl FnOnce(&mut project::Project),
+	) -> Result<(), errors::ChangeProjectError> {
+		let project = self.projects.get_mut(&project_uuid).ok_or(errors::ProjectNotFound::from(project_uuid))?;
+		let previous = project.clone();
+		change(project);
+		self.write_changed_project(&project_uuid).map_err(|err| {
+			self.projects.insert(project_uuid, previous);
+			errors::ChangeProjectError::Storage(err)
 		})
 	}
-	
-	fn parse_field_template(
-		val: &serde_json::Value,
-	) -> Result<definition::FieldTemplate, errors::ParsingError> {
-		val.as_object().map_or(
-			Err(errors::ParsingError {
-				message: "Expected object".into(),
-			}),
-			|obj| {
-				// for (key, value) in obj {
-				// 	println!("field: {} -> {}", key, value);
-				// }
-				let field_type =
-					Self::parse_str(val, "type")?.ok_or_else(|| errors::ParsingError {
-						message: "Field type is required".into(),
-					})?;
-				let label = Self::parse_str(val, "label")?.ok_or_else(|| errors::ParsingError {
-					message: "Field label is required".into(),
-				})?;
-				let name = Self::parse_str(val, "name")?.unwrap_or(Self::map_name(&label));
-
-				let default_value = val.get("default-value");
-
-				Ok(definition::FieldTemplate {
-					name,
-					label,
-					value: match field_type.as_str() {
-						"Number" => Ok(definition::FieldValueTemplate::Number(
-							definition::NumberTemplate {
-								default_value: default_value
-									.map(|val| {
-										val.as_f64().ok_or_else(|| errors::ParsingError {
-											message: "Expected a number".into(),
-										})
-									})
-									.transpose()?,
-							},
-						)),
-						"Text" => Ok(definition::FieldValueTemplate::Text(
-							definition::TextTemplate {
-								default_value: default_value
-									.map(|val| {
-										val.as_str().map(|val| val.to_string()).ok_or_else(|| {
-											errors::ParsingError {
-												message: "Expected a string".into(),
-											}
-										})
-									})
-									.transpose()?,
-							},
-						)),
-						"Boolean" => Ok(definition::FieldValueTemplate::Bool(
-							definition::BoolTemplate {
-								default_value: default_value
-									.map(|val| {
-										val.as_bool().ok_or_else(|| errors::ParsingError {
-											message: "Expected a boolean".into(),
-										})
-									})
-									.transpose()?,
-							},
-						)),
-						"Enumerated" => Ok(definition::FieldValueTemplate::Enumerated(
-							Self::parse_enumerated_template(val)?,
-						)),
-						_ => Err(format!("Unknown field type: {}", field_type).into()),
-					}?,
-				})
-			},
-		)
-	}
 
-	fn parse_field_templates(
-		val: &serde_json::Value,
-	) -> Result<Vec<definition::FieldTemplate>, errors::ParsingError> {
-		val.as_array().map_or(
-			Err(errors::ParsingError {
-				message: "Expected array".into(),
-			}),
-			|fields| {
-				fields
-					.iter()
-					.map(|field| Self::parse_field_template(field))
-					.collect()
-			},
-		)
-	}
-
-	fn parse_event_template(
-		val: &serde_json::Value,
-		project_uuid: Uuid,
-	) -> Result<definition::EventTemplate, errors::ParsingError> {
-		val.as_object().map_or(
-			Err(errors::ParsingError {
-				message: "Expected object".into(),
-			}),
-			|obj| {
-				Ok(definition::EventTemplate {
-					event_template_uuid: Self::parse_required_uuid(val, "event-template-uuid")?,
-					trace_template_uuid: Self::parse_required_uuid(val, "trace-template-uuid")?,
-					name: Self::parse_str(val, "name")?
-						.unwrap_or("Unnamed Event Template".to_string()),
-					created_at: Utc::now(), // TODO
-					fields: obj
-						.get("fields")
-						.map_or(Ok(vec![]), |fields| Self::parse_field_templates(fields))?,
-					default_tags: vec![], // TODO
-				})
-			},
-		)
+	fn remove_project_definition(&mut self, project_uuid: &Uuid) {
+		let project_definition = self.collect_project_definition(project_uuid);
+		for event_template in project_definition.event_templates {
+			self.event_templates.remove(&event_template.event_template_uuid);
+		}
+		for trace_template in project_definition.trace_templates {
+			self.trace_templates.remove(&trace_template.trace_template_uuid);
+		}
 	}
 
-	fn parse_event_templates(
-		val: &serde_json::Value,
-		project_uuid: Uuid,
-	) -> Result<Vec<definition::EventTemplate>, errors::ParsingError> {
-		val.as_array().map_or(
-			Err(errors::ParsingError {
-				message: "Expected array".into(),
-			}),
-			|event_templates| {
-				event_templates
-					.iter()
-					.map(|event_template| Self::parse_event_template(event_template, project_uuid))
-					.collect()
-			},
-		)
+	// The log only holds events and traces, in log mode a change to anything else is written
+	// as a snapshot right away
+	fn write_changed_project(&mut self, project_uuid: &Uuid) -> std::io::Result<()> {
+		if self.storage_mode == StorageMode::Log {
+			self.compact(project_uuid)?;
+		}
+		Ok(())
 	}
 
-	fn parse_trace_flow_entry(
-		// from: Uuid,
-		val: &serde_json::Value,
-	) -> Result<definition::TraceFlowEntry, errors::ParsingError> {
-		Ok(definition::TraceFlowEntry {
-			from: Self::parse_required_uuid(val, "from")?,
-			to: val
-				.as_object()
-				.ok_or(errors::ParsingError {
-					message: "Expected flow to be an object".into(),
-				})?
-				.get("to")
-				.ok_or(errors::ParsingError {
-					message: "Expected flow to have a 'to' field".into(),
-				})?
-				.as_array()
-				.ok_or(errors::ParsingError {
-					message: "Expected flow 'to' to be an array".into(),
-				})?
-				.iter()
-				.map(|val| {
-					val.as_str().map_or(
-						Err(errors::ParsingError {
-							message: format!("Expected a string, found: {:?}", val),
-						}),
-						|uuid_str| {
-							Uuid::parse_str(uuid_str).map_err(|e| errors::ParsingError {
-								message: e.to_string(),
-							})
-						},
-					)
+	fn view_project_item(&self, project: &project::Project) -> views::ProjectItemView {
+		let project_uuid = &project.project_uuid;
+		let traces: Vec<&events::Trace> = self
+			.traces
+			.values()
+			.filter(|trace| self.project_contains_trace(project_uuid, &trace.trace_uuid))
+			.collect();
+		let events: Vec<&events::Event> = self
+			.events
+			.values()
+			.filter(|event| !event.is_deleted() && self.project_contains_event(project_uuid, &event.event_uuid))
+			.collect();
+		views::ProjectItemView {
+			project_uuid: project.project_uuid,
+			name: project.name.clone(),
+			description: project.description.clone(),
+			created_at: project.created_at,
+			event_template_count: self
+				.event_templates
+				.keys()
+				.filter(|event_template_uuid| {
+					self.project_contains_event_template(project_uuid, event_template_uuid)
 				})
-				.collect::<Result<Vec<Uuid>, errors::ParsingError>>()?,
-		})
-		// val.as_array().map_or(
-		// 	Err(errors::ParsingError {
-		// 		message: "Expected a trace flow to be an object".into(),
-		// 	}),
-		// 	|arr: &Vec<serde_json::Value>| {
-		// 		Ok(definition::TraceFlowEntry {
-		// 			from,
-		// 			to: arr
-		// 				.iter()
-		// 				.map(|val| {
-		// 					val.as_str().map_or(
-		// 						Err(errors::ParsingError {
-		// 							message: format!("Expected a string, found: {:?}", val),
-		// 						}),
-		// 						|uuid_str| {
-		// 							Uuid::parse_str(uuid_str).map_err(|e| errors::ParsingError {
-		// 								message: e.to_string(),
-		// 							})
-		// 						},
-		// 					)
-		// 				})
-		// 				.collect::<Result<Vec<Uuid>, errors::ParsingError>>()?,
-		// 		})
-		// 	},
-		// )
-	}
-	fn parse_trace_flow_entries(
-		val: &serde_json::Value,
-	) -> Result<Vec<definition::TraceFlowEntry>, errors::ParsingError> {
-		val.as_array().map_or(
-			Err(errors::ParsingError {
-				message: format!("Expected an array of trace flow entries. Found: {:?}", val),
-			}),
-			|flow_entries| {
-				flow_entries
-					.iter()
-					.map(|flow_entry| {
-						Self::parse_trace_flow_entry(
-							// Uuid::parse_str(uuid_str).map_err(|e| errors::ParsingError {
-							// 	message: e.to_string(),
-							// })?,
-							flow_entry,
-						)
-					})
-					.collect()
-			},
-		)
+				.count(),
+			trace_template_count: self
+				.trace_templates
+				.values()
+				.filter(|trace_template| &trace_template.project_uuid == project_uuid)
+				.count(),
+			trace_count: traces.len(),
+			event_count: events.len(),
+			last_activity: traces
+				.iter()
+				.map(|trace| trace.created_at)
+				.chain(events.iter().map(|event| event.created_at))
+				.max(),
+		}
 	}
 
-	fn parse_trace_template(
-		val: &serde_json::Value,
-		project_uuid: Uuid,
-	) -> Result<definition::TraceTemplate, errors::ParsingError> {
-		val.as_object().map_or(
-			Err(errors::ParsingError {
-				message: "Expected object".into(),
-			}),
-			|obj| {
-				Ok(definition::TraceTemplate {
-					trace_template_uuid: Self::parse_required_uuid(val, "trace-template-uuid")?,
-					project_uuid,
-					name: Self::parse_str(val, "name")?
-						.unwrap_or("Unnamed Trace Template".to_string()),
-					created_at: Utc::now(), // TODO
-					flow: obj.get("transitions").map_or(Ok(vec![]), |transitions| {
-						Self::parse_trace_flow_entries(transitions)
-					})?,
-				})
-			},
-		)
+	// Applies a change to a trace and logs the result
+	fn change_trace(
+		&mut self,
+		trace_uuid: Uuid,
+		change: impl FnOnce(&mut events::Trace),
+	) -> Result<(), errors::ChangeTraceError> {
+		let trace = self.traces.get_mut(&trace_uuid).ok_or(errors::TraceNotFound::from(trace_uuid))?;
+		let previous = trace.clone();
+		change(trace);
+		let record = event_log::LogRecord::Trace(trace.clone());
+		self.log(&trace_uuid, record).map_err(|err| {
+			self.traces.insert(trace_uuid, previous);
+			errors::ChangeTraceError::Storage(err)
+		})
 	}
 
-	fn parse_trace_templates(
-		val: &serde_json::Value,
-		project_uuid: Uuid,
-	) -> Result<Vec<definition::TraceTemplate>, errors::ParsingError> {
-		val.as_array().map_or(
-			Err(errors::ParsingError {
-				message: "Expected array".into(),
-			}),
-			|trace_templates| {
-				trace_templates
-					.iter()
-					.map(|trace_template| Self::parse_trace_template(trace_template, project_uuid))
-					.collect()
-			},
-		)
+	pub fn save_project(
+		project_path: &std::path::PathBuf,
+		project: &project::Project,
+	) -> Result<(), std::io::Error> {
+		let mut project_file = std::fs::File::create(project_path)?;
+		let project_str = serde_json::to_string(&project)?;
+		project_file.write_all(project_str.as_bytes())?;
+		Ok(())
 	}
 
 	fn collect_project_data(&self, project_uuid: &Uuid) -> ProjectData {
@@ -900,8 +1197,11 @@ impl FileSystemEventsService {
 		}
 	}
 	fn collect_project_definition(&self, project_uuid: &Uuid) -> ProjectDefinition {
+		let project = self.projects.get(project_uuid);
 		ProjectDefinition {
 			project_uuid: project_uuid.clone(),
+			version: project.map_or(0, |project| project.definition_version),
+			migrations: project.map_or(vec![], |project| project.migrations.clone()),
 			event_templates: self
 				.event_templates
 				.values()
@@ -926,20 +1226,85 @@ impl FileSystemEventsService {
 				.collect(),
 		}
 	}
-	fn import_project_data(&mut self, project_data: ProjectData) {
-		self.events.extend(
-			project_data
-				.events
-				.into_iter()
-				.map(|trace| (trace.event_uuid, trace)),
-		);
-		self.traces.extend(
-			project_data
-				.traces
-				.into_iter()
-				.map(|trace| (trace.trace_uuid, trace)),
-		);
+	// Adds a project read from disk. When the project is already in memory, only the events and
+	// traces it does not have are added, and what differs is returned.
+	fn merge_stored_project(&mut self, stored_project: StoredProject) -> Option<views::LoadConflictView> {
+		let project_uuid = stored_project.project.project_uuid;
+		let Some(project) = self.projects.get(&project_uuid) else {
+			self.projects.insert(project_uuid, stored_project.project);
+			self.import_project_definition(stored_project.definition);
+			self.events.extend(stored_project.events);
+			self.traces.extend(stored_project.traces);
+			self.log_lengths.insert(project_uuid, stored_project.log_length);
+			return None;
+		};
+
+		let mut conflict = views::LoadConflictView {
+			project_uuid,
+			definition_differs: project.definition_version != stored_project.project.definition_version
+				|| stored_project.definition.event_templates.iter().any(|event_template| {
+					self.event_templates
+						.get(&event_template.event_template_uuid)
+						.is_none_or(|existing| !same_json(existing, event_template))
+				})
+				|| stored_project.definition.trace_templates.iter().any(|trace_template| {
+					self.trace_templates
+						.get(&trace_template.trace_template_uuid)
+						.is_none_or(|existing| !same_json(existing, trace_template))
+				}),
+			events: 0,
+			traces: 0,
+		};
+		for (event_uuid, event) in stored_project.events {
+			match self.events.get(&event_uuid) {
+				Some(existing) => {
+					if !same_json(existing, &event) {
+						conflict.events += 1;
+					}
+				}
+				None => {
+					self.events.insert(event_uuid, event);
+				}
+			}
+		}
+		for (trace_uuid, trace) in stored_project.traces {
+			match self.traces.get(&trace_uuid) {
+				Some(existing) => {
+					if !same_json(existing, &trace) {
+						conflict.traces += 1;
+					}
+				}
+				None => {
+					self.traces.insert(trace_uuid, trace);
+				}
+			}
+		}
+		if conflict.definition_differs || conflict.events > 0 || conflict.traces > 0 {
+			Some(conflict)
+		} else {
+			None
+		}
+	}
+
+	// Templates the new definition no longer has are dropped, their events and traces are kept
+	fn replace_project_definition(&mut self, project_definition: ProjectDefinition) {
+		let project_uuid = project_definition.project_uuid;
+		let event_template_uuids: Vec<Uuid> = self
+			.event_templates
+			.keys()
+			.filter(|event_template_uuid| {
+				self.project_contains_event_template(&project_uuid, event_template_uuid)
+			})
+			.cloned()
+			.collect();
+		for event_template_uuid in event_template_uuids {
+			self.event_templates.remove(&event_template_uuid);
+		}
+		self.trace_templates
+			.retain(|_, trace_template| trace_template.project_uuid != project_uuid);
+		self.import_project_definition(project_definition);
 	}
+
 	fn import_project_definition(&mut self, project_definition: ProjectDefinition) {
 		self.event_templates.extend(
 			project_definition
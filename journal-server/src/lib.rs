@@ -4,3 +4,14 @@ pub mod project;
 pub mod fs_service;
 pub mod events;
 pub mod definition;
+pub mod lineage;
+pub mod definition_parser;
+pub mod definition_writer;
+pub mod source_map;
+pub mod validation;
+pub mod reimport;
+pub mod atomic;
+pub mod event_log;
+pub mod sqlite_service;
+pub mod config;
+pub mod schema;
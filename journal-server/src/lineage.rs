@@ -0,0 +1,413 @@
+use crate::events;
+
+use journal_lib::errors;
+use journal_lib::service;
+use journal_lib::views;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+use uuid::Uuid;
+
+// Walks the graph formed by events::Trace::origin_trace_uuids.
+// Edges point from a trace to its origins; children are found through the reverse index.
+pub struct LineageGraph<'a> {
+	traces: &'a HashMap<Uuid, events::Trace>,
+	children: HashMap<Uuid, Vec<Uuid>>,
+}
+
+impl<'a> LineageGraph<'a> {
+	pub fn new(traces: &'a HashMap<Uuid, events::Trace>) -> Self {
+		let mut children: HashMap<Uuid, Vec<Uuid>> = HashMap::new();
+		for trace in traces.values() {
+			for origin_uuid in trace.origin_trace_uuids.iter() {
+				children
+					.entry(*origin_uuid)
+					.or_default()
+					.push(trace.trace_uuid);
+			}
+		}
+		for child_uuids in children.values_mut() {
+			child_uuids.sort();
+			child_uuids.dedup();
+		}
+		LineageGraph { traces, children }
+	}
+
+	fn origins_of(&self, trace_uuid: &Uuid) -> Vec<Uuid> {
+		// Origins that were never saved (or were lost) are not part of the graph
+		self.traces.get(trace_uuid).map_or(vec![], |trace| {
+			trace
+				.origin_trace_uuids
+				.iter()
+				.filter(|origin_uuid| self.traces.contains_key(origin_uuid))
+				.cloned()
+				.collect()
+		})
+	}
+
+	fn children_of(&self, trace_uuid: &Uuid) -> Vec<Uuid> {
+		self.children.get(trace_uuid).cloned().unwrap_or_default()
+	}
+
+	fn neighbors(&self, trace_uuid: &Uuid, direction: &service::LineageDirection) -> Vec<Uuid> {
+		match direction {
+			service::LineageDirection::Ancestors => self.origins_of(trace_uuid),
+			service::LineageDirection::Descendants => self.children_of(trace_uuid),
+			service::LineageDirection::Family => {
+				let mut neighbors = self.origins_of(trace_uuid);
+				neighbors.extend(self.children_of(trace_uuid));
+				neighbors
+			}
+		}
+	}
+
+	// Breadth first, so every trace is reported with its shortest distance from the start.
+	// The start trace itself is not included.
+	pub fn walk(&self, trace_uuid: Uuid, direction: &service::LineageDirection) -> Vec<(Uuid, usize)> {
+		let mut visited: HashSet<Uuid> = HashSet::from([trace_uuid]);
+		let mut queue: VecDeque<(Uuid, usize)> = VecDeque::from([(trace_uuid, 0)]);
+		let mut found = vec![];
+		while let Some((current, distance)) = queue.pop_front() {
+			for next in self.neighbors(&current, direction) {
+				if visited.insert(next) {
+					found.push((next, distance + 1));
+					queue.push_back((next, distance + 1));
+				}
+			}
+		}
+		found
+	}
+
+	// Shortest path following origin links in either direction.
+	pub fn shortest_path(&self, from: Uuid, to: Uuid) -> Option<Vec<(Uuid, views::LineageLink)>> {
+		let mut previous: HashMap<Uuid, (Uuid, views::LineageLink)> = HashMap::new();
+		let mut visited: HashSet<Uuid> = HashSet::from([from]);
+		let mut queue: VecDeque<Uuid> = VecDeque::from([from]);
+		while let Some(current) = queue.pop_front() {
+			if current == to {
+				let mut path = vec![];
+				let mut step = current;
+				while let Some((before, link)) = previous.get(&step) {
+					path.push((step, link.clone()));
+					step = *before;
+				}
+				path.push((from, views::LineageLink::Start));
+				path.reverse();
+				return Some(path);
+			}
+			let origins = self
+				.origins_of(&current)
+				.into_iter()
+				.map(|uuid| (uuid, views::LineageLink::Origin));
+			let children = self
+				.children_of(&current)
+				.into_iter()
+				.map(|uuid| (uuid, views::LineageLink::Child));
+			for (next, link) in origins.chain(children) {
+				if visited.insert(next) {
+					previous.insert(next, (current, link));
+					queue.push_back(next);
+				}
+			}
+		}
+		None
+	}
+
+	// Cycles among the given traces, following origin links.
+	// Each cycle is reported once, starting from the trace where it was first entered.
+	pub fn find_cycles(&self, trace_uuids: &HashSet<Uuid>) -> Vec<Vec<Uuid>> {
+		let mut starts: Vec<Uuid> = trace_uuids.iter().cloned().collect();
+		starts.sort();
+
+		let mut finished: HashSet<Uuid> = HashSet::new();
+		let mut cycles = vec![];
+		for start in starts {
+			if finished.contains(&start) {
+				continue;
+			}
+			let mut stack: Vec<Uuid> = vec![];
+			let mut on_stack: HashSet<Uuid> = HashSet::new();
+			let mut pending: Vec<(Uuid, Vec<Uuid>)> = vec![(start, self.origins_of(&start))];
+			stack.push(start);
+			on_stack.insert(start);
+			while let Some((current, remaining)) = pending.last_mut() {
+				if let Some(next) = remaining.pop() {
+					if !trace_uuids.contains(&next) || finished.contains(&next) {
+						continue;
+					}
+					if on_stack.contains(&next) {
+						let position = stack.iter().position(|uuid| uuid == &next).unwrap();
+						cycles.push(stack[position..].to_vec());
+						continue;
+					}
+					let origins = self.origins_of(&next);
+					stack.push(next);
+					on_stack.insert(next);
+					pending.push((next, origins));
+				} else {
+					let current = *current;
+					finished.insert(current);
+					on_stack.remove(&current);
+					stack.pop();
+					pending.pop();
+				}
+			}
+		}
+		cycles
+	}
+}
+
+// Views shared by the services, view_trace_item turns a trace into the item they show
+pub fn view_lineage(
+	traces: &HashMap<Uuid, events::Trace>,
+	trace_uuid: Uuid,
+	direction: &service::LineageDirection,
+	view_trace_item: impl Fn(&events::Trace) -> views::TraceItemView,
+) -> Result<views::LineageView, errors::TraceNotFound> {
+	let trace = traces.get(&trace_uuid).ok_or(trace_uuid)?;
+	let graph = LineageGraph::new(traces);
+	let found = graph.walk(trace_uuid, direction);
+
+	let mut involved: HashSet<Uuid> = found.iter().map(|(uuid, _)| *uuid).collect();
+	involved.insert(trace_uuid);
+	let cycles = graph
+		.find_cycles(&involved)
+		.into_iter()
+		.map(|cycle| {
+			cycle
+				.iter()
+				.flat_map(|uuid| traces.get(uuid))
+				.map(&view_trace_item)
+				.collect()
+		})
+		.collect();
+
+	let mut entries: Vec<views::LineageEntryView> = found
+		.into_iter()
+		.flat_map(|(uuid, distance)| traces.get(&uuid).map(|trace| (trace, distance)))
+		.map(|(trace, distance)| views::LineageEntryView {
+			trace: view_trace_item(trace),
+			distance,
+			origin_trace_uuids: trace.origin_trace_uuids.clone(),
+		})
+		.collect();
+	entries.sort_by(|a, b| {
+		a.distance
+			.cmp(&b.distance)
+			.then_with(|| a.trace.name.cmp(&b.trace.name))
+	});
+
+	Ok(views::LineageView {
+		trace: view_trace_item(trace),
+		traces: entries,
+		cycles,
+	})
+}
+
+pub fn find_lineage_path(
+	traces: &HashMap<Uuid, events::Trace>,
+	from_trace_uuid: Uuid,
+	to_trace_uuid: Uuid,
+	view_trace_item: impl Fn(&events::Trace) -> views::TraceItemView,
+) -> Result<Option<Vec<views::LineagePathStepView>>, errors::TraceNotFound> {
+	for trace_uuid in [from_trace_uuid, to_trace_uuid] {
+		if !traces.contains_key(&trace_uuid) {
+			return Err(trace_uuid.into());
+		}
+	}
+	Ok(LineageGraph::new(traces)
+		.shortest_path(from_trace_uuid, to_trace_uuid)
+		.map(|path| {
+			path.into_iter()
+				.flat_map(|(uuid, link)| traces.get(&uuid).map(|trace| (trace, link)))
+				.map(|(trace, link)| views::LineagePathStepView {
+					trace: view_trace_item(trace),
+					link,
+				})
+				.collect()
+		}))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use chrono::Utc;
+
+	const ROOT: Uuid = Uuid::from_u128(1);
+	const STARTER: Uuid = Uuid::from_u128(2);
+	const LOAF: Uuid = Uuid::from_u128(3);
+	// LEAVEN and REFRESH are each other's origin
+	const LEAVEN: Uuid = Uuid::from_u128(4);
+	const REFRESH: Uuid = Uuid::from_u128(5);
+	const LONE: Uuid = Uuid::from_u128(6);
+
+	fn trace(trace_uuid: Uuid, name: &str, origin_trace_uuids: &[Uuid]) -> (Uuid, events::Trace) {
+		(
+			trace_uuid,
+			events::Trace {
+				trace_uuid,
+				trace_template_uuid: Uuid::nil(),
+				project_uuid: Uuid::nil(),
+				origin_trace_uuids: origin_trace_uuids.to_vec(),
+				created_at: Utc::now(),
+				name: name.to_string(),
+				completion: None,
+				archived_at: None,
+			},
+		)
+	}
+
+	// root <- starter <- loaf <- leaven <-> refresh, and a lone trace
+	fn traces() -> HashMap<Uuid, events::Trace> {
+		HashMap::from([
+			trace(ROOT, "Root", &[]),
+			trace(STARTER, "Starter", &[ROOT]),
+			trace(LOAF, "Loaf", &[STARTER]),
+			trace(LEAVEN, "Leaven", &[LOAF, REFRESH]),
+			trace(REFRESH, "Refresh", &[LEAVEN]),
+			trace(LONE, "Lone", &[]),
+		])
+	}
+
+	fn sorted(mut found: Vec<(Uuid, usize)>) -> Vec<(Uuid, usize)> {
+		found.sort();
+		found
+	}
+
+	#[test]
+	fn walks_ancestors_with_their_distance() {
+		let traces = traces();
+		let graph = LineageGraph::new(&traces);
+		assert_eq!(
+			sorted(graph.walk(LOAF, &service::LineageDirection::Ancestors)),
+			vec![(ROOT, 2), (STARTER, 1)]
+		);
+		assert_eq!(
+			sorted(graph.walk(LEAVEN, &service::LineageDirection::Ancestors)),
+			vec![(ROOT, 3), (STARTER, 2), (LOAF, 1), (REFRESH, 1)]
+		);
+		assert!(graph.walk(ROOT, &service::LineageDirection::Ancestors).is_empty());
+	}
+
+	#[test]
+	fn walks_descendants_through_the_cycle_once() {
+		let traces = traces();
+		let graph = LineageGraph::new(&traces);
+		assert_eq!(
+			sorted(graph.walk(STARTER, &service::LineageDirection::Descendants)),
+			vec![(LOAF, 1), (LEAVEN, 2), (REFRESH, 3)]
+		);
+		assert_eq!(
+			sorted(graph.walk(REFRESH, &service::LineageDirection::Descendants)),
+			vec![(LEAVEN, 1)]
+		);
+	}
+
+	#[test]
+	fn walks_the_family_in_both_directions() {
+		let traces = traces();
+		let graph = LineageGraph::new(&traces);
+		assert_eq!(
+			sorted(graph.walk(LOAF, &service::LineageDirection::Family)),
+			vec![(ROOT, 2), (STARTER, 1), (LEAVEN, 1), (REFRESH, 2)]
+		);
+		assert!(graph.walk(LONE, &service::LineageDirection::Family).is_empty());
+	}
+
+	#[test]
+	fn origins_that_were_never_saved_are_left_out() {
+		let mut traces = traces();
+		traces.extend([trace(Uuid::from_u128(7), "Orphan", &[Uuid::from_u128(99)])]);
+		let graph = LineageGraph::new(&traces);
+		assert!(graph
+			.walk(Uuid::from_u128(7), &service::LineageDirection::Ancestors)
+			.is_empty());
+	}
+
+	#[test]
+	fn finds_each_cycle_once() {
+		let traces = traces();
+		let graph = LineageGraph::new(&traces);
+		let mut cycles = graph.find_cycles(&traces.keys().cloned().collect());
+		assert_eq!(cycles.len(), 1);
+		cycles[0].sort();
+		assert_eq!(cycles[0], vec![LEAVEN, REFRESH]);
+
+		// Only among the traces asked about
+		assert!(graph.find_cycles(&HashSet::from([ROOT, STARTER, LOAF, LEAVEN])).is_empty());
+	}
+
+	#[test]
+	fn shortest_path_follows_links_in_either_direction() {
+		let traces = traces();
+		let graph = LineageGraph::new(&traces);
+		assert_eq!(
+			graph.shortest_path(ROOT, REFRESH),
+			Some(vec![
+				(ROOT, views::LineageLink::Start),
+				(STARTER, views::LineageLink::Child),
+				(LOAF, views::LineageLink::Child),
+				(LEAVEN, views::LineageLink::Child),
+				// Refresh is both an origin and a child of Leaven, origins are followed first
+				(REFRESH, views::LineageLink::Origin),
+			])
+		);
+		assert_eq!(
+			graph.shortest_path(REFRESH, STARTER),
+			Some(vec![
+				(REFRESH, views::LineageLink::Start),
+				(LEAVEN, views::LineageLink::Origin),
+				(LOAF, views::LineageLink::Origin),
+				(STARTER, views::LineageLink::Origin),
+			])
+		);
+	}
+
+	#[test]
+	fn shortest_path_to_an_unrelated_trace_is_none() {
+		let traces = traces();
+		assert_eq!(LineageGraph::new(&traces).shortest_path(ROOT, LONE), None);
+	}
+
+	#[test]
+	fn shortest_path_to_itself_is_the_start() {
+		let traces = traces();
+		assert_eq!(
+			LineageGraph::new(&traces).shortest_path(LOAF, LOAF),
+			Some(vec![(LOAF, views::LineageLink::Start)])
+		);
+	}
+
+	#[test]
+	fn view_lineage_sorts_by_distance_and_reports_cycles() {
+		let traces = traces();
+		let lineage = view_lineage(&traces, LOAF, &service::LineageDirection::Descendants, |trace| {
+			trace.to_item(None)
+		})
+		.ok()
+		.unwrap();
+		let names: Vec<(String, usize)> = lineage
+			.traces
+			.iter()
+			.map(|entry| (entry.trace.name.clone(), entry.distance))
+			.collect();
+		assert_eq!(names, vec![("Leaven".to_string(), 1), ("Refresh".to_string(), 2)]);
+		assert_eq!(lineage.cycles.len(), 1);
+
+		assert!(view_lineage(&traces, Uuid::from_u128(99), &service::LineageDirection::Family, |trace| {
+			trace.to_item(None)
+		})
+		.is_err());
+	}
+
+	#[test]
+	fn find_lineage_path_needs_both_traces() {
+		let traces = traces();
+		let view_trace_item = |trace: &events::Trace| trace.to_item(None);
+		assert!(find_lineage_path(&traces, ROOT, Uuid::from_u128(99), view_trace_item).is_err());
+		let path = find_lineage_path(&traces, ROOT, LOAF, view_trace_item).unwrap().unwrap();
+		let names: Vec<&str> = path.iter().map(|step| step.trace.name.as_str()).collect();
+		assert_eq!(names, vec!["Root", "Starter", "Loaf"]);
+		assert_eq!(find_lineage_path(&traces, ROOT, LONE, view_trace_item).unwrap().map(|path| path.len()), None);
+	}
+}
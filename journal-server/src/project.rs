@@ -1,3 +1,6 @@
+use crate::definition;
+use journal_lib::views;
+
 use chrono::serde::ts_milliseconds;
 use uuid::Uuid;
 
@@ -5,12 +8,39 @@ use uuid::Uuid;
 pub struct Project {
 	pub project_uuid: Uuid,
 	pub name: String,
+	pub description: String,
 
 	#[serde(with = "ts_milliseconds")]
 	pub created_at: chrono::DateTime<chrono::Utc>,
+
+	// Version of the imported definition, 0 for projects imported before definitions had one
+	#[serde(default)]
+	pub definition_version: u32,
+	#[serde(default)]
+	pub migrations: Vec<definition::Migration>,
 }
 
 impl Project {
+	pub fn to_view(
+		&self,
+		item: views::ProjectItemView,
+		event_templates: Vec<views::EventTemplateItemView>,
+		trace_templates: Vec<views::TraceTemplateItemView>,
+	) -> views::ProjectView {
+		views::ProjectView {
+			project_uuid: self.project_uuid,
+			name: self.name.clone(),
+			description: self.description.clone(),
+			created_at: self.created_at,
+			definition_version: self.definition_version,
+			event_templates,
+			trace_templates,
+			trace_count: item.trace_count,
+			event_count: item.event_count,
+			last_activity: item.last_activity,
+		}
+	}
+
 	// pub fn parse_js(js: serde_json::Value) -> Result<Self, serde_json::Error> {
 	// 	println!("The value is: {}", js);
 	// 	for (key, value) in js.as_object().unwrap() {
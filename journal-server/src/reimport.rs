@@ -0,0 +1,640 @@
+use crate::definition;
+use crate::definition_parser;
+use crate::events;
+use crate::fs_service;
+use crate::source_map;
+use crate::validation;
+
+use journal_lib::errors;
+use journal_lib::views;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use uuid::Uuid;
+
+use source_map::child_index;
+use source_map::child_key;
+
+// What importing a new version of a definition does to a project: how the templates change
+// and which stored events get rewritten on the way.
+pub struct Reimport {
+	pub changes: views::DefinitionChangesView,
+	// Migrated copies, nothing is written until the import is committed
+	pub migrated_events: Vec<events::Event>,
+	// How many events or traces each template of the current definition leaves behind
+	pub orphans: HashMap<Uuid, usize>,
+}
+
+pub struct PlannedImport {
+	pub project_definition: fs_service::ProjectDefinition,
+	pub reimport: Reimport,
+	pub warnings: Vec<errors::ParsingError>,
+}
+
+// Validates a parsed definition against what is stored and works out what importing it
+// would do, without changing anything. `current` is the stored definition of the project,
+// if it was imported before.
+pub fn plan_definition_import<'a>(
+	parsed: definition_parser::ParsedDefinition,
+	current: Option<&fs_service::ProjectDefinition>,
+	event_templates: &HashMap<Uuid, definition::EventTemplate>,
+	trace_templates: &HashMap<Uuid, definition::TraceTemplate>,
+	events: impl Iterator<Item = &'a events::Event>,
+	traces: impl Iterator<Item = &'a events::Trace>,
+) -> Result<PlannedImport, errors::DefinitionErrors> {
+	let mut project_definition = parsed.project_definition;
+	let mut report = validation::validate_definition(
+		&project_definition,
+		event_templates,
+		trace_templates,
+		current.map(|current| current.version),
+	);
+	if !report.errors.is_empty() {
+		let report = report.locate(&parsed.source_map);
+		return Err(errors::DefinitionErrors {
+			errors: report.errors,
+			warnings: report.warnings,
+		});
+	}
+
+	// Templates that already exist keep their creation time
+	for event_template in project_definition.event_templates.iter_mut() {
+		if let Some(existing) = event_templates.get(&event_template.event_template_uuid) {
+			event_template.created_at = existing.created_at;
+		}
+	}
+	for trace_template in project_definition.trace_templates.iter_mut() {
+		if let Some(existing) = trace_templates.get(&trace_template.trace_template_uuid) {
+			trace_template.created_at = existing.created_at;
+		}
+	}
+
+	let events: Vec<&events::Event> = events.collect();
+	if let Some(current) = current {
+		report.warnings.extend(unknown_move_warnings(current, &project_definition, &events));
+	}
+
+	let reimport = plan_reimport(current, &project_definition, events.into_iter(), traces);
+	let changes = &reimport.changes;
+	if changes.from_version == Some(changes.to_version)
+		&& changes.changes_templates()
+		&& !project_definition.migrations.is_empty()
+	{
+		report.warnings.push(errors::ParsingError {
+			path: Some("version".into()),
+			..errors::ParsingError::new(
+				"The templates changed but the version did not, so no migrations run".into(),
+			)
+		});
+	}
+	if let Some(current) = current {
+		report.warnings.extend(derived_uuid_warnings(current, &reimport.orphans));
+	}
+	if changes.orphaned_events > 0 || changes.orphaned_traces > 0 {
+		report.warnings.push(
+			format!(
+				"{} events and {} traces use templates this definition no longer has",
+				changes.orphaned_events, changes.orphaned_traces
+			)
+			.into(),
+		);
+	}
+
+	Ok(PlannedImport {
+		project_definition,
+		reimport,
+		warnings: report.locate(&parsed.source_map).warnings,
+	})
+}
+
+// A slug that names no template still resolves, to the uuid it would have generated, so a
+// typo in move-events would quietly move nothing. Only migrations that are about to run are
+// looked at.
+fn unknown_move_warnings(
+	current: &fs_service::ProjectDefinition,
+	new: &fs_service::ProjectDefinition,
+	events: &[&events::Event],
+) -> Vec<errors::ParsingError> {
+	let known = |uuid: &Uuid| {
+		current
+			.event_templates
+			.iter()
+			.chain(new.event_templates.iter())
+			.any(|template| &template.event_template_uuid == uuid)
+			|| events.iter().any(|event| &event.event_template_uuid == uuid)
+	};
+	new.migrations
+		.iter()
+		.enumerate()
+		.filter(|(_, migration)| migration.version > current.version)
+		.filter_map(|(index, migration)| match &migration.step {
+			definition::MigrationStep::MoveEvents { from, .. } if !known(from) => Some(errors::ParsingError {
+				path: Some(child_key(&child_key(&child_index("migrations", index), "move-events"), "from")),
+				..errors::ParsingError::new(format!(
+					"No template or stored event has uuid {}, so nothing is moved. Is the slug right?",
+					from
+				))
+			}),
+			_ => None,
+		})
+		.collect()
+}
+
+// A template without an event-template-uuid or slug gets its uuid from its name, so renaming
+// it makes a new template and leaves the stored events behind.
+fn derived_uuid_warnings(
+	current: &fs_service::ProjectDefinition,
+	orphans: &HashMap<Uuid, usize>,
+) -> Vec<errors::ParsingError> {
+	let event_templates = current.event_templates.iter().map(|template| {
+		(
+			template.event_template_uuid,
+			definition_parser::derive_event_template_uuid(&current.project_uuid, &template.slug),
+			&template.name,
+			&template.slug,
+			"events",
+			"event-template-uuid",
+		)
+	});
+	let trace_templates = current.trace_templates.iter().map(|template| {
+		(
+			template.trace_template_uuid,
+			definition_parser::derive_trace_template_uuid(&current.project_uuid, &template.slug),
+			&template.name,
+			&template.slug,
+			"traces",
+			"trace-template-uuid",
+		)
+	});
+	event_templates
+		.chain(trace_templates)
+		.filter(|(uuid, derived_uuid, ..)| uuid == derived_uuid)
+		.filter_map(|(uuid, _, name, slug, kind, uuid_field)| {
+			let orphaned = orphans.get(&uuid)?;
+			Some(errors::ParsingError::new(format!(
+				"'{}' is gone and its {} {} would be left behind. Its uuid came from the slug '{}', if it was renamed give it 'slug: {}' or '{}: {}'",
+				name, orphaned, kind, slug, slug, uuid_field, uuid
+			)))
+		})
+		.collect()
+}
+
+// Migrations that take events from `from_version` up to the definition's version, in the
+// order they have to run. Steps of the same version keep the order they were written in.
+pub fn pending_migrations(
+	project_definition: &fs_service::ProjectDefinition,
+	from_version: u32,
+) -> Vec<&definition::Migration> {
+	let mut migrations: Vec<&definition::Migration> = project_definition
+		.migrations
+		.iter()
+		.filter(|migration| {
+			migration.version > from_version && migration.version <= project_definition.version
+		})
+		.collect();
+	migrations.sort_by_key(|migration| migration.version);
+	migrations
+}
+
+// `current` is None when the project does not exist yet. `events` and `traces` are all the
+// project has, so move-events also picks up those an earlier import left without a template.
+pub fn plan_reimport<'a>(
+	current: Option<&fs_service::ProjectDefinition>,
+	new: &fs_service::ProjectDefinition,
+	events: impl Iterator<Item = &'a events::Event>,
+	traces: impl Iterator<Item = &'a events::Trace>,
+) -> Reimport {
+	let Some(current) = current else {
+		return Reimport {
+			changes: views::DefinitionChangesView {
+				from_version: None,
+				to_version: new.version,
+				..diff_definitions(&fs_service::ProjectDefinition::default(), new, &[])
+			},
+			migrated_events: vec![],
+			orphans: HashMap::new(),
+		};
+	};
+
+	let migrations = pending_migrations(new, current.version);
+	let mut changes = diff_definitions(current, new, &migrations);
+	changes.from_version = Some(current.version);
+	changes.to_version = new.version;
+	changes.applied_migrations = migrations.len();
+
+	let new_event_templates: HashMap<Uuid, &definition::EventTemplate> = new
+		.event_templates
+		.iter()
+		.map(|template| (template.event_template_uuid, template))
+		.collect();
+
+	let mut migrated_events = vec![];
+	let mut orphans = HashMap::new();
+	for event in events {
+		let mut migrated = event.clone();
+		let event_template_uuid = if migrate_event(&mut migrated, &migrations, &new_event_templates) {
+			let event_template_uuid = migrated.event_template_uuid;
+			migrated_events.push(migrated);
+			event_template_uuid
+		} else {
+			event.event_template_uuid
+		};
+		if !new_event_templates.contains_key(&event_template_uuid) {
+			changes.orphaned_events += 1;
+			*orphans.entry(event.event_template_uuid).or_insert(0) += 1;
+		}
+	}
+	changes.migrated_events = migrated_events.len();
+
+	let new_trace_templates: HashSet<Uuid> = new
+		.trace_templates
+		.iter()
+		.map(|template| template.trace_template_uuid)
+		.collect();
+	for trace in traces.filter(|trace| !new_trace_templates.contains(&trace.trace_template_uuid)) {
+		changes.orphaned_traces += 1;
+		*orphans.entry(trace.trace_template_uuid).or_insert(0) += 1;
+	}
+
+	Reimport {
+		changes,
+		migrated_events,
+		orphans,
+	}
+}
+
+// Runs the migrations over one event, then brings its labels in line with the new
+// templates. Returns whether anything changed. Fields are named by their dotted paths.
+pub fn migrate_event(
+	event: &mut events::Event,
+	migrations: &[&definition::Migration],
+	event_templates: &HashMap<Uuid, &definition::EventTemplate>,
+) -> bool {
+	let mut changed = false;
+	for migration in migrations {
+		match &migration.step {
+			definition::MigrationStep::RenameField {
+				event_template_uuid,
+				from,
+				to,
+			} if event_template_uuid == &event.event_template_uuid => {
+				// Validation keeps the field in its group
+				let to_name = to.rsplit('.').next().unwrap_or(to);
+				changed |= events::visit_parent_fields(&mut event.fields, from, &mut |fields, name| {
+					fields.iter_mut().filter(|field| field.name == name).fold(false, |_, field| {
+						field.name = to_name.to_string();
+						true
+					})
+				});
+			}
+			definition::MigrationStep::RemoveField {
+				event_template_uuid,
+				field,
+			} if event_template_uuid == &event.event_template_uuid => {
+				changed |= events::visit_parent_fields(&mut event.fields, field, &mut |fields, name| {
+					let before = fields.len();
+					fields.retain(|event_field| event_field.name != name);
+					fields.len() != before
+				});
+			}
+			definition::MigrationStep::RenameOption {
+				event_template_uuid,
+				field,
+				from,
+				to,
+			} if event_template_uuid == &event.event_template_uuid => {
+				changed |= events::visit_parent_fields(&mut event.fields, field, &mut |fields, name| {
+					fields
+						.iter_mut()
+						.filter(|event_field| event_field.name == name)
+						.filter_map(|event_field| event_field.value.as_mut())
+						.fold(false, |changed, value| rename_option(value, from, to) | changed)
+				});
+			}
+			definition::MigrationStep::MoveEvents { from, to } if from == &event.event_template_uuid => {
+				event.event_template_uuid = *to;
+				changed = true;
+			}
+			_ => {}
+		}
+	}
+
+	if let Some(event_template) = event_templates.get(&event.event_template_uuid) {
+		changed |= relabel_fields(&mut event.fields, &event_template.fields);
+	}
+	changed
+}
+
+fn relabel_fields(fields: &mut [events::Field], templates: &[definition::FieldTemplate]) -> bool {
+	let mut changed = false;
+	for field in fields.iter_mut() {
+		let Some(field_template) = templates.iter().find(|field_template| field_template.name == field.name) else {
+			continue;
+		};
+		if field.label != field_template.label {
+			field.label = field_template.label.clone();
+			changed = true;
+		}
+		if let Some(value) = &mut field.value {
+			changed |= relabel_options(value, &field_template.value);
+		}
+	}
+	changed
+}
+
+// Also in the rows of an array field
+fn rename_option(value: &mut events::FieldValue, from: &str, to: &str) -> bool {
+	match value {
+		events::FieldValue::Enumerated(option) if option.name == from => {
+			option.name = to.to_string();
+			true
+		}
+		events::FieldValue::Array(items) => {
+			items.iter_mut().fold(false, |changed, item| rename_option(item, from, to) | changed)
+		}
+		_ => false,
+	}
+}
+
+// Gives chosen options, and the fields of groups, the labels the template has for them now
+fn relabel_options(value: &mut events::FieldValue, template: &definition::FieldValueTemplate) -> bool {
+	match (value, template) {
+		(events::FieldValue::Enumerated(option), definition::FieldValueTemplate::Enumerated(enumerated)) => {
+			match enumerated
+				.options
+				.iter()
+				.find(|template_option| template_option.name == option.name)
+			{
+				Some(template_option) if option.label != template_option.label => {
+					option.label = template_option.label.clone();
+					true
+				}
+				_ => false,
+			}
+		}
+		(events::FieldValue::Array(items), definition::FieldValueTemplate::Array(array)) => {
+			items.iter_mut().fold(false, |changed, item| relabel_options(item, &array.item) | changed)
+		}
+		(events::FieldValue::Group(fields), definition::FieldValueTemplate::Group(group)) => {
+			relabel_fields(fields, &group.fields)
+		}
+		_ => false,
+	}
+}
+
+fn template_changes(
+	current: &[(Uuid, String)],
+	new: &[(Uuid, String)],
+) -> views::TemplateChangesView {
+	let current_names: HashMap<&Uuid, &String> = current.iter().map(|(uuid, name)| (uuid, name)).collect();
+	let new_uuids: HashSet<&Uuid> = new.iter().map(|(uuid, _)| uuid).collect();
+	let mut changes = views::TemplateChangesView::default();
+	for (uuid, name) in new.iter() {
+		match current_names.get(uuid) {
+			None => changes.added.push(name.clone()),
+			Some(current_name) if *current_name != name => changes.renamed.push(views::RenameView {
+				from: current_name.to_string(),
+				to: name.clone(),
+			}),
+			Some(_) => {}
+		}
+	}
+	for (uuid, name) in current.iter() {
+		if !new_uuids.contains(uuid) {
+			changes.removed.push(name.clone());
+		}
+	}
+	changes
+}
+
+// Renames are only reported when a migration says so, otherwise the field or option
+// shows up as removed and added, which is also what happens to the stored values.
+fn field_changes(
+	current: &definition::EventTemplate,
+	new: &definition::EventTemplate,
+	migrations: &[&definition::Migration],
+) -> views::FieldChangesView {
+	let mut changes = views::FieldChangesView {
+		event_template: new.name.clone(),
+		..Default::default()
+	};
+	// Fields in groups are compared one by one under their dotted paths
+	let current_paths = definition::field_paths("", &current.fields);
+	let new_paths = definition::field_paths("", &new.fields);
+	let current_fields: HashMap<&str, &definition::FieldTemplate> =
+		current_paths.iter().map(|(path, field)| (path.as_str(), *field)).collect();
+	let new_fields: HashMap<&str, &definition::FieldTemplate> =
+		new_paths.iter().map(|(path, field)| (path.as_str(), *field)).collect();
+
+	let mut renamed_from: HashSet<&str> = HashSet::new();
+	let mut renamed_to: HashSet<&str> = HashSet::new();
+	for migration in migrations {
+		if let definition::MigrationStep::RenameField {
+			event_template_uuid,
+			from,
+			to,
+		} = &migration.step
+		{
+			if event_template_uuid == &new.event_template_uuid
+				&& current_fields.contains_key(from.as_str())
+				&& new_fields.contains_key(to.as_str())
+			{
+				renamed_from.insert(from);
+				renamed_to.insert(to);
+				changes.renamed.push(views::RenameView {
+					from: from.clone(),
+					to: to.clone(),
+				});
+			}
+		}
+	}
+
+	for (path, field) in new_paths.iter() {
+		let Some(current_field) = current_fields.get(path.as_str()) else {
+			if !renamed_to.contains(path.as_str()) {
+				changes.added.push(path.clone());
+			}
+			continue;
+		};
+		if !current_field.value.same_type(&field.value) {
+			changes.retyped.push(path.clone());
+			continue;
+		}
+		if let (Some(current_enumerated), Some(enumerated)) =
+			(current_field.value.enumerated(), field.value.enumerated())
+		{
+			let options = option_changes(path, current_enumerated, enumerated, &new.event_template_uuid, migrations);
+			if !options.added.is_empty() || !options.removed.is_empty() || !options.renamed.is_empty() {
+				changes.options.push(options);
+			}
+		}
+	}
+	for (path, _) in current_paths.iter() {
+		if !new_fields.contains_key(path.as_str()) && !renamed_from.contains(path.as_str()) {
+			changes.removed.push(path.clone());
+		}
+	}
+	changes
+}
+
+fn option_changes(
+	field_name: &str,
+	current: &definition::EnumeratedTemplate,
+	new: &definition::EnumeratedTemplate,
+	event_template_uuid: &Uuid,
+	migrations: &[&definition::Migration],
+) -> views::OptionChangesView {
+	let current_options: HashSet<&str> = current.options.iter().map(|option| option.name.as_str()).collect();
+	let new_options: HashSet<&str> = new.options.iter().map(|option| option.name.as_str()).collect();
+	let mut changes = views::OptionChangesView {
+		field: field_name.to_string(),
+		..Default::default()
+	};
+	let mut renamed_from: HashSet<&str> = HashSet::new();
+	let mut renamed_to: HashSet<&str> = HashSet::new();
+	for migration in migrations {
+		if let definition::MigrationStep::RenameOption {
+			event_template_uuid: migration_template_uuid,
+			field,
+			from,
+			to,
+		} = &migration.step
+		{
+			if migration_template_uuid == event_template_uuid
+				&& field == field_name
+				&& current_options.contains(from.as_str())
+				&& new_options.contains(to.as_str())
+			{
+				renamed_from.insert(from);
+				renamed_to.insert(to);
+				changes.renamed.push(views::RenameView {
+					from: from.clone(),
+					to: to.clone(),
+				});
+			}
+		}
+	}
+	for option in new.options.iter() {
+		if !current_options.contains(option.name.as_str()) && !renamed_to.contains(option.name.as_str()) {
+			changes.added.push(option.name.clone());
+		}
+	}
+	for option in current.options.iter() {
+		if !new_options.contains(option.name.as_str()) && !renamed_from.contains(option.name.as_str()) {
+			changes.removed.push(option.name.clone());
+		}
+	}
+	changes
+}
+
+fn transitions(
+	trace_template: &definition::TraceTemplate,
+	names: &HashMap<Uuid, &str>,
+) -> Vec<String> {
+	let name = |uuid: &Uuid| names.get(uuid).map_or(uuid.to_string(), |name| name.to_string());
+	trace_template
+		.flow
+		.iter()
+		.flat_map(|entry| {
+			entry
+				.to
+				.iter()
+				.map(move |to| format!("{} -> {}", name(&entry.from), name(to)))
+		})
+		.collect()
+}
+
+pub fn diff_definitions(
+	current: &fs_service::ProjectDefinition,
+	new: &fs_service::ProjectDefinition,
+	migrations: &[&definition::Migration],
+) -> views::DefinitionChangesView {
+	let trace_templates = |definition: &fs_service::ProjectDefinition| -> Vec<(Uuid, String)> {
+		definition
+			.trace_templates
+			.iter()
+			.map(|template| (template.trace_template_uuid, template.name.clone()))
+			.collect()
+	};
+	let event_templates = |definition: &fs_service::ProjectDefinition| -> Vec<(Uuid, String)> {
+		definition
+			.event_templates
+			.iter()
+			.map(|template| (template.event_template_uuid, template.name.clone()))
+			.collect()
+	};
+
+	let current_event_templates: HashMap<Uuid, &definition::EventTemplate> = current
+		.event_templates
+		.iter()
+		.map(|template| (template.event_template_uuid, template))
+		.collect();
+	let fields = new
+		.event_templates
+		.iter()
+		.filter_map(|template| {
+			current_event_templates
+				.get(&template.event_template_uuid)
+				.map(|current_template| field_changes(current_template, template, migrations))
+		})
+		.filter(|changes| {
+			!changes.added.is_empty()
+				|| !changes.removed.is_empty()
+				|| !changes.renamed.is_empty()
+				|| !changes.retyped.is_empty()
+				|| !changes.options.is_empty()
+		})
+		.collect();
+
+	// Transitions are compared by name, templates that are gone keep their old one and
+	// renaming a template does not show up as a changed transition. Every transition of a new
+	// trace template is added.
+	let mut names: HashMap<Uuid, &str> = current
+		.event_templates
+		.iter()
+		.map(|template| (template.event_template_uuid, template.name.as_str()))
+		.collect();
+	names.extend(
+		new.event_templates
+			.iter()
+			.map(|template| (template.event_template_uuid, template.name.as_str())),
+	);
+	let current_trace_templates: HashMap<Uuid, &definition::TraceTemplate> = current
+		.trace_templates
+		.iter()
+		.map(|template| (template.trace_template_uuid, template))
+		.collect();
+	let flows = new
+		.trace_templates
+		.iter()
+		.filter_map(|template| {
+			let current_transitions = current_trace_templates
+				.get(&template.trace_template_uuid)
+				.map_or(vec![], |current_template| transitions(current_template, &names));
+			let new_transitions = transitions(template, &names);
+			let flow = views::FlowChangesView {
+				trace_template: template.name.clone(),
+				added: new_transitions
+					.iter()
+					.filter(|transition| !current_transitions.contains(transition))
+					.cloned()
+					.collect(),
+				removed: current_transitions
+					.iter()
+					.filter(|transition| !new_transitions.contains(transition))
+					.cloned()
+					.collect(),
+			};
+			Some(flow).filter(|flow| !flow.added.is_empty() || !flow.removed.is_empty())
+		})
+		.collect();
+
+	views::DefinitionChangesView {
+		from_version: Some(current.version),
+		to_version: new.version,
+		trace_templates: template_changes(&trace_templates(current), &trace_templates(new)),
+		event_templates: template_changes(&event_templates(current), &event_templates(new)),
+		fields,
+		flows,
+		..Default::default()
+	}
+}
@@ -0,0 +1,163 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+// Every JSON file the service keeps on disk starts with the format it was written in, files
+// from before the header existed are format 0. Reading a file brings it up to CURRENT_FORMAT one
+// step at a time. A change to a persisted type therefore adds a step to STEPS, bumps
+// CURRENT_FORMAT and adds a fixture under tests/fixtures written in the old format.
+// The exception is a new field whose #[serde(default)] is what older files meant, like a
+// field template's value_policy: files without it read the same, and so do the template
+// bodies the SQLite backend keeps without a format of their own.
+
+pub const FORMAT_KEY: &str = "format_version";
+pub const CURRENT_FORMAT: u32 = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileKind {
+	// project.json, a project::Project
+	Project,
+	// definition.json, a fs_service::ProjectDefinition
+	Definition,
+	// data.json, a fs_service::ProjectData
+	Data,
+	// A line of the event log, an event_log::LogRecord
+	LogRecord,
+}
+
+struct Step {
+	// The format the step leaves the file in
+	to_format: u32,
+	migrate: fn(FileKind, &mut serde_json::Value) -> Result<(), String>,
+}
+
+// In order, the last one ends at CURRENT_FORMAT
+const STEPS: &[Step] = &[
+	Step {
+		to_format: 1,
+		migrate: add_header,
+	},
+	Step {
+		to_format: 2,
+		migrate: add_event_history,
+	},
+	Step {
+		to_format: 3,
+		migrate: add_trace_archiving,
+	},
+	Step {
+		to_format: 4,
+		migrate: add_project_description,
+	},
+];
+
+// Format 1 only added the header, the files kept their shape
+fn add_header(_kind: FileKind, _value: &mut serde_json::Value) -> Result<(), String> {
+	Ok(())
+}
+
+// The events a file holds
+fn events_of(kind: FileKind, value: &mut serde_json::Value) -> Vec<&mut serde_json::Value> {
+	match kind {
+		FileKind::Data => value
+			.get_mut("events")
+			.and_then(|events| events.as_array_mut())
+			.map_or(vec![], |events| events.iter_mut().collect()),
+		FileKind::LogRecord => value.get_mut("Event").into_iter().collect(),
+		FileKind::Project | FileKind::Definition => vec![],
+	}
+}
+
+// Format 2 keeps the earlier versions of edited events and tombstones of deleted ones
+fn add_event_history(kind: FileKind, value: &mut serde_json::Value) -> Result<(), String> {
+	for event in events_of(kind, value) {
+		let event = event.as_object_mut().ok_or("an event is not a JSON object")?;
+		event.insert("revisions".to_string(), serde_json::json!([]));
+		event.insert("deleted_at".to_string(), serde_json::Value::Null);
+	}
+	Ok(())
+}
+
+// The traces a file holds
+fn traces_of(kind: FileKind, value: &mut serde_json::Value) -> Vec<&mut serde_json::Value> {
+	match kind {
+		FileKind::Data => value
+			.get_mut("traces")
+			.and_then(|traces| traces.as_array_mut())
+			.map_or(vec![], |traces| traces.iter_mut().collect()),
+		FileKind::LogRecord => value.get_mut("Trace").into_iter().collect(),
+		FileKind::Project | FileKind::Definition => vec![],
+	}
+}
+
+// Format 3 lets traces be archived
+fn add_trace_archiving(kind: FileKind, value: &mut serde_json::Value) -> Result<(), String> {
+	for trace in traces_of(kind, value) {
+		let trace = trace.as_object_mut().ok_or("a trace is not a JSON object")?;
+		trace.insert("archived_at".to_string(), serde_json::Value::Null);
+	}
+	Ok(())
+}
+
+// Format 4 gives projects a description
+fn add_project_description(kind: FileKind, value: &mut serde_json::Value) -> Result<(), String> {
+	if kind == FileKind::Project {
+		let project = value.as_object_mut().ok_or("the project is not a JSON object")?;
+		project.insert("description".to_string(), serde_json::json!(""));
+	}
+	Ok(())
+}
+
+#[derive(Serialize)]
+struct Versioned<'a, T> {
+	format_version: u32,
+	#[serde(flatten)]
+	body: &'a T,
+}
+
+fn versioned<T: Serialize>(body: &T) -> Versioned<'_, T> {
+	Versioned {
+		format_version: CURRENT_FORMAT,
+		body,
+	}
+}
+
+pub fn to_vec_pretty<T: Serialize>(body: &T) -> serde_json::Result<Vec<u8>> {
+	serde_json::to_vec_pretty(&versioned(body))
+}
+
+// On a single line, for the event log
+pub fn to_vec<T: Serialize>(body: &T) -> serde_json::Result<Vec<u8>> {
+	serde_json::to_vec(&versioned(body))
+}
+
+// Takes the header off and applies the steps the file is missing. Returns the format the file
+// was written in.
+pub fn upgrade(kind: FileKind, value: &mut serde_json::Value) -> Result<u32, String> {
+	let Some(object) = value.as_object_mut() else {
+		return Err("the file does not hold a JSON object".to_string());
+	};
+	let format = match object.remove(FORMAT_KEY) {
+		None => 0,
+		Some(format) => format
+			.as_u64()
+			.and_then(|format| u32::try_from(format).ok())
+			.ok_or(format!("{} is not a number: {}", FORMAT_KEY, format))?,
+	};
+	if format > CURRENT_FORMAT {
+		return Err(format!(
+			"it was written in format {} by a newer version of Journaler, this one reads up to format {}",
+			format, CURRENT_FORMAT
+		));
+	}
+	for step in STEPS.iter().filter(|step| step.to_format > format) {
+		(step.migrate)(kind, value)
+			.map_err(|err| format!("upgrading it to format {} failed: {}", step.to_format, err))?;
+	}
+	Ok(format)
+}
+
+pub fn from_slice<T: DeserializeOwned>(kind: FileKind, bytes: &[u8]) -> Result<T, String> {
+	let mut value: serde_json::Value = serde_json::from_slice(bytes).map_err(|err| err.to_string())?;
+	upgrade(kind, &mut value)?;
+	serde_json::from_value(value).map_err(|err| err.to_string())
+}
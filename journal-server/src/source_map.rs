@@ -0,0 +1,187 @@
+use std::collections::HashMap;
+use yaml_rust2::parser::Event;
+use yaml_rust2::parser::MarkedEventReceiver;
+use yaml_rust2::parser::Parser;
+use yaml_rust2::scanner::Marker;
+
+// Positions of every value in a definition file, keyed by the same paths the parser
+// reports errors with (e.g. event-templates[3].fields[1].default-value).
+// JSON is valid YAML, so one event stream covers both formats.
+#[derive(Default)]
+pub struct SourceMap {
+	positions: HashMap<String, (usize, usize)>,
+}
+
+enum Frame {
+	Mapping { path: String, key: Option<String> },
+	Sequence { path: String, index: usize },
+}
+
+#[derive(Default)]
+struct Builder {
+	frames: Vec<Frame>,
+	positions: HashMap<String, (usize, usize)>,
+}
+
+pub fn child_key(path: &str, key: &str) -> String {
+	if path.is_empty() {
+		key.to_string()
+	} else {
+		format!("{}.{}", path, key)
+	}
+}
+
+pub fn child_index(path: &str, index: usize) -> String {
+	format!("{}[{}]", path, index)
+}
+
+impl Builder {
+	// Works out where the value that starts here lives, and moves the parent on to its next entry.
+	// Returns None for mapping keys.
+	fn next_path(&mut self) -> Option<String> {
+		match self.frames.last_mut() {
+			None => Some("".to_string()),
+			Some(Frame::Mapping { path, key }) => key.take().map(|key| child_key(path, &key)),
+			Some(Frame::Sequence { path, index }) => {
+				*index += 1;
+				Some(child_index(path, *index - 1))
+			}
+		}
+	}
+
+	fn record(&mut self, path: &str, marker: &Marker) {
+		// yaml-rust2 reports 1-based lines and 0-based columns
+		self.positions
+			.entry(path.to_string())
+			.or_insert((marker.line(), marker.col() + 1));
+	}
+}
+
+impl MarkedEventReceiver for Builder {
+	fn on_event(&mut self, event: Event, marker: Marker) {
+		match event {
+			Event::Scalar(value, ..) => match self.next_path() {
+				Some(path) => self.record(&path, &marker),
+				None => {
+					if let Some(Frame::Mapping { path, key }) = self.frames.last_mut() {
+						*key = Some(value);
+						// Block mappings are only reported as started at their first value,
+						// their first key is a better place to point at
+						let path = path.clone();
+						let position = (marker.line(), marker.col() + 1);
+						if let Some(start) = self.positions.get_mut(&path) {
+							if position < *start {
+								*start = position;
+							}
+						}
+					}
+				}
+			},
+			Event::Alias(_) => {
+				if let Some(path) = self.next_path() {
+					self.record(&path, &marker);
+				}
+			}
+			Event::MappingStart(..) => {
+				// Complex keys are not used by definitions, they just get an unreachable path
+				let path = self.next_path().unwrap_or("?".to_string());
+				self.record(&path, &marker);
+				self.frames.push(Frame::Mapping { path, key: None });
+			}
+			Event::SequenceStart(..) => {
+				let path = self.next_path().unwrap_or("?".to_string());
+				self.record(&path, &marker);
+				self.frames.push(Frame::Sequence { path, index: 0 });
+			}
+			Event::MappingEnd | Event::SequenceEnd => {
+				self.frames.pop();
+			}
+			_ => {}
+		}
+	}
+}
+
+impl SourceMap {
+	// Positions are a convenience, so a file that cannot be scanned just has none.
+	pub fn new(contents: &str) -> SourceMap {
+		let mut builder = Builder::default();
+		let mut parser = Parser::new_from_str(contents);
+		if parser.load(&mut builder, false).is_err() {
+			return SourceMap::default();
+		}
+		SourceMap {
+			positions: builder.positions,
+		}
+	}
+
+	// The position of the value at the path, or of the closest enclosing value that exists
+	// (for instance the object a required field is missing from).
+	pub fn locate(&self, path: &str) -> Option<(usize, usize)> {
+		let mut path = path;
+		loop {
+			if let Some(position) = self.positions.get(path) {
+				return Some(*position);
+			}
+			if path.is_empty() {
+				return None;
+			}
+			path = match path.rfind(['.', '[']) {
+				Some(split) => &path[..split],
+				None => "",
+			};
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const YAML: &str = "\
+project-uuid: 6b3a2c36-3b5e-4f7e-9a51-1c1d6f0f9e01
+event-templates:
+  - name: Bake
+    fields:
+      - label: Weight
+        type: Number
+        default-value: heavy
+";
+
+	const JSON: &str = r#"{
+	"project-uuid": "6b3a2c36-3b5e-4f7e-9a51-1c1d6f0f9e01",
+	"event-templates": [
+		{"name": "Bake", "fields": [{"label": "Weight", "type": "Number"}]}
+	]
+}"#;
+
+	#[test]
+	fn locates_yaml_values() {
+		let source_map = SourceMap::new(YAML);
+		assert_eq!(source_map.locate("project-uuid"), Some((1, 15)));
+		assert_eq!(source_map.locate("event-templates[0].fields[0].default-value"), Some((7, 24)));
+		// A block mapping is pointed at by its first key
+		assert_eq!(source_map.locate("event-templates[0].fields[0]"), Some((5, 9)));
+	}
+
+	#[test]
+	fn locates_json_values() {
+		let source_map = SourceMap::new(JSON);
+		assert_eq!(source_map.locate("project-uuid"), Some((2, 18)));
+		assert_eq!(source_map.locate("event-templates[0].fields[0].type"), Some((4, 59)));
+	}
+
+	#[test]
+	fn missing_values_are_located_at_what_encloses_them() {
+		let source_map = SourceMap::new(JSON);
+		assert_eq!(
+			source_map.locate("event-templates[0].fields[0].default-value"),
+			source_map.locate("event-templates[0].fields[0]")
+		);
+		assert_eq!(source_map.locate("trace-templates[2].name"), source_map.locate(""));
+	}
+
+	#[test]
+	fn unreadable_contents_have_no_positions() {
+		assert_eq!(SourceMap::new("{ \"open\": [").locate("open"), None);
+	}
+}
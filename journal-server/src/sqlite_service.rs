@@ -0,0 +1,1424 @@
+use crate::builders;
+use crate::definition;
+use crate::definition_parser;
+use crate::definition_writer;
+use crate::events;
+use crate::fs_service;
+use crate::lineage;
+use crate::project;
+use crate::reimport;
+use journal_lib::errors;
+use journal_lib::service;
+use journal_lib::views;
+
+use chrono::DateTime;
+use chrono::Utc;
+use rusqlite::params;
+use rusqlite::OptionalExtension;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+// Each entry brings the schema from the version before it to its own. The version lives in
+// SQLite's user_version, so released entries must never change, only be added to.
+// Templates and field values are stored as JSON, so json_extract works on them from any
+// SQLite client. Times are milliseconds since the epoch, as in the JSON files.
+pub const SCHEMA_MIGRATIONS: &[&str] = &["
+	CREATE TABLE projects (
+		project_uuid TEXT PRIMARY KEY,
+		name TEXT NOT NULL,
+		created_at INTEGER NOT NULL,
+		definition_version INTEGER NOT NULL,
+		migrations TEXT NOT NULL
+	);
+	CREATE TABLE trace_templates (
+		trace_template_uuid TEXT PRIMARY KEY,
+		project_uuid TEXT NOT NULL REFERENCES projects (project_uuid),
+		body TEXT NOT NULL
+	);
+	CREATE INDEX trace_templates_by_project ON trace_templates (project_uuid);
+	CREATE TABLE event_templates (
+		event_template_uuid TEXT PRIMARY KEY,
+		trace_template_uuid TEXT NOT NULL REFERENCES trace_templates (trace_template_uuid),
+		body TEXT NOT NULL
+	);
+	CREATE INDEX event_templates_by_trace_template ON event_templates (trace_template_uuid);
+	CREATE TABLE traces (
+		trace_uuid TEXT PRIMARY KEY,
+		trace_template_uuid TEXT NOT NULL,
+		name TEXT NOT NULL,
+		created_at INTEGER NOT NULL,
+		completed_at INTEGER
+	);
+	CREATE INDEX traces_by_template ON traces (trace_template_uuid);
+	CREATE TABLE trace_origins (
+		trace_uuid TEXT NOT NULL REFERENCES traces (trace_uuid) ON DELETE CASCADE,
+		origin_trace_uuid TEXT NOT NULL,
+		PRIMARY KEY (trace_uuid, origin_trace_uuid)
+	);
+	CREATE INDEX trace_origins_by_origin ON trace_origins (origin_trace_uuid);
+	CREATE TABLE events (
+		event_uuid TEXT PRIMARY KEY,
+		event_template_uuid TEXT NOT NULL,
+		trace_uuid TEXT NOT NULL,
+		fields TEXT NOT NULL,
+		began_at INTEGER NOT NULL,
+		created_at INTEGER NOT NULL
+	);
+	CREATE INDEX events_by_trace ON events (trace_uuid, created_at);
+	CREATE INDEX events_by_template ON events (event_template_uuid, created_at);
+	CREATE INDEX events_by_began_at ON events (began_at);
+	CREATE TABLE event_tags (
+		event_uuid TEXT NOT NULL REFERENCES events (event_uuid) ON DELETE CASCADE,
+		position INTEGER NOT NULL,
+		tag TEXT NOT NULL,
+		PRIMARY KEY (event_uuid, position)
+	);
+	CREATE INDEX event_tags_by_tag ON event_tags (tag);
+", "
+	ALTER TABLE events ADD COLUMN revisions TEXT NOT NULL DEFAULT '[]';
+	ALTER TABLE events ADD COLUMN deleted_at INTEGER;
+", "
+	ALTER TABLE traces ADD COLUMN archived_at INTEGER;
+", "
+	ALTER TABLE projects ADD COLUMN description TEXT NOT NULL DEFAULT '';
+", "
+	ALTER TABLE traces ADD COLUMN project_uuid TEXT;
+	UPDATE traces SET project_uuid =
+		(SELECT project_uuid FROM trace_templates WHERE trace_templates.trace_template_uuid = traces.trace_template_uuid);
+	CREATE INDEX traces_by_project ON traces (project_uuid);
+"];
+
+const TRACE_COLUMNS: &str = "traces.trace_uuid, traces.trace_template_uuid, traces.name,
+	traces.created_at, traces.completed_at,
+	(SELECT group_concat(origin_trace_uuid) FROM
+		(SELECT origin_trace_uuid FROM trace_origins
+		WHERE trace_origins.trace_uuid = traces.trace_uuid ORDER BY origin_trace_uuid)),
+	traces.archived_at, traces.project_uuid";
+
+const EVENT_COLUMNS: &str = "events.event_uuid, events.event_template_uuid, events.trace_uuid,
+	events.fields, events.began_at, events.created_at,
+	(SELECT json_group_array(tag) FROM
+		(SELECT tag FROM event_tags WHERE event_tags.event_uuid = events.event_uuid ORDER BY position)),
+	events.revisions, events.deleted_at";
+
+// Counts what each project holds, the newest trace and event come separately
+const PROJECT_ITEM_COLUMNS: &str = "projects.project_uuid, projects.name, projects.description,
+	projects.created_at,
+	(SELECT count(*) FROM event_templates JOIN trace_templates USING (trace_template_uuid)
+		WHERE trace_templates.project_uuid = projects.project_uuid),
+	(SELECT count(*) FROM trace_templates WHERE trace_templates.project_uuid = projects.project_uuid),
+	(SELECT count(*) FROM traces WHERE traces.project_uuid = projects.project_uuid),
+	(SELECT count(*) FROM events JOIN traces USING (trace_uuid)
+		WHERE traces.project_uuid = projects.project_uuid AND events.deleted_at IS NULL),
+	(SELECT max(traces.created_at) FROM traces WHERE traces.project_uuid = projects.project_uuid),
+	(SELECT max(events.created_at) FROM events JOIN traces USING (trace_uuid)
+		WHERE traces.project_uuid = projects.project_uuid AND events.deleted_at IS NULL)";
+
+// Traces keep the project they were saved in and events go with their traces, so both stay
+// in the project when a reimport removes their template
+const PROJECT_TRACES: &str = "SELECT trace_uuid FROM traces WHERE project_uuid = ?1";
+const PROJECT_EVENT_TEMPLATES: &str = "SELECT event_template_uuid FROM event_templates
+	JOIN trace_templates USING (trace_template_uuid) WHERE project_uuid = ?1";
+const PROJECT_TRACE_TEMPLATES: &str = "SELECT trace_template_uuid FROM trace_templates
+	WHERE project_uuid = ?1";
+
+// Keeps every project in one SQLite file. All changes are committed as they are made, so
+// load_from_disk and save_to_disk have nothing left to do.
+pub struct SqliteEventsService {
+	connection: rusqlite::Connection,
+}
+
+fn io_error(err: rusqlite::Error) -> std::io::Error {
+	std::io::Error::other(err)
+}
+
+fn definition_error(err: rusqlite::Error) -> errors::DefinitionErrors {
+	errors::ParsingError::new(format!("Failed to access the database: {}", err)).into()
+}
+
+// Only for reads behind service methods that have no error to return, they fall back to
+// nothing. Writes report their errors.
+fn read_or_default<T: Default>(result: rusqlite::Result<T>) -> T {
+	result.unwrap_or_else(|err| {
+		log::error!("Failed to read the database: {}", err);
+		T::default()
+	})
+}
+
+fn trace_error(err: rusqlite::Error) -> errors::ChangeTraceError {
+	errors::ChangeTraceError::Storage(io_error(err))
+}
+
+fn event_error(err: rusqlite::Error) -> errors::ChangeEventError {
+	errors::ChangeEventError::Storage(io_error(err))
+}
+
+fn project_error(err: rusqlite::Error) -> errors::ChangeProjectError {
+	errors::ChangeProjectError::Storage(io_error(err))
+}
+
+fn conversion_error(
+	index: usize,
+	err: impl std::error::Error + Send + Sync + 'static,
+) -> rusqlite::Error {
+	rusqlite::Error::FromSqlConversionFailure(index, rusqlite::types::Type::Text, Box::new(err))
+}
+
+fn parse_uuid(index: usize, value: &str) -> rusqlite::Result<Uuid> {
+	Uuid::parse_str(value).map_err(|err| conversion_error(index, err))
+}
+
+fn uuid_at(row: &rusqlite::Row, index: usize) -> rusqlite::Result<Uuid> {
+	parse_uuid(index, &row.get::<_, String>(index)?)
+}
+
+fn json_at<T: serde::de::DeserializeOwned>(row: &rusqlite::Row, index: usize) -> rusqlite::Result<T> {
+	serde_json::from_str(&row.get::<_, String>(index)?).map_err(|err| conversion_error(index, err))
+}
+
+fn to_json(value: &impl serde::Serialize) -> rusqlite::Result<String> {
+	serde_json::to_string(value).map_err(|err| rusqlite::Error::ToSqlConversionFailure(Box::new(err)))
+}
+
+fn time_at(row: &rusqlite::Row, index: usize) -> rusqlite::Result<DateTime<Utc>> {
+	let millis: i64 = row.get(index)?;
+	DateTime::from_timestamp_millis(millis).ok_or(rusqlite::Error::IntegralValueOutOfRange(index, millis))
+}
+
+fn optional_time_at(row: &rusqlite::Row, index: usize) -> rusqlite::Result<Option<DateTime<Utc>>> {
+	match row.get::<_, Option<i64>>(index)? {
+		Some(_) => time_at(row, index).map(Some),
+		None => Ok(None),
+	}
+}
+
+fn trace_from_row(row: &rusqlite::Row) -> rusqlite::Result<events::Trace> {
+	let origin_trace_uuids: Option<String> = row.get(5)?;
+	Ok(events::Trace {
+		trace_uuid: uuid_at(row, 0)?,
+		trace_template_uuid: uuid_at(row, 1)?,
+		name: row.get(2)?,
+		created_at: time_at(row, 3)?,
+		completion: match row.get::<_, Option<i64>>(4)? {
+			Some(_) => Some(views::TraceCompletion {
+				completed_at: time_at(row, 4)?,
+			}),
+			None => None,
+		},
+		origin_trace_uuids: origin_trace_uuids.map_or(Ok(vec![]), |origin_trace_uuids| {
+			origin_trace_uuids
+				.split(',')
+				.map(|origin_trace_uuid| parse_uuid(5, origin_trace_uuid))
+				.collect()
+		})?,
+		archived_at: optional_time_at(row, 6)?,
+		// Traces whose template was gone before the column was added have no project
+		project_uuid: match row.get::<_, Option<String>>(7)? {
+			Some(project_uuid) => parse_uuid(7, &project_uuid)?,
+			None => Uuid::nil(),
+		},
+	})
+}
+
+fn project_item_from_row(row: &rusqlite::Row) -> rusqlite::Result<views::ProjectItemView> {
+	Ok(views::ProjectItemView {
+		project_uuid: uuid_at(row, 0)?,
+		name: row.get(1)?,
+		description: row.get(2)?,
+		created_at: time_at(row, 3)?,
+		event_template_count: row.get(4)?,
+		trace_template_count: row.get(5)?,
+		trace_count: row.get(6)?,
+		event_count: row.get(7)?,
+		last_activity: optional_time_at(row, 8)?.max(optional_time_at(row, 9)?),
+	})
+}
+
+fn trace_filter_condition(filter: service::TraceFilter) -> &'static str {
+	match filter {
+		service::TraceFilter::Active => "completed_at IS NULL AND archived_at IS NULL",
+		service::TraceFilter::Completed => "completed_at IS NOT NULL AND archived_at IS NULL",
+		service::TraceFilter::Archived => "archived_at IS NOT NULL",
+		service::TraceFilter::All => "1",
+	}
+}
+
+fn event_from_row(row: &rusqlite::Row) -> rusqlite::Result<events::Event> {
+	Ok(events::Event {
+		event_uuid: uuid_at(row, 0)?,
+		event_template_uuid: uuid_at(row, 1)?,
+		trace_uuid: uuid_at(row, 2)?,
+		fields: json_at(row, 3)?,
+		began_at: time_at(row, 4)?,
+		created_at: time_at(row, 5)?,
+		tags: json_at(row, 6)?,
+		revisions: json_at(row, 7)?,
+		deleted_at: optional_time_at(row, 8)?,
+	})
+}
+
+fn migrate_schema(connection: &mut rusqlite::Connection) -> rusqlite::Result<()> {
+	let version: usize = connection.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+	if version > SCHEMA_MIGRATIONS.len() {
+		return Err(rusqlite::Error::SqliteFailure(
+			rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_CANTOPEN),
+			Some(format!(
+				"The database has schema version {}, this version of Journaler only knows up to {}",
+				version,
+				SCHEMA_MIGRATIONS.len()
+			)),
+		));
+	}
+	let transaction = connection.transaction()?;
+	for (index, migration) in SCHEMA_MIGRATIONS.iter().enumerate().skip(version) {
+		transaction.execute_batch(migration)?;
+		transaction.pragma_update(None, "user_version", (index + 1) as i64)?;
+	}
+	transaction.commit()
+}
+
+fn write_project(transaction: &rusqlite::Transaction, project: &project::Project) -> rusqlite::Result<()> {
+	// The creation time of a project that already exists is kept
+	transaction.execute(
+		"INSERT INTO projects (project_uuid, name, description, created_at, definition_version, migrations)
+		VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+		ON CONFLICT (project_uuid) DO UPDATE SET
+			name = excluded.name,
+			description = excluded.description,
+			definition_version = excluded.definition_version,
+			migrations = excluded.migrations",
+		params![
+			project.project_uuid.to_string(),
+			project.name,
+			project.description,
+			project.created_at.timestamp_millis(),
+			project.definition_version,
+			to_json(&project.migrations)?,
+		],
+	)?;
+	Ok(())
+}
+
+fn write_trace(transaction: &rusqlite::Transaction, trace: &events::Trace) -> rusqlite::Result<()> {
+	transaction.execute(
+		"INSERT INTO traces (trace_uuid, trace_template_uuid, name, created_at, completed_at, archived_at, project_uuid)
+		VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+		ON CONFLICT (trace_uuid) DO UPDATE SET
+			trace_template_uuid = excluded.trace_template_uuid,
+			name = excluded.name,
+			created_at = excluded.created_at,
+			completed_at = excluded.completed_at,
+			archived_at = excluded.archived_at",
+		params![
+			trace.trace_uuid.to_string(),
+			trace.trace_template_uuid.to_string(),
+			trace.name,
+			trace.created_at.timestamp_millis(),
+			trace
+				.completion
+				.as_ref()
+				.map(|completion| completion.completed_at.timestamp_millis()),
+			trace.archived_at.map(|archived_at| archived_at.timestamp_millis()),
+			trace.project_uuid.to_string(),
+		],
+	)?;
+	transaction.execute(
+		"DELETE FROM trace_origins WHERE trace_uuid = ?1",
+		params![trace.trace_uuid.to_string()],
+	)?;
+	for origin_trace_uuid in trace.origin_trace_uuids.iter() {
+		transaction.execute(
+			"INSERT OR IGNORE INTO trace_origins (trace_uuid, origin_trace_uuid) VALUES (?1, ?2)",
+			params![trace.trace_uuid.to_string(), origin_trace_uuid.to_string()],
+		)?;
+	}
+	Ok(())
+}
+
+fn write_event(transaction: &rusqlite::Transaction, event: &events::Event) -> rusqlite::Result<()> {
+	transaction.execute(
+		"INSERT INTO events (event_uuid, event_template_uuid, trace_uuid, fields, began_at, created_at,
+			revisions, deleted_at)
+		VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+		ON CONFLICT (event_uuid) DO UPDATE SET
+			event_template_uuid = excluded.event_template_uuid,
+			trace_uuid = excluded.trace_uuid,
+			fields = excluded.fields,
+			began_at = excluded.began_at,
+			created_at = excluded.created_at,
+			revisions = excluded.revisions,
+			deleted_at = excluded.deleted_at",
+		params![
+			event.event_uuid.to_string(),
+			event.event_template_uuid.to_string(),
+			event.trace_uuid.to_string(),
+			to_json(&event.fields)?,
+			event.began_at.timestamp_millis(),
+			event.created_at.timestamp_millis(),
+			to_json(&event.revisions)?,
+			event.deleted_at.map(|deleted_at| deleted_at.timestamp_millis()),
+		],
+	)?;
+	transaction.execute(
+		"DELETE FROM event_tags WHERE event_uuid = ?1",
+		params![event.event_uuid.to_string()],
+	)?;
+	for (position, tag) in event.tags.iter().enumerate() {
+		transaction.execute(
+			"INSERT INTO event_tags (event_uuid, position, tag) VALUES (?1, ?2, ?3)",
+			params![event.event_uuid.to_string(), position as i64, tag],
+		)?;
+	}
+	Ok(())
+}
+
+// Replaces the templates of the project, the definition has been validated
+fn write_definition(
+	transaction: &rusqlite::Transaction,
+	project_definition: &fs_service::ProjectDefinition,
+) -> rusqlite::Result<()> {
+	let project_uuid = project_definition.project_uuid.to_string();
+	transaction.execute(
+		&format!("DELETE FROM event_templates WHERE event_template_uuid IN ({})", PROJECT_EVENT_TEMPLATES),
+		params![project_uuid],
+	)?;
+	transaction.execute(
+		"DELETE FROM trace_templates WHERE project_uuid = ?1",
+		params![project_uuid],
+	)?;
+	for trace_template in project_definition.trace_templates.iter() {
+		transaction.execute(
+			"INSERT INTO trace_templates (trace_template_uuid, project_uuid, body) VALUES (?1, ?2, ?3)",
+			params![
+				trace_template.trace_template_uuid.to_string(),
+				project_uuid,
+				to_json(trace_template)?,
+			],
+		)?;
+	}
+	for event_template in project_definition.event_templates.iter() {
+		transaction.execute(
+			"INSERT INTO event_templates (event_template_uuid, trace_template_uuid, body) VALUES (?1, ?2, ?3)",
+			params![
+				event_template.event_template_uuid.to_string(),
+				event_template.trace_template_uuid.to_string(),
+				to_json(event_template)?,
+			],
+		)?;
+	}
+	Ok(())
+}
+
+impl SqliteEventsService {
+	// Creates the database when it does not exist and brings its schema up to date
+	pub fn open(path: &std::path::Path) -> Result<Self, std::io::Error> {
+		Self::from_connection(rusqlite::Connection::open(path).map_err(io_error)?)
+	}
+
+	pub fn open_in_memory() -> Result<Self, std::io::Error> {
+		Self::from_connection(rusqlite::Connection::open_in_memory().map_err(io_error)?)
+	}
+
+	fn from_connection(mut connection: rusqlite::Connection) -> Result<Self, std::io::Error> {
+		connection
+			.pragma_update(None, "foreign_keys", true)
+			.map_err(io_error)?;
+		connection
+			.pragma_update_and_check(None, "journal_mode", "WAL", |_| Ok(()))
+			.map_err(io_error)?;
+		migrate_schema(&mut connection).map_err(io_error)?;
+		Ok(SqliteEventsService { connection })
+	}
+
+	// Copies everything the file system service holds, for moving an existing journal over
+	pub fn copy_from(&mut self, service: &fs_service::FileSystemEventsService) -> Result<(), std::io::Error> {
+		let transaction = self.connection.transaction().map_err(io_error)?;
+		for project in service.projects.values() {
+			write_project(&transaction, project).map_err(io_error)?;
+			let project_definition = fs_service::ProjectDefinition {
+				project_uuid: project.project_uuid,
+				version: project.definition_version,
+				migrations: project.migrations.clone(),
+				event_templates: service
+					.event_templates
+					.values()
+					.filter(|event_template| {
+						service
+							.trace_templates
+							.get(&event_template.trace_template_uuid)
+							.is_some_and(|trace_template| trace_template.project_uuid == project.project_uuid)
+					})
+					.cloned()
+					.collect(),
+				trace_templates: service
+					.trace_templates
+					.values()
+					.filter(|trace_template| trace_template.project_uuid == project.project_uuid)
+					.cloned()
+					.collect(),
+			};
+			write_definition(&transaction, &project_definition).map_err(io_error)?;
+		}
+		for trace in service.traces.values() {
+			write_trace(&transaction, trace).map_err(io_error)?;
+		}
+		for event in service.events.values() {
+			write_event(&transaction, event).map_err(io_error)?;
+		}
+		transaction.commit().map_err(io_error)
+	}
+
+	fn query_traces(&self, sql: &str, params: impl rusqlite::Params) -> rusqlite::Result<Vec<events::Trace>> {
+		self.connection
+			.prepare_cached(sql)?
+			.query_map(params, trace_from_row)?
+			.collect()
+	}
+
+	fn query_events(&self, sql: &str, params: impl rusqlite::Params) -> rusqlite::Result<Vec<events::Event>> {
+		self.connection
+			.prepare_cached(sql)?
+			.query_map(params, event_from_row)?
+			.collect()
+	}
+
+	// The newest events of the template, newest first
+	fn recent_events(
+		&self,
+		event_template_uuid: Uuid,
+		trace_uuid: Uuid,
+		history: service::HistoryScope,
+	) -> rusqlite::Result<Vec<events::Event>> {
+		self.query_events(
+			&format!(
+				"SELECT {} FROM events WHERE event_template_uuid = ?1 AND deleted_at IS NULL
+				AND (?2 IS NULL OR trace_uuid = ?2) ORDER BY created_at DESC LIMIT ?3",
+				EVENT_COLUMNS
+			),
+			params![
+				event_template_uuid.to_string(),
+				(history == service::HistoryScope::SameTrace).then(|| trace_uuid.to_string()),
+				builders::HISTORY_EVENTS,
+			],
+		)
+	}
+
+	fn trace(&self, trace_uuid: &Uuid) -> rusqlite::Result<Option<events::Trace>> {
+		self.connection
+			.query_row(
+				&format!("SELECT {} FROM traces WHERE trace_uuid = ?1", TRACE_COLUMNS),
+				params![trace_uuid.to_string()],
+				trace_from_row,
+			)
+			.optional()
+	}
+
+	fn event(&self, event_uuid: &Uuid) -> rusqlite::Result<Option<events::Event>> {
+		self.connection
+			.query_row(
+				&format!("SELECT {} FROM events WHERE event_uuid = ?1", EVENT_COLUMNS),
+				params![event_uuid.to_string()],
+				event_from_row,
+			)
+			.optional()
+	}
+
+	// All traces, for walking origin links
+	fn traces(&self) -> rusqlite::Result<HashMap<Uuid, events::Trace>> {
+		Ok(self
+			.query_traces(&format!("SELECT {} FROM traces", TRACE_COLUMNS), [])?
+			.into_iter()
+			.map(|trace| (trace.trace_uuid, trace))
+			.collect())
+	}
+
+	fn event_template(&self, event_template_uuid: &Uuid) -> rusqlite::Result<Option<definition::EventTemplate>> {
+		self.connection
+			.query_row(
+				"SELECT body FROM event_templates WHERE event_template_uuid = ?1",
+				params![event_template_uuid.to_string()],
+				|row| json_at(row, 0),
+			)
+			.optional()
+	}
+
+	fn trace_template(&self, trace_template_uuid: &Uuid) -> rusqlite::Result<Option<definition::TraceTemplate>> {
+		self.connection
+			.query_row(
+				"SELECT body FROM trace_templates WHERE trace_template_uuid = ?1",
+				params![trace_template_uuid.to_string()],
+				|row| json_at(row, 0),
+			)
+			.optional()
+	}
+
+	// Templates are few, so they are read whole
+	fn event_templates(&self) -> rusqlite::Result<HashMap<Uuid, definition::EventTemplate>> {
+		self.connection
+			.prepare_cached("SELECT body FROM event_templates")?
+			.query_map([], |row| json_at(row, 0))?
+			.map(|template| template.map(|template: definition::EventTemplate| (template.event_template_uuid, template)))
+			.collect()
+	}
+
+	fn trace_templates(&self) -> rusqlite::Result<HashMap<Uuid, definition::TraceTemplate>> {
+		self.connection
+			.prepare_cached("SELECT body FROM trace_templates")?
+			.query_map([], |row| json_at(row, 0))?
+			.map(|template| template.map(|template: definition::TraceTemplate| (template.trace_template_uuid, template)))
+			.collect()
+	}
+
+	fn project(&self, project_uuid: &Uuid) -> rusqlite::Result<Option<project::Project>> {
+		self.connection
+			.query_row(
+				"SELECT project_uuid, name, description, created_at, definition_version, migrations
+				FROM projects WHERE project_uuid = ?1",
+				params![project_uuid.to_string()],
+				|row| {
+					Ok(project::Project {
+						project_uuid: uuid_at(row, 0)?,
+						name: row.get(1)?,
+						description: row.get(2)?,
+						created_at: time_at(row, 3)?,
+						definition_version: row.get(4)?,
+						migrations: json_at(row, 5)?,
+					})
+				},
+			)
+			.optional()
+	}
+
+	fn project_definition(&self, project_uuid: &Uuid) -> rusqlite::Result<Option<fs_service::ProjectDefinition>> {
+		let Some(project) = self.project(project_uuid)? else {
+			return Ok(None);
+		};
+		let trace_templates: Vec<definition::TraceTemplate> = self
+			.trace_templates()?
+			.into_values()
+			.filter(|trace_template| &trace_template.project_uuid == project_uuid)
+			.collect();
+		let event_templates = self
+			.event_templates()?
+			.into_values()
+			.filter(|event_template| {
+				trace_templates
+					.iter()
+					.any(|trace_template| trace_template.trace_template_uuid == event_template.trace_template_uuid)
+			})
+			.collect();
+		Ok(Some(fs_service::ProjectDefinition {
+			project_uuid: project.project_uuid,
+			version: project.definition_version,
+			migrations: project.migrations,
+			event_templates,
+			trace_templates,
+		}))
+	}
+
+	fn view_trace_item(&self, trace: &events::Trace) -> rusqlite::Result<views::TraceItemView> {
+		Ok(trace.to_item(
+			self.trace_template(&trace.trace_template_uuid)?
+				.map(|trace_template| trace_template.name),
+		))
+	}
+
+	fn view_event_item(&self, event: &events::Event) -> rusqlite::Result<views::EventItemView> {
+		Ok(views::EventItemView {
+			event_uuid: event.event_uuid,
+			event_template: self
+				.event_template(&event.event_template_uuid)?
+				.map(|template| template.to_item(None)),
+			trace_name: self.trace(&event.trace_uuid)?.map(|trace| trace.name),
+			created_at: event.created_at,
+		})
+	}
+
+	fn event_items(&self, events: Vec<events::Event>) -> rusqlite::Result<Vec<views::EventItemView>> {
+		events.iter().map(|event| self.view_event_item(event)).collect()
+	}
+
+	// All projects when no project is given, oldest first
+	fn project_items(&self, project_uuid: Option<Uuid>) -> rusqlite::Result<Vec<views::ProjectItemView>> {
+		self.connection
+			.prepare_cached(&format!(
+				"SELECT {} FROM projects WHERE ?1 IS NULL OR project_uuid = ?1 ORDER BY created_at",
+				PROJECT_ITEM_COLUMNS
+			))?
+			.query_map(params![project_uuid.map(|uuid| uuid.to_string())], project_item_from_row)?
+			.collect()
+	}
+
+	fn project_view(&self, project_uuid: Uuid) -> rusqlite::Result<Option<views::ProjectView>> {
+		let Some(project) = self.project(&project_uuid)? else {
+			return Ok(None);
+		};
+		let Some(item) = self.project_items(Some(project_uuid))?.pop() else {
+			return Ok(None);
+		};
+		let mut event_templates = self.event_template_items(Some(project_uuid))?;
+		event_templates.sort_by(|a, b| a.name.cmp(&b.name));
+		let mut trace_templates = self.trace_template_items(Some(project_uuid))?;
+		trace_templates.sort_by(|a, b| a.name.cmp(&b.name));
+		Ok(Some(project.to_view(item, event_templates, trace_templates)))
+	}
+
+	fn delete_project_rows(&mut self, project_uuid: Uuid) -> rusqlite::Result<()> {
+		let project_parameter = params![project_uuid.to_string()];
+		// Tags and origins go with their events and traces. Traces go by the project they were
+		// saved in, those of a template a reimport removed are still the project's
+		let transaction = self.connection.transaction()?;
+		transaction.execute(
+			&format!("DELETE FROM events WHERE trace_uuid IN ({})", PROJECT_TRACES),
+			project_parameter,
+		)?;
+		transaction.execute("DELETE FROM traces WHERE project_uuid = ?1", project_parameter)?;
+		transaction.execute(
+			&format!(
+				"DELETE FROM event_templates WHERE trace_template_uuid IN ({})",
+				PROJECT_TRACE_TEMPLATES
+			),
+			project_parameter,
+		)?;
+		transaction.execute("DELETE FROM trace_templates WHERE project_uuid = ?1", project_parameter)?;
+		transaction.execute("DELETE FROM projects WHERE project_uuid = ?1", project_parameter)?;
+		transaction.commit()
+	}
+
+	fn write_new_project(
+		&mut self,
+		project: &project::Project,
+		project_definition: &fs_service::ProjectDefinition,
+	) -> rusqlite::Result<()> {
+		let transaction = self.connection.transaction()?;
+		write_project(&transaction, project)?;
+		write_definition(&transaction, project_definition)?;
+		transaction.commit()
+	}
+
+	fn event_template_items(&self, project_uuid: Option<Uuid>) -> rusqlite::Result<Vec<views::EventTemplateItemView>> {
+		let trace_templates = self.trace_templates()?;
+		let last_used: HashMap<Uuid, DateTime<Utc>> = self
+			.connection
+			.prepare_cached("SELECT event_template_uuid, max(created_at) FROM events
+				WHERE deleted_at IS NULL GROUP BY event_template_uuid")?
+			.query_map([], |row| Ok((uuid_at(row, 0)?, time_at(row, 1)?)))?
+			.collect::<rusqlite::Result<_>>()?;
+		let mut items: Vec<views::EventTemplateItemView> = self
+			.event_templates()?
+			.into_values()
+			.filter(|event_template| {
+				project_uuid.as_ref().is_none_or(|project_uuid| {
+					trace_templates
+						.get(&event_template.trace_template_uuid)
+						.is_some_and(|trace_template| &trace_template.project_uuid == project_uuid)
+				})
+			})
+			.map(|event_template| event_template.to_item(last_used.get(&event_template.event_template_uuid).cloned()))
+			.collect();
+		items.sort_by_key(|item| item.last_used);
+		Ok(items)
+	}
+
+	fn trace_template_items(&self, project_uuid: Option<Uuid>) -> rusqlite::Result<Vec<views::TraceTemplateItemView>> {
+		let last_used: HashMap<Uuid, DateTime<Utc>> = self
+			.connection
+			.prepare_cached("SELECT trace_template_uuid, max(created_at) FROM traces GROUP BY trace_template_uuid")?
+			.query_map([], |row| Ok((uuid_at(row, 0)?, time_at(row, 1)?)))?
+			.collect::<rusqlite::Result<_>>()?;
+		let mut items: Vec<views::TraceTemplateItemView> = self
+			.trace_templates()?
+			.into_values()
+			.filter(|trace_template| {
+				project_uuid
+					.as_ref()
+					.is_none_or(|project_uuid| &trace_template.project_uuid == project_uuid)
+			})
+			.map(|trace_template| trace_template.to_item(last_used.get(&trace_template.trace_template_uuid).cloned()))
+			.collect();
+		items.sort_by_key(|item| item.last_used);
+		Ok(items)
+	}
+
+	fn trace_items(
+		&self,
+		project_uuid: Option<Uuid>,
+		filter: service::TraceFilter,
+	) -> rusqlite::Result<Vec<views::TraceItemView>> {
+		let condition = trace_filter_condition(filter);
+		let traces = match project_uuid {
+			Some(project_uuid) => self.query_traces(
+				&format!(
+					"SELECT {} FROM traces WHERE {} AND project_uuid = ?1",
+					TRACE_COLUMNS, condition
+				),
+				params![project_uuid.to_string()],
+			)?,
+			None => self.query_traces(
+				&format!("SELECT {} FROM traces WHERE {}", TRACE_COLUMNS, condition),
+				[],
+			)?,
+		};
+		traces.iter().map(|trace| self.view_trace_item(trace)).collect()
+	}
+
+	fn project_events(&self, project_uuid: Option<Uuid>) -> rusqlite::Result<Vec<events::Event>> {
+		match project_uuid {
+			Some(project_uuid) => self.query_events(
+				&format!(
+					"SELECT {} FROM events
+					WHERE deleted_at IS NULL AND trace_uuid IN ({}) ORDER BY created_at",
+					EVENT_COLUMNS, PROJECT_TRACES
+				),
+				params![project_uuid.to_string()],
+			),
+			None => self.query_events(
+				&format!("SELECT {} FROM events WHERE deleted_at IS NULL ORDER BY created_at", EVENT_COLUMNS),
+				[],
+			),
+		}
+	}
+
+	// Events that began in [from, to), oldest first
+	pub fn list_events_between(
+		&self,
+		from: DateTime<Utc>,
+		to: DateTime<Utc>,
+	) -> Vec<views::EventItemView> {
+		read_or_default(
+			self.query_events(
+				&format!(
+					"SELECT {} FROM events
+					WHERE deleted_at IS NULL AND began_at >= ?1 AND began_at < ?2 ORDER BY began_at",
+					EVENT_COLUMNS
+				),
+				params![from.timestamp_millis(), to.timestamp_millis()],
+			)
+			.and_then(|events| self.event_items(events)),
+		)
+	}
+
+	pub fn list_events_with_tag(&self, tag: &str) -> Vec<views::EventItemView> {
+		read_or_default(
+			self.query_events(
+				&format!(
+					"SELECT {} FROM events
+					WHERE deleted_at IS NULL AND event_uuid IN (SELECT event_uuid FROM event_tags WHERE tag = ?1)
+					ORDER BY began_at",
+					EVENT_COLUMNS
+				),
+				params![tag],
+			)
+			.and_then(|events| self.event_items(events)),
+		)
+	}
+
+	fn create_trace_view(&self, trace: &events::Trace) -> rusqlite::Result<views::TraceView> {
+		let last_event = self
+			.query_events(
+				&format!(
+					"SELECT {} FROM events WHERE trace_uuid = ?1 AND deleted_at IS NULL
+					ORDER BY created_at DESC LIMIT 1",
+					EVENT_COLUMNS
+				),
+				params![trace.trace_uuid.to_string()],
+			)?
+			.pop();
+		let last_used = last_event.as_ref().map(|event| event.created_at);
+		let trace_template = self.trace_template(&trace.trace_template_uuid)?;
+
+		let mut suggested_event_templates = vec![];
+		let mut other_event_templates = vec![];
+		if let Some(trace_template) = &trace_template {
+			let suggested = trace_template
+				.get_suggested_event_templates(last_event.as_ref().map(|event| event.event_template_uuid));
+			let event_templates: Vec<definition::EventTemplate> = self
+				.connection
+				.prepare_cached("SELECT body FROM event_templates WHERE trace_template_uuid = ?1")?
+				.query_map(params![trace.trace_template_uuid.to_string()], |row| json_at(row, 0))?
+				.collect::<rusqlite::Result<_>>()?;
+			for event_template in event_templates {
+				if suggested.contains(&event_template.event_template_uuid) {
+					suggested_event_templates.push(event_template.to_item(last_used));
+				} else {
+					other_event_templates.push(event_template.to_item(last_used));
+				}
+			}
+		}
+
+		let mut origin_traces = vec![];
+		for origin_trace_uuid in trace.origin_trace_uuids.iter() {
+			if let Some(origin) = self.trace(origin_trace_uuid)? {
+				origin_traces.push(self.view_trace_item(&origin)?);
+			}
+		}
+		let child_traces = self
+			.query_traces(
+				&format!(
+					"SELECT {} FROM traces WHERE trace_uuid IN
+					(SELECT trace_uuid FROM trace_origins WHERE origin_trace_uuid = ?1)",
+					TRACE_COLUMNS
+				),
+				params![trace.trace_uuid.to_string()],
+			)?
+			.iter()
+			.map(|child| self.view_trace_item(child))
+			.collect::<rusqlite::Result<_>>()?;
+
+		Ok(views::TraceView {
+			trace_uuid: trace.trace_uuid,
+			name: trace.name.clone(),
+			trace_template: trace_template.map(|template| template.to_item(last_used)),
+			tags: vec![], // TODO
+			completion: trace.completion.clone(),
+			archived_at: trace.archived_at,
+			origin_traces,
+			child_traces,
+			last_event: last_event.map(|event| self.view_event_item(&event)).transpose()?,
+			suggested_event_templates,
+			other_event_templates,
+		})
+	}
+
+	fn view_found_event(&self, event: &events::Event) -> rusqlite::Result<views::EventView> {
+		Ok(views::EventView {
+			event_uuid: event.event_uuid,
+			event_template: self
+				.event_template(&event.event_template_uuid)?
+				.map(|template| template.to_item(None)),
+			fields: event.fields.iter().map(|field| field.to_view()).collect(),
+			tags: event.tags.clone(),
+			trace: self
+				.trace(&event.trace_uuid)?
+				.map(|trace| self.view_trace_item(&trace))
+				.transpose()?,
+			began_at: event.began_at,
+			created_at: event.created_at,
+			deleted_at: event.deleted_at,
+			revisions: event.revisions.iter().map(|revision| revision.to_view()).collect(),
+		})
+	}
+
+	// Sets columns of the row whose key is ?1, the values follow it. Returns how many rows were
+	// updated, none when there is no such row.
+	fn update_row(
+		&mut self,
+		table: &str,
+		key_column: &str,
+		key: Uuid,
+		assignments: &str,
+		values: &[&dyn rusqlite::ToSql],
+	) -> rusqlite::Result<usize> {
+		let key_parameter = key.to_string();
+		let mut parameters: Vec<&dyn rusqlite::ToSql> = vec![&key_parameter];
+		parameters.extend_from_slice(values);
+		self.connection.execute(
+			&format!("UPDATE {} SET {} WHERE {} = ?1", table, assignments, key_column),
+			parameters.as_slice(),
+		)
+	}
+
+	fn update_trace(
+		&mut self,
+		trace_uuid: Uuid,
+		assignments: &str,
+		values: &[&dyn rusqlite::ToSql],
+	) -> Result<(), errors::ChangeTraceError> {
+		match self.update_row("traces", "trace_uuid", trace_uuid, assignments, values) {
+			Ok(0) => Err(errors::ChangeTraceError::TraceNotFound(trace_uuid.into())),
+			Ok(_) => Ok(()),
+			Err(err) => Err(trace_error(err)),
+		}
+	}
+
+	fn update_project(
+		&mut self,
+		project_uuid: Uuid,
+		assignments: &str,
+		values: &[&dyn rusqlite::ToSql],
+	) -> Result<(), errors::ChangeProjectError> {
+		match self.update_row("projects", "project_uuid", project_uuid, assignments, values) {
+			Ok(0) => Err(errors::ChangeProjectError::ProjectNotFound(project_uuid.into())),
+			Ok(_) => Ok(()),
+			Err(err) => Err(project_error(err)),
+		}
+	}
+
+	// Applies a change to a saved event and writes the result
+	fn change_event<T>(
+		&mut self,
+		event_uuid: Uuid,
+		change: impl FnOnce(&mut events::Event) -> T,
+	) -> Result<T, errors::ChangeEventError> {
+		let Some(mut event) = self.event(&event_uuid).map_err(event_error)? else {
+			return Err(errors::ChangeEventError::EventNotFound(event_uuid.into()));
+		};
+		let result = change(&mut event);
+		let transaction = self.connection.transaction().map_err(event_error)?;
+		write_event(&transaction, &event).map_err(event_error)?;
+		transaction.commit().map_err(event_error)?;
+		Ok(result)
+	}
+
+	fn plan_definition_import(
+		&self,
+		definition_path: &std::path::Path,
+		into_project: Option<Uuid>,
+	) -> Result<reimport::PlannedImport, errors::DefinitionErrors> {
+		if let Some(project_uuid) = into_project {
+			if self.project(&project_uuid).map_err(definition_error)?.is_none() {
+				return Err(errors::ParsingError::new(format!("Project {} does not exist", project_uuid)).into());
+			}
+		}
+		let parsed = definition_parser::read_definition(definition_path, into_project)?;
+		let project_uuid = parsed.project_definition.project_uuid;
+		let current = self.project_definition(&project_uuid).map_err(definition_error)?;
+		// Only the project's own data is migrated, including whatever an earlier import orphaned
+		let project_parameter = params![project_uuid.to_string()];
+		let events = self
+			.query_events(
+				&format!("SELECT {} FROM events WHERE trace_uuid IN ({})", EVENT_COLUMNS, PROJECT_TRACES),
+				project_parameter,
+			)
+			.map_err(definition_error)?;
+		let traces = self
+			.query_traces(
+				&format!("SELECT {} FROM traces WHERE project_uuid = ?1", TRACE_COLUMNS),
+				project_parameter,
+			)
+			.map_err(definition_error)?;
+		reimport::plan_definition_import(
+			parsed,
+			current.as_ref(),
+			&self.event_templates().map_err(definition_error)?,
+			&self.trace_templates().map_err(definition_error)?,
+			events.iter(),
+			traces.iter(),
+		)
+	}
+}
+
+impl service::EventsService for SqliteEventsService {
+	fn list_event_templates(
+		&self,
+		project_uuid: Option<Uuid>,
+	) -> Box<dyn Iterator<Item = views::EventTemplateItemView>> {
+		Box::new(read_or_default(self.event_template_items(project_uuid)).into_iter())
+	}
+
+	fn create_event(
+		&self,
+		trace: &views::TraceView,
+		template_uuid: Uuid,
+		history: service::HistoryScope,
+	) -> Result<service::EventBuilder, errors::EventTemplateNotFound> {
+		let event_template = read_or_default(self.event_template(&template_uuid)).ok_or(template_uuid)?;
+		let mut event_builder = event_template.create_builder(trace);
+		let in_trace = read_or_default(self.recent_events(template_uuid, trace.trace_uuid, service::HistoryScope::SameTrace));
+		let in_template = read_or_default(self.recent_events(template_uuid, trace.trace_uuid, service::HistoryScope::AnyTrace));
+		builders::suggest_values(
+			&mut event_builder.fields,
+			&event_template,
+			history,
+			&in_trace.iter().collect::<Vec<_>>(),
+			&in_template.iter().collect::<Vec<_>>(),
+		);
+		Ok(event_builder)
+	}
+
+	fn create_trace(
+		&self,
+		trace_template_uuid: Uuid,
+	) -> Result<service::TraceBuilder, errors::TraceTemplateNotFound> {
+		Ok(read_or_default(self.trace_template(&trace_template_uuid))
+			.ok_or(trace_template_uuid)?
+			.create_builder())
+	}
+
+	fn complete_trace(&mut self, trace_uuid: Uuid) -> Result<(), errors::ChangeTraceError> {
+		self.update_trace(trace_uuid, "completed_at = coalesce(completed_at, ?2)", &[&Utc::now().timestamp_millis()])
+	}
+
+	fn reopen_trace(&mut self, trace_uuid: Uuid) -> Result<(), errors::ChangeTraceError> {
+		self.update_trace(trace_uuid, "completed_at = NULL", &[])
+	}
+
+	fn rename_trace(&mut self, trace_uuid: Uuid, name: String) -> Result<(), errors::ChangeTraceError> {
+		self.update_trace(trace_uuid, "name = ?2", &[&name])
+	}
+
+	fn change_trace_template(
+		&mut self,
+		trace_uuid: Uuid,
+		trace_template_uuid: Uuid,
+	) -> Result<(), errors::ChangeTraceTemplateError> {
+		let storage_error = |err| errors::ChangeTraceTemplateError::Storage(io_error(err));
+		let Some(trace) = self.trace(&trace_uuid).map_err(storage_error)? else {
+			return Err(errors::ChangeTraceTemplateError::TraceNotFound(trace_uuid.into()));
+		};
+		// The trace stays within its project
+		if self
+			.trace_template(&trace_template_uuid)
+			.map_err(storage_error)?
+			.is_none_or(|trace_template| trace_template.project_uuid != trace.project_uuid)
+		{
+			return Err(errors::ChangeTraceTemplateError::TraceTemplateNotFound(
+				trace_template_uuid.into(),
+			));
+		}
+		Ok(self.update_trace(trace_uuid, "trace_template_uuid = ?2", &[&trace_template_uuid.to_string()])?)
+	}
+
+	fn archive_trace(&mut self, trace_uuid: Uuid) -> Result<(), errors::ChangeTraceError> {
+		self.update_trace(trace_uuid, "archived_at = coalesce(archived_at, ?2)", &[&Utc::now().timestamp_millis()])
+	}
+
+	fn unarchive_trace(&mut self, trace_uuid: Uuid) -> Result<(), errors::ChangeTraceError> {
+		self.update_trace(trace_uuid, "archived_at = NULL", &[])
+	}
+
+	fn save_event(&mut self, event_builder: &service::EventBuilder) -> Result<(), errors::SaveEventError> {
+		let storage_error = |err| errors::SaveEventError::Storage(io_error(err));
+		let event = builders::build_event(event_builder);
+		if self.trace(&event.trace_uuid).map_err(storage_error)?.is_none() {
+			return Err(errors::SaveEventError::TraceNotFound(event.trace_uuid.into()));
+		}
+		self.connection
+			.transaction()
+			.and_then(|transaction| {
+				write_event(&transaction, &event)?;
+				transaction.commit()
+			})
+			.map_err(storage_error)
+	}
+
+	fn edit_event(&self, event_uuid: Uuid) -> Result<service::EventBuilder, errors::EventNotFound> {
+		let event = read_or_default(self.event(&event_uuid))
+			.filter(|event| !event.is_deleted())
+			.ok_or(event_uuid)?;
+		let trace = read_or_default(
+			self.trace(&event.trace_uuid)
+				.and_then(|trace| trace.map(|trace| self.view_trace_item(&trace)).transpose()),
+		);
+		Ok(builders::edit_event_builder(
+			&event,
+			read_or_default(self.event_template(&event.event_template_uuid)).as_ref(),
+			trace,
+		))
+	}
+
+	fn update_event(&mut self, event_builder: &service::EventBuilder) -> Result<(), errors::ChangeEventError> {
+		let event_uuid = event_builder.uuid;
+		if self.event(&event_uuid).map_err(event_error)?.is_none_or(|event| event.is_deleted()) {
+			return Err(errors::ChangeEventError::EventNotFound(event_uuid.into()));
+		}
+		// An event of a trace that is not there would not be saved with any project
+		if let service::TraceSelection::Selected(trace) = &event_builder.selected_trace {
+			if self.trace(&trace.trace_uuid).map_err(event_error)?.is_none() {
+				return Err(errors::ChangeEventError::TraceNotFound(trace.trace_uuid.into()));
+			}
+		}
+		let fields = builders::build_fields(event_builder);
+		self.change_event(event_uuid, |event| {
+			let trace_uuid = match &event_builder.selected_trace {
+				service::TraceSelection::Selected(trace) => trace.trace_uuid,
+				service::TraceSelection::None => event.trace_uuid,
+			};
+			event.revise(trace_uuid, fields, event_builder.tags.clone(), event_builder.began_at);
+		})
+	}
+
+	fn delete_event(&mut self, event_uuid: Uuid) -> Result<(), errors::ChangeEventError> {
+		self.change_event(event_uuid, |event| event.delete())
+	}
+
+	fn undo_event_change(&mut self, event_uuid: Uuid) -> Result<bool, errors::ChangeEventError> {
+		self.change_event(event_uuid, |event| event.undo())
+	}
+
+	fn save_trace(&mut self, trace_builder: &service::TraceBuilder) -> Result<(), errors::SaveTraceError> {
+		let storage_error = |err| errors::SaveTraceError::Storage(io_error(err));
+		let trace_template_uuid = trace_builder.trace_template.trace_template_uuid;
+		let project_uuid = self
+			.trace_template(&trace_template_uuid)
+			.map_err(storage_error)?
+			.map(|trace_template| trace_template.project_uuid)
+			.ok_or(errors::SaveTraceError::TraceTemplateNotFound(trace_template_uuid.into()))?;
+		let trace = builders::build_trace(trace_builder, project_uuid);
+		for origin_trace_uuid in trace.origin_trace_uuids.iter() {
+			if self.trace(origin_trace_uuid).map_err(storage_error)?.is_none() {
+				return Err(errors::SaveTraceError::OriginTraceNotFound((*origin_trace_uuid).into()));
+			}
+		}
+		self.connection
+			.transaction()
+			.and_then(|transaction| {
+				write_trace(&transaction, &trace)?;
+				transaction.commit()
+			})
+			.map_err(storage_error)
+	}
+
+	fn view_event(&self, event_uuid: Uuid) -> Option<views::EventView> {
+		read_or_default(
+			self.event(&event_uuid)
+				.and_then(|event| event.map(|event| self.view_found_event(&event)).transpose()),
+		)
+	}
+
+	fn view_trace(&self, trace_uuid: Uuid) -> Option<views::TraceView> {
+		read_or_default(
+			self.trace(&trace_uuid)
+				.and_then(|trace| trace.map(|trace| self.create_trace_view(&trace)).transpose()),
+		)
+	}
+
+	fn list_events(
+		&self,
+		project_uuid: Option<Uuid>,
+	) -> Box<dyn Iterator<Item = views::EventItemView> + '_> {
+		Box::new(
+			read_or_default(
+				self.project_events(project_uuid)
+					.and_then(|events| self.event_items(events)),
+			)
+			.into_iter(),
+		)
+	}
+
+	fn summarize_duration(
+		&self,
+		event_template_uuid: Uuid,
+		field_path: &str,
+	) -> Result<views::DurationSummaryView, errors::EventTemplateNotFound> {
+		read_or_default(self.event_template(&event_template_uuid)).ok_or(event_template_uuid)?;
+		let events = read_or_default(self.query_events(
+			&format!(
+				"SELECT {} FROM events WHERE event_template_uuid = ?1 AND deleted_at IS NULL",
+				EVENT_COLUMNS
+			),
+			params![event_template_uuid.to_string()],
+		));
+		Ok(events::summarize_duration(events.iter(), field_path))
+	}
+
+	fn list_traces(
+		&self,
+		project_uuid: Option<Uuid>,
+		filter: service::TraceFilter,
+	) -> Box<dyn Iterator<Item = views::TraceItemView> + '_> {
+		Box::new(read_or_default(self.trace_items(project_uuid, filter)).into_iter())
+	}
+
+	fn list_trace_templates(
+		&self,
+		project_uuid: Option<Uuid>,
+	) -> Box<dyn Iterator<Item = views::TraceTemplateItemView>> {
+		Box::new(read_or_default(self.trace_template_items(project_uuid)).into_iter())
+	}
+
+	fn view_lineage(
+		&self,
+		trace_uuid: Uuid,
+		direction: service::LineageDirection,
+	) -> Result<views::LineageView, errors::TraceNotFound> {
+		let trace_templates = read_or_default(self.trace_templates());
+		lineage::view_lineage(&read_or_default(self.traces()), trace_uuid, &direction, |trace| {
+			trace.to_item(
+				trace_templates
+					.get(&trace.trace_template_uuid)
+					.map(|trace_template| trace_template.name.clone()),
+			)
+		})
+	}
+
+	fn find_lineage_path(
+		&self,
+		from_trace_uuid: Uuid,
+		to_trace_uuid: Uuid,
+	) -> Result<Option<Vec<views::LineagePathStepView>>, errors::TraceNotFound> {
+		let trace_templates = read_or_default(self.trace_templates());
+		lineage::find_lineage_path(&read_or_default(self.traces()), from_trace_uuid, to_trace_uuid, |trace| {
+			trace.to_item(
+				trace_templates
+					.get(&trace.trace_template_uuid)
+					.map(|trace_template| trace_template.name.clone()),
+			)
+		})
+	}
+
+	// The database is the only copy, there is nothing to replace or merge
+	fn load_from_disk(&mut self, _mode: service::LoadMode) -> Result<views::LoadReport, std::io::Error> {
+		Ok(views::LoadReport {
+			loaded_projects: self
+				.connection
+				.prepare_cached("SELECT project_uuid FROM projects ORDER BY project_uuid")
+				.and_then(|mut statement| statement.query_map([], |row| uuid_at(row, 0))?.collect())
+				.map_err(io_error)?,
+			..Default::default()
+		})
+	}
+
+	fn save_to_disk(&self) -> Result<(), std::io::Error> {
+		Ok(())
+	}
+
+	fn import_definition(
+		&mut self,
+		project_name: String,
+		definition_path: &std::path::Path,
+		into_project: Option<Uuid>,
+	) -> Result<views::ImportReport, errors::DefinitionErrors> {
+		let planned = self.plan_definition_import(definition_path, into_project)?;
+		let project_definition = &planned.project_definition;
+		// Importing again keeps what was written about the project
+		let description = self
+			.project(&project_definition.project_uuid)
+			.map_err(definition_error)?
+			.map_or(String::new(), |project| project.description);
+		let project = project::Project {
+			project_uuid: project_definition.project_uuid,
+			name: project_name,
+			description,
+			created_at: Utc::now(),
+			definition_version: project_definition.version,
+			migrations: project_definition.migrations.clone(),
+		};
+
+		// The templates and the events migrated to them change together or not at all
+		let transaction = self.connection.transaction().map_err(definition_error)?;
+		write_project(&transaction, &project).map_err(definition_error)?;
+		write_definition(&transaction, project_definition).map_err(definition_error)?;
+		for event in planned.reimport.migrated_events.iter() {
+			write_event(&transaction, event).map_err(definition_error)?;
+		}
+		transaction.commit().map_err(definition_error)?;
+
+		Ok(views::ImportReport {
+			project_uuid: project.project_uuid,
+			warnings: planned.warnings,
+			changes: planned.reimport.changes,
+		})
+	}
+
+	fn preview_definition_import(
+		&self,
+		definition_path: &std::path::Path,
+		into_project: Option<Uuid>,
+	) -> Result<views::ImportReport, errors::DefinitionErrors> {
+		let planned = self.plan_definition_import(definition_path, into_project)?;
+		Ok(views::ImportReport {
+			project_uuid: planned.project_definition.project_uuid,
+			warnings: planned.warnings,
+			changes: planned.reimport.changes,
+		})
+	}
+
+	fn export_definition(
+		&self,
+		project_uuid: Uuid,
+		format: service::DefinitionFormat,
+	) -> Result<String, errors::ProjectNotFound> {
+		let project_definition = read_or_default(self.project_definition(&project_uuid)).ok_or(project_uuid)?;
+		Ok(definition_writer::write_definition(&project_definition, format))
+	}
+
+	fn list_projects(&self) -> Box<dyn Iterator<Item = views::ProjectItemView> + '_> {
+		Box::new(read_or_default(self.project_items(None)).into_iter())
+	}
+
+	fn view_project(&self, project_uuid: Uuid) -> Option<views::ProjectView> {
+		read_or_default(self.project_view(project_uuid))
+	}
+
+	fn create_project(&mut self, name: String, description: String) -> Result<views::ProjectView, std::io::Error> {
+		let project = project::Project {
+			project_uuid: Uuid::new_v4(),
+			name,
+			description,
+			created_at: Utc::now(),
+			definition_version: 0,
+			migrations: vec![],
+		};
+		let project_definition = fs_service::ProjectDefinition {
+			project_uuid: project.project_uuid,
+			..Default::default()
+		};
+		self.write_new_project(&project, &project_definition).map_err(io_error)?;
+		Ok(project.to_view(
+			views::ProjectItemView {
+				project_uuid: project.project_uuid,
+				name: project.name.clone(),
+				description: project.description.clone(),
+				created_at: project.created_at,
+				event_template_count: 0,
+				trace_template_count: 0,
+				trace_count: 0,
+				event_count: 0,
+				last_activity: None,
+			},
+			vec![],
+			vec![],
+		))
+	}
+
+	fn rename_project(&mut self, project_uuid: Uuid, name: String) -> Result<(), errors::ChangeProjectError> {
+		self.update_project(project_uuid, "name = ?2", &[&name])
+	}
+
+	fn describe_project(
+		&mut self,
+		project_uuid: Uuid,
+		description: String,
+	) -> Result<(), errors::ChangeProjectError> {
+		self.update_project(project_uuid, "description = ?2", &[&description])
+	}
+
+	fn delete_project(
+		&mut self,
+		project_uuid: Uuid,
+		confirmation: &str,
+	) -> Result<(), errors::DeleteProjectError> {
+		let project = self
+			.project(&project_uuid)
+			.map_err(|err| errors::DeleteProjectError::Storage(io_error(err)))?;
+		let Some(project) = project else {
+			return Err(errors::DeleteProjectError::ProjectNotFound(project_uuid.into()));
+		};
+		if project.name != confirmation {
+			return Err(errors::DeleteProjectError::NotConfirmed);
+		}
+		self.delete_project_rows(project_uuid)
+			.map_err(|err| errors::DeleteProjectError::Storage(io_error(err)))
+	}
+
+	fn duplicate_project(
+		&mut self,
+		project_uuid: Uuid,
+		name: String,
+	) -> Result<views::ProjectView, errors::ChangeProjectError> {
+		let project = self
+			.project(&project_uuid)
+			.map_err(project_error)?
+			.ok_or(errors::ProjectNotFound::from(project_uuid))?;
+		let duplicate_uuid = Uuid::new_v4();
+		let project_definition = self
+			.project_definition(&project_uuid)
+			.map_err(project_error)?
+			.ok_or(errors::ProjectNotFound::from(project_uuid))?
+			.duplicate(duplicate_uuid);
+		let duplicate = project::Project {
+			project_uuid: duplicate_uuid,
+			name,
+			description: project.description,
+			created_at: Utc::now(),
+			definition_version: project_definition.version,
+			migrations: vec![],
+		};
+		self.write_new_project(&duplicate, &project_definition).map_err(project_error)?;
+		Ok(self
+			.project_view(duplicate_uuid)
+			.map_err(project_error)?
+			.ok_or(errors::ProjectNotFound::from(duplicate_uuid))?)
+	}
+}
@@ -0,0 +1,647 @@
+use crate::definition;
+use crate::fs_service;
+use crate::source_map;
+
+use journal_lib::errors;
+use journal_lib::service;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use uuid::Uuid;
+
+use source_map::child_index;
+use source_map::child_key;
+
+// Problems that make a definition unusable are errors, anything that merely looks
+// unintended is a warning. Paths follow the authoring format, as in parse errors.
+#[derive(Default)]
+pub struct ValidationReport {
+	pub errors: Vec<errors::ParsingError>,
+	pub warnings: Vec<errors::ParsingError>,
+}
+
+impl ValidationReport {
+	fn issue(path: &str, template_name: &str, message: String) -> errors::ParsingError {
+		errors::ParsingError {
+			path: Some(path.to_string()),
+			template_name: Some(template_name.to_string()).filter(|name| !name.is_empty()),
+			..errors::ParsingError::new(message)
+		}
+	}
+
+	fn error(&mut self, path: &str, template_name: &str, message: String) {
+		self.errors.push(Self::issue(path, template_name, message));
+	}
+
+	fn warning(&mut self, path: &str, template_name: &str, message: String) {
+		self.warnings.push(Self::issue(path, template_name, message));
+	}
+
+	pub fn locate(mut self, source_map: &source_map::SourceMap) -> Self {
+		for issue in self.errors.iter_mut().chain(self.warnings.iter_mut()) {
+			if let Some((line, column)) = issue.path.as_ref().and_then(|path| source_map.locate(path)) {
+				issue.line = Some(line);
+				issue.column = Some(column);
+			}
+		}
+		self
+	}
+}
+
+// Checks a definition before it is imported. The existing templates are only used to
+// make sure the new ones do not take over uuids that belong to another project, the
+// current version is that of the project being re-imported, if it exists.
+pub fn validate_definition(
+	project_definition: &fs_service::ProjectDefinition,
+	existing_event_templates: &HashMap<Uuid, definition::EventTemplate>,
+	existing_trace_templates: &HashMap<Uuid, definition::TraceTemplate>,
+	current_version: Option<u32>,
+) -> ValidationReport {
+	let mut report = ValidationReport::default();
+	let project_uuid = project_definition.project_uuid;
+
+	if let Some(current_version) = current_version.filter(|current| current > &project_definition.version) {
+		report.error(
+			"version",
+			"",
+			format!(
+				"Version {} is older than version {} the project is already at",
+				project_definition.version, current_version
+			),
+		);
+	}
+
+	let mut trace_template_uuids: HashSet<Uuid> = HashSet::new();
+	let mut trace_template_names: HashSet<&str> = HashSet::new();
+	for (index, trace_template) in project_definition.trace_templates.iter().enumerate() {
+		let path = child_index("trace-templates", index);
+		let name = trace_template.name.as_str();
+		let uuid_path = child_key(&path, "trace-template-uuid");
+		if !trace_template_uuids.insert(trace_template.trace_template_uuid) {
+			report.error(
+				&uuid_path,
+				name,
+				format!("Duplicate trace template uuid {}", trace_template.trace_template_uuid),
+			);
+		}
+		if let Some(other_project) = existing_trace_templates
+			.get(&trace_template.trace_template_uuid)
+			.map(|existing| existing.project_uuid)
+			.filter(|other_project| other_project != &project_uuid)
+		{
+			report.error(
+				&uuid_path,
+				name,
+				format!(
+					"Trace template uuid {} already belongs to project {}",
+					trace_template.trace_template_uuid, other_project
+				),
+			);
+		}
+		if !trace_template_names.insert(name) {
+			report.warning(&path, name, format!("Another trace template is also named '{}'", name));
+		}
+	}
+
+	let mut event_template_uuids: HashMap<Uuid, Uuid> = HashMap::new();
+	let mut event_template_names: HashSet<(Uuid, &str)> = HashSet::new();
+	for (index, event_template) in project_definition.event_templates.iter().enumerate() {
+		let path = child_index("event-templates", index);
+		let name = event_template.name.as_str();
+		let uuid_path = child_key(&path, "event-template-uuid");
+		if trace_template_uuids.contains(&event_template.event_template_uuid) {
+			report.error(
+				&uuid_path,
+				name,
+				format!(
+					"Event template uuid {} is also used by a trace template",
+					event_template.event_template_uuid
+				),
+			);
+		}
+		if event_template_uuids
+			.insert(event_template.event_template_uuid, event_template.trace_template_uuid)
+			.is_some()
+		{
+			report.error(
+				&uuid_path,
+				name,
+				format!("Duplicate event template uuid {}", event_template.event_template_uuid),
+			);
+		}
+		if let Some(other_project) = existing_event_templates
+			.get(&event_template.event_template_uuid)
+			.and_then(|existing| existing_trace_templates.get(&existing.trace_template_uuid))
+			.map(|existing| existing.project_uuid)
+			.filter(|other_project| other_project != &project_uuid)
+		{
+			report.error(
+				&uuid_path,
+				name,
+				format!(
+					"Event template uuid {} already belongs to project {}",
+					event_template.event_template_uuid, other_project
+				),
+			);
+		}
+		if !trace_template_uuids.contains(&event_template.trace_template_uuid) {
+			report.error(
+				&child_key(&path, "trace-template-uuid"),
+				name,
+				format!(
+					"Trace template {} is not defined in this project",
+					event_template.trace_template_uuid
+				),
+			);
+		}
+		if !event_template_names.insert((event_template.trace_template_uuid, name)) {
+			report.warning(
+				&path,
+				name,
+				format!("Another event template of the same trace template is also named '{}'", name),
+			);
+		}
+
+		validate_fields(&mut report, &child_key(&path, "fields"), name, &event_template.fields);
+	}
+
+	for (index, trace_template) in project_definition.trace_templates.iter().enumerate() {
+		let path = child_index("trace-templates", index);
+		let name = trace_template.name.as_str();
+		let event_templates_of_trace: HashSet<Uuid> = event_template_uuids
+			.iter()
+			.filter(|(_, trace_template_uuid)| *trace_template_uuid == &trace_template.trace_template_uuid)
+			.map(|(event_template_uuid, _)| *event_template_uuid)
+			.collect();
+		if event_templates_of_trace.is_empty() {
+			report.warning(&path, name, "Trace template has no event templates".into());
+		}
+
+		let mut in_flow: HashSet<Uuid> = HashSet::new();
+		for (flow_index, entry) in trace_template.flow.iter().enumerate() {
+			let entry_path = child_index(&child_key(&path, "transitions"), flow_index);
+			let references = std::iter::once((child_key(&entry_path, "from"), entry.from)).chain(
+				entry.to.iter().enumerate().map(|(to_index, to)| {
+					(child_index(&child_key(&entry_path, "to"), to_index), *to)
+				}),
+			);
+			for (reference_path, event_template_uuid) in references {
+				in_flow.insert(event_template_uuid);
+				if !event_template_uuids.contains_key(&event_template_uuid) {
+					report.error(
+						&reference_path,
+						name,
+						format!("Event template {} is not defined in this project", event_template_uuid),
+					);
+				} else if !event_templates_of_trace.contains(&event_template_uuid) {
+					report.warning(
+						&reference_path,
+						name,
+						format!(
+							"Event template {} belongs to another trace template",
+							event_template_uuid
+						),
+					);
+				}
+			}
+		}
+		if !trace_template.flow.is_empty() {
+			for (index, event_template) in project_definition.event_templates.iter().enumerate() {
+				if event_templates_of_trace.contains(&event_template.event_template_uuid)
+					&& !in_flow.contains(&event_template.event_template_uuid)
+				{
+					report.warning(
+						&child_index("event-templates", index),
+						&event_template.name,
+						"Event template never appears in its trace template's transitions".into(),
+					);
+				}
+			}
+		}
+	}
+
+	validate_migrations(&mut report, project_definition);
+
+	report
+}
+
+fn validate_migrations(report: &mut ValidationReport, project_definition: &fs_service::ProjectDefinition) {
+	let event_templates: HashMap<Uuid, &definition::EventTemplate> = project_definition
+		.event_templates
+		.iter()
+		.map(|template| (template.event_template_uuid, template))
+		.collect();
+	let has_field = |template: &definition::EventTemplate, path: &str| template.field(path).is_some();
+
+	for (index, migration) in project_definition.migrations.iter().enumerate() {
+		let path = child_index("migrations", index);
+		if migration.version > project_definition.version {
+			report.error(
+				&child_key(&path, "version"),
+				"",
+				format!(
+					"Migration for version {} is newer than the definition (version {})",
+					migration.version, project_definition.version
+				),
+			);
+		}
+
+		let event_template_uuid = match &migration.step {
+			definition::MigrationStep::RenameField { event_template_uuid, .. }
+			| definition::MigrationStep::RemoveField { event_template_uuid, .. }
+			| definition::MigrationStep::RenameOption { event_template_uuid, .. } => event_template_uuid,
+			definition::MigrationStep::MoveEvents { from, to } => {
+				let step_path = child_key(&path, "move-events");
+				if !event_templates.contains_key(to) {
+					report.error(
+						&child_key(&step_path, "to"),
+						"",
+						format!("Event template {} is not defined in this project", to),
+					);
+				}
+				if let Some(template) = event_templates.get(from) {
+					report.warning(
+						&child_key(&step_path, "from"),
+						&template.name,
+						"Events are moved away from a template that is still defined".into(),
+					);
+				}
+				continue;
+			}
+		};
+		let Some(template) = event_templates.get(event_template_uuid) else {
+			report.error(
+				&child_key(&path, "event-template"),
+				"",
+				format!("Event template {} is not defined in this project", event_template_uuid),
+			);
+			continue;
+		};
+		let name = template.name.as_str();
+		match &migration.step {
+			definition::MigrationStep::RenameField { from, to, .. } => {
+				let step_path = child_key(&path, "rename-field");
+				let group = |path: &str| path.rsplit_once('.').map(|(group, _)| group.to_string());
+				if group(from) != group(to) {
+					report.error(
+						&child_key(&step_path, "to"),
+						name,
+						format!("Field '{}' can only be renamed within its group", from),
+					);
+				}
+				if !has_field(template, to) {
+					report.error(
+						&child_key(&step_path, "to"),
+						name,
+						format!("Field '{}' is not defined", to),
+					);
+				}
+				if has_field(template, from) {
+					report.warning(
+						&child_key(&step_path, "from"),
+						name,
+						format!("Field '{}' is renamed but still defined", from),
+					);
+				}
+			}
+			definition::MigrationStep::RemoveField { field, .. } => {
+				if has_field(template, field) {
+					report.warning(
+						&child_key(&path, "remove-field"),
+						name,
+						format!("Field '{}' is removed from events but still defined", field),
+					);
+				}
+			}
+			definition::MigrationStep::RenameOption { field, to, .. } => {
+				let step_path = child_key(&path, "rename-option");
+				match template.field(field).and_then(|template_field| template_field.value.enumerated()) {
+					Some(enumerated) => {
+						if !enumerated.options.iter().any(|option| &option.name == to) {
+							report.error(
+								&child_key(&step_path, "to"),
+								name,
+								format!("Option '{}' is not one of the options of field '{}'", to, field),
+							);
+						}
+					}
+					_ => report.error(
+						&child_key(&step_path, "field"),
+						name,
+						format!("Field '{}' is not an enumerated field", field),
+					),
+				}
+			}
+			definition::MigrationStep::MoveEvents { .. } => {}
+		}
+	}
+}
+
+fn validate_fields(
+	report: &mut ValidationReport,
+	path: &str,
+	template_name: &str,
+	fields: &[definition::FieldTemplate],
+) {
+	let mut names: HashSet<&str> = HashSet::new();
+	for (index, field) in fields.iter().enumerate() {
+		let field_path = child_index(path, index);
+		if !names.insert(field.name.as_str()) {
+			report.error(
+				&child_key(&field_path, "name"),
+				template_name,
+				format!("Duplicate field name '{}'", field.name),
+			);
+		}
+		// Dots separate the names in a field's path
+		if field.name.contains('.') {
+			report.error(
+				&child_key(&field_path, "name"),
+				template_name,
+				format!("Field name '{}' cannot contain a '.'", field.name),
+			);
+		}
+		let policy_fits = match (field.value_policy, &field.value) {
+			(service::ValuePolicy::RollingMedian, definition::FieldValueTemplate::Number(_))
+			| (service::ValuePolicy::RollingMedian, definition::FieldValueTemplate::Duration(_)) => true,
+			(service::ValuePolicy::RollingMedian, _) => false,
+			(service::ValuePolicy::MostFrequentOption, definition::FieldValueTemplate::Enumerated(_)) => true,
+			(service::ValuePolicy::MostFrequentOption, _) => false,
+			// Its fields have their own policies
+			(policy, definition::FieldValueTemplate::Group(_)) => policy == service::ValuePolicy::default(),
+			_ => true,
+		};
+		if !policy_fits {
+			report.error(
+				&child_key(&field_path, "value-policy"),
+				template_name,
+				format!(
+					"Value policy {} does not fit the type of field '{}'",
+					field.value_policy.name(),
+					field.name
+				),
+			);
+		}
+		validate_value(report, &field_path, template_name, &field.name, &field.value);
+	}
+}
+
+// The options of an enumerated field and the fields of a group, also as the item of an array
+fn validate_value(
+	report: &mut ValidationReport,
+	path: &str,
+	template_name: &str,
+	field_name: &str,
+	value: &definition::FieldValueTemplate,
+) {
+	match value {
+		definition::FieldValueTemplate::Enumerated(enumerated) => {
+			if enumerated.options.is_empty() {
+				report.warning(
+					&child_key(path, "options"),
+					template_name,
+					format!("Field '{}' has no options", field_name),
+				);
+			}
+			let mut option_names: HashSet<&str> = HashSet::new();
+			for (option_index, option) in enumerated.options.iter().enumerate() {
+				if !option_names.insert(option.name.as_str()) {
+					report.error(
+						&child_index(&child_key(path, "options"), option_index),
+						template_name,
+						format!("Duplicate option '{}' in field '{}'", option.name, field_name),
+					);
+				}
+			}
+			if let Some(default_value) = &enumerated.default_value {
+				if !enumerated.options.contains(default_value) {
+					report.error(
+						&child_key(path, "default-value"),
+						template_name,
+						format!(
+							"Default value {} of field '{}' is not one of its options",
+							default_value.name, field_name
+						),
+					);
+				}
+			}
+		}
+		definition::FieldValueTemplate::Array(array) => {
+			validate_value(report, &child_key(path, "item"), template_name, field_name, &array.item);
+		}
+		definition::FieldValueTemplate::Group(group) => {
+			validate_fields(report, &child_key(path, "fields"), template_name, &group.fields);
+		}
+		_ => {}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::definition_parser;
+	use journal_lib::views;
+
+	const PROJECT_UUID: Uuid = Uuid::from_u128(0x6b3a2c36_3b5e_4f7e_9a51_1c1d6f0f9e01);
+
+	// Valid as it is, each test breaks one rule
+	fn definition() -> fs_service::ProjectDefinition {
+		let yaml = "\
+version: 2
+trace-templates:
+  - name: Loaf
+    transitions:
+      - from: mix
+        to: [bake]
+event-templates:
+  - name: Mix
+    trace-template: loaf
+    fields:
+      - label: Flour
+        type: Number
+  - name: Bake
+    trace-template: loaf
+    fields:
+      - label: Oven
+        type: Enumerated
+        options:
+          - label: Hot
+          - label: Cold
+        default-value: Hot
+";
+		definition_parser::parse_definition(yaml, service::DefinitionFormat::Yaml, Some(PROJECT_UUID))
+			.ok()
+			.unwrap()
+			.project_definition
+	}
+
+	fn validate(project_definition: &fs_service::ProjectDefinition) -> ValidationReport {
+		validate_definition(project_definition, &HashMap::new(), &HashMap::new(), None)
+	}
+
+	fn paths(issues: &[errors::ParsingError]) -> Vec<&str> {
+		issues.iter().flat_map(|issue| issue.path.as_deref()).collect()
+	}
+
+	fn event_template<'d>(
+		project_definition: &'d mut fs_service::ProjectDefinition,
+		name: &str,
+	) -> &'d mut definition::EventTemplate {
+		project_definition
+			.event_templates
+			.iter_mut()
+			.find(|event_template| event_template.name == name)
+			.unwrap()
+	}
+
+	fn index_of(project_definition: &fs_service::ProjectDefinition, name: &str) -> usize {
+		project_definition
+			.event_templates
+			.iter()
+			.position(|event_template| event_template.name == name)
+			.unwrap()
+	}
+
+	#[test]
+	fn accepts_a_valid_definition() {
+		let report = validate(&definition());
+		assert!(report.errors.is_empty());
+		assert!(report.warnings.is_empty());
+	}
+
+	#[test]
+	fn rejects_a_dangling_trace_template() {
+		let mut project_definition = definition();
+		event_template(&mut project_definition, "Mix").trace_template_uuid = Uuid::new_v4();
+		let report = validate(&project_definition);
+		let path = format!("event-templates[{}].trace-template-uuid", index_of(&project_definition, "Mix"));
+		assert_eq!(paths(&report.errors), vec![path.as_str()]);
+		assert_eq!(report.errors[0].template_name.as_deref(), Some("Mix"));
+	}
+
+	#[test]
+	fn rejects_dangling_transitions() {
+		let mut project_definition = definition();
+		project_definition.trace_templates[0].flow[0].from = Uuid::new_v4();
+		project_definition.trace_templates[0].flow[0].to.push(Uuid::new_v4());
+		let report = validate(&project_definition);
+		assert_eq!(
+			paths(&report.errors),
+			vec!["trace-templates[0].transitions[0].from", "trace-templates[0].transitions[0].to[1]"]
+		);
+		// Mix is no longer part of the flow
+		let path = format!("event-templates[{}]", index_of(&project_definition, "Mix"));
+		assert_eq!(paths(&report.warnings), vec![path.as_str()]);
+	}
+
+	#[test]
+	fn rejects_uuids_of_another_project() {
+		let other = definition_parser::parse_definition(
+			"trace-templates:\n  - name: Loaf\n",
+			service::DefinitionFormat::Yaml,
+			Some(Uuid::new_v4()),
+		)
+		.ok()
+		.unwrap()
+		.project_definition;
+		let mut project_definition = definition();
+		let taken = other.trace_templates[0].trace_template_uuid;
+		project_definition.trace_templates[0].trace_template_uuid = taken;
+		for event_template in project_definition.event_templates.iter_mut() {
+			event_template.trace_template_uuid = taken;
+		}
+		let bake = event_template(&mut project_definition, "Bake");
+		let existing_event_templates = HashMap::from([(
+			bake.event_template_uuid,
+			definition::EventTemplate { trace_template_uuid: taken, ..bake.clone() },
+		)]);
+		let existing_trace_templates = HashMap::from([(taken, other.trace_templates[0].clone())]);
+
+		let report = validate_definition(&project_definition, &existing_event_templates, &existing_trace_templates, None);
+		let bake_path = format!("event-templates[{}].event-template-uuid", index_of(&project_definition, "Bake"));
+		assert_eq!(
+			paths(&report.errors),
+			vec!["trace-templates[0].trace-template-uuid", bake_path.as_str()]
+		);
+		assert!(report.errors.iter().all(|error| error.message.contains("already belongs to project")));
+	}
+
+	#[test]
+	fn rejects_duplicate_uuids_within_the_definition() {
+		let mut project_definition = definition();
+		let mix_uuid = event_template(&mut project_definition, "Mix").event_template_uuid;
+		event_template(&mut project_definition, "Bake").event_template_uuid = mix_uuid;
+		let report = validate(&project_definition);
+		assert!(report.errors.iter().any(|error| error.message.starts_with("Duplicate event template uuid")));
+	}
+
+	#[test]
+	fn rejects_duplicate_field_names() {
+		let mut project_definition = definition();
+		let mix = event_template(&mut project_definition, "Mix");
+		mix.fields.push(mix.fields[0].clone());
+		let report = validate(&project_definition);
+		let path = format!("event-templates[{}].fields[1].name", index_of(&project_definition, "Mix"));
+		assert_eq!(paths(&report.errors), vec![path.as_str()]);
+	}
+
+	#[test]
+	fn rejects_an_enumerated_default_outside_the_options() {
+		let mut project_definition = definition();
+		let bake = event_template(&mut project_definition, "Bake");
+		let definition::FieldValueTemplate::Enumerated(enumerated) = &mut bake.fields[0].value else {
+			panic!("Oven should be enumerated");
+		};
+		enumerated.default_value = Some(views::EnumerationOption {
+			name: "lukewarm".into(),
+			label: "Lukewarm".into(),
+		});
+		let report = validate(&project_definition);
+		let path = format!("event-templates[{}].fields[0].default-value", index_of(&project_definition, "Bake"));
+		assert_eq!(paths(&report.errors), vec![path.as_str()]);
+	}
+
+	#[test]
+	fn rejects_migrations_past_the_version_and_undefined_fields() {
+		let mut project_definition = definition();
+		let mix_uuid = event_template(&mut project_definition, "Mix").event_template_uuid;
+		project_definition.migrations = vec![
+			definition::Migration {
+				version: 3,
+				step: definition::MigrationStep::RemoveField {
+					event_template_uuid: mix_uuid,
+					field: "water".into(),
+				},
+			},
+			definition::Migration {
+				version: 2,
+				step: definition::MigrationStep::RenameField {
+					event_template_uuid: mix_uuid,
+					from: "flour-weight".into(),
+					to: "rye".into(),
+				},
+			},
+		];
+		let report = validate(&project_definition);
+		assert_eq!(paths(&report.errors), vec!["migrations[0].version", "migrations[1].rename-field.to"]);
+	}
+
+	#[test]
+	fn rejects_going_back_a_version() {
+		let report = validate_definition(&definition(), &HashMap::new(), &HashMap::new(), Some(3));
+		assert_eq!(paths(&report.errors), vec!["version"]);
+	}
+
+	#[test]
+	fn what_looks_unintended_is_only_a_warning() {
+		let mut project_definition = definition();
+		let mut second = project_definition.trace_templates[0].clone();
+		second.trace_template_uuid = Uuid::new_v4();
+		second.flow = vec![];
+		project_definition.trace_templates.push(second);
+		let report = validate(&project_definition);
+		assert!(report.errors.is_empty());
+		assert_eq!(paths(&report.warnings), vec!["trace-templates[1]", "trace-templates[1]"]);
+		assert_eq!(report.warnings[0].message, "Another trace template is also named 'Loaf'");
+		assert_eq!(report.warnings[1].message, "Trace template has no event templates");
+	}
+}
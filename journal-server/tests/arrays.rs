@@ -0,0 +1,133 @@
+mod common;
+
+use journal_lib::service::ArraySuggestion;
+use journal_lib::service::EventBuilder;
+use journal_lib::service::EventsService;
+use journal_lib::service::FieldValueSuggestion;
+use journal_lib::service::HistoryScope;
+use journal_lib::views;
+use journal_server::fs_service::FileSystemEventsService;
+
+use uuid::Uuid;
+
+// Rows of an array field are added, removed and reordered in the builder and saved in order
+
+const DEFINITION: &str = "
+trace-templates:
+  - name: Loaf
+event-templates:
+  - name: Mix
+    trace-template: loaf
+    fields:
+      - label: Additions
+        type: Array
+        item:
+          type: Text
+";
+
+fn new_event(service: &FileSystemEventsService, trace_uuid: Uuid) -> EventBuilder {
+	let trace = service.view_trace(trace_uuid).unwrap();
+	service
+		.create_event(&trace, common::event_template_uuid("mix"), HistoryScope::SameTrace)
+		.ok()
+		.unwrap()
+}
+
+fn additions(event: &mut EventBuilder) -> &mut ArraySuggestion {
+	match &mut event.fields[0].value {
+		FieldValueSuggestion::Array(array) => array,
+		_ => panic!("additions is an array"),
+	}
+}
+
+fn rows(array: &ArraySuggestion) -> Vec<Option<String>> {
+	array
+		.items
+		.iter()
+		.map(|item| match item {
+			FieldValueSuggestion::Text(text) => text.value.clone(),
+			_ => panic!("additions are text"),
+		})
+		.collect()
+}
+
+fn add_row(array: &mut ArraySuggestion, text: &str) {
+	array.add_item();
+	match array.items.last_mut().unwrap() {
+		FieldValueSuggestion::Text(row) => row.value = Some(text.to_string()),
+		_ => panic!("additions are text"),
+	}
+}
+
+fn saved_rows(service: &FileSystemEventsService, event_uuid: Uuid) -> Vec<Option<String>> {
+	let event = service.view_event(event_uuid).unwrap();
+	match event.fields[0].value.as_ref() {
+		Some(views::FieldValueView::Array(array)) => array
+			.items
+			.iter()
+			.map(|item| match item {
+				views::FieldValueView::Text(text) => text.value.clone(),
+				_ => panic!("additions are text"),
+			})
+			.collect(),
+		_ => panic!("additions is an array"),
+	}
+}
+
+fn text(values: &[&str]) -> Vec<Option<String>> {
+	values.iter().map(|value| Some(value.to_string())).collect()
+}
+
+#[test]
+fn rows_are_added_removed_and_reordered() {
+	let directory = common::temp_directory();
+	let mut service = common::new_service(&directory, DEFINITION);
+	let trace_uuid = common::new_trace(&mut service, common::trace_template_uuid("loaf"));
+	let mut event = new_event(&service, trace_uuid);
+	let array = additions(&mut event);
+	assert!(array.items.is_empty());
+
+	// An added row starts out empty
+	array.add_item();
+	assert_eq!(rows(array), vec![None]);
+	array.remove_item(0);
+	for row in ["seeds", "salt", "honey", "oats"] {
+		add_row(array, row);
+	}
+	array.move_item(3, 0);
+	assert_eq!(rows(array), text(&["oats", "seeds", "salt", "honey"]));
+	array.move_item(1, 2);
+	assert_eq!(rows(array), text(&["oats", "salt", "seeds", "honey"]));
+	array.remove_item(2);
+	// Rows that are not there are left alone
+	array.remove_item(3);
+	array.move_item(0, 3);
+	assert_eq!(rows(array), text(&["oats", "salt", "honey"]));
+}
+
+#[test]
+fn rows_are_saved_in_order_and_edited_again() {
+	let directory = common::temp_directory();
+	let mut service = common::new_service(&directory, DEFINITION);
+	let trace_uuid = common::new_trace(&mut service, common::trace_template_uuid("loaf"));
+	let mut event = new_event(&service, trace_uuid);
+	for row in ["seeds", "salt", "honey"] {
+		add_row(additions(&mut event), row);
+	}
+	additions(&mut event).move_item(2, 0);
+	service.save_event(&event).unwrap();
+	let event_uuid = *service.events.keys().next().unwrap();
+	assert_eq!(saved_rows(&service, event_uuid), text(&["honey", "seeds", "salt"]));
+
+	let mut edited = service.edit_event(event_uuid).ok().unwrap();
+	assert_eq!(rows(additions(&mut edited)), text(&["honey", "seeds", "salt"]));
+	additions(&mut edited).remove_item(1);
+	add_row(additions(&mut edited), "oats");
+	service.update_event(&edited).unwrap();
+	assert_eq!(saved_rows(&service, event_uuid), text(&["honey", "salt", "oats"]));
+
+	// The next event can take the rows over
+	let mut next = new_event(&service, trace_uuid);
+	assert!(next.fields[0].value.use_last_value(0));
+	assert_eq!(rows(additions(&mut next)), text(&["honey", "salt", "oats"]));
+}
@@ -0,0 +1,124 @@
+mod common;
+
+use journal_lib::service::EventsService;
+use journal_lib::service::LoadMode;
+use journal_server::atomic;
+use journal_server::fs_service::FileSystemEventsService;
+
+use common::PROJECT_UUID;
+use std::path::Path;
+use tempfile::TempDir;
+
+// An interrupted save is left on disk by hand, the way a crash at each point of write_files
+// would leave it.
+
+fn read(directory: &Path, name: &str) -> String {
+	std::fs::read_to_string(directory.join(name)).unwrap()
+}
+
+fn file_names(directory: &Path) -> Vec<String> {
+	let mut names: Vec<String> = std::fs::read_dir(directory)
+		.unwrap()
+		.map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+		.collect();
+	names.sort();
+	names
+}
+
+// Old versions of both files, as a finished save leaves them
+fn saved_directory() -> TempDir {
+	let directory = common::temp_directory();
+	atomic::write_files(directory.path(), &[("a.json", b"old a".to_vec()), ("b.json", b"old b".to_vec())]).unwrap();
+	directory
+}
+
+#[test]
+fn write_files_leaves_only_the_files() {
+	let saved = saved_directory();
+	let directory = saved.path();
+	atomic::write_files(directory, &[("a.json", b"new a".to_vec()), ("b.json", b"new b".to_vec())]).unwrap();
+
+	assert_eq!(file_names(directory), vec!["a.json", "b.json"]);
+	assert_eq!(read(directory, "a.json"), "new a");
+	assert_eq!(read(directory, "b.json"), "new b");
+	assert_eq!(atomic::recover(directory).unwrap(), atomic::Recovery::Clean);
+}
+
+#[test]
+fn a_save_interrupted_before_the_marker_is_rolled_back() {
+	let saved = saved_directory();
+	let directory = saved.path();
+	// Crashed while writing the second file and the marker
+	std::fs::write(directory.join("a.json.tmp"), "new a").unwrap();
+	std::fs::write(directory.join("b.json.tmp"), "new").unwrap();
+	std::fs::write(directory.join("save.pending.tmp"), "[\"a.js").unwrap();
+
+	assert_eq!(atomic::recover(directory).unwrap(), atomic::Recovery::RolledBack);
+	assert_eq!(file_names(directory), vec!["a.json", "b.json"]);
+	assert_eq!(read(directory, "a.json"), "old a");
+	assert_eq!(read(directory, "b.json"), "old b");
+	assert_eq!(atomic::recover(directory).unwrap(), atomic::Recovery::Clean);
+}
+
+#[test]
+fn a_save_interrupted_after_the_marker_is_rolled_forward() {
+	let saved = saved_directory();
+	let directory = saved.path();
+	// Crashed after moving the first file into place
+	std::fs::write(directory.join("a.json"), "new a").unwrap();
+	std::fs::write(directory.join("b.json.tmp"), "new b").unwrap();
+	std::fs::write(directory.join("save.pending"), "[\"a.json\",\"b.json\"]").unwrap();
+
+	assert_eq!(atomic::recover(directory).unwrap(), atomic::Recovery::RolledForward);
+	assert_eq!(file_names(directory), vec!["a.json", "b.json"]);
+	assert_eq!(read(directory, "a.json"), "new a");
+	assert_eq!(read(directory, "b.json"), "new b");
+	assert_eq!(atomic::recover(directory).unwrap(), atomic::Recovery::Clean);
+}
+
+#[test]
+fn a_committed_save_with_nothing_left_to_move_is_finished() {
+	let saved = saved_directory();
+	let directory = saved.path();
+	// Crashed after moving every file but before removing the marker
+	std::fs::write(directory.join("a.json"), "new a").unwrap();
+	std::fs::write(directory.join("b.json"), "new b").unwrap();
+	std::fs::write(directory.join("save.pending"), "[\"a.json\",\"b.json\"]").unwrap();
+
+	assert_eq!(atomic::recover(directory).unwrap(), atomic::Recovery::RolledForward);
+	assert_eq!(file_names(directory), vec!["a.json", "b.json"]);
+	assert_eq!(read(directory, "b.json"), "new b");
+}
+
+#[test]
+fn loading_rolls_back_a_torn_save() {
+	let directory = common::copy_fixture("format-3");
+	let project_directory = directory.path().join(PROJECT_UUID);
+	std::fs::write(project_directory.join("data.json.tmp"), "{\"torn").unwrap();
+
+	let mut service = FileSystemEventsService::new(directory.path().to_path_buf());
+	let report = service.load_from_disk(LoadMode::Replace).unwrap();
+	assert!(report.skipped_projects.is_empty());
+	assert_eq!(report.recovered_projects, vec![common::project_uuid()]);
+	assert_eq!(service.events.len(), 3);
+	assert!(!project_directory.join("data.json.tmp").exists());
+}
+
+#[test]
+fn loading_rolls_forward_a_committed_save() {
+	let directory = common::copy_fixture("format-3");
+	let project_directory = directory.path().join(PROJECT_UUID);
+	// The committed project.json renames the project
+	let mut project: serde_json::Value =
+		serde_json::from_str(&read(&project_directory, "project.json")).unwrap();
+	project["name"] = "Saved before the crash".into();
+	std::fs::write(project_directory.join("project.json.tmp"), serde_json::to_vec(&project).unwrap()).unwrap();
+	std::fs::write(project_directory.join("save.pending"), "[\"project.json\"]").unwrap();
+
+	let mut service = FileSystemEventsService::new(directory.path().to_path_buf());
+	let report = service.load_from_disk(LoadMode::Replace).unwrap();
+	assert!(report.skipped_projects.is_empty());
+	assert_eq!(report.recovered_projects.len(), 1);
+	assert_eq!(service.projects.values().next().unwrap().name, "Saved before the crash");
+	assert!(!project_directory.join("save.pending").exists());
+}
@@ -0,0 +1,93 @@
+// Helpers shared by the integration tests. Each test file is built on its own and uses only
+// some of them.
+#![allow(dead_code)]
+
+use journal_lib::errors;
+use journal_lib::service::EventsService;
+use journal_lib::service::TraceFilter;
+use journal_lib::views;
+use journal_server::definition_parser;
+use journal_server::fs_service::FileSystemEventsService;
+
+use std::path::Path;
+use std::path::PathBuf;
+use tempfile::TempDir;
+use uuid::Uuid;
+
+// The project the definitions are written for, and the one in the fixtures
+pub const PROJECT_UUID: &str = "6d1f2b43-5d7a-4a8e-9a77-0d6a2f3c2a11";
+
+pub fn project_uuid() -> Uuid {
+	Uuid::parse_str(PROJECT_UUID).unwrap()
+}
+
+pub fn event_template_uuid(slug: &str) -> Uuid {
+	definition_parser::derive_event_template_uuid(&project_uuid(), slug)
+}
+
+pub fn trace_template_uuid(slug: &str) -> Uuid {
+	definition_parser::derive_trace_template_uuid(&project_uuid(), slug)
+}
+
+// Removed with everything in it when dropped, so it has to outlive the service using it
+pub fn temp_directory() -> TempDir {
+	tempfile::Builder::new().prefix("journaler-").tempdir().unwrap()
+}
+
+// Loading may recover or truncate files, so fixtures are worked on in a copy
+pub fn copy_fixture(name: &str) -> TempDir {
+	let directory = temp_directory();
+	copy_directory(&fixtures_directory().join(name), directory.path());
+	directory
+}
+
+pub fn fixtures_directory() -> PathBuf {
+	Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("fixtures")
+}
+
+fn copy_directory(from: &Path, to: &Path) {
+	std::fs::create_dir_all(to).unwrap();
+	for entry in std::fs::read_dir(from).unwrap() {
+		let path = entry.unwrap().path();
+		let target = to.join(path.file_name().unwrap());
+		if path.is_dir() {
+			copy_directory(&path, &target);
+		} else {
+			std::fs::copy(&path, &target).unwrap();
+		}
+	}
+}
+
+// Loading skips files, so the definition can be kept next to the projects
+pub fn write_definition(directory: &Path, project_uuid: Uuid, yaml: &str) -> PathBuf {
+	let path = directory.join(format!("definition-{}.yaml", Uuid::new_v4()));
+	std::fs::write(&path, format!("project-uuid: {}\n{}", project_uuid, yaml)).unwrap();
+	path
+}
+
+pub fn import(
+	service: &mut dyn EventsService,
+	directory: &Path,
+	yaml: &str,
+) -> Result<views::ImportReport, errors::DefinitionErrors> {
+	service.import_definition("Bakery".into(), &write_definition(directory, project_uuid(), yaml), None)
+}
+
+// A journal kept in the directory, with the definition imported
+pub fn new_service(directory: &TempDir, yaml: &str) -> FileSystemEventsService {
+	let mut service = FileSystemEventsService::new(directory.path().to_path_buf());
+	import(&mut service, directory.path(), yaml).unwrap();
+	service
+}
+
+// Saving gives the trace a new uuid, it is found again by its name
+pub fn new_trace(service: &mut dyn EventsService, trace_template_uuid: Uuid) -> Uuid {
+	let mut trace = service.create_trace(trace_template_uuid).ok().unwrap();
+	trace.name = Uuid::new_v4().to_string();
+	service.save_trace(&trace).unwrap();
+	service
+		.list_traces(None, TraceFilter::All)
+		.find(|item| item.name == trace.name)
+		.unwrap()
+		.trace_uuid
+}
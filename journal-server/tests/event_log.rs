@@ -0,0 +1,180 @@
+mod common;
+
+use journal_lib::errors;
+use journal_lib::service::EventsService;
+use journal_lib::service::LoadMode;
+use journal_server::event_log;
+use journal_server::events;
+use journal_server::fs_service::FileSystemEventsService;
+use journal_server::fs_service::StorageMode;
+
+use common::PROJECT_UUID;
+use std::path::Path;
+use uuid::Uuid;
+
+fn trace_record(name: &str) -> event_log::LogRecord {
+	event_log::LogRecord::Trace(events::Trace {
+		trace_uuid: Uuid::new_v4(),
+		trace_template_uuid: Uuid::new_v4(),
+		project_uuid: Uuid::new_v4(),
+		origin_trace_uuids: vec![],
+		created_at: chrono::Utc::now(),
+		name: name.to_string(),
+		completion: None,
+		archived_at: None,
+	})
+}
+
+fn trace_names(records: &[event_log::LogRecord]) -> Vec<&str> {
+	records
+		.iter()
+		.map(|record| match record {
+			event_log::LogRecord::Trace(trace) => trace.name.as_str(),
+			event_log::LogRecord::Event(_) => panic!("expected only traces"),
+		})
+		.collect()
+}
+
+fn log_lines(directory: &Path) -> usize {
+	std::fs::read_to_string(directory.join(event_log::LOG_FILE)).map_or(0, |log| log.lines().count())
+}
+
+#[test]
+fn replay_returns_the_records_in_order() {
+	let temp_directory = common::temp_directory();
+	let directory = temp_directory.path();
+	assert!(event_log::replay(directory).unwrap().is_empty());
+	for name in ["first", "second", "third"] {
+		event_log::append(directory, &trace_record(name)).unwrap();
+	}
+	assert_eq!(trace_names(&event_log::replay(directory).unwrap()), vec!["first", "second", "third"]);
+}
+
+#[test]
+fn a_partially_written_record_is_cut_off() {
+	let temp_directory = common::temp_directory();
+	let directory = temp_directory.path();
+	event_log::append(directory, &trace_record("first")).unwrap();
+	let complete_length = std::fs::metadata(directory.join(event_log::LOG_FILE)).unwrap().len();
+	// A crash in the middle of the second append
+	let mut log = std::fs::read(directory.join(event_log::LOG_FILE)).unwrap();
+	log.extend_from_slice(b"{\"format_version\":4,\"Trace\":{\"trace_uu");
+	std::fs::write(directory.join(event_log::LOG_FILE), log).unwrap();
+
+	assert_eq!(trace_names(&event_log::replay(directory).unwrap()), vec!["first"]);
+	assert_eq!(std::fs::metadata(directory.join(event_log::LOG_FILE)).unwrap().len(), complete_length);
+
+	// The next record starts on its own line
+	event_log::append(directory, &trace_record("second")).unwrap();
+	assert_eq!(trace_names(&event_log::replay(directory).unwrap()), vec!["first", "second"]);
+}
+
+#[test]
+fn a_corrupt_complete_record_is_an_error() {
+	let temp_directory = common::temp_directory();
+	let directory = temp_directory.path();
+	event_log::append(directory, &trace_record("first")).unwrap();
+	let mut log = std::fs::read(directory.join(event_log::LOG_FILE)).unwrap();
+	log.extend_from_slice(b"not a record\n");
+	std::fs::write(directory.join(event_log::LOG_FILE), log).unwrap();
+
+	let err = event_log::replay(directory).err().unwrap();
+	assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+}
+
+const DEFINITION: &str = "
+trace-templates:
+  - name: Loaf
+event-templates:
+  - name: Bake
+    trace-template: loaf
+";
+
+// A service in log mode whose project has been snapshotted by the import
+fn logging_service(directory: &Path) -> FileSystemEventsService {
+	let mut service = FileSystemEventsService::new(directory.to_path_buf());
+	service.storage_mode = StorageMode::Log;
+	common::import(&mut service, directory, DEFINITION).unwrap();
+	service
+}
+
+fn save_traces(service: &mut FileSystemEventsService, count: usize) {
+	let trace_template_uuid = *service.trace_templates.keys().next().unwrap();
+	let trace = service.create_trace(trace_template_uuid).ok().unwrap();
+	for _ in 0..count {
+		// Every save makes a new trace
+		service.save_trace(&trace).unwrap();
+	}
+}
+
+#[test]
+fn logged_traces_are_replayed_on_load() {
+	let directory = common::temp_directory();
+	let project_directory = directory.path().join(PROJECT_UUID);
+	let mut service = logging_service(directory.path());
+	save_traces(&mut service, 3);
+	assert_eq!(log_lines(&project_directory), 3);
+
+	// Never saved with save_to_disk
+	let mut reloaded = FileSystemEventsService::new(directory.path().to_path_buf());
+	reloaded.load_from_disk(LoadMode::Replace).unwrap();
+	assert_eq!(reloaded.traces.len(), 3);
+}
+
+#[test]
+fn the_log_is_compacted_at_the_threshold() {
+	let directory = common::temp_directory();
+	let project_directory = directory.path().join(PROJECT_UUID);
+	let mut service = logging_service(directory.path());
+	save_traces(&mut service, 1000);
+	assert_eq!(log_lines(&project_directory), 1000);
+
+	// The next record goes into a snapshot together with the log
+	save_traces(&mut service, 1);
+	assert!(!project_directory.join(event_log::LOG_FILE).exists());
+	let data: serde_json::Value =
+		serde_json::from_slice(&std::fs::read(project_directory.join("data.json")).unwrap()).unwrap();
+	assert_eq!(data["traces"].as_array().unwrap().len(), 1001);
+
+	save_traces(&mut service, 1);
+	assert_eq!(log_lines(&project_directory), 1);
+	let mut reloaded = FileSystemEventsService::new(directory.path().to_path_buf());
+	reloaded.load_from_disk(LoadMode::Replace).unwrap();
+	assert_eq!(reloaded.traces.len(), 1002);
+}
+
+#[test]
+fn save_to_disk_folds_the_log_into_the_snapshot() {
+	let directory = common::temp_directory();
+	let project_directory = directory.path().join(PROJECT_UUID);
+	let mut service = logging_service(directory.path());
+	save_traces(&mut service, 2);
+	service.save_to_disk().unwrap();
+	assert!(!project_directory.join(event_log::LOG_FILE).exists());
+
+	let mut reloaded = FileSystemEventsService::new(directory.path().to_path_buf());
+	reloaded.load_from_disk(LoadMode::Replace).unwrap();
+	assert_eq!(reloaded.traces.len(), 2);
+}
+
+#[test]
+fn a_record_that_cannot_be_logged_is_an_error_and_changes_nothing() {
+	let directory = common::temp_directory();
+	let project_directory = directory.path().join(PROJECT_UUID);
+	let mut service = logging_service(directory.path());
+	save_traces(&mut service, 1);
+	let trace_uuid = *service.traces.keys().next().unwrap();
+
+	// Nothing can be appended to a log that is a directory
+	std::fs::remove_file(project_directory.join(event_log::LOG_FILE)).unwrap();
+	std::fs::create_dir(project_directory.join(event_log::LOG_FILE)).unwrap();
+	let trace_template_uuid = *service.trace_templates.keys().next().unwrap();
+	let trace = service.create_trace(trace_template_uuid).ok().unwrap();
+	assert!(matches!(service.save_trace(&trace), Err(errors::SaveTraceError::Storage(_))));
+	assert_eq!(service.traces.len(), 1);
+	assert!(matches!(
+		service.rename_trace(trace_uuid, "Renamed".into()),
+		Err(errors::ChangeTraceError::Storage(_))
+	));
+	assert_ne!(service.traces[&trace_uuid].name, "Renamed");
+}
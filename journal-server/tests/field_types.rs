@@ -0,0 +1,186 @@
+mod common;
+
+use journal_lib::service::DefinitionFormat;
+use journal_lib::service::EventBuilder;
+use journal_lib::service::EventsService;
+use journal_lib::service::FieldValueSuggestion;
+use journal_lib::service::HistoryScope;
+use journal_lib::views;
+use journal_server::definition_parser;
+use journal_server::sqlite_service::SqliteEventsService;
+
+use chrono::NaiveDate;
+use chrono::NaiveTime;
+use uuid::Uuid;
+
+// Dates, times of day and durations, as definitions write them and as events hold them
+
+const DEFINITION: &str = "
+trace-templates:
+  - name: Loaf
+event-templates:
+  - name: Proof
+    trace-template: loaf
+    fields:
+      - type: Duration
+        label: Time
+      - type: DateTime
+        label: Started
+        default-value: 2024-03-01T08:30:00+01:00
+      - type: Date
+        label: Milled
+        default-value: 2024-02-20
+  - name: Bake
+    trace-template: loaf
+    fields:
+      - type: Duration
+        label: Time
+";
+
+fn new_trace(service: &mut dyn EventsService) -> Uuid {
+	common::new_trace(service, common::trace_template_uuid("loaf"))
+}
+
+fn new_event(service: &dyn EventsService, trace_uuid: Uuid, slug: &str) -> EventBuilder {
+	let trace = service.view_trace(trace_uuid).unwrap();
+	service
+		.create_event(&trace, common::event_template_uuid(slug), HistoryScope::SameTrace)
+		.ok()
+		.unwrap()
+}
+
+fn record_time(service: &mut dyn EventsService, trace_uuid: Uuid, slug: &str, seconds: Option<i64>) {
+	let mut event = new_event(service, trace_uuid, slug);
+	match &mut event.fields[0].value {
+		FieldValueSuggestion::Duration(duration) => duration.value = seconds,
+		_ => panic!("time is a duration"),
+	}
+	service.save_event(&event).unwrap();
+}
+
+#[test]
+fn durations_are_read_with_units_or_colons() {
+	assert_eq!(definition_parser::parse_duration("4h30m"), Some(16200));
+	assert_eq!(definition_parser::parse_duration("90m"), Some(5400));
+	assert_eq!(definition_parser::parse_duration("1d 2h"), Some(93600));
+	assert_eq!(definition_parser::parse_duration(" 45s "), Some(45));
+	assert_eq!(definition_parser::parse_duration("4:30"), Some(16200));
+	assert_eq!(definition_parser::parse_duration("0:01:05"), Some(65));
+
+	assert_eq!(definition_parser::parse_duration(""), None);
+	assert_eq!(definition_parser::parse_duration("90"), None);
+	assert_eq!(definition_parser::parse_duration("4h30"), None);
+	assert_eq!(definition_parser::parse_duration("h"), None);
+	assert_eq!(definition_parser::parse_duration("2w"), None);
+	assert_eq!(definition_parser::parse_duration("4:60"), None);
+	assert_eq!(definition_parser::parse_duration("4:30:60"), None);
+	assert_eq!(definition_parser::parse_duration("-4:30"), None);
+	assert_eq!(definition_parser::parse_duration("1:2:3:4"), None);
+}
+
+#[test]
+fn durations_too_long_to_hold_are_rejected() {
+	assert_eq!(definition_parser::parse_duration("9223372036854775807s"), Some(i64::MAX));
+	assert_eq!(definition_parser::parse_duration("9223372036854775807s 1s"), None);
+	assert_eq!(definition_parser::parse_duration("9223372036854775807h"), None);
+	assert_eq!(definition_parser::parse_duration("99999999999999999999s"), None);
+	assert_eq!(definition_parser::parse_duration("4294967295:00"), Some(4294967295 * 3600));
+	assert_eq!(definition_parser::parse_duration("4294967296:00"), None);
+}
+
+#[test]
+fn times_of_day_are_hours_and_minutes_with_optional_seconds() {
+	assert_eq!(definition_parser::parse_time_of_day("07:30"), NaiveTime::from_hms_opt(7, 30, 0));
+	assert_eq!(definition_parser::parse_time_of_day(" 7:30:15 "), NaiveTime::from_hms_opt(7, 30, 15));
+	assert_eq!(definition_parser::parse_time_of_day("24:00"), None);
+	assert_eq!(definition_parser::parse_time_of_day("07:60"), None);
+	assert_eq!(definition_parser::parse_time_of_day("0730"), None);
+	assert_eq!(definition_parser::parse_time_of_day(""), None);
+}
+
+#[test]
+fn durations_are_written_the_way_they_are_read() {
+	assert_eq!(views::format_duration(0), "0s");
+	assert_eq!(views::format_duration(45), "45s");
+	assert_eq!(views::format_duration(5400), "1h30m");
+	assert_eq!(views::format_duration(3605), "1h5s");
+	// Days are written as hours
+	assert_eq!(views::format_duration(93600), "26h");
+	for seconds in [1, 59, 60, 3599, 3600, 16200, 93601] {
+		assert_eq!(definition_parser::parse_duration(&views::format_duration(seconds)), Some(seconds));
+	}
+}
+
+#[test]
+fn dates_can_default_to_a_fixed_day_or_time() {
+	let directory = common::temp_directory();
+	let mut service = common::new_service(&directory, DEFINITION);
+	let trace_uuid = new_trace(&mut service);
+	let event = new_event(&service, trace_uuid, "proof");
+	match &event.fields[1].value {
+		FieldValueSuggestion::DateTime(date_time) => {
+			assert_eq!(date_time.value, Some("2024-03-01T07:30:00Z".parse().unwrap()));
+		}
+		_ => panic!("started is a date and time"),
+	}
+	match &event.fields[2].value {
+		FieldValueSuggestion::Date(date) => assert_eq!(date.value, NaiveDate::from_ymd_opt(2024, 2, 20)),
+		_ => panic!("milled is a date"),
+	}
+
+	// Exported the way they were read
+	let exported = service.export_definition(common::project_uuid(), DefinitionFormat::Yaml).unwrap();
+	assert!(exported.contains("2024-03-01T07:30:00Z"), "{}", exported);
+	assert!(exported.contains("2024-02-20"), "{}", exported);
+}
+
+#[test]
+fn dates_that_cannot_be_read_are_reported() {
+	let directory = common::temp_directory();
+	let mut service = common::new_service(&directory, DEFINITION);
+	for default_value in ["2024-03-01T08:30:00", "tomorrow"] {
+		let yaml = DEFINITION.replace("2024-03-01T08:30:00+01:00", default_value);
+		let err = common::import(&mut service, directory.path(), &yaml).err().unwrap();
+		assert!(err.to_string().contains("a time like 2024-03-01T08:00:00Z"), "{}", err);
+	}
+	let yaml = DEFINITION.replace("2024-02-20", "20.02.2024");
+	let err = common::import(&mut service, directory.path(), &yaml).err().unwrap();
+	assert!(err.to_string().contains("a date like 2024-03-01"), "{}", err);
+}
+
+fn summarize_durations(service: &mut dyn EventsService) {
+	let trace_uuid = new_trace(service);
+	record_time(service, trace_uuid, "proof", Some(3600));
+	record_time(service, trace_uuid, "proof", Some(5401));
+	// Left empty, and of another template
+	record_time(service, trace_uuid, "proof", None);
+	record_time(service, trace_uuid, "bake", Some(2700));
+
+	let proof_uuid = common::event_template_uuid("proof");
+	let summary = service.summarize_duration(proof_uuid, "time").ok().unwrap();
+	assert_eq!(summary.count, 2);
+	assert_eq!(summary.total, 9001);
+	assert_eq!(summary.mean(), Some(4501));
+
+	// A deleted event is not counted
+	let bake_uuid = common::event_template_uuid("bake");
+	let baked = service.list_events(None).find(|item| {
+		item.event_template.as_ref().map(|template| template.event_template_uuid) == Some(bake_uuid)
+	});
+	service.delete_event(baked.unwrap().event_uuid).unwrap();
+	let summary = service.summarize_duration(bake_uuid, "time").ok().unwrap();
+	assert_eq!(summary.count, 0);
+	assert_eq!(summary.mean(), None);
+
+	assert!(service.summarize_duration(Uuid::new_v4(), "time").is_err());
+}
+
+#[test]
+fn durations_are_summed_over_a_template() {
+	let directory = common::temp_directory();
+	summarize_durations(&mut common::new_service(&directory, DEFINITION));
+
+	let mut database = SqliteEventsService::open_in_memory().unwrap();
+	common::import(&mut database, directory.path(), DEFINITION).unwrap();
+	summarize_durations(&mut database);
+}
@@ -0,0 +1,160 @@
+mod common;
+
+use journal_lib::service::EventsService;
+use journal_lib::service::FieldValueSuggestion;
+use journal_lib::service::HistoryScope;
+use journal_server::events;
+use journal_server::events::Field;
+use journal_server::events::FieldValue;
+
+use uuid::Uuid;
+
+// Fields of groups are addressed by dotted paths, also through the rows of an array of groups
+
+fn field(name: &str, value: FieldValue) -> Field {
+	Field {
+		name: name.to_string(),
+		label: name.to_string(),
+		value: Some(value),
+	}
+}
+
+fn fold(rest: i64) -> FieldValue {
+	FieldValue::Group(vec![
+		field("rest", FieldValue::Duration(rest)),
+		field("strength", FieldValue::Number(1.0)),
+	])
+}
+
+// A proof group and an array of folds, each fold a group
+fn dough() -> Vec<Field> {
+	vec![
+		field(
+			"proof",
+			FieldValue::Group(vec![
+				field("temperature", FieldValue::Number(24.0)),
+				field("shape", FieldValue::Group(vec![field("kind", FieldValue::Text("boule".into()))])),
+			]),
+		),
+		field("folds", FieldValue::Array(vec![fold(600), fold(900)])),
+	]
+}
+
+// The names of each fold's fields
+fn fold_names(fields: &[Field]) -> Vec<Vec<&str>> {
+	match events::find_field(fields, "folds").and_then(|folds| folds.value.as_ref()) {
+		Some(FieldValue::Array(rows)) => rows
+			.iter()
+			.map(|row| match row {
+				FieldValue::Group(fields) => fields.iter().map(|field| field.name.as_str()).collect(),
+				_ => panic!("folds are groups"),
+			})
+			.collect(),
+		_ => panic!("folds is an array"),
+	}
+}
+
+#[test]
+fn find_field_follows_dotted_paths_into_groups() {
+	let fields = dough();
+	let value = |path: &str| events::find_field(&fields, path).and_then(|field| field.value.clone());
+	assert!(value("proof.temperature") == Some(FieldValue::Number(24.0)));
+	assert!(value("proof.shape.kind") == Some(FieldValue::Text("boule".into())));
+	assert!(events::find_field(&fields, "proof").is_some());
+	assert!(events::find_field(&fields, "proof.missing").is_none());
+	// Neither a value that is not a group nor the rows of an array hold a single field
+	assert!(events::find_field(&fields, "proof.temperature.degrees").is_none());
+	assert!(events::find_field(&fields, "folds.rest").is_none());
+}
+
+#[test]
+fn visit_parent_fields_reaches_every_row_of_an_array_of_groups() {
+	let mut fields = dough();
+	let mut visited = vec![];
+	let changed = events::visit_parent_fields(&mut fields, "folds.rest", &mut |parent, name| {
+		visited.push(parent.len());
+		parent.iter_mut().filter(|field| field.name == name).fold(false, |_, field| {
+			field.name = "pause".to_string();
+			true
+		})
+	});
+	assert!(changed);
+	assert_eq!(visited, vec![2, 2]);
+	assert_eq!(fold_names(&fields), vec![vec!["pause", "strength"], vec!["pause", "strength"]]);
+
+	// The group the path leads into is visited once, with the last name of the path
+	let mut names = vec![];
+	let changed = events::visit_parent_fields(&mut fields, "proof.shape.kind", &mut |parent, name| {
+		names.push((parent.len(), name.to_string()));
+		false
+	});
+	assert!(!changed);
+	assert_eq!(names, vec![(1, "kind".to_string())]);
+
+	// A path through a value that is not a group visits nothing
+	let changed = events::visit_parent_fields(&mut fields, "proof.temperature.degrees", &mut |_, _| true);
+	assert!(!changed);
+}
+
+const DEFINITION: &str = "
+trace-templates:
+  - name: Loaf
+event-templates:
+  - name: Mix
+    trace-template: loaf
+    fields:
+      - label: Folds
+        type: Array
+        item:
+          type: Group
+          fields:
+            - label: Rest
+              type: Duration
+            - label: Strength
+              type: Number
+";
+
+#[test]
+fn renaming_a_field_in_an_array_of_groups_renames_it_in_every_row() {
+	let directory = common::temp_directory();
+	let mut service = common::new_service(&directory, DEFINITION);
+	let trace_uuid = common::new_trace(&mut service, common::trace_template_uuid("loaf"));
+	let trace = service.view_trace(trace_uuid).unwrap();
+	let mut event = service
+		.create_event(&trace, common::event_template_uuid("mix"), HistoryScope::SameTrace)
+		.ok()
+		.unwrap();
+	let FieldValueSuggestion::Array(folds) = &mut event.fields[0].value else {
+		panic!("folds is an array");
+	};
+	for rest in [600, 900] {
+		folds.add_item();
+		let Some(FieldValueSuggestion::Group(row)) = folds.items.last_mut() else {
+			panic!("folds are groups");
+		};
+		let FieldValueSuggestion::Duration(duration) = &mut row.fields[0].value else {
+			panic!("rest is a duration");
+		};
+		duration.value = Some(rest);
+		let FieldValueSuggestion::Number(strength) = &mut row.fields[1].value else {
+			panic!("strength is a number");
+		};
+		strength.value = Some(2.0);
+	}
+	service.save_event(&event).unwrap();
+	let event_uuid: Uuid = *service.events.keys().next().unwrap();
+
+	let report = common::import(
+		&mut service,
+		directory.path(),
+		&format!(
+			"version: 2\n{}migrations:\n  - version: 2\n    event-template: mix\n    \
+			rename-field: {{ from: folds.rest, to: folds.pause }}\n",
+			DEFINITION.replace("label: Rest", "label: Pause")
+		),
+	)
+	.unwrap();
+	assert_eq!(report.changes.migrated_events, 1);
+	let fields = &service.events[&event_uuid].fields;
+	assert_eq!(fold_names(fields), vec![vec!["pause", "strength"], vec!["pause", "strength"]]);
+}
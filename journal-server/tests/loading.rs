@@ -0,0 +1,187 @@
+mod common;
+
+use journal_lib::service::EventsService;
+use journal_lib::service::HistoryScope;
+use journal_lib::service::LoadMode;
+use journal_server::fs_service::FileSystemEventsService;
+
+use common::project_uuid;
+use std::collections::HashSet;
+use std::path::Path;
+use uuid::Uuid;
+
+const DEFINITION: &str = "
+trace-templates:
+  - name: Loaf
+event-templates:
+  - name: Bake
+    trace-template: loaf
+";
+
+// A trace with one bake, only in memory
+fn record_bake(service: &mut FileSystemEventsService) -> Uuid {
+	let trace_uuid = common::new_trace(service, common::trace_template_uuid("loaf"));
+	let trace = service.view_trace(trace_uuid).unwrap();
+	let event = service
+		.create_event(&trace, common::event_template_uuid("bake"), HistoryScope::SameTrace)
+		.ok()
+		.unwrap();
+	service.save_event(&event).unwrap();
+	trace_uuid
+}
+
+// A saved journal with one trace and its bake
+fn saved_bakery(directory: &Path) -> FileSystemEventsService {
+	let mut service = FileSystemEventsService::new(directory.to_path_buf());
+	common::import(&mut service, directory, DEFINITION).unwrap();
+	record_bake(&mut service);
+	service.save_to_disk().unwrap();
+	service
+}
+
+#[test]
+fn the_directory_is_created_on_first_run() {
+	let directory = common::temp_directory();
+	let projects_directory = directory.path().join("journal").join("projects");
+
+	let service = FileSystemEventsService::load(&projects_directory).unwrap();
+	assert!(projects_directory.is_dir());
+	assert!(service.projects.is_empty());
+
+	let mut service = FileSystemEventsService::new(directory.path().join("other"));
+	let report = service.load_from_disk(LoadMode::Replace).unwrap();
+	assert!(directory.path().join("other").is_dir());
+	assert!(report.loaded_projects.is_empty());
+	assert!(!report.has_problems());
+}
+
+#[test]
+fn a_journal_is_saved_to_and_loaded_from_any_path() {
+	let directory = common::temp_directory();
+	let service = saved_bakery(directory.path());
+	let elsewhere = directory.path().join("copies").join("bakery");
+
+	service.save(&elsewhere).unwrap();
+	assert!(elsewhere.join(common::PROJECT_UUID).join("data.json").exists());
+	let loaded = FileSystemEventsService::load(&elsewhere).unwrap();
+	assert_eq!(loaded.projects_directory().unwrap(), elsewhere);
+	assert_eq!(loaded.projects.keys().collect::<Vec<_>>(), vec![&project_uuid()]);
+	assert_eq!(loaded.event_templates.len(), 1);
+	assert_eq!(loaded.traces.keys().collect::<HashSet<_>>(), service.traces.keys().collect::<HashSet<_>>());
+	assert_eq!(loaded.events.len(), 1);
+}
+
+#[test]
+fn loading_again_with_replace_changes_nothing() {
+	let directory = common::temp_directory();
+	saved_bakery(directory.path());
+	let mut service = FileSystemEventsService::load(directory.path()).unwrap();
+	let counts = |service: &FileSystemEventsService| {
+		(service.projects.len(), service.event_templates.len(), service.traces.len(), service.events.len())
+	};
+	assert_eq!(counts(&service), (1, 1, 1, 1));
+
+	let report = service.load_from_disk(LoadMode::Replace).unwrap();
+	assert_eq!(counts(&service), (1, 1, 1, 1));
+	assert_eq!(report.loaded_projects, vec![project_uuid()]);
+	assert!(!report.has_problems());
+
+	// What was never saved is dropped
+	record_bake(&mut service);
+	service.load_from_disk(LoadMode::Replace).unwrap();
+	assert_eq!(counts(&service), (1, 1, 1, 1));
+}
+
+#[test]
+fn merging_keeps_what_is_in_memory_and_adds_what_it_lacks() {
+	let directory = common::temp_directory();
+	saved_bakery(directory.path());
+	let mut service = FileSystemEventsService::load(directory.path()).unwrap();
+	let saved_trace_uuid = *service.traces.keys().next().unwrap();
+
+	// Meanwhile another copy of the journal adds a trace and a project on disk
+	let mut other = FileSystemEventsService::load(directory.path()).unwrap();
+	let added_on_disk = record_bake(&mut other);
+	let other_project_uuid = Uuid::new_v4();
+	let path = common::write_definition(directory.path(), other_project_uuid, DEFINITION);
+	other.import_definition("Pastry".into(), &path, None).unwrap();
+	other.rename_trace(saved_trace_uuid, "Renamed on disk".into()).unwrap();
+	other.save_to_disk().unwrap();
+
+	service.rename_trace(saved_trace_uuid, "Renamed in memory".into()).unwrap();
+	let added_in_memory = record_bake(&mut service);
+	let report = service.load_from_disk(LoadMode::Merge).unwrap();
+
+	assert_eq!(report.loaded_projects.len(), 2);
+	assert!(service.projects.contains_key(&other_project_uuid));
+	assert!(service.traces.contains_key(&added_in_memory));
+	assert!(service.traces.contains_key(&added_on_disk));
+	assert_eq!(service.events.len(), 3);
+	assert_eq!(service.traces[&saved_trace_uuid].name, "Renamed in memory");
+	// Only the project in both is a conflict, and only for the trace both changed
+	assert_eq!(report.conflicts.len(), 1);
+	let conflict = &report.conflicts[0];
+	assert_eq!(conflict.project_uuid, project_uuid());
+	assert!(!conflict.definition_differs);
+	assert_eq!((conflict.traces, conflict.events), (1, 0));
+}
+
+#[test]
+fn a_changed_definition_on_disk_is_a_conflict_and_memory_keeps_its_own() {
+	let directory = common::temp_directory();
+	saved_bakery(directory.path());
+	let mut service = FileSystemEventsService::load(directory.path()).unwrap();
+
+	let mut other = FileSystemEventsService::load(directory.path()).unwrap();
+	common::import(&mut other, directory.path(), &format!("{}  - name: Slice\n    trace-template: loaf\n", DEFINITION))
+		.unwrap();
+	other.save_to_disk().unwrap();
+
+	let report = service.load_from_disk(LoadMode::Merge).unwrap();
+	assert_eq!(report.conflicts.len(), 1);
+	assert!(report.conflicts[0].definition_differs);
+	assert_eq!(service.event_templates.len(), 1);
+}
+
+#[test]
+fn projects_that_cannot_be_used_are_skipped_with_a_reason() {
+	let directory = common::temp_directory();
+	saved_bakery(directory.path());
+	let saved = directory.path().join(common::PROJECT_UUID);
+	let copy = |name: &str| {
+		let target = directory.path().join(name);
+		std::fs::create_dir(&target).unwrap();
+		for file in ["project.json", "definition.json", "data.json"] {
+			std::fs::copy(saved.join(file), target.join(file)).unwrap();
+		}
+		target
+	};
+	// The files name another project than the directory does
+	let mismatched_uuid = Uuid::new_v4();
+	copy(&mismatched_uuid.to_string());
+	let missing_definition_uuid = Uuid::new_v4();
+	let missing_definition = copy(&missing_definition_uuid.to_string());
+	std::fs::remove_file(missing_definition.join("definition.json")).unwrap();
+	copy("not-a-project");
+
+	let mut service = FileSystemEventsService::new(directory.path().to_path_buf());
+	let report = service.load_from_disk(LoadMode::Replace).unwrap();
+	assert_eq!(report.loaded_projects, vec![project_uuid()]);
+	assert!(report.has_problems());
+	let reason = |name: &str| {
+		report
+			.skipped_projects
+			.iter()
+			.find(|skipped| skipped.directory.ends_with(name))
+			.map(|skipped| skipped.reason.as_str())
+			.unwrap()
+	};
+	assert_eq!(report.skipped_projects.len(), 3);
+	assert_eq!(
+		reason(&mismatched_uuid.to_string()),
+		format!("project.json belongs to project {}", project_uuid())
+	);
+	assert_eq!(reason(&missing_definition_uuid.to_string()), "definition.json is missing");
+	assert_eq!(reason("not-a-project"), "the directory is not named after a project uuid");
+	assert_eq!(service.projects.len(), 1);
+}
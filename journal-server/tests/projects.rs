@@ -0,0 +1,142 @@
+mod common;
+
+use journal_lib::errors;
+use journal_lib::service::EventsService;
+use journal_lib::service::HistoryScope;
+use journal_lib::service::LoadMode;
+use journal_server::fs_service::FileSystemEventsService;
+use journal_server::fs_service::StorageMode;
+
+use common::project_uuid;
+use common::PROJECT_UUID;
+use std::collections::HashSet;
+use std::path::Path;
+use uuid::Uuid;
+
+const DEFINITION: &str = "
+trace-templates:
+  - name: Loaf
+    transitions:
+      - from: mix
+        to: [bake]
+event-templates:
+  - name: Mix
+    trace-template: loaf
+  - name: Bake
+    trace-template: loaf
+";
+
+// A saved project with one trace, a mix and a deleted bake. Changes are written as they are
+// made, as in the app.
+fn bakery(directory: &Path) -> FileSystemEventsService {
+	let mut service = FileSystemEventsService::new(directory.to_path_buf());
+	service.storage_mode = StorageMode::Log;
+	common::import(&mut service, directory, DEFINITION).unwrap();
+
+	let trace_uuid = common::new_trace(&mut service, common::trace_template_uuid("loaf"));
+	let trace = service.view_trace(trace_uuid).unwrap();
+	for slug in ["mix", "bake"] {
+		let event_template_uuid = common::event_template_uuid(slug);
+		let event = service.create_event(&trace, event_template_uuid, HistoryScope::SameTrace).ok().unwrap();
+		service.save_event(&event).unwrap();
+	}
+	let bake_uuid = common::event_template_uuid("bake");
+	let bake = *service
+		.events
+		.values()
+		.find(|event| event.event_template_uuid == bake_uuid)
+		.map(|event| &event.event_uuid)
+		.unwrap();
+	service.delete_event(bake).unwrap();
+	service.save_to_disk().unwrap();
+	service
+}
+
+#[test]
+fn project_items_count_what_the_project_holds() {
+	let directory = common::temp_directory();
+	let service = bakery(directory.path());
+	let items: Vec<_> = service.list_projects().collect();
+	assert_eq!(items.len(), 1);
+	let item = &items[0];
+	assert_eq!(item.name, "Bakery");
+	assert_eq!(item.event_template_count, 2);
+	assert_eq!(item.trace_template_count, 1);
+	assert_eq!(item.trace_count, 1);
+	// The deleted bake is not counted
+	assert_eq!(item.event_count, 1);
+	assert!(item.last_activity.is_some());
+}
+
+#[test]
+fn a_duplicate_gets_its_own_templates_and_no_data() {
+	let directory = common::temp_directory();
+	let mut service = bakery(directory.path());
+	service.describe_project(project_uuid(), "Loaves".into()).unwrap();
+	let duplicate = service.duplicate_project(project_uuid(), "Bakery copy".into()).ok().unwrap();
+	assert_ne!(duplicate.project_uuid, project_uuid());
+	assert_eq!(duplicate.name, "Bakery copy");
+	assert_eq!(duplicate.description, "Loaves");
+	assert_eq!(duplicate.trace_count, 0);
+	assert_eq!(duplicate.event_count, 0);
+
+	let original_uuids: HashSet<Uuid> = service
+		.event_templates
+		.values()
+		.filter(|event_template| {
+			service.trace_templates[&event_template.trace_template_uuid].project_uuid == project_uuid()
+		})
+		.map(|event_template| event_template.event_template_uuid)
+		.collect();
+	let trace_template = service
+		.trace_templates
+		.values()
+		.find(|trace_template| trace_template.project_uuid == duplicate.project_uuid)
+		.unwrap();
+	assert_eq!(trace_template.name, "Loaf");
+	let duplicate_uuids: HashSet<Uuid> = service
+		.event_templates
+		.values()
+		.filter(|event_template| event_template.trace_template_uuid == trace_template.trace_template_uuid)
+		.map(|event_template| event_template.event_template_uuid)
+		.collect();
+	assert_eq!(duplicate_uuids.len(), 2);
+	assert!(duplicate_uuids.is_disjoint(&original_uuids));
+
+	// The transition from mix to bake points at the duplicate's own templates
+	let flow = &trace_template.flow;
+	assert_eq!(flow.len(), 1);
+	assert_eq!(service.event_templates[&flow[0].from].name, "Mix");
+	assert_eq!(flow[0].to.len(), 1);
+	assert_eq!(service.event_templates[&flow[0].to[0]].name, "Bake");
+	assert!(duplicate_uuids.contains(&flow[0].from));
+	assert!(duplicate_uuids.contains(&flow[0].to[0]));
+}
+
+#[test]
+fn a_deleted_project_stays_deleted() {
+	let directory = common::temp_directory();
+	let mut service = bakery(directory.path());
+	let duplicate = service.duplicate_project(project_uuid(), "Bakery copy".into()).ok().unwrap();
+
+	assert!(matches!(
+		service.delete_project(project_uuid(), "bakery"),
+		Err(errors::DeleteProjectError::NotConfirmed)
+	));
+	assert!(matches!(
+		service.delete_project(Uuid::new_v4(), "Bakery"),
+		Err(errors::DeleteProjectError::ProjectNotFound(_))
+	));
+	service.delete_project(project_uuid(), "Bakery").unwrap();
+	assert!(service.view_project(project_uuid()).is_none());
+	assert!(service.traces.is_empty());
+	assert!(service.events.is_empty());
+	assert_eq!(service.event_templates.len(), 2);
+	assert!(!directory.path().join(PROJECT_UUID).exists());
+
+	let mut reloaded = FileSystemEventsService::new(directory.path().to_path_buf());
+	let report = reloaded.load_from_disk(LoadMode::Replace).unwrap();
+	assert_eq!(report.loaded_projects, vec![duplicate.project_uuid]);
+	assert!(reloaded.view_project(project_uuid()).is_none());
+	assert!(reloaded.traces.is_empty());
+}
@@ -0,0 +1,323 @@
+mod common;
+
+use journal_lib::service::EventsService;
+use journal_lib::service::FieldSuggestion;
+use journal_lib::service::FieldValueSuggestion;
+use journal_lib::service::HistoryScope;
+use journal_lib::service::TraceFilter;
+use journal_server::events;
+use journal_server::fs_service::FileSystemEventsService;
+
+use common::event_template_uuid;
+use common::project_uuid;
+use uuid::Uuid;
+
+// Re-importing a definition of a project that already has events
+
+// Fields in groups are named by their dotted paths, options by their names
+fn fill_fields(fields: &mut [FieldSuggestion], prefix: &str, values: &[(&str, &str)]) {
+	for field in fields.iter_mut() {
+		let path = format!("{}{}", prefix, field.name);
+		if let FieldValueSuggestion::Group(group) = &mut field.value {
+			fill_fields(&mut group.fields, &format!("{}.", path), values);
+			continue;
+		}
+		let Some((_, value)) = values.iter().find(|(name, _)| *name == path) else {
+			continue;
+		};
+		match &mut field.value {
+			FieldValueSuggestion::Number(number) => number.value = Some(value.parse().unwrap()),
+			FieldValueSuggestion::Text(text) => text.value = Some(value.to_string()),
+			FieldValueSuggestion::Enumerated(enumerated) => {
+				enumerated.selected = enumerated.options.iter().find(|option| option.name == *value).cloned()
+			}
+			_ => panic!("{} cannot be filled in", path),
+		}
+	}
+}
+
+// Records an event in a new trace, its fields get the values given
+fn record_event(service: &mut FileSystemEventsService, event_template: &str, values: &[(&str, &str)]) -> Uuid {
+	// Saving gives the event a new uuid
+	let trace_uuid = common::new_trace(service, common::trace_template_uuid("loaf"));
+	let trace = service.view_trace(trace_uuid).unwrap();
+	let mut event = service
+		.create_event(&trace, event_template_uuid(event_template), HistoryScope::SameTrace)
+		.ok()
+		.unwrap();
+	fill_fields(&mut event.fields, "", values);
+	let before: Vec<Uuid> = service.list_events(None).map(|item| item.event_uuid).collect();
+	service.save_event(&event).unwrap();
+	service
+		.list_events(None)
+		.map(|item| item.event_uuid)
+		.find(|event_uuid| !before.contains(event_uuid))
+		.unwrap()
+}
+
+const BAKE: &str = "
+trace-templates:
+  - name: Loaf
+event-templates:
+  - name: Bake
+    trace-template: loaf
+    fields:
+      - type: Number
+        label: Temp
+";
+
+#[test]
+fn renaming_a_template_without_a_slug_warns_about_its_events() {
+	let directory = common::temp_directory();
+	let mut service = common::new_service(&directory, BAKE);
+	record_event(&mut service, "bake", &[("temp", "230")]);
+
+	let report = common::import(&mut service, directory.path(), &BAKE.replace("name: Bake", "name: Baking")).unwrap();
+	assert_eq!(report.changes.orphaned_events, 1);
+	assert!(
+		report.warnings.iter().any(|warning| warning.message.contains("Its uuid came from the slug 'bake'")),
+		"{:?}",
+		report.warnings
+	);
+}
+
+#[test]
+fn renaming_a_template_that_keeps_its_slug_does_not_warn() {
+	let directory = common::temp_directory();
+	let mut service = common::new_service(&directory, BAKE);
+	record_event(&mut service, "bake", &[("temp", "230")]);
+
+	let report = common::import(&mut service, directory.path(), &BAKE.replace("name: Bake", "name: Baking\n    slug: bake")).unwrap();
+	assert_eq!(report.changes.orphaned_events, 0);
+	assert!(report.warnings.is_empty(), "{:?}", report.warnings);
+	assert_eq!(report.changes.event_templates.renamed.len(), 1);
+}
+
+#[test]
+fn removing_a_template_without_events_does_not_warn() {
+	let directory = common::temp_directory();
+	let mut service = common::new_service(&directory, BAKE);
+
+	let report = common::import(&mut service, directory.path(), &BAKE.replace("name: Bake", "name: Baking")).unwrap();
+	assert!(report.warnings.is_empty(), "{:?}", report.warnings);
+}
+
+#[test]
+fn events_of_a_removed_template_are_saved_and_reloaded() {
+	let directory = common::temp_directory();
+	let mut service = common::new_service(&directory, BAKE);
+	let event_uuid = record_event(&mut service, "bake", &[("temp", "230")]);
+
+	// Neither the trace template nor the event template is left
+	let report = common::import(&mut service, directory.path(), "trace-templates:\n  - name: Roll\nevent-templates: []\n").unwrap();
+	assert_eq!(report.changes.orphaned_events, 1);
+	assert_eq!(report.changes.orphaned_traces, 1);
+	service.save_to_disk().unwrap();
+
+	let reloaded = FileSystemEventsService::load(&service.projects_directory().unwrap()).unwrap();
+	assert!(reloaded.events.contains_key(&event_uuid));
+	let events: Vec<Uuid> = reloaded.list_events(Some(project_uuid())).map(|item| item.event_uuid).collect();
+	assert_eq!(events, vec![event_uuid]);
+	assert_eq!(reloaded.list_traces(Some(project_uuid()), TraceFilter::All).count(), 1);
+}
+
+#[test]
+fn move_events_recovers_events_an_earlier_import_left_behind() {
+	let directory = common::temp_directory();
+	let mut service = common::new_service(&directory, BAKE);
+	let event_uuid = record_event(&mut service, "bake", &[("temp", "230")]);
+	let baking = BAKE.replace("name: Bake", "name: Baking");
+	assert_eq!(common::import(&mut service, directory.path(), &baking).unwrap().changes.orphaned_events, 1);
+
+	let report = common::import(
+		&mut service,
+		directory.path(),
+		&format!("version: 2\n{}migrations:\n  - version: 2\n    move-events: {{ from: bake, to: baking }}\n", baking),
+	)
+	.unwrap();
+	assert!(report.warnings.is_empty(), "{:?}", report.warnings);
+	assert_eq!(report.changes.migrated_events, 1);
+	assert_eq!(report.changes.orphaned_events, 0);
+	assert_eq!(service.events[&event_uuid].event_template_uuid, event_template_uuid("baking"));
+}
+
+fn stored_value<'a>(service: &'a FileSystemEventsService, event_uuid: Uuid, path: &str) -> Option<&'a events::FieldValue> {
+	service.events[&event_uuid].field(path).and_then(|field| field.value.as_ref())
+}
+
+const PROOF: &str = "
+trace-templates:
+  - name: Loaf
+event-templates:
+  - name: Bake
+    trace-template: loaf
+    fields:
+      - type: Group
+        label: Proof
+        fields:
+          - type: Number
+            label: Temp
+          - type: Text
+            label: Note
+      - type: Enumerated
+        label: Flour
+        options:
+          - label: Wheat
+          - label: Rye
+";
+
+#[test]
+fn rename_field_moves_the_stored_value() {
+	let directory = common::temp_directory();
+	let mut service = common::new_service(&directory, PROOF);
+	let event_uuid = record_event(&mut service, "bake", &[("proof.temp", "24")]);
+
+	let report = common::import(
+		&mut service,
+		directory.path(),
+		&format!(
+			"version: 2\n{}migrations:\n  - version: 2\n    event-template: bake\n    rename-field: {{ from: proof.temp, to: proof.temperature }}\n",
+			PROOF.replace("label: Temp", "label: Temperature")
+		),
+	)
+	.unwrap();
+	assert_eq!(report.changes.applied_migrations, 1);
+	assert_eq!(report.changes.migrated_events, 1);
+	let fields = &report.changes.fields[0];
+	assert_eq!(fields.renamed.len(), 1);
+	assert!(fields.added.is_empty() && fields.removed.is_empty(), "{:?}", fields);
+
+	assert!(stored_value(&service, event_uuid, "proof.temperature") == Some(&events::FieldValue::Number(24.0)));
+	assert!(service.events[&event_uuid].field("proof.temp").is_none());
+	assert_eq!(service.events[&event_uuid].field("proof.temperature").unwrap().label, "Temperature");
+}
+
+#[test]
+fn remove_field_drops_the_stored_value() {
+	let directory = common::temp_directory();
+	let mut service = common::new_service(&directory, PROOF);
+	let event_uuid = record_event(&mut service, "bake", &[("proof.temp", "24"), ("proof.note", "warm")]);
+
+	let report = common::import(
+		&mut service,
+		directory.path(),
+		&format!(
+			"version: 2\n{}migrations:\n  - version: 2\n    event-template: bake\n    remove-field: proof.note\n",
+			PROOF.replace("          - type: Text\n            label: Note\n", "")
+		),
+	)
+	.unwrap();
+	assert_eq!(report.changes.migrated_events, 1);
+	assert_eq!(report.changes.fields[0].removed, vec!["proof.note".to_string()]);
+	assert!(service.events[&event_uuid].field("proof.note").is_none());
+	assert!(stored_value(&service, event_uuid, "proof.temp") == Some(&events::FieldValue::Number(24.0)));
+}
+
+#[test]
+fn rename_option_changes_the_chosen_option() {
+	let directory = common::temp_directory();
+	let mut service = common::new_service(&directory, PROOF);
+	let event_uuid = record_event(&mut service, "bake", &[("flour", "rye")]);
+
+	let report = common::import(
+		&mut service,
+		directory.path(),
+		&format!(
+			"version: 2\n{}migrations:\n  - version: 2\n    event-template: bake\n    rename-option: {{ field: flour, from: rye, to: dark-rye }}\n",
+			PROOF.replace("label: Rye", "label: Dark rye")
+		),
+	)
+	.unwrap();
+	assert_eq!(report.changes.fields[0].options[0].renamed.len(), 1);
+	match stored_value(&service, event_uuid, "flour") {
+		Some(events::FieldValue::Enumerated(option)) => {
+			assert_eq!(option.name, "dark-rye");
+			assert_eq!(option.label, "Dark rye");
+		}
+		_ => panic!("flour lost its option"),
+	}
+}
+
+#[test]
+fn move_events_gives_events_the_new_template() {
+	let directory = common::temp_directory();
+	let mut service = common::new_service(&directory, PROOF);
+	let event_uuid = record_event(&mut service, "bake", &[("proof.temp", "24")]);
+
+	let report = common::import(
+		&mut service,
+		directory.path(),
+		&format!(
+			"version: 2\n{}migrations:\n  - version: 2\n    move-events: {{ from: bake, to: baking }}\n",
+			PROOF.replace("name: Bake", "name: Baking")
+		),
+	)
+	.unwrap();
+	assert_eq!(report.changes.orphaned_events, 0);
+	assert!(report.warnings.is_empty(), "{:?}", report.warnings);
+	assert_eq!(service.events[&event_uuid].event_template_uuid, event_template_uuid("baking"));
+	assert!(stored_value(&service, event_uuid, "proof.temp") == Some(&events::FieldValue::Number(24.0)));
+}
+
+#[test]
+fn moving_events_from_an_unknown_slug_warns() {
+	let directory = common::temp_directory();
+	let mut service = common::new_service(&directory, PROOF);
+	record_event(&mut service, "bake", &[("proof.temp", "24")]);
+
+	let report = common::import(
+		&mut service,
+		directory.path(),
+		&format!(
+			"version: 2\n{}migrations:\n  - version: 2\n    move-events: {{ from: bkae, to: baking }}\n",
+			PROOF.replace("name: Bake", "name: Baking")
+		),
+	)
+	.unwrap();
+	let warning = report
+		.warnings
+		.iter()
+		.find(|warning| warning.message.contains("Is the slug right?"))
+		.expect("no warning about the slug");
+	assert_eq!(warning.path.as_deref(), Some("migrations[0].move-events.from"));
+	assert_eq!(report.changes.orphaned_events, 1);
+}
+
+#[test]
+fn migrations_only_run_when_the_version_goes_up() {
+	let directory = common::temp_directory();
+	let mut service = common::new_service(&directory, PROOF);
+	let event_uuid = record_event(&mut service, "bake", &[("proof.temp", "24")]);
+
+	let report = common::import(
+		&mut service,
+		directory.path(),
+		&format!(
+			"{}migrations:\n  - version: 1\n    event-template: bake\n    rename-field: {{ from: proof.temp, to: proof.temperature }}\n",
+			PROOF.replace("label: Temp", "label: Temperature")
+		),
+	)
+	.unwrap();
+	assert_eq!(report.changes.applied_migrations, 0);
+	assert!(report.warnings.iter().any(|warning| warning.message.contains("no migrations run")));
+	assert!(stored_value(&service, event_uuid, "proof.temp") == Some(&events::FieldValue::Number(24.0)));
+}
+
+#[test]
+fn changes_without_migrations_are_added_and_removed() {
+	let directory = common::temp_directory();
+	let mut service = common::new_service(&directory, PROOF);
+
+	let report = common::import(
+		&mut service,
+		directory.path(),
+		&PROOF
+			.replace("label: Temp", "label: Temperature")
+			.replace("type: Text\n            label: Note", "type: Number\n            label: Note"),
+	)
+	.unwrap();
+	let fields = &report.changes.fields[0];
+	assert_eq!(fields.added, vec!["proof.temperature".to_string()]);
+	assert_eq!(fields.removed, vec!["proof.temp".to_string()]);
+	assert_eq!(fields.retyped, vec!["proof.note".to_string()]);
+}
@@ -0,0 +1,158 @@
+mod common;
+
+use journal_lib::errors;
+use journal_lib::service::EventsService;
+use journal_lib::service::FieldSuggestion;
+use journal_lib::service::FieldValueSuggestion;
+use journal_lib::service::HistoryScope;
+use journal_lib::service::TraceSelection;
+use journal_server::events;
+use journal_server::fs_service::FileSystemEventsService;
+
+use tempfile::TempDir;
+use uuid::Uuid;
+
+// Updating and deleting events keeps what they were, so both can be undone
+
+const DEFINITION: &str = "
+trace-templates:
+  - name: Loaf
+event-templates:
+  - name: Bake
+    trace-template: loaf
+    fields:
+      - type: Number
+        label: Temp
+";
+
+fn bake_uuid() -> Uuid {
+	common::event_template_uuid("bake")
+}
+
+// A service with one trace to record events in
+fn new_service(directory: &TempDir) -> (FileSystemEventsService, Uuid) {
+	let mut service = common::new_service(directory, DEFINITION);
+	let trace_uuid = common::new_trace(&mut service, common::trace_template_uuid("loaf"));
+	(service, trace_uuid)
+}
+
+fn temp_suggestion(fields: &mut [FieldSuggestion]) -> &mut Option<f64> {
+	match &mut fields[0].value {
+		FieldValueSuggestion::Number(number) => &mut number.value,
+		_ => panic!("temp is a number"),
+	}
+}
+
+fn record_event(service: &mut FileSystemEventsService, trace_uuid: Uuid, temp: f64) -> Uuid {
+	let trace = service.view_trace(trace_uuid).unwrap();
+	let mut event = service.create_event(&trace, bake_uuid(), HistoryScope::SameTrace).ok().unwrap();
+	*temp_suggestion(&mut event.fields) = Some(temp);
+	// Events are ordered by when they were created
+	std::thread::sleep(std::time::Duration::from_millis(2));
+	let before: Vec<Uuid> = service.events.keys().cloned().collect();
+	service.save_event(&event).unwrap();
+	*service.events.keys().find(|event_uuid| !before.contains(event_uuid)).unwrap()
+}
+
+fn stored_temp(service: &FileSystemEventsService, event_uuid: Uuid) -> Option<&events::FieldValue> {
+	service.events[&event_uuid].field("temp").and_then(|field| field.value.as_ref())
+}
+
+// The most recent value a new event offers for its temp
+fn suggested_temp(service: &FileSystemEventsService, trace_uuid: Uuid) -> Option<f64> {
+	let trace = service.view_trace(trace_uuid).unwrap();
+	let event = service.create_event(&trace, bake_uuid(), HistoryScope::SameTrace).ok().unwrap();
+	match &event.fields[0].value {
+		FieldValueSuggestion::Number(number) => number.last_values.first().copied(),
+		_ => panic!("temp is a number"),
+	}
+}
+
+#[test]
+fn an_update_can_be_undone() {
+	let directory = common::temp_directory();
+	let (mut service, trace_uuid) = new_service(&directory);
+	let event_uuid = record_event(&mut service, trace_uuid, 220.0);
+
+	let mut event = service.edit_event(event_uuid).ok().unwrap();
+	assert!(event.editing);
+	*temp_suggestion(&mut event.fields) = Some(240.0);
+	service.update_event(&event).unwrap();
+	assert!(stored_temp(&service, event_uuid) == Some(&events::FieldValue::Number(240.0)));
+	assert_eq!(service.view_event(event_uuid).unwrap().revisions.len(), 1);
+
+	assert!(service.undo_event_change(event_uuid).unwrap());
+	assert!(stored_temp(&service, event_uuid) == Some(&events::FieldValue::Number(220.0)));
+	assert!(service.view_event(event_uuid).unwrap().revisions.is_empty());
+	// Nothing is left to undo
+	assert!(!service.undo_event_change(event_uuid).unwrap());
+}
+
+#[test]
+fn a_deletion_can_be_undone() {
+	let directory = common::temp_directory();
+	let (mut service, trace_uuid) = new_service(&directory);
+	let event_uuid = record_event(&mut service, trace_uuid, 220.0);
+
+	service.delete_event(event_uuid).unwrap();
+	assert!(service.view_event(event_uuid).unwrap().deleted_at.is_some());
+	assert!(service.edit_event(event_uuid).is_err());
+
+	assert!(service.undo_event_change(event_uuid).unwrap());
+	assert!(service.view_event(event_uuid).unwrap().deleted_at.is_none());
+	assert_eq!(service.list_events(None).count(), 1);
+	assert!(stored_temp(&service, event_uuid) == Some(&events::FieldValue::Number(220.0)));
+}
+
+#[test]
+fn undoing_a_deletion_keeps_the_update_before_it() {
+	let directory = common::temp_directory();
+	let (mut service, trace_uuid) = new_service(&directory);
+	let event_uuid = record_event(&mut service, trace_uuid, 220.0);
+	let mut event = service.edit_event(event_uuid).ok().unwrap();
+	*temp_suggestion(&mut event.fields) = Some(240.0);
+	service.update_event(&event).unwrap();
+	service.delete_event(event_uuid).unwrap();
+
+	assert!(service.update_event(&event).is_err());
+	assert!(service.undo_event_change(event_uuid).unwrap());
+	assert!(stored_temp(&service, event_uuid) == Some(&events::FieldValue::Number(240.0)));
+	assert!(service.undo_event_change(event_uuid).unwrap());
+	assert!(stored_temp(&service, event_uuid) == Some(&events::FieldValue::Number(220.0)));
+}
+
+#[test]
+fn a_deleted_event_is_left_out() {
+	let directory = common::temp_directory();
+	let (mut service, trace_uuid) = new_service(&directory);
+	let kept = record_event(&mut service, trace_uuid, 220.0);
+	let deleted = record_event(&mut service, trace_uuid, 250.0);
+	assert_eq!(suggested_temp(&service, trace_uuid), Some(250.0));
+
+	service.delete_event(deleted).unwrap();
+	let listed: Vec<Uuid> = service.list_events(None).map(|item| item.event_uuid).collect();
+	assert_eq!(listed, vec![kept]);
+	let last_event = service.view_trace(trace_uuid).unwrap().last_event.unwrap();
+	assert_eq!(last_event.event_uuid, kept);
+	assert_eq!(suggested_temp(&service, trace_uuid), Some(220.0));
+	// Still there to be undone
+	assert!(service.view_event(deleted).is_some());
+}
+
+#[test]
+fn an_event_is_not_moved_to_a_trace_that_is_not_there() {
+	let directory = common::temp_directory();
+	let (mut service, trace_uuid) = new_service(&directory);
+	let event_uuid = record_event(&mut service, trace_uuid, 220.0);
+	let mut event = service.edit_event(event_uuid).ok().unwrap();
+	*temp_suggestion(&mut event.fields) = Some(240.0);
+	let TraceSelection::Selected(trace) = &mut event.selected_trace else {
+		panic!("the event's trace is selected");
+	};
+	trace.trace_uuid = Uuid::new_v4();
+
+	assert!(matches!(service.update_event(&event), Err(errors::ChangeEventError::TraceNotFound(_))));
+	assert_eq!(service.events[&event_uuid].trace_uuid, trace_uuid);
+	assert!(stored_temp(&service, event_uuid) == Some(&events::FieldValue::Number(220.0)));
+	assert!(service.view_event(event_uuid).unwrap().revisions.is_empty());
+}
@@ -0,0 +1,95 @@
+mod common;
+
+use journal_lib::service::EventsService;
+use journal_lib::service::LoadMode;
+use journal_lib::service::ValuePolicy;
+use journal_server::fs_service::FileSystemEventsService;
+use journal_server::schema;
+
+use common::PROJECT_UUID;
+use uuid::Uuid;
+
+// Each directory under tests/fixtures is a projects directory written by an older version.
+// They must keep loading, so fixtures are only ever added.
+
+// Everything is in memory once loaded, the copy is removed on return
+fn load_fixture(name: &str) -> FileSystemEventsService {
+	let directory = common::copy_fixture(name);
+	let mut service = FileSystemEventsService::new(directory.path().to_path_buf());
+	let report = service.load_from_disk(LoadMode::Replace).unwrap();
+	assert!(
+		report.skipped_projects.is_empty(),
+		"{} did not load:\n{}",
+		name,
+		report
+	);
+	assert_eq!(report.loaded_projects, vec![common::project_uuid()]);
+	service
+}
+
+#[test]
+fn every_fixture_loads() {
+	for entry in std::fs::read_dir(common::fixtures_directory()).unwrap() {
+		let name = entry.unwrap().file_name().to_string_lossy().to_string();
+		let service = load_fixture(&name);
+
+		// Two events in the snapshot and one in the log, the log also renames a trace
+		assert_eq!(service.events.len(), 3, "{}", name);
+		assert_eq!(service.traces.len(), 2, "{}", name);
+		assert_eq!(service.event_templates.len(), 2, "{}", name);
+		assert_eq!(service.trace_templates.len(), 1, "{}", name);
+		let trace = &service.traces[&Uuid::from_u128(0x101)];
+		assert_eq!(trace.name, "Sunday loaf (renamed)", "{}", name);
+		assert_eq!(trace.origin_trace_uuids, vec![Uuid::from_u128(0x100)], "{}", name);
+		// Written before fields had value policies
+		for event_template in service.event_templates.values() {
+			assert!(
+				event_template.fields.iter().all(|field| field.value_policy == ValuePolicy::TemplateDefault),
+				"{}",
+				name
+			);
+		}
+	}
+}
+
+#[test]
+fn saving_writes_the_current_format() {
+	let service = load_fixture("format-0");
+	let directory = common::temp_directory();
+	service.save(directory.path()).unwrap();
+
+	for name in ["project.json", "definition.json", "data.json"] {
+		let contents = std::fs::read(directory.path().join(PROJECT_UUID).join(name)).unwrap();
+		let value: serde_json::Value = serde_json::from_slice(&contents).unwrap();
+		assert_eq!(value[schema::FORMAT_KEY], schema::CURRENT_FORMAT, "{}", name);
+	}
+
+	let reloaded = FileSystemEventsService::load(directory.path()).unwrap();
+	assert_eq!(reloaded.events.len(), service.events.len());
+	assert_eq!(reloaded.traces.len(), service.traces.len());
+}
+
+#[test]
+fn newer_formats_are_skipped() {
+	let directory = common::copy_fixture("format-1");
+	let project_path = directory.path().join(PROJECT_UUID).join("project.json");
+	let mut value: serde_json::Value =
+		serde_json::from_slice(&std::fs::read(&project_path).unwrap()).unwrap();
+	value[schema::FORMAT_KEY] = (schema::CURRENT_FORMAT + 1).into();
+	std::fs::write(&project_path, serde_json::to_vec(&value).unwrap()).unwrap();
+
+	let mut service = FileSystemEventsService::new(directory.path().to_path_buf());
+	let report = service.load_from_disk(LoadMode::Replace).unwrap();
+	assert!(report.loaded_projects.is_empty());
+	assert_eq!(report.skipped_projects.len(), 1);
+	assert!(report.skipped_projects[0].reason.contains("newer version"));
+}
+
+#[test]
+fn format_2_keeps_revisions_and_leaves_traces_unarchived() {
+	let service = load_fixture("format-2");
+	let event = &service.events[&Uuid::from_u128(0x201)];
+	assert_eq!(event.revisions.len(), 1);
+	assert_eq!(event.revisions[0].began_at.timestamp_millis(), 1722592800000);
+	assert!(service.traces.values().all(|trace| trace.archived_at.is_none()));
+}
@@ -0,0 +1,277 @@
+mod common;
+
+use journal_lib::errors;
+use journal_lib::service::EventsService;
+use journal_lib::service::HistoryScope;
+use journal_lib::service::TraceFilter;
+use journal_server::definition_parser;
+use journal_server::sqlite_service;
+use journal_server::sqlite_service::SqliteEventsService;
+
+use chrono::DateTime;
+use chrono::Utc;
+use common::project_uuid;
+use uuid::Uuid;
+
+const DEFINITION: &str = "
+trace-templates:
+  - name: Loaf
+event-templates:
+  - name: Bake
+    trace-template: loaf
+  - name: Slice
+    trace-template: loaf
+";
+
+// The database holds the definition once it is imported
+fn import(service: &mut SqliteEventsService, project_uuid: Uuid, yaml: &str) {
+	let directory = common::temp_directory();
+	let path = common::write_definition(directory.path(), project_uuid, yaml);
+	service.import_definition("Bakery".into(), &path, None).unwrap();
+}
+
+fn imported_service() -> SqliteEventsService {
+	let mut service = SqliteEventsService::open_in_memory().unwrap();
+	import(&mut service, project_uuid(), DEFINITION);
+	service
+}
+
+fn record_trace(service: &mut SqliteEventsService, project_uuid: Uuid) -> Uuid {
+	common::new_trace(service, definition_parser::derive_trace_template_uuid(&project_uuid, "loaf"))
+}
+
+fn record_event(
+	service: &mut SqliteEventsService,
+	trace_uuid: Uuid,
+	event_template: &str,
+	began_at: DateTime<Utc>,
+	tags: &[&str],
+) -> Uuid {
+	let trace = service.view_trace(trace_uuid).unwrap();
+	let project_uuid = project_uuid();
+	let mut event = service
+		.create_event(
+			&trace,
+			definition_parser::derive_event_template_uuid(&project_uuid, event_template),
+			HistoryScope::SameTrace,
+		)
+		.ok()
+		.unwrap();
+	event.began_at = began_at;
+	event.tags = tags.iter().map(|tag| tag.to_string()).collect();
+	let before: Vec<Uuid> = service.list_events(None).map(|item| item.event_uuid).collect();
+	service.save_event(&event).unwrap();
+	service
+		.list_events(None)
+		.map(|item| item.event_uuid)
+		.find(|event_uuid| !before.contains(event_uuid))
+		.unwrap()
+}
+
+fn time(text: &str) -> DateTime<Utc> {
+	text.parse().unwrap()
+}
+
+#[test]
+fn opening_brings_an_old_schema_up_to_date() {
+	let directory = common::temp_directory();
+	let path = directory.path().join("journal.sqlite");
+	let trace_uuid = Uuid::new_v4();
+	{
+		// A database written when there was only the first schema version
+		let connection = rusqlite::Connection::open(&path).unwrap();
+		connection.execute_batch(sqlite_service::SCHEMA_MIGRATIONS[0]).unwrap();
+		connection.pragma_update(None, "user_version", 1).unwrap();
+		connection
+			.execute(
+				"INSERT INTO traces (trace_uuid, trace_template_uuid, name, created_at) VALUES (?1, ?2, 'Old', 0)",
+				[trace_uuid.to_string(), Uuid::new_v4().to_string()],
+			)
+			.unwrap();
+	}
+
+	let service = SqliteEventsService::open(&path).unwrap();
+	let trace = service.view_trace(trace_uuid).unwrap();
+	assert_eq!(trace.name, "Old");
+	assert!(trace.archived_at.is_none());
+	drop(service);
+
+	let connection = rusqlite::Connection::open(&path).unwrap();
+	let version: usize = connection.query_row("PRAGMA user_version", [], |row| row.get(0)).unwrap();
+	assert_eq!(version, sqlite_service::SCHEMA_MIGRATIONS.len());
+	// Opening again has nothing left to do
+	drop(connection);
+	SqliteEventsService::open(&path).unwrap();
+}
+
+#[test]
+fn a_newer_schema_is_not_opened() {
+	let directory = common::temp_directory();
+	let path = directory.path().join("journal.sqlite");
+	let connection = rusqlite::Connection::open(&path).unwrap();
+	connection
+		.pragma_update(None, "user_version", sqlite_service::SCHEMA_MIGRATIONS.len() as i64 + 1)
+		.unwrap();
+	drop(connection);
+
+	let err = SqliteEventsService::open(&path).err().unwrap();
+	assert!(err.to_string().contains("only knows up to"), "{}", err);
+}
+
+#[test]
+fn traces_are_saved_renamed_completed_and_archived() {
+	let mut service = imported_service();
+	let trace_uuid = record_trace(&mut service, project_uuid());
+	service.rename_trace(trace_uuid, "Sourdough".into()).unwrap();
+	assert_eq!(service.view_trace(trace_uuid).unwrap().name, "Sourdough");
+
+	service.complete_trace(trace_uuid).unwrap();
+	let completed_at = service.view_trace(trace_uuid).unwrap().completion.unwrap().completed_at;
+	// Completing again keeps the first completion
+	std::thread::sleep(std::time::Duration::from_millis(5));
+	service.complete_trace(trace_uuid).unwrap();
+	assert_eq!(service.view_trace(trace_uuid).unwrap().completion.unwrap().completed_at, completed_at);
+	assert_eq!(service.list_traces(None, TraceFilter::Completed).count(), 1);
+
+	service.reopen_trace(trace_uuid).unwrap();
+	service.archive_trace(trace_uuid).unwrap();
+	assert_eq!(service.list_traces(None, TraceFilter::Active).count(), 0);
+	assert_eq!(service.list_traces(None, TraceFilter::Archived).count(), 1);
+	service.unarchive_trace(trace_uuid).unwrap();
+	assert_eq!(service.list_traces(None, TraceFilter::Active).count(), 1);
+
+	assert!(matches!(
+		service.rename_trace(Uuid::new_v4(), "Missing".into()),
+		Err(errors::ChangeTraceError::TraceNotFound(_))
+	));
+	assert!(matches!(
+		service.complete_trace(Uuid::new_v4()),
+		Err(errors::ChangeTraceError::TraceNotFound(_))
+	));
+}
+
+#[test]
+fn events_are_saved_updated_deleted_and_restored() {
+	let mut service = imported_service();
+	let trace_uuid = record_trace(&mut service, project_uuid());
+	let event_uuid = record_event(&mut service, trace_uuid, "bake", time("2024-03-01T08:00:00Z"), &["rye"]);
+
+	let mut event = service.edit_event(event_uuid).ok().unwrap();
+	event.began_at = time("2024-03-01T09:00:00Z");
+	service.update_event(&event).unwrap();
+	let view = service.view_event(event_uuid).unwrap();
+	assert_eq!(view.began_at, time("2024-03-01T09:00:00Z"));
+	assert_eq!(view.revisions.len(), 1);
+
+	service.delete_event(event_uuid).unwrap();
+	assert!(service.view_event(event_uuid).unwrap().deleted_at.is_some());
+	assert_eq!(service.list_events(None).count(), 0);
+	assert!(service.edit_event(event_uuid).is_err());
+
+	assert!(service.undo_event_change(event_uuid).unwrap());
+	assert_eq!(service.list_events(None).count(), 1);
+	assert!(service.delete_event(Uuid::new_v4()).is_err());
+}
+
+#[test]
+fn projects_are_renamed_described_and_duplicated() {
+	let mut service = imported_service();
+	service.rename_project(project_uuid(), "Bread".into()).unwrap();
+	service.describe_project(project_uuid(), "Loaves".into()).unwrap();
+	let project = service.view_project(project_uuid()).unwrap();
+	assert_eq!(project.name, "Bread");
+	assert_eq!(project.description, "Loaves");
+	assert_eq!(project.event_templates.len(), 2);
+
+	let duplicate = service.duplicate_project(project_uuid(), "Bread copy".into()).ok().unwrap();
+	assert_ne!(duplicate.project_uuid, project_uuid());
+	assert_eq!(duplicate.event_templates.len(), 2);
+	assert_eq!(service.list_projects().count(), 2);
+}
+
+#[test]
+fn deleting_a_project_removes_its_events_and_nothing_else() {
+	let mut service = imported_service();
+	let other_project_uuid = Uuid::new_v4();
+	import(&mut service, other_project_uuid, DEFINITION);
+	let trace_uuid = record_trace(&mut service, project_uuid());
+	record_event(&mut service, trace_uuid, "bake", time("2024-03-01T08:00:00Z"), &[]);
+	record_event(&mut service, trace_uuid, "slice", time("2024-03-01T12:00:00Z"), &[]);
+	let other_trace_uuid = record_trace(&mut service, other_project_uuid);
+	let other_trace = service.view_trace(other_trace_uuid).unwrap();
+	let other_event = service
+		.create_event(
+			&other_trace,
+			definition_parser::derive_event_template_uuid(&other_project_uuid, "bake"),
+			HistoryScope::SameTrace,
+		)
+		.ok()
+		.unwrap();
+	service.save_event(&other_event).unwrap();
+
+	// Slice is gone from the definition, its event is still the project's
+	import(&mut service, project_uuid(), &DEFINITION.replace("  - name: Slice\n    trace-template: loaf\n", ""));
+	assert!(service.delete_project(project_uuid(), "Wrong name").is_err());
+	service.delete_project(project_uuid(), "Bakery").unwrap();
+
+	assert!(service.view_project(project_uuid()).is_none());
+	assert_eq!(service.list_traces(None, TraceFilter::All).count(), 1);
+	let events: Vec<Uuid> = service.list_events(None).map(|item| item.event_uuid).collect();
+	assert_eq!(events.len(), 1);
+	assert!(service.view_trace(other_trace_uuid).is_some());
+}
+
+#[test]
+fn events_of_a_removed_template_stay_in_their_project() {
+	let mut service = imported_service();
+	let trace_uuid = record_trace(&mut service, project_uuid());
+	let event_uuid = record_event(&mut service, trace_uuid, "slice", time("2024-03-01T12:00:00Z"), &[]);
+
+	import(&mut service, project_uuid(), "trace-templates:\n  - name: Roll\nevent-templates: []\n");
+	let events: Vec<Uuid> = service.list_events(Some(project_uuid())).map(|item| item.event_uuid).collect();
+	assert_eq!(events, vec![event_uuid]);
+	let traces: Vec<Uuid> = service
+		.list_traces(Some(project_uuid()), TraceFilter::All)
+		.map(|item| item.trace_uuid)
+		.collect();
+	assert_eq!(traces, vec![trace_uuid]);
+	let project = service.view_project(project_uuid()).unwrap();
+	assert_eq!((project.trace_count, project.event_count), (1, 1));
+}
+
+#[test]
+fn deleting_a_project_removes_traces_of_a_removed_template() {
+	let mut service = imported_service();
+	let trace_uuid = record_trace(&mut service, project_uuid());
+	let event_uuid = record_event(&mut service, trace_uuid, "bake", time("2024-03-01T08:00:00Z"), &["rye"]);
+	// Loaf is gone from the definition, the trace only belongs to the project
+	import(&mut service, project_uuid(), "trace-templates:\n  - name: Roll\nevent-templates: []\n");
+
+	service.delete_project(project_uuid(), "Bakery").unwrap();
+	assert!(service.view_trace(trace_uuid).is_none());
+	assert!(service.view_event(event_uuid).is_none());
+	assert!(service.list_events_with_tag("rye").is_empty());
+}
+
+#[test]
+fn events_are_found_by_time_and_tag() {
+	let mut service = imported_service();
+	let trace_uuid = record_trace(&mut service, project_uuid());
+	let early = record_event(&mut service, trace_uuid, "bake", time("2024-03-01T08:00:00Z"), &["rye", "oven"]);
+	let late = record_event(&mut service, trace_uuid, "slice", time("2024-03-02T08:00:00Z"), &["rye"]);
+	let deleted = record_event(&mut service, trace_uuid, "bake", time("2024-03-01T10:00:00Z"), &["oven"]);
+	service.delete_event(deleted).unwrap();
+
+	let between: Vec<Uuid> = service
+		.list_events_between(time("2024-03-01T00:00:00Z"), time("2024-03-02T08:00:00Z"))
+		.iter()
+		.map(|item| item.event_uuid)
+		.collect();
+	assert_eq!(between, vec![early]);
+
+	let with_rye: Vec<Uuid> = service.list_events_with_tag("rye").iter().map(|item| item.event_uuid).collect();
+	assert_eq!(with_rye, vec![early, late]);
+	let with_oven: Vec<Uuid> = service.list_events_with_tag("oven").iter().map(|item| item.event_uuid).collect();
+	assert_eq!(with_oven, vec![early]);
+	assert!(service.list_events_with_tag("wheat").is_empty());
+}
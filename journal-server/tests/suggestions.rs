@@ -0,0 +1,256 @@
+mod common;
+
+use journal_lib::service::EventBuilder;
+use journal_lib::service::EventsService;
+use journal_lib::service::FieldSuggestion;
+use journal_lib::service::FieldValueSuggestion;
+use journal_lib::service::HistoryScope;
+use journal_lib::service::ValuePolicy;
+use journal_server::builders;
+use journal_server::fs_service::FileSystemEventsService;
+
+use uuid::Uuid;
+
+// The value a new event proposes for each field, as its template's value policy says
+
+const DEFINITION: &str = "
+trace-templates:
+  - name: Loaf
+event-templates:
+  - name: Bake
+    trace-template: loaf
+    fields:
+      - type: Number
+        label: Temp
+        default-value: 200
+        value-policy: rolling-median
+      - type: Duration
+        label: Proof
+        value-policy: rolling-median
+      - type: Number
+        label: Weight
+        default-value: 450
+        value-policy: last-in-trace
+      - type: Number
+        label: Hydration
+        value-policy: last-in-template
+      - type: Enumerated
+        label: Flour
+        value-policy: most-frequent-option
+        options:
+          - label: Wheat
+          - label: Rye
+          - label: Spelt
+";
+
+fn new_trace(service: &mut FileSystemEventsService) -> Uuid {
+	common::new_trace(service, common::trace_template_uuid("loaf"))
+}
+
+fn new_event(service: &FileSystemEventsService, trace_uuid: Uuid) -> EventBuilder {
+	new_event_with_history(service, trace_uuid, HistoryScope::SameTrace)
+}
+
+fn new_event_with_history(service: &FileSystemEventsService, trace_uuid: Uuid, history: HistoryScope) -> EventBuilder {
+	let trace = service.view_trace(trace_uuid).unwrap();
+	service.create_event(&trace, common::event_template_uuid("bake"), history).ok().unwrap()
+}
+
+fn field<'a>(event: &'a mut EventBuilder, name: &str) -> &'a mut FieldSuggestion {
+	event.fields.iter_mut().find(|field| field.name == name).unwrap()
+}
+
+fn number<'a>(event: &'a mut EventBuilder, name: &str) -> &'a mut Option<f64> {
+	match &mut field(event, name).value {
+		FieldValueSuggestion::Number(number) => &mut number.value,
+		_ => panic!("{} is a number", name),
+	}
+}
+
+fn last_numbers(event: &mut EventBuilder, name: &str) -> Vec<f64> {
+	match &field(event, name).value {
+		FieldValueSuggestion::Number(number) => number.last_values.clone(),
+		_ => panic!("{} is a number", name),
+	}
+}
+
+fn proof(event: &mut EventBuilder) -> &mut Option<i64> {
+	match &mut field(event, "proof").value {
+		FieldValueSuggestion::Duration(duration) => &mut duration.value,
+		_ => panic!("proof is a duration"),
+	}
+}
+
+fn flour(event: &mut EventBuilder) -> Option<String> {
+	match &field(event, "flour").value {
+		FieldValueSuggestion::Enumerated(enumerated) => {
+			enumerated.selected.as_ref().map(|option| option.name.clone())
+		}
+		_ => panic!("flour is enumerated"),
+	}
+}
+
+fn select_flour(event: &mut EventBuilder, name: &str) {
+	match &mut field(event, "flour").value {
+		FieldValueSuggestion::Enumerated(enumerated) => {
+			enumerated.selected = enumerated.options.iter().find(|option| option.name == name).cloned()
+		}
+		_ => panic!("flour is enumerated"),
+	}
+}
+
+// Saves an event with only the given fields filled in
+fn record(service: &mut FileSystemEventsService, trace_uuid: Uuid, fill: impl FnOnce(&mut EventBuilder)) {
+	let mut event = new_event(service, trace_uuid);
+	for field in event.fields.iter_mut() {
+		match &mut field.value {
+			FieldValueSuggestion::Number(number) => number.value = None,
+			FieldValueSuggestion::Duration(duration) => duration.value = None,
+			FieldValueSuggestion::Enumerated(enumerated) => enumerated.selected = None,
+			_ => {}
+		}
+	}
+	fill(&mut event);
+	service.save_event(&event).unwrap();
+	// Policies look at events newest first
+	std::thread::sleep(std::time::Duration::from_millis(2));
+}
+
+#[test]
+fn without_history_every_field_falls_back_to_its_default() {
+	let directory = common::temp_directory();
+	let mut service = common::new_service(&directory, DEFINITION);
+	let trace_uuid = new_trace(&mut service);
+	let mut event = new_event(&service, trace_uuid);
+	assert_eq!(*number(&mut event, "temp"), Some(200.0));
+	assert_eq!(*number(&mut event, "weight"), Some(450.0));
+	assert_eq!(*number(&mut event, "hydration"), None);
+	assert_eq!(flour(&mut event), None);
+	assert_eq!(field(&mut event, "temp").source, Some(ValuePolicy::TemplateDefault));
+	assert_eq!(field(&mut event, "hydration").source, None);
+}
+
+#[test]
+fn rolling_median_takes_the_middle_value() {
+	let directory = common::temp_directory();
+	let mut service = common::new_service(&directory, DEFINITION);
+	let trace_uuid = new_trace(&mut service);
+	for temp in [260.0, 200.0, 220.0] {
+		record(&mut service, trace_uuid, |event| *number(event, "temp") = Some(temp));
+	}
+	let mut event = new_event(&service, trace_uuid);
+	assert_eq!(*number(&mut event, "temp"), Some(220.0));
+	assert_eq!(field(&mut event, "temp").source, Some(ValuePolicy::RollingMedian));
+
+	// Between the two middle values of an even count
+	record(&mut service, trace_uuid, |event| *number(event, "temp") = Some(240.0));
+	assert_eq!(*number(&mut new_event(&service, trace_uuid), "temp"), Some(230.0));
+}
+
+#[test]
+fn rolling_median_of_durations_is_rounded_to_a_duration() {
+	let directory = common::temp_directory();
+	let mut service = common::new_service(&directory, DEFINITION);
+	let trace_uuid = new_trace(&mut service);
+	for seconds in [60, 91] {
+		record(&mut service, trace_uuid, |event| *proof(event) = Some(seconds));
+	}
+	assert_eq!(*proof(&mut new_event(&service, trace_uuid)), Some(76));
+}
+
+#[test]
+fn last_in_trace_only_looks_at_the_trace() {
+	let directory = common::temp_directory();
+	let mut service = common::new_service(&directory, DEFINITION);
+	let first_trace = new_trace(&mut service);
+	let second_trace = new_trace(&mut service);
+	record(&mut service, first_trace, |event| *number(event, "weight") = Some(500.0));
+	record(&mut service, second_trace, |event| *number(event, "weight") = Some(700.0));
+
+	let mut event = new_event(&service, first_trace);
+	assert_eq!(*number(&mut event, "weight"), Some(500.0));
+	assert_eq!(field(&mut event, "weight").source, Some(ValuePolicy::LastInTrace));
+	// A trace without events of its own gets the default
+	let third_trace = new_trace(&mut service);
+	let mut event = new_event(&service, third_trace);
+	assert_eq!(*number(&mut event, "weight"), Some(450.0));
+	assert_eq!(field(&mut event, "weight").source, Some(ValuePolicy::TemplateDefault));
+}
+
+#[test]
+fn last_in_template_looks_at_every_trace() {
+	let directory = common::temp_directory();
+	let mut service = common::new_service(&directory, DEFINITION);
+	let first_trace = new_trace(&mut service);
+	let second_trace = new_trace(&mut service);
+	record(&mut service, first_trace, |event| *number(event, "hydration") = Some(70.0));
+	record(&mut service, second_trace, |event| *number(event, "hydration") = Some(75.0));
+	// An event that left the field empty is skipped
+	record(&mut service, second_trace, |_| {});
+
+	let third_trace = new_trace(&mut service);
+	let mut event = new_event(&service, third_trace);
+	assert_eq!(*number(&mut event, "hydration"), Some(75.0));
+	assert_eq!(field(&mut event, "hydration").source, Some(ValuePolicy::LastInTemplate));
+}
+
+#[test]
+fn most_frequent_option_wins_and_a_tie_goes_to_the_newest() {
+	let directory = common::temp_directory();
+	let mut service = common::new_service(&directory, DEFINITION);
+	let trace_uuid = new_trace(&mut service);
+	for name in ["rye", "wheat", "rye"] {
+		record(&mut service, trace_uuid, |event| select_flour(event, name));
+	}
+	let mut event = new_event(&service, trace_uuid);
+	assert_eq!(flour(&mut event).as_deref(), Some("rye"));
+	assert_eq!(field(&mut event, "flour").source, Some(ValuePolicy::MostFrequentOption));
+
+	// Two of each, wheat was used last
+	record(&mut service, trace_uuid, |event| select_flour(event, "wheat"));
+	assert_eq!(flour(&mut new_event(&service, trace_uuid)).as_deref(), Some("wheat"));
+}
+
+#[test]
+fn last_values_come_from_the_newest_events_newest_first() {
+	let directory = common::temp_directory();
+	let mut service = common::new_service(&directory, DEFINITION);
+	let trace_uuid = new_trace(&mut service);
+	for hydration in [60.0, 61.0, 62.0, 63.0, 64.0, 65.0, 64.0] {
+		record(&mut service, trace_uuid, |event| *number(event, "hydration") = Some(hydration));
+	}
+	// An event that left the field empty still counts as one of the newest
+	record(&mut service, trace_uuid, |_| {});
+
+	let mut event = new_event(&service, trace_uuid);
+	assert_eq!(builders::LAST_VALUES_EVENTS, 5);
+	// Of empty, 64, 65, 64 and 63, 64 is listed once at its most recent use
+	assert_eq!(last_numbers(&mut event, "hydration"), vec![64.0, 65.0, 63.0]);
+	assert!(field(&mut event, "hydration").value.use_last_value(1));
+	assert_eq!(*number(&mut event, "hydration"), Some(65.0));
+	assert!(!field(&mut event, "hydration").value.use_last_value(3));
+}
+
+#[test]
+fn last_values_come_from_the_trace_or_from_any_trace() {
+	let directory = common::temp_directory();
+	let mut service = common::new_service(&directory, DEFINITION);
+	let first_trace = new_trace(&mut service);
+	let second_trace = new_trace(&mut service);
+	record(&mut service, first_trace, |event| *number(event, "hydration") = Some(70.0));
+	record(&mut service, second_trace, |event| *number(event, "hydration") = Some(75.0));
+	record(&mut service, first_trace, |event| *number(event, "hydration") = Some(72.0));
+
+	let mut same_trace = new_event_with_history(&service, first_trace, HistoryScope::SameTrace);
+	assert_eq!(last_numbers(&mut same_trace, "hydration"), vec![72.0, 70.0]);
+	let mut any_trace = new_event_with_history(&service, first_trace, HistoryScope::AnyTrace);
+	assert_eq!(last_numbers(&mut any_trace, "hydration"), vec![72.0, 75.0, 70.0]);
+
+	// A trace without events only has the others' to go on
+	let third_trace = new_trace(&mut service);
+	assert!(last_numbers(&mut new_event_with_history(&service, third_trace, HistoryScope::SameTrace), "hydration").is_empty());
+	assert_eq!(
+		last_numbers(&mut new_event_with_history(&service, third_trace, HistoryScope::AnyTrace), "hydration"),
+		vec![72.0, 75.0, 70.0]
+	);
+}
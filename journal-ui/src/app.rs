@@ -2,10 +2,14 @@ use chrono::Datelike;
 use chrono::Timelike;
 
 use journal_lib::service;
+use journal_server::config;
 use journal_server::fs_service;
+use journal_server::sqlite_service;
 
 use journal_lib::views;
 
+use crate::import_dialog;
+
 
 fn format_a_time(time: &chrono::DateTime<chrono::Utc>) -> String {
 	let current_time = chrono::Utc::now();
@@ -44,6 +48,8 @@ enum Tab {
 #[serde(default)]
 pub struct TemplateApp {
 	project_uuid: Option<uuid::Uuid>,
+	// Where new events take the last values of their fields from
+	history_scope: service::HistoryScope,
 
 	#[serde(skip)]
 	service: Box<dyn service::EventsService>,
@@ -63,26 +69,84 @@ pub struct TemplateApp {
 	trace_index: Option<usize>,
 	#[serde(skip)]
 	trace_we_viewing: Option<views::TraceView>,
+	#[serde(skip)]
+	trace_filter: service::TraceFilter,
+	#[serde(skip)]
+	trace_name_we_editing: String,
+
+	// Shown until dismissed when loading skipped projects or had conflicts
+	#[serde(skip)]
+	load_report: Option<views::LoadReport>,
+	#[serde(skip)]
+	import_dialog: Option<import_dialog::ImportDialog>,
 }
 
-impl Default for TemplateApp {
-	fn default() -> Self {
-		let mut service = fs_service::FileSystemEventsService::default();
+// Starts out empty when the journal cannot be read, the error is logged
+fn load_service(
+	data_location: &config::DataLocation,
+) -> (Box<dyn service::EventsService>, Option<views::LoadReport>) {
+	let mut service = match open_service(data_location) {
+		Ok(service) => service,
+		Err(err) => {
+			log::error!("Failed to open the journal: {}", err);
+			Box::new(fs_service::FileSystemEventsService::default())
+		}
+	};
+	let load_report = load(service.as_mut(), service::LoadMode::Replace);
+	(service, load_report)
+}
 
-		// if let Err(err) = service.load_from_disk(&projects_directory) {
-		// 	log::error!("Failed to load from disk: {}", err);
-		// 	panic!();
-		// }
-		// println!("Loaded from disk");
+fn open_service(data_location: &config::DataLocation) -> Result<Box<dyn service::EventsService>, std::io::Error> {
+	let mut files = fs_service::FileSystemEventsService::new(data_location.projects_directory()?);
+	files.storage_mode = fs_service::StorageMode::Log;
+	match data_location.backend {
+		config::Backend::Files => Ok(Box::new(files)),
+		config::Backend::Sqlite => {
+			let database_path = data_location.database_path()?;
+			let is_new = !database_path.exists();
+			if let Some(parent) = database_path.parent() {
+				std::fs::create_dir_all(parent)?;
+			}
+			let mut database = sqlite_service::SqliteEventsService::open(&database_path)?;
+			// A new database starts out with the journal kept in files so far
+			if is_new {
+				service::EventsService::load_from_disk(&mut files, service::LoadMode::Replace)?;
+				database.copy_from(&files)?;
+			}
+			Ok(Box::new(database))
+		}
+	}
+}
 
+fn load(service: &mut dyn service::EventsService, mode: service::LoadMode) -> Option<views::LoadReport> {
+	match service.load_from_disk(mode) {
+		Ok(report) => {
+			log::info!("{}", report);
+			Some(report).filter(|report| report.has_problems())
+		}
+		Err(err) => {
+			log::error!("Failed to load from disk: {}", err);
+			None
+		}
+	}
+}
+
+impl Default for TemplateApp {
+	fn default() -> Self {
+		// The journal is opened by new(), once it is known where it is
 		Self {
 			tab: Tab::Traces,
-			service: Box::new(service),
+			service: Box::new(fs_service::FileSystemEventsService::default()),
+			load_report: None,
 			project_uuid: None,
+			history_scope: service::HistoryScope::AnyTrace,
 			event_we_building: Vec::new(),
 			event_we_viewing: None,
 			trace_we_building: Vec::new(),
 			trace_we_viewing: None,
+			trace_filter: service::TraceFilter::Active,
+			trace_name_we_editing: String::new(),
+			import_dialog: None,
 			event_index: None,
 			trace_index: None,
 		}
@@ -91,12 +155,53 @@ impl Default for TemplateApp {
 
 impl TemplateApp {
 	/// Called once before the first frame.
-	pub fn new(cc: &eframe::CreationContext<'_>) -> Self {
+	pub fn new(cc: &eframe::CreationContext<'_>, data_location: config::DataLocation) -> Self {
 		cc.egui_ctx.set_pixels_per_point(1.0);
-		if let Some(storage) = cc.storage {
-			return eframe::get_value(storage, eframe::APP_KEY).unwrap_or_default();
+		let mut app: Self = match cc.storage {
+			Some(storage) => eframe::get_value(storage, eframe::APP_KEY).unwrap_or_default(),
+			None => Default::default(),
+		};
+		let (service, load_report) = load_service(&data_location);
+		app.service = service;
+		app.load_report = load_report;
+		app.forget_missing_project();
+		app
+	}
+
+	// The remembered project may be gone after loading another journal
+	fn forget_missing_project(&mut self) {
+		if let Some(project_uuid) = self.project_uuid {
+			if self.service.view_project(project_uuid).is_none() {
+				self.select_project(None);
+			}
+		}
+	}
+
+	fn select_project(&mut self, project_uuid: Option<uuid::Uuid>) {
+		self.project_uuid = project_uuid;
+		// What is on view may belong to another project
+		self.event_we_viewing = None;
+		self.trace_we_viewing = None;
+	}
+
+	fn draw_project_selector(&mut self, ui: &mut egui::Ui) {
+		let projects: Vec<views::ProjectItemView> = self.service.list_projects().collect();
+		let selected_name = self
+			.project_uuid
+			.and_then(|project_uuid| projects.iter().find(|project| project.project_uuid == project_uuid))
+			.map_or("All projects".to_string(), |project| project.name.clone());
+		let mut project_uuid = self.project_uuid;
+		egui::ComboBox::from_label("Project")
+			.selected_text(selected_name)
+			.show_ui(ui, |ui| {
+				ui.selectable_value(&mut project_uuid, None, "All projects");
+				for project in projects.iter() {
+					ui.selectable_value(&mut project_uuid, Some(project.project_uuid), &project.name);
+				}
+			});
+		if project_uuid != self.project_uuid {
+			self.select_project(project_uuid);
 		}
-		Default::default()
 	}
 
 	fn draw_event_templates_side_panel(&mut self, ui: &mut egui::Ui) {
@@ -110,6 +215,9 @@ impl TemplateApp {
 					trace_template: None,
 					tags: Vec::new(),
 					completion: None,
+					archived_at: None,
+					origin_traces: Vec::new(),
+					child_traces: Vec::new(),
 					last_event: None,
 					suggested_event_templates: Vec::new(),
 					other_event_templates: Vec::new(),
@@ -117,7 +225,9 @@ impl TemplateApp {
 				ui.label(&entry.name);
 
 				if ui.button("Create").clicked() {
-					if let Ok(event) = self.service.create_event(&trace, entry.event_template_uuid)
+					// There is no trace yet to take last values from
+					if let Ok(event) =
+						self.service.create_event(&trace, entry.event_template_uuid, service::HistoryScope::AnyTrace)
 					{
 						self.event_we_building.push(event);
 						self.event_index = Some(self.event_we_building.len() - 1);
@@ -179,8 +289,21 @@ impl TemplateApp {
 	fn draw_traces_side_panel(&mut self, ui: &mut egui::Ui) {
 		ui.heading("Traces");
 
+		egui::ComboBox::from_label("Show")
+			.selected_text(self.trace_filter.label())
+			.show_ui(ui, |ui| {
+				for filter in [
+					service::TraceFilter::Active,
+					service::TraceFilter::Completed,
+					service::TraceFilter::Archived,
+					service::TraceFilter::All,
+				] {
+					ui.selectable_value(&mut self.trace_filter, filter, filter.label());
+				}
+			});
+
 		egui::Grid::new("traces").show(ui, |ui| {
-			for entry in self.service.list_traces(self.project_uuid) {
+			for entry in self.service.list_traces(self.project_uuid, self.trace_filter) {
 				if let Some(template_name) = &entry.template_name {
 					ui.label(template_name);
 				} else {
@@ -189,6 +312,7 @@ impl TemplateApp {
 				ui.label(entry.name.clone());
 				if ui.button("View").clicked() {
 					self.trace_we_viewing = self.service.view_trace(entry.trace_uuid);
+					self.trace_name_we_editing = entry.name.clone();
 				}
 				ui.end_row();
 			}
@@ -221,9 +345,18 @@ impl TemplateApp {
 		let mut remove = false;
 		if let Some(event_index) = self.event_index {
 			if let Some(event) = self.event_we_building.get_mut(event_index) {
-				ui.heading(&event.event_template.name);
+				if event.editing {
+					ui.heading(format!("Editing {}", event.event_template.name));
+				} else {
+					ui.heading(&event.event_template.name);
+				}
 				ui.separator();
 				ui.label(format_a_time(&event.began_at));
+				if event.fields.iter().any(|field| field.value.has_last_values())
+					&& ui.button("Fill all from last event").clicked()
+				{
+					event.fill_from_last_values();
+				}
 
 				egui::Grid::new("889de043-d0ce-4d8a-9c5c-76f952e0d3f2").show(ui, |ui| {
 					for field in event.fields.iter_mut() {
@@ -234,7 +367,7 @@ impl TemplateApp {
 
 				let traces = self
 					.service
-					.list_traces(self.project_uuid)
+					.list_traces(self.project_uuid, service::TraceFilter::Active)
 					.collect::<Vec<views::TraceItemView>>();
 
 				if traces.len() > 0 {
@@ -257,8 +390,20 @@ impl TemplateApp {
 				ui.separator();
 				ui.horizontal(|ui| {
 					if ui.button("Save").clicked() {
-						self.service.save_event(event);
-						remove = true;
+						if event.editing {
+							match self.service.update_event(event) {
+								Ok(()) => {
+									self.event_we_viewing = self.service.view_event(event.uuid);
+									remove = true;
+								}
+								Err(err) => log::error!("Failed to update event: {:?}", err),
+							}
+						} else {
+							match self.service.save_event(event) {
+								Ok(()) => remove = true,
+								Err(err) => log::error!("Failed to save event: {:?}", err),
+							}
+						}
 					}
 					if ui.button("Cancel").clicked() {
 						remove = true;
@@ -327,30 +472,63 @@ impl TemplateApp {
 				for field in &entry.fields {
 					ui.label(field.label.clone());
 					if let Some(value) = &field.value {
-						match value {
-							views::FieldValueView::Text(text) => {
-								// remove option on the value? or remove the option of the value
-								ui.label(&text.value.as_ref().cloned().unwrap());
-							}
-							views::FieldValueView::Number(number) => {
-								ui.label(&number.value.unwrap().to_string());
-							}
-							views::FieldValueView::Bool(boolean) => {
-								ui.label(&boolean.value.unwrap().to_string());
-							}
-							views::FieldValueView::Enumerated(enumerated) => {
-								ui.label(enumerated.label.clone());
-							}
-						}
+						show_field_value_view(ui, &field.name, value);
 					}
 					ui.end_row();
 				}
 			});
 
-			// ui.separator();
-			// if ui.button("Delete").clicked() {
-			// 	// self.service.delete_event(entry.uuid);
-			// }
+			if let Some(deleted_at) = &entry.deleted_at {
+				ui.separator();
+				ui.label(format!("Deleted at {}", format_a_time(deleted_at)));
+			}
+			if !entry.revisions.is_empty() {
+				ui.separator();
+				ui.label("Earlier versions");
+				egui::Grid::new("event_revisions").show(ui, |ui| {
+					for revision in entry.revisions.iter().rev() {
+						ui.label(format!("Replaced at {}", format_a_time(&revision.replaced_at)));
+						ui.label(format!("Began at {}", format_a_time(&revision.began_at)));
+						ui.label(format!("{} fields", revision.fields.len()));
+						ui.end_row();
+					}
+				});
+			}
+
+			ui.separator();
+			let event_uuid = entry.event_uuid;
+			let mut changed = false;
+			ui.horizontal(|ui| {
+				if entry.deleted_at.is_none() {
+					if ui.button("Edit").clicked() {
+						match self.service.edit_event(event_uuid) {
+							Ok(builder) => {
+								self.event_we_building.push(builder);
+								self.event_index = Some(self.event_we_building.len() - 1);
+								self.tab = Tab::EventTemplates;
+							}
+							Err(err) => log::error!("Failed to edit event: {:?}", err),
+						}
+					}
+					if ui.button("Delete").clicked() {
+						if let Err(err) = self.service.delete_event(event_uuid) {
+							log::error!("Failed to delete event: {:?}", err);
+						}
+						changed = true;
+					}
+				}
+				if (entry.deleted_at.is_some() || !entry.revisions.is_empty())
+					&& ui.button("Undo").on_hover_text("Undo the last change").clicked()
+				{
+					if let Err(err) = self.service.undo_event_change(event_uuid) {
+						log::error!("Failed to undo: {:?}", err);
+					}
+					changed = true;
+				}
+			});
+			if changed {
+				self.event_we_viewing = self.service.view_event(event_uuid);
+			}
 		}
 	}
 
@@ -376,8 +554,10 @@ impl TemplateApp {
 				});
 				ui.separator();
 				if ui.button("Create").clicked() {
-					self.service.save_trace(builder);
-					removed = true;
+					match self.service.save_trace(builder) {
+						Ok(()) => removed = true,
+						Err(err) => log::error!("Failed to save trace: {:?}", err),
+					}
 				}
 				if ui.button("Cancel").clicked() {
 					removed = true;
@@ -385,7 +565,7 @@ impl TemplateApp {
 
 				let mut traces_included = Vec::new();
 				let mut traces_excluded = Vec::new();
-				for trace in self.service.list_traces(self.project_uuid) {
+				for trace in self.service.list_traces(self.project_uuid, service::TraceFilter::Active) {
 					if builder.origin_traces.contains(&trace) {
 						traces_included.push(trace);
 					} else {
@@ -406,7 +586,7 @@ impl TemplateApp {
 
 				if traces_excluded.len() > 0 {
 					egui::ComboBox::from_label("Add trace origin")
-						.selected_text(format!("{:?}", builder.selected_trace))
+						.selected_text(builder.selected_trace.label())
 						.show_ui(ui, |ui| {
 							for trace in traces_excluded {
 								ui.selectable_value(
@@ -463,6 +643,81 @@ impl TemplateApp {
 			if let Some(completion) = &entry.completion {
 				ui.label(format_a_time(&completion.completed_at));
 			}
+			if let Some(archived_at) = &entry.archived_at {
+				ui.label(format!("Archived {}", format_a_time(archived_at)));
+			}
+
+			let mut trace_to_view = None;
+			if !entry.origin_traces.is_empty() {
+				ui.separator();
+				ui.label("Origin traces");
+				egui::Grid::new("origin_traces").show(ui, |ui| {
+					for trace in &entry.origin_traces {
+						ui.label(trace.template_name.clone().unwrap_or("Missing template".into()));
+						ui.label(&trace.name);
+						if ui.button("View").clicked() {
+							trace_to_view = Some(trace.trace_uuid);
+						}
+						ui.end_row();
+					}
+				});
+			}
+			if !entry.child_traces.is_empty() {
+				ui.separator();
+				ui.label("Child traces");
+				egui::Grid::new("child_traces").show(ui, |ui| {
+					for trace in &entry.child_traces {
+						ui.label(trace.template_name.clone().unwrap_or("Missing template".into()));
+						ui.label(&trace.name);
+						if ui.button("View").clicked() {
+							trace_to_view = Some(trace.trace_uuid);
+						}
+						ui.end_row();
+					}
+				});
+			}
+			egui::CollapsingHeader::new("Lineage").show(ui, |ui| {
+				for (heading, direction) in [
+					("Ancestors", service::LineageDirection::Ancestors),
+					("Descendants", service::LineageDirection::Descendants),
+				] {
+					let Ok(lineage) = self.service.view_lineage(entry.trace_uuid, direction) else {
+						continue;
+					};
+					ui.label(heading);
+					egui::Grid::new(heading).show(ui, |ui| {
+						for relative in &lineage.traces {
+							ui.label(relative.distance.to_string());
+							ui.label(&relative.trace.name);
+							if ui.button("View").clicked() {
+								trace_to_view = Some(relative.trace.trace_uuid);
+							}
+							ui.end_row();
+						}
+					});
+					for cycle in &lineage.cycles {
+						ui.colored_label(
+							egui::Color32::RED,
+							format!(
+								"Origin cycle: {}",
+								cycle
+									.iter()
+									.map(|trace| trace.name.clone())
+									.collect::<Vec<String>>()
+									.join(" -> ")
+							),
+						);
+					}
+				}
+			});
+			if let Some(trace_uuid) = trace_to_view {
+				self.trace_we_viewing = self.service.view_trace(trace_uuid);
+				self.trace_name_we_editing =
+					self.trace_we_viewing.as_ref().map_or(String::new(), |trace| trace.name.clone());
+				return;
+			}
+
+			ui.separator();
 			if let Some(last_event) = &entry.last_event {
 				ui.label("Last event");
 				ui.label(
@@ -478,13 +733,21 @@ impl TemplateApp {
 			}
 
 			ui.separator();
+			let mut same_trace = self.history_scope == service::HistoryScope::SameTrace;
+			if ui.checkbox(&mut same_trace, "Last values from this trace only").changed() {
+				self.history_scope = if same_trace {
+					service::HistoryScope::SameTrace
+				} else {
+					service::HistoryScope::AnyTrace
+				};
+			}
 			ui.label("Suggested events");
 			egui::Grid::new("suggested_events").show(ui, |ui| {
 				for event in &entry.suggested_event_templates {
 					ui.label(&event.name);
 					if ui.button("Create").clicked() {
 						if let Ok(event) =
-							self.service.create_event(&entry, event.event_template_uuid)
+							self.service.create_event(entry, event.event_template_uuid, self.history_scope)
 						{
 							self.event_we_building.push(event);
 							self.tab = Tab::EventTemplates;
@@ -502,7 +765,7 @@ impl TemplateApp {
 					ui.label(&event.name);
 					if ui.button("Create").clicked() {
 						if let Ok(event) =
-							self.service.create_event(&entry, event.event_template_uuid)
+							self.service.create_event(entry, event.event_template_uuid, self.history_scope)
 						{
 							self.event_we_building.push(event);
 							self.tab = Tab::EventTemplates;
@@ -513,9 +776,51 @@ impl TemplateApp {
 				}
 			});
 
-			if ui.button("Complete").clicked() {
-				self.service.complete_trace(entry.trace_uuid);
-				self.trace_we_viewing = None;
+			ui.separator();
+			let trace_uuid = entry.trace_uuid;
+			let mut changed = false;
+			ui.horizontal(|ui| {
+				if entry.completion.is_none() {
+					if ui.button("Complete").clicked() {
+						changed |= self.service.complete_trace(trace_uuid).is_ok();
+					}
+				} else if ui.button("Reopen").clicked() {
+					changed |= self.service.reopen_trace(trace_uuid).is_ok();
+				}
+				if entry.archived_at.is_none() {
+					if ui.button("Archive").clicked() {
+						changed |= self.service.archive_trace(trace_uuid).is_ok();
+					}
+				} else if ui.button("Unarchive").clicked() {
+					changed |= self.service.unarchive_trace(trace_uuid).is_ok();
+				}
+			});
+			ui.horizontal(|ui| {
+				ui.text_edit_singleline(&mut self.trace_name_we_editing);
+				if ui.button("Rename").clicked() && !self.trace_name_we_editing.is_empty() {
+					changed |= self
+						.service
+						.rename_trace(trace_uuid, self.trace_name_we_editing.clone())
+						.is_ok();
+				}
+			});
+			ui.menu_button("Change template", |ui| {
+				for trace_template in self.service.list_trace_templates(self.project_uuid) {
+					if ui.button(&trace_template.name).clicked() {
+						match self
+							.service
+							.change_trace_template(trace_uuid, trace_template.trace_template_uuid)
+						{
+							Ok(()) => changed = true,
+							Err(err) => log::error!("Failed to change the trace template: {:?}", err),
+						}
+						ui.close_menu();
+					}
+				}
+			});
+
+			if changed {
+				self.trace_we_viewing = self.service.view_trace(trace_uuid);
 			}
 		}
 	}
@@ -529,6 +834,46 @@ impl TemplateApp {
 		});
 	}
 
+	fn draw_load_report(&mut self, ctx: &egui::Context) {
+		let Some(report) = &self.load_report else {
+			return;
+		};
+		let mut open = true;
+		egui::Window::new("Loaded from disk").open(&mut open).show(ctx, |ui| {
+			ui.label(format!("Loaded {} projects", report.loaded_projects.len()));
+			for skipped in report.skipped_projects.iter() {
+				ui.label(format!("Skipped {}: {}", skipped.directory, skipped.reason));
+			}
+			for conflict in report.conflicts.iter() {
+				ui.label(format!(
+					"Kept the copy in memory of {} events and {} traces of project {}{}",
+					conflict.events,
+					conflict.traces,
+					conflict.project_uuid,
+					if conflict.definition_differs { " and its definition" } else { "" }
+				));
+			}
+		});
+		if !open {
+			self.load_report = None;
+		}
+	}
+
+	fn draw_import_dialog(&mut self, ctx: &egui::Context) {
+		let Some(import_dialog) = &mut self.import_dialog else {
+			return;
+		};
+		match import_dialog.show(ctx, self.service.as_mut()) {
+			import_dialog::ImportDialogState::Open => {}
+			import_dialog::ImportDialogState::Closed => self.import_dialog = None,
+			import_dialog::ImportDialogState::Imported(report) => {
+				log::info!("Imported definition:\n{}", report.changes);
+				self.import_dialog = None;
+				self.select_project(Some(report.project_uuid));
+			}
+		}
+	}
+
 	fn draw_top_panel(&mut self, ctx: &egui::Context) {
 		egui::TopBottomPanel::top("top_panel").show(ctx, |ui| {
 			egui::menu::bar(ui, |ui| {
@@ -547,20 +892,17 @@ impl TemplateApp {
 								log::error!("Failed to write to disk: {}", err);
 							}
 						}
+						// Reads the journal again in place of what is in memory
 						if ui.button("Load").clicked() {
-							if let Err(err) = self.service.load_from_disk() {
-								log::error!("Failed to write to disk: {}", err);
-							}
+							self.load_report = load(self.service.as_mut(), service::LoadMode::Replace);
+							self.forget_missing_project();
+						}
+						if ui.button("Merge from disk").clicked() {
+							self.load_report = load(self.service.as_mut(), service::LoadMode::Merge);
 						}
 						if ui.button("Import").clicked() {
-							let definition = &std::path::PathBuf::from(
-								"/work/projects/journal/template/eframe_template/projects/bread/definition.json");
-
-							if let Err(err) =
-								self.service.import_definition("Bread".into(), &definition)
-							{
-								log::error!("Failed to import definition: {:?}", err);
-							}
+							self.import_dialog = Some(import_dialog::ImportDialog::new());
+							ui.close_menu();
 						}
 					});
 					ui.add_space(16.0);
@@ -581,6 +923,8 @@ impl TemplateApp {
 						self.tab = Tab::Events;
 					}
 				});
+				ui.add_space(16.0);
+				self.draw_project_selector(ui);
 
 				// egui::widgets::global_dark_light_mode_buttons(ui);
 			});
@@ -590,7 +934,60 @@ impl TemplateApp {
 
 fn show_field(ui: &mut egui::Ui, field: &mut service::FieldSuggestion) {
 	ui.label(&field.name);
-	match &mut field.value {
+	show_field_value(ui, &field.name, &mut field.value);
+	if let Some(source) = field.source {
+		ui.weak(source.label());
+	}
+	let last_values = field.value.last_value_labels();
+	if !last_values.is_empty() {
+		ui.menu_button("Use last", |ui| {
+			for (index, label) in last_values.iter().enumerate() {
+				if ui.button(label).clicked() {
+					field.value.use_last_value(index);
+					field.source = None;
+					ui.close_menu();
+				}
+			}
+		});
+	}
+	// match field.value {
+	// 	events::FieldValue::Text(text) => {
+	// 		ui.horizontal(|ui| {
+	// 			ui.label(&text.name);
+	// 			if let Some(value) = &mut text.value {
+	// 				ui.text_edit_singleline(value);
+	// 			}
+	// 		});
+	// 	}
+	// 	events::Field::Number(number) => {
+	// 		ui.horizontal(|ui| {
+	// 			ui.label(&number.name);
+	// 			if let Some(value) = &mut number.value {
+	// 				ui.add(egui::widgets::DragValue::new(value));
+	// 			}
+	// 		});
+	// 	}
+	// 	events::Field::Bool(boolean) => {
+	// 		ui.horizontal(|ui| {
+	// 			// ui.label();
+	// 			if let Some(value) = &mut boolean.value {
+	// 				ui.checkbox(value, &boolean.name);
+	// 			}
+	// 		});
+	// 	}
+	// 	events::Field::Enumerated(enumerated) => {
+	// 		ui.horizontal(|ui| {
+	// 			ui.label(&enumerated.name);
+	// 			ui.label("TODO");
+	// 			// ui.checkbox(&mut enumerated.value.unwrap_or(false), "");
+	// 		});
+	// 	}
+	// }
+}
+
+// Names the value's widgets, the rows of an array field by their number
+fn show_field_value(ui: &mut egui::Ui, name: &str, value: &mut service::FieldValueSuggestion) {
+	match value {
 		service::FieldValueSuggestion::Text(text) => {
 			if let Some(value) = &mut text.value {
 				ui.text_edit_singleline(value);
@@ -631,7 +1028,7 @@ fn show_field(ui: &mut egui::Ui, field: &mut service::FieldSuggestion) {
 			}
 		}
 		service::FieldValueSuggestion::Enumerated(enumerated) => {
-			egui::ComboBox::from_label(format!("Select {}", &field.name))
+			egui::ComboBox::from_label(format!("Select {}", name))
 				.selected_text(match &enumerated.selected {
 					Some(selection) => selection.label.clone(),
 					None => "None".to_string(),
@@ -648,40 +1045,207 @@ fn show_field(ui: &mut egui::Ui, field: &mut service::FieldSuggestion) {
 					});
 				});
 		}
+		service::FieldValueSuggestion::DateTime(date_time) => {
+			if let Some(value) = &mut date_time.value {
+				let local = value.with_timezone(&chrono::Local);
+				let mut date = local.date_naive();
+				let mut time = local.time();
+				edit_date(ui, &mut date);
+				edit_time_of_day(ui, &mut time);
+				if date != local.date_naive() || time != local.time() {
+					if let Some(edited) = date.and_time(time).and_local_timezone(chrono::Local).earliest() {
+						*value = edited.with_timezone(&chrono::Utc);
+					}
+				}
+				if ui.button("Now").clicked() {
+					*value = chrono::Utc::now();
+				}
+				if ui.button("Clear").clicked() {
+					date_time.value = None;
+				}
+			} else {
+				ui.label("No value");
+				if ui.button("Set").clicked() {
+					date_time.value = Some(date_time.default_value.unwrap_or_else(chrono::Utc::now));
+				}
+			}
+		}
+		service::FieldValueSuggestion::Date(date) => {
+			if let Some(value) = &mut date.value {
+				edit_date(ui, value);
+				if ui.button("Today").clicked() {
+					*value = chrono::Local::now().date_naive();
+				}
+				if ui.button("Clear").clicked() {
+					date.value = None;
+				}
+			} else {
+				ui.label("No value");
+				if ui.button("Set").clicked() {
+					date.value = Some(date.default_value.unwrap_or_else(|| chrono::Local::now().date_naive()));
+				}
+			}
+		}
+		service::FieldValueSuggestion::TimeOfDay(time_of_day) => {
+			if let Some(value) = &mut time_of_day.value {
+				edit_time_of_day(ui, value);
+				if ui.button("Clear").clicked() {
+					time_of_day.value = None;
+				}
+			} else {
+				ui.label("No value");
+				if ui.button("Set").clicked() {
+					time_of_day.value = Some(time_of_day.default_value.unwrap_or_default());
+				}
+			}
+		}
+		service::FieldValueSuggestion::Duration(duration) => {
+			if let Some(value) = &mut duration.value {
+				let mut hours = *value / 3600;
+				let mut minutes = *value % 3600 / 60;
+				ui.add(egui::widgets::DragValue::new(&mut hours).range(0..=i64::MAX).suffix("h"));
+				ui.add(egui::widgets::DragValue::new(&mut minutes).range(0..=59).suffix("m"));
+				// Seconds only come from the definition or earlier events, keep them
+				*value = hours * 3600 + minutes * 60 + *value % 60;
+				if ui.button("Clear").clicked() {
+					duration.value = None;
+				}
+			} else {
+				ui.label("No value");
+				if ui.button("Set").clicked() {
+					duration.value = Some(duration.default_value.unwrap_or(0));
+				}
+			}
+		}
+		service::FieldValueSuggestion::Array(array) => {
+			show_array(ui, name, array);
+		}
+		service::FieldValueSuggestion::Group(group) => {
+			egui::CollapsingHeader::new(name).default_open(true).show(ui, |ui| {
+				egui::Grid::new(name).show(ui, |ui| {
+					for field in group.fields.iter_mut() {
+						show_field(ui, field);
+						ui.end_row();
+					}
+				});
+			});
+		}
+	}
+}
+
+fn edit_date(ui: &mut egui::Ui, date: &mut chrono::NaiveDate) {
+	let mut year = date.year();
+	let mut month = date.month();
+	let mut day = date.day();
+	ui.add(egui::widgets::DragValue::new(&mut year));
+	ui.add(egui::widgets::DragValue::new(&mut month).range(1..=12));
+	ui.add(egui::widgets::DragValue::new(&mut day).range(1..=31));
+	// Days past the end of the month fall back to its last day
+	if let Some(edited) = (1..=day)
+		.rev()
+		.find_map(|day| chrono::NaiveDate::from_ymd_opt(year, month, day))
+	{
+		*date = edited;
+	}
+}
+
+fn edit_time_of_day(ui: &mut egui::Ui, time: &mut chrono::NaiveTime) {
+	let mut hour = time.hour();
+	let mut minute = time.minute();
+	ui.add(egui::widgets::DragValue::new(&mut hour).range(0..=23));
+	ui.add(egui::widgets::DragValue::new(&mut minute).range(0..=59));
+	if let Some(edited) = time.with_hour(hour).and_then(|time| time.with_minute(minute)) {
+		*time = edited;
+	}
+}
+
+fn show_array(ui: &mut egui::Ui, name: &str, array: &mut service::ArraySuggestion) {
+	let mut moved = None;
+	let mut removed = None;
+	let rows = array.items.len();
+	ui.vertical(|ui| {
+		for (index, item) in array.items.iter_mut().enumerate() {
+			ui.horizontal(|ui| {
+				show_field_value(ui, &format!("{} {}", name, index + 1), item);
+				if ui.add_enabled(index > 0, egui::Button::new("Up")).clicked() {
+					moved = Some((index, index - 1));
+				}
+				if ui.add_enabled(index + 1 < rows, egui::Button::new("Down")).clicked() {
+					moved = Some((index, index + 1));
+				}
+				if ui.button("Remove").clicked() {
+					removed = Some(index);
+				}
+			});
+		}
+		if ui.button("Add").clicked() {
+			array.add_item();
+		}
+	});
+	if let Some((from, to)) = moved {
+		array.move_item(from, to);
+	}
+	if let Some(index) = removed {
+		array.remove_item(index);
+	}
+}
+
+fn show_field_value_view(ui: &mut egui::Ui, name: &str, value: &views::FieldValueView) {
+	match value {
+		// An empty value shows as an empty label, like the other kinds of values below
+		views::FieldValueView::Text(text) => {
+			ui.label(text.value.clone().unwrap_or_default());
+		}
+		views::FieldValueView::Number(number) => {
+			ui.label(number.value.map(|value| value.to_string()).unwrap_or_default());
+		}
+		views::FieldValueView::Bool(boolean) => {
+			ui.label(boolean.value.map(|value| value.to_string()).unwrap_or_default());
+		}
+		views::FieldValueView::Enumerated(enumerated) => {
+			ui.label(enumerated.label.clone());
+		}
+		views::FieldValueView::DateTime(date_time) => {
+			ui.label(
+				date_time
+					.value
+					.map(|value| value.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M").to_string())
+					.unwrap_or_default(),
+			);
+		}
+		views::FieldValueView::Date(date) => {
+			ui.label(date.value.map(|value| value.to_string()).unwrap_or_default());
+		}
+		views::FieldValueView::TimeOfDay(time_of_day) => {
+			ui.label(time_of_day.value.as_ref().map(views::format_time_of_day).unwrap_or_default());
+		}
+		views::FieldValueView::Duration(duration) => {
+			ui.label(duration.value.map(views::format_duration).unwrap_or_default());
+		}
+		views::FieldValueView::Array(array) => {
+			ui.vertical(|ui| {
+				for (index, item) in array.items.iter().enumerate() {
+					show_field_value_view(ui, &format!("{} {}", name, index + 1), item);
+				}
+			});
+		}
+		views::FieldValueView::Group(group) => {
+			egui::CollapsingHeader::new(format!("{} fields", group.fields.len()))
+				.id_source(name)
+				.default_open(true)
+				.show(ui, |ui| {
+					egui::Grid::new(name).show(ui, |ui| {
+						for field in group.fields.iter() {
+							ui.label(field.label.clone());
+							if let Some(value) = &field.value {
+								show_field_value_view(ui, &field.name, value);
+							}
+							ui.end_row();
+						}
+					});
+				});
+		}
 	}
-	// match field.value {
-	// 	events::FieldValue::Text(text) => {
-	// 		ui.horizontal(|ui| {
-	// 			ui.label(&text.name);
-	// 			if let Some(value) = &mut text.value {
-	// 				ui.text_edit_singleline(value);
-	// 			}
-	// 		});
-	// 	}
-	// 	events::Field::Number(number) => {
-	// 		ui.horizontal(|ui| {
-	// 			ui.label(&number.name);
-	// 			if let Some(value) = &mut number.value {
-	// 				ui.add(egui::widgets::DragValue::new(value));
-	// 			}
-	// 		});
-	// 	}
-	// 	events::Field::Bool(boolean) => {
-	// 		ui.horizontal(|ui| {
-	// 			// ui.label();
-	// 			if let Some(value) = &mut boolean.value {
-	// 				ui.checkbox(value, &boolean.name);
-	// 			}
-	// 		});
-	// 	}
-	// 	events::Field::Enumerated(enumerated) => {
-	// 		ui.horizontal(|ui| {
-	// 			ui.label(&enumerated.name);
-	// 			ui.label("TODO");
-	// 			// ui.checkbox(&mut enumerated.value.unwrap_or(false), "");
-	// 		});
-	// 	}
-	// }
 }
 
 impl eframe::App for TemplateApp {
@@ -695,5 +1259,7 @@ impl eframe::App for TemplateApp {
 		self.draw_top_panel(ctx);
 		self.draw_side_panel(ctx);
 		self.draw_main_panel(ctx);
+		self.draw_load_report(ctx);
+		self.draw_import_dialog(ctx);
 	}
 }
@@ -0,0 +1,292 @@
+use journal_lib::errors;
+use journal_lib::service;
+use journal_lib::views;
+
+use std::path::Path;
+use std::path::PathBuf;
+
+// Directories and definition files, or why the directory could not be read
+type Listing = Result<(Vec<PathBuf>, Vec<PathBuf>), String>;
+
+// Picks a definition file, names the project and shows what importing it would do before
+// anything changes. Problems with the file are listed in the dialog.
+pub struct ImportDialog {
+	// The directory the file list shows, read again only when it changes
+	directory: PathBuf,
+	listing: Listing,
+	definition_path: Option<PathBuf>,
+	project_name: String,
+	// Once typed in, the name is no longer taken from the project
+	project_name_edited: bool,
+	// An existing project whose definition is replaced, otherwise the project the definition names
+	into_project: Option<uuid::Uuid>,
+	preview: Option<Result<views::ImportReport, errors::DefinitionErrors>>,
+	// Import was pressed for an import that drops templates or leaves data behind
+	confirming: bool,
+}
+
+pub enum ImportDialogState {
+	Open,
+	Closed,
+	Imported(Box<views::ImportReport>),
+}
+
+fn is_definition_file(path: &Path) -> bool {
+	path.extension()
+		.is_some_and(|extension| extension == "json" || extension == "yaml" || extension == "yml")
+}
+
+// Directories first, then definition files, each sorted by name
+fn list_directory(directory: &Path) -> std::io::Result<(Vec<PathBuf>, Vec<PathBuf>)> {
+	let mut directories = vec![];
+	let mut files = vec![];
+	for entry in std::fs::read_dir(directory)? {
+		let path = entry?.path();
+		if path.is_dir() {
+			directories.push(path);
+		} else if is_definition_file(&path) {
+			files.push(path);
+		}
+	}
+	directories.sort();
+	files.sort();
+	Ok((directories, files))
+}
+
+fn read_listing(directory: &Path) -> Listing {
+	list_directory(directory).map_err(|err| err.to_string())
+}
+
+fn file_name(path: &Path) -> String {
+	path.file_name()
+		.map_or(path.display().to_string(), |name| name.to_string_lossy().to_string())
+}
+
+// Replacing a definition removes what the file does not have, which is confirmed first
+fn needs_confirmation(report: &views::ImportReport) -> bool {
+	let changes = &report.changes;
+	changes.from_version.is_some()
+		&& (!changes.event_templates.removed.is_empty()
+			|| !changes.trace_templates.removed.is_empty()
+			|| changes.orphaned_events > 0
+			|| changes.orphaned_traces > 0)
+}
+
+fn show_parsing_errors(ui: &mut egui::Ui, color: egui::Color32, what: &str, errors: &[errors::ParsingError]) {
+	for error in errors {
+		ui.colored_label(color, format!("{}: {}", what, error));
+	}
+}
+
+impl Default for ImportDialog {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl ImportDialog {
+	pub fn new() -> Self {
+		let directory = directories_next::UserDirs::new()
+			.map(|user_dirs| user_dirs.home_dir().to_path_buf())
+			.or_else(|| std::env::current_dir().ok())
+			.unwrap_or_default();
+		ImportDialog {
+			listing: read_listing(&directory),
+			directory,
+			definition_path: None,
+			project_name: String::new(),
+			project_name_edited: false,
+			into_project: None,
+			preview: None,
+			confirming: false,
+		}
+	}
+
+	fn set_directory(&mut self, directory: PathBuf) {
+		self.listing = read_listing(&directory);
+		self.directory = directory;
+	}
+
+	fn update_preview(&mut self, service: &dyn service::EventsService) {
+		self.confirming = false;
+		let Some(definition_path) = &self.definition_path else {
+			self.preview = None;
+			return;
+		};
+		let preview = service.preview_definition_import(definition_path, self.into_project);
+		// Imported again, a project keeps its name unless another one is typed in here
+		if !self.project_name_edited {
+			if let Ok(report) = &preview {
+				self.project_name = match service.view_project(report.project_uuid) {
+					Some(project) => project.name,
+					None => definition_path
+						.file_stem()
+						.map_or(String::new(), |stem| stem.to_string_lossy().to_string()),
+				};
+			}
+		}
+		self.preview = Some(preview);
+	}
+
+	fn import(&mut self, service: &mut dyn service::EventsService) -> ImportDialogState {
+		let Some(definition_path) = &self.definition_path else {
+			return ImportDialogState::Open;
+		};
+		match service.import_definition(self.project_name.clone(), definition_path, self.into_project) {
+			Ok(report) => ImportDialogState::Imported(Box::new(report)),
+			Err(errors) => {
+				self.confirming = false;
+				self.preview = Some(Err(errors));
+				ImportDialogState::Open
+			}
+		}
+	}
+
+	pub fn show(&mut self, ctx: &egui::Context, service: &mut dyn service::EventsService) -> ImportDialogState {
+		let mut open = true;
+		let mut state = ImportDialogState::Open;
+		egui::Window::new("Import definition").open(&mut open).show(ctx, |ui| {
+			self.draw_file_picker(ui, service);
+			ui.separator();
+			self.draw_target(ui, service);
+			ui.separator();
+			self.draw_preview(ui);
+
+			if self.confirming {
+				state = self.draw_confirmation(ui, service);
+				return;
+			}
+			let ready = matches!(self.preview, Some(Ok(_))) && !self.project_name.is_empty();
+			if ui.add_enabled(ready, egui::Button::new("Import")).clicked() {
+				match &self.preview {
+					Some(Ok(report)) if needs_confirmation(report) => self.confirming = true,
+					_ => state = self.import(service),
+				}
+			}
+		});
+		if !open {
+			return ImportDialogState::Closed;
+		}
+		state
+	}
+
+	fn draw_file_picker(&mut self, ui: &mut egui::Ui, service: &dyn service::EventsService) {
+		let mut directory = None;
+		let mut definition_path = None;
+		ui.horizontal(|ui| {
+			if ui.button("Up").clicked() {
+				directory = self.directory.parent().map(Path::to_path_buf);
+			}
+			ui.label(self.directory.display().to_string());
+		});
+		match &self.listing {
+			Ok((directories, files)) => {
+				egui::ScrollArea::vertical().max_height(200.0).show(ui, |ui| {
+					for path in directories {
+						if ui.button(format!("{}/", file_name(path))).clicked() {
+							directory = Some(path.clone());
+						}
+					}
+					for path in files {
+						let selected = self.definition_path.as_ref() == Some(path);
+						if ui.selectable_label(selected, file_name(path)).clicked() {
+							definition_path = Some(path.clone());
+						}
+					}
+				});
+			}
+			Err(err) => {
+				ui.colored_label(ui.visuals().error_fg_color, format!("Cannot read the directory: {}", err));
+			}
+		}
+		if let Some(directory) = directory {
+			self.set_directory(directory);
+		}
+		if definition_path.is_some() {
+			self.definition_path = definition_path;
+			self.update_preview(service);
+		}
+	}
+
+	fn draw_target(&mut self, ui: &mut egui::Ui, service: &dyn service::EventsService) {
+		let projects: Vec<views::ProjectItemView> = service.list_projects().collect();
+		let selected_text = self
+			.into_project
+			.and_then(|project_uuid| projects.iter().find(|project| project.project_uuid == project_uuid))
+			.map_or("The project in the file".to_string(), |project| {
+				format!("Replace definition of {}", project.name)
+			});
+		let mut into_project = self.into_project;
+		egui::ComboBox::from_label("Import into")
+			.selected_text(selected_text)
+			.show_ui(ui, |ui| {
+				ui.selectable_value(&mut into_project, None, "The project in the file");
+				for project in projects.iter() {
+					ui.selectable_value(
+						&mut into_project,
+						Some(project.project_uuid),
+						format!("Replace definition of {}", project.name),
+					);
+				}
+			});
+		if into_project != self.into_project {
+			self.into_project = into_project;
+			self.update_preview(service);
+		}
+		ui.horizontal(|ui| {
+			ui.label("Project name");
+			if ui.text_edit_singleline(&mut self.project_name).changed() {
+				self.project_name_edited = true;
+			}
+		});
+	}
+
+	fn draw_confirmation(&mut self, ui: &mut egui::Ui, service: &mut dyn service::EventsService) -> ImportDialogState {
+		let Some(Ok(report)) = &self.preview else {
+			self.confirming = false;
+			return ImportDialogState::Open;
+		};
+		let changes = &report.changes;
+		let project_name = service
+			.view_project(report.project_uuid)
+			.map_or(self.project_name.clone(), |project| project.name);
+		ui.colored_label(
+			ui.visuals().warn_fg_color,
+			format!(
+				"This replaces the definition of {}. {} event templates and {} trace templates that the file \
+				does not have are removed, {} events and {} traces are left without a template.",
+				project_name,
+				changes.event_templates.removed.len(),
+				changes.trace_templates.removed.len(),
+				changes.orphaned_events,
+				changes.orphaned_traces
+			),
+		);
+		let mut state = ImportDialogState::Open;
+		ui.horizontal(|ui| {
+			if ui.button("Replace").clicked() {
+				state = self.import(service);
+			}
+			if ui.button("Cancel").clicked() {
+				self.confirming = false;
+			}
+		});
+		state
+	}
+
+	fn draw_preview(&mut self, ui: &mut egui::Ui) {
+		match &self.preview {
+			None => {
+				ui.label("Pick a definition file");
+			}
+			Some(Err(errors)) => {
+				show_parsing_errors(ui, ui.visuals().error_fg_color, "error", &errors.errors);
+				show_parsing_errors(ui, ui.visuals().warn_fg_color, "warning", &errors.warnings);
+			}
+			Some(Ok(report)) => {
+				ui.monospace(report.changes.to_string());
+				show_parsing_errors(ui, ui.visuals().warn_fg_color, "warning", &report.warnings);
+			}
+		}
+	}
+}
@@ -1,4 +1,5 @@
 #![warn(clippy::all, rust_2018_idioms)]
 
 mod app;
+mod import_dialog;
 pub use app::TemplateApp;
@@ -6,6 +6,18 @@
 fn main() -> eframe::Result {
 	env_logger::init(); // Log to stderr (if you run with `RUST_LOG=debug`).
 
+	// --data-dir <path>, --profile <name> and --backend <files|sqlite>, see journal_server::config
+	let data_location = match journal_server::config::DataLocation::from_args(std::env::args().skip(1))
+		.and_then(|location| location.root_directory().map(|_| location))
+	{
+		Ok(data_location) => data_location,
+		Err(err) => {
+			eprintln!("{}", err);
+			std::process::exit(2);
+		}
+	};
+	log::info!("Using {:?}", data_location);
+
 	let native_options = eframe::NativeOptions {
 		viewport: egui::ViewportBuilder::default()
 			.with_inner_size([400.0, 300.0])
@@ -20,7 +32,7 @@ fn main() -> eframe::Result {
 	eframe::run_native(
 		"eframe template",
 		native_options,
-		Box::new(|cc| Ok(Box::new(journal_ui::TemplateApp::new(cc)))),
+		Box::new(|cc| Ok(Box::new(journal_ui::TemplateApp::new(cc, data_location)))),
 	)
 }
 
@@ -37,7 +49,10 @@ fn main() {
 			.start(
 				"the_canvas_id",
 				web_options,
-				Box::new(|cc| Ok(Box::new(journal_ui::TemplateApp::new(cc)))),
+				Box::new(|cc| {
+					let data_location = journal_server::config::DataLocation::from_env();
+					Ok(Box::new(journal_ui::TemplateApp::new(cc, data_location)))
+				}),
 			)
 			.await;
 